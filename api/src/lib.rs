@@ -27,18 +27,20 @@ mod client;
 mod crypto;
 mod error;
 mod server;
+mod sinks;
 
 pub use crate::client::{url, WebSocketClient};
 use crate::crypto::{decrypt, encrypt};
 pub use crate::crypto::{load_api_token, load_or_create_api_token, ApiToken};
 pub use crate::error::KeyError;
 pub use crate::server::WebSocketServer;
+pub use crate::sinks::{EventSinkConfig, EventSinkKind, EventSinkService};
 use log::*;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_derive::{Deserialize, Serialize};
 use stegos_crypto::pbc;
-pub use stegos_network::NodeInfo;
+pub use stegos_network::{NodeInfo, PeerReputation, TrafficStats};
 pub use stegos_node::{ChainNotification, NodeRequest, NodeResponse, StatusNotification};
 pub use stegos_wallet::api::*;
 pub use websocket::WebSocketError;
@@ -77,13 +79,29 @@ pub enum NetworkRequest {
         data: Vec<u8>,
     },
     ConnectedNodesRequest {},
+    BanPeer {
+        peer_id: String,
+    },
+    UnbanPeer {
+        peer_id: String,
+    },
+    AllowPeer {
+        peer_id: String,
+    },
+    DisallowPeer {
+        peer_id: String,
+    },
+    PeerReputationRequest {},
+    BandwidthStats {},
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum NetworkResponse {
-    VersionInfo { version: String },
+    VersionInfo {
+        version: String,
+    },
     SubscribedUnicast,
     SubscribedBroadcast,
     UnsubscribedUnicast,
@@ -91,8 +109,25 @@ pub enum NetworkResponse {
     SentUnicast,
     PublishedBroadcast,
     ConnectedNodesRequested,
-    ConnectedNodes { total: usize, nodes: Vec<NodeInfo> },
-    Error { error: String },
+    ConnectedNodes {
+        total: usize,
+        nodes: Vec<NodeInfo>,
+    },
+    PeerBanned,
+    PeerUnbanned,
+    PeerAllowed,
+    PeerDisallowed,
+    PeerReputation {
+        peers: Vec<PeerReputation>,
+    },
+    BandwidthStats {
+        protocols: Vec<TrafficStats>,
+        topics: Vec<TrafficStats>,
+        peers: Vec<TrafficStats>,
+    },
+    Error {
+        error: String,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,13 +26,14 @@ use crate::{
     decode, encode, NetworkNotification, NetworkRequest, NetworkResponse, Request, RequestId,
     RequestKind, Response, ResponseKind,
 };
-use failure::Error;
+use failure::{format_err, Error};
 use futures::sync::{mpsc, oneshot};
 use futures::{task, Async, AsyncSink, Future, Poll, Sink, Stream};
 use log::*;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use stegos_network::{Network, NetworkResponse as NetworkServiceResponse, UnicastMessage};
+use std::str::FromStr;
+use stegos_network::{Network, NetworkResponse as NetworkServiceResponse, PeerId, UnicastMessage};
 use stegos_node::{ChainNotification, Node, NodeResponse, StatusNotification};
 use stegos_wallet::api::{WalletNotification, WalletResponse};
 use stegos_wallet::Wallet;
@@ -95,6 +96,10 @@ enum NetworkResult {
     Async(oneshot::Receiver<NetworkServiceResponse>),
 }
 
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, Error> {
+    PeerId::from_str(peer_id).map_err(|e| format_err!("Invalid peer_id '{}': {:?}", peer_id, e))
+}
+
 impl WebSocketHandler {
     fn new(
         peer: SocketAddr,
@@ -195,6 +200,30 @@ impl WebSocketHandler {
                 let rx = self.network.list_connected_nodes()?;
                 Ok(NetworkResult::Async(rx))
             }
+            NetworkRequest::BanPeer { peer_id } => {
+                self.network.ban_peer(parse_peer_id(&peer_id)?)?;
+                Ok(NetworkResult::Immediate(NetworkResponse::PeerBanned))
+            }
+            NetworkRequest::UnbanPeer { peer_id } => {
+                self.network.unban_peer(parse_peer_id(&peer_id)?)?;
+                Ok(NetworkResult::Immediate(NetworkResponse::PeerUnbanned))
+            }
+            NetworkRequest::AllowPeer { peer_id } => {
+                self.network.allow_peer(parse_peer_id(&peer_id)?)?;
+                Ok(NetworkResult::Immediate(NetworkResponse::PeerAllowed))
+            }
+            NetworkRequest::DisallowPeer { peer_id } => {
+                self.network.disallow_peer(parse_peer_id(&peer_id)?)?;
+                Ok(NetworkResult::Immediate(NetworkResponse::PeerDisallowed))
+            }
+            NetworkRequest::PeerReputationRequest {} => {
+                let rx = self.network.list_peer_reputation()?;
+                Ok(NetworkResult::Async(rx))
+            }
+            NetworkRequest::BandwidthStats {} => {
+                let rx = self.network.bandwidth_stats()?;
+                Ok(NetworkResult::Async(rx))
+            }
         }
     }
 }
@@ -365,6 +394,20 @@ impl Future for WebSocketHandler {
                             }),
                             id,
                         },
+                        NetworkServiceResponse::PeerReputation { peers } => Response {
+                            kind: ResponseKind::NetworkResponse(NetworkResponse::PeerReputation {
+                                peers,
+                            }),
+                            id,
+                        },
+                        NetworkServiceResponse::BandwidthStats { stats } => Response {
+                            kind: ResponseKind::NetworkResponse(NetworkResponse::BandwidthStats {
+                                protocols: stats.protocols,
+                                topics: stats.topics,
+                                peers: stats.peers,
+                            }),
+                            id,
+                        },
                     };
                     try_send!(self, resp);
                     continue;
@@ -0,0 +1,764 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Event sinks - delivery of chain, status and wallet notifications
+//! without a WebSocket client.
+//!
+//! Each notification is serialized to a single JSON line, the same as
+//! the body of the corresponding WebSocket notification. Sinks do blocking
+//! I/O in their own threads and drop events if they can't keep up.
+//! Webhooks retry failed events in the background, without holding back
+//! newer ones, so events may be delivered out of order.
+//!
+
+use failure::{bail, format_err, Error};
+use futures::sync::{mpsc, oneshot};
+use futures::{Async, Future, Poll, Stream};
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use stegos_node::{ChainNotification, Node, NodeRequest, NodeResponse, StatusNotification};
+use stegos_wallet::api::{AccountId, WalletNotification};
+use stegos_wallet::Wallet;
+use tokio::runtime::TaskExecutor;
+
+/// The maximal number of events waiting for delivery, per sink.
+const EVENT_QUEUE_SIZE: usize = 1024;
+/// Timeout for network operations of webhooks.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Notification types fed by the chain subscription.
+const CHAIN_EVENTS: &[&str] = &[
+    "micro_block_prepared",
+    "micro_block_reverted",
+    "macro_block_committed",
+];
+/// Notification types fed by the status subscription.
+const STATUS_EVENTS: &[&str] = &["status_changed"];
+
+/// Destination of events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum EventSinkKind {
+    /// Append-only file with one JSON object per line.
+    File {
+        path: PathBuf,
+        /// Rotate the file when it exceeds this size, in bytes. Zero disables rotation.
+        #[serde(default)]
+        max_size: u64,
+        /// The number of rotated files to keep.
+        #[serde(default = "default_max_files")]
+        max_files: usize,
+    },
+    /// HTTP POST of each event to a local endpoint.
+    Webhook {
+        /// http://host:port/path
+        url: String,
+        /// The number of retries after a failed delivery.
+        #[serde(default = "default_max_retries")]
+        max_retries: u32,
+        /// Delay before the first retry, doubled on each next one.
+        #[serde(default = "default_retry_delay")]
+        #[serde(with = "humantime_serde")]
+        retry_delay: Duration,
+    },
+    /// Unix domain socket with one JSON object per line, Unix platforms only.
+    UnixSocket { path: PathBuf },
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_retry_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+/// Configuration of an event sink.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventSinkConfig {
+    #[serde(flatten)]
+    pub kind: EventSinkKind,
+    /// Types of notifications to deliver, e.g. "received" or "transaction_status".
+    /// Empty list means all wallet notifications.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Accounts to deliver wallet notifications for. Empty list means all accounts.
+    #[serde(default)]
+    pub accounts: Vec<AccountId>,
+}
+
+impl EventSinkConfig {
+    /// Returns true if the notification should be delivered to this sink.
+    fn matches(&self, event_type: &str, account_id: Option<&AccountId>) -> bool {
+        if self.events.is_empty() {
+            // Chain and status events are noisy, deliver them on demand.
+            if account_id.is_none() {
+                return false;
+            }
+        } else if !self.events.iter().any(|e| e == event_type) {
+            return false;
+        }
+        match account_id {
+            Some(account_id) => self.accounts.is_empty() || self.accounts.contains(account_id),
+            None => true,
+        }
+    }
+
+    fn wants_any(&self, event_types: &[&str]) -> bool {
+        self.events
+            .iter()
+            .any(|e| event_types.iter().any(|t| e == t))
+    }
+}
+
+struct EventSink {
+    cfg: EventSinkConfig,
+    name: String,
+    tx: std_mpsc::SyncSender<String>,
+    /// The number of events dropped since the queue has overflowed.
+    dropped: u64,
+}
+
+impl EventSink {
+    fn send(&mut self, event_type: &str, account_id: Option<&AccountId>, line: &str) {
+        if !self.cfg.matches(event_type, account_id) {
+            return;
+        }
+        match self.tx.try_send(line.to_string()) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    warn!(
+                        "Event sink has recovered: sink={}, dropped={}",
+                        self.name, self.dropped
+                    );
+                    self.dropped = 0;
+                }
+            }
+            Err(std_mpsc::TrySendError::Full(_)) => {
+                if self.dropped % EVENT_QUEUE_SIZE as u64 == 0 {
+                    warn!(
+                        "Event sink is overloaded, dropping events: sink={}, dropped={}",
+                        self.name, self.dropped
+                    );
+                }
+                self.dropped += 1;
+            }
+            Err(std_mpsc::TrySendError::Disconnected(_)) => {
+                error!("Event sink has terminated: sink={}", self.name);
+            }
+        }
+    }
+}
+
+///
+/// Delivers notifications to the configured sinks.
+///
+pub struct EventSinkService {
+    sinks: Vec<EventSink>,
+    node: Node,
+    wants_chain: bool,
+    wallet_notifications: mpsc::UnboundedReceiver<WalletNotification>,
+    node_response: Option<oneshot::Receiver<NodeResponse>>,
+    status_notifications: Option<mpsc::Receiver<StatusNotification>>,
+    chain_notifications: Option<mpsc::Receiver<ChainNotification>>,
+}
+
+impl EventSinkService {
+    /// Start all sinks and deliver notifications to them.
+    pub fn spawn(
+        cfgs: Vec<EventSinkConfig>,
+        executor: TaskExecutor,
+        wallet: Wallet,
+        node: Node,
+    ) -> Result<(), Error> {
+        if cfgs.is_empty() {
+            return Ok(());
+        }
+        let mut sinks = Vec::with_capacity(cfgs.len());
+        for (i, cfg) in cfgs.into_iter().enumerate() {
+            let name = format!("sink-{}", i);
+            let writer = EventWriter::new(&cfg.kind)?;
+            let (tx, rx) = std_mpsc::sync_channel(EVENT_QUEUE_SIZE);
+            let name2 = name.clone();
+            thread::Builder::new()
+                .name(name.clone())
+                .spawn(move || writer.run(&name2, rx))?;
+            info!("Started event sink: sink={}, kind={:?}", name, cfg.kind);
+            sinks.push(EventSink {
+                cfg,
+                name,
+                tx,
+                dropped: 0,
+            });
+        }
+
+        let wants_status = sinks.iter().any(|s| s.cfg.wants_any(STATUS_EVENTS));
+        let wants_chain = sinks.iter().any(|s| s.cfg.wants_any(CHAIN_EVENTS));
+        let node_response = if wants_status || wants_chain {
+            Some(node.request(NodeRequest::SubscribeStatus {}))
+        } else {
+            None
+        };
+        let service = EventSinkService {
+            sinks,
+            node,
+            wants_chain,
+            wallet_notifications: wallet.subscribe(),
+            node_response,
+            status_notifications: None,
+            chain_notifications: None,
+        };
+        executor.spawn(service);
+        Ok(())
+    }
+
+    fn dispatch<T: serde::Serialize>(&mut self, notification: &T) {
+        let value = match serde_json::to_value(notification) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Failed to serialize notification: {}", e);
+                return;
+            }
+        };
+        let event_type = value
+            .get("type")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let account_id = value
+            .get("account_id")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string());
+        let line = value.to_string();
+        for sink in &mut self.sinks {
+            sink.send(&event_type, account_id.as_ref(), &line);
+        }
+    }
+}
+
+impl Future for EventSinkService {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(mut node_response) = self.node_response.take() {
+            match node_response.poll() {
+                Ok(Async::Ready(NodeResponse::SubscribedStatus { status, rx })) => {
+                    self.status_notifications = rx;
+                    if self.wants_chain {
+                        let request = NodeRequest::SubscribeChain {
+                            epoch: status.epoch,
+                            offset: status.offset,
+                        };
+                        self.node_response = Some(self.node.request(request));
+                        self.wants_chain = false;
+                        futures::task::current().notify();
+                    }
+                }
+                Ok(Async::Ready(NodeResponse::SubscribedChain { rx, .. })) => {
+                    self.chain_notifications = rx;
+                }
+                Ok(Async::Ready(response)) => {
+                    error!("Failed to subscribe for events: {:?}", response);
+                }
+                Ok(Async::NotReady) => self.node_response = Some(node_response),
+                Err(oneshot::Canceled) => return Ok(Async::Ready(())), // shutdown.
+            }
+        }
+
+        loop {
+            match self.wallet_notifications.poll().unwrap() {
+                Async::Ready(Some(notification)) => self.dispatch(&notification),
+                Async::Ready(None) => return Ok(Async::Ready(())), // shutdown.
+                Async::NotReady => break,
+            }
+        }
+
+        if let Some(mut status_notifications) = self.status_notifications.take() {
+            loop {
+                match status_notifications.poll().unwrap() {
+                    Async::Ready(Some(notification)) => self.dispatch(&notification),
+                    Async::Ready(None) => return Ok(Async::Ready(())), // shutdown.
+                    Async::NotReady => break,
+                }
+            }
+            self.status_notifications = Some(status_notifications);
+        }
+
+        if let Some(mut chain_notifications) = self.chain_notifications.take() {
+            loop {
+                match chain_notifications.poll().unwrap() {
+                    Async::Ready(Some(notification)) => self.dispatch(&notification),
+                    Async::Ready(None) => return Ok(Async::Ready(())), // shutdown.
+                    Async::NotReady => break,
+                }
+            }
+            self.chain_notifications = Some(chain_notifications);
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+/// Blocking part of a sink, runs in a dedicated thread.
+enum EventWriter {
+    File(FileWriter),
+    Webhook(WebhookWriter),
+    #[cfg(unix)]
+    UnixSocket(UnixSocketWriter),
+}
+
+impl EventWriter {
+    fn new(kind: &EventSinkKind) -> Result<Self, Error> {
+        let writer = match kind {
+            EventSinkKind::File {
+                path,
+                max_size,
+                max_files,
+            } => EventWriter::File(FileWriter::open(path.clone(), *max_size, *max_files)?),
+            EventSinkKind::Webhook {
+                url,
+                max_retries,
+                retry_delay,
+            } => EventWriter::Webhook(WebhookWriter::new(url, *max_retries, *retry_delay)?),
+            #[cfg(unix)]
+            EventSinkKind::UnixSocket { path } => {
+                if path.as_os_str().is_empty() {
+                    bail!("Empty path of the event socket");
+                }
+                EventWriter::UnixSocket(UnixSocketWriter {
+                    path: path.clone(),
+                    stream: None,
+                })
+            }
+            #[cfg(not(unix))]
+            EventSinkKind::UnixSocket { path } => bail!(
+                "Unix socket event sinks are not supported on this platform: path={:?}",
+                path
+            ),
+        };
+        Ok(writer)
+    }
+
+    fn run(self, name: &str, rx: std_mpsc::Receiver<String>) {
+        match self {
+            EventWriter::File(mut w) => write_each(name, rx, |line| w.write(line)),
+            EventWriter::Webhook(mut w) => w.run(name, rx),
+            #[cfg(unix)]
+            EventWriter::UnixSocket(mut w) => write_each(name, rx, |line| w.write(line)),
+        }
+        debug!("Event sink stopped: sink={}", name);
+    }
+}
+
+/// Writes events one by one, in order.
+fn write_each<F>(name: &str, rx: std_mpsc::Receiver<String>, mut write: F)
+where
+    F: FnMut(&str) -> Result<(), Error>,
+{
+    for line in rx {
+        if let Err(e) = write(&line) {
+            error!("Failed to deliver event: sink={}, error={}", name, e);
+        }
+    }
+}
+
+/// NDJSON file with size-based rotation.
+struct FileWriter {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl FileWriter {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> Result<Self, Error> {
+        let file = Self::open_file(&path)?;
+        let size = file.metadata()?.len();
+        Ok(FileWriter {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<File, Error> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format_err!("Failed to open {:?}: {}", path, e))
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Shift file -> file.1 -> file.2 ... and drop the oldest one.
+    fn rotate(&mut self) -> Result<(), Error> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.rotated_path(self.max_files);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), Error> {
+        let len = line.len() as u64 + 1;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// An event waiting for the next delivery attempt.
+struct Retry {
+    line: String,
+    attempt: u32,
+    deadline: Instant,
+}
+
+/// HTTP POST to a local endpoint, with retries.
+struct WebhookWriter {
+    host: String,
+    path: String,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl WebhookWriter {
+    fn new(url: &str, max_retries: u32, retry_delay: Duration) -> Result<Self, Error> {
+        let rest = if url.starts_with("http://") {
+            &url[7..]
+        } else {
+            bail!("Only http:// webhooks are supported: url={}", url);
+        };
+        let (host, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            bail!("Missing host in webhook url: url={}", url);
+        }
+        Ok(WebhookWriter {
+            host: host.to_string(),
+            path: path.to_string(),
+            max_retries,
+            retry_delay,
+        })
+    }
+
+    fn post(&self, body: &str) -> Result<(), Error> {
+        let addr = if self.host.contains(':') {
+            self.host.clone()
+        } else {
+            format!("{}:80", self.host)
+        };
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format_err!("Failed to resolve {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT)?;
+        stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes())?;
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        // HTTP/1.1 200 OK
+        let code = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| format_err!("Invalid HTTP response: {:?}", status_line))?;
+        if code < 200 || code >= 300 {
+            bail!("HTTP status {}", code);
+        }
+        Ok(())
+    }
+
+    /// Posts new events as they come and failed ones when their retry is due.
+    fn run(&mut self, name: &str, rx: std_mpsc::Receiver<String>) {
+        let mut retries: Vec<Retry> = Vec::new();
+        let mut closed = false;
+        loop {
+            let now = Instant::now();
+            if let Some(pos) = retries.iter().position(|r| r.deadline <= now) {
+                let retry = retries.swap_remove(pos);
+                self.deliver(name, retry.line, retry.attempt, &mut retries);
+                continue;
+            }
+            let next_retry = retries.iter().map(|r| r.deadline).min();
+            if closed {
+                // Finish pending retries on shutdown.
+                match next_retry {
+                    Some(deadline) => thread::sleep(deadline - now),
+                    None => break,
+                }
+                continue;
+            }
+            let received = match next_retry {
+                Some(deadline) => rx.recv_timeout(deadline - now),
+                None => rx
+                    .recv()
+                    .map_err(|_| std_mpsc::RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(line) => self.deliver(name, line, 0, &mut retries),
+                Err(std_mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => closed = true,
+            }
+        }
+    }
+
+    /// Posts an event, schedules a retry on failure.
+    fn deliver(&self, name: &str, line: String, attempt: u32, retries: &mut Vec<Retry>) {
+        let e = match self.post(&line) {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        if attempt >= self.max_retries {
+            error!("Failed to deliver event: sink={}, error={}", name, e);
+            return;
+        }
+        if retries.len() >= EVENT_QUEUE_SIZE {
+            error!(
+                "Too many events waiting for retry, dropping event: sink={}, error={}",
+                name, e
+            );
+            return;
+        }
+        warn!(
+            "Failed to post event, retrying: host={}, attempt={}, error={}",
+            self.host, attempt, e
+        );
+        let delay = self.retry_delay * 2u32.pow(attempt.min(16));
+        retries.push(Retry {
+            line,
+            attempt: attempt + 1,
+            deadline: Instant::now() + delay,
+        });
+    }
+}
+
+/// A client connection to a Unix domain socket.
+#[cfg(unix)]
+struct UnixSocketWriter {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixSocketWriter {
+    fn write(&mut self, line: &str) -> Result<(), Error> {
+        // Reconnect once if the listener has been restarted.
+        for _ in 0..2 {
+            if self.stream.is_none() {
+                let stream = UnixStream::connect(&self.path)
+                    .map_err(|e| format_err!("Failed to connect to {:?}: {}", self.path, e))?;
+                self.stream = Some(stream);
+            }
+            let stream = self.stream.as_mut().unwrap();
+            match stream
+                .write_all(line.as_bytes())
+                .and_then(|()| stream.write_all(b"\n"))
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    debug!(
+                        "Event socket disconnected: path={:?}, error={}",
+                        self.path, e
+                    );
+                    self.stream = None;
+                }
+            }
+        }
+        Err(format_err!("Failed to write to {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use tempdir::TempDir;
+
+    #[test]
+    fn filters() {
+        let cfg = EventSinkConfig {
+            kind: EventSinkKind::UnixSocket {
+                path: PathBuf::from("/tmp/events.sock"),
+            },
+            events: Vec::new(),
+            accounts: Vec::new(),
+        };
+        assert!(cfg.matches("received", Some(&"1".to_string())));
+        assert!(!cfg.matches("micro_block_prepared", None));
+
+        let cfg = EventSinkConfig {
+            events: vec!["received".to_string(), "status_changed".to_string()],
+            accounts: vec!["2".to_string()],
+            ..cfg
+        };
+        assert!(!cfg.matches("received", Some(&"1".to_string())));
+        assert!(cfg.matches("received", Some(&"2".to_string())));
+        assert!(!cfg.matches("transaction_status", Some(&"2".to_string())));
+        assert!(cfg.matches("status_changed", None));
+        assert!(cfg.wants_any(STATUS_EVENTS));
+        assert!(!cfg.wants_any(CHAIN_EVENTS));
+    }
+
+    #[test]
+    fn config() {
+        let cfg: EventSinkConfig = serde_json::from_str(
+            r#"{"type": "webhook", "url": "http://127.0.0.1:8080/events", "retry_delay": "5s", "events": ["received"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.kind,
+            EventSinkKind::Webhook {
+                url: "http://127.0.0.1:8080/events".to_string(),
+                max_retries: 5,
+                retry_delay: Duration::from_secs(5),
+            }
+        );
+        assert_eq!(cfg.events, vec!["received".to_string()]);
+        assert!(cfg.accounts.is_empty());
+
+        let w = WebhookWriter::new("http://localhost:8080", 0, Duration::from_secs(1)).unwrap();
+        assert_eq!(w.host, "localhost:8080");
+        assert_eq!(w.path, "/");
+        assert!(WebhookWriter::new("https://localhost", 0, Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn file_rotation() {
+        let dir = TempDir::new("sinks").unwrap();
+        let path = dir.path().join("events.ndjson");
+        let mut writer = FileWriter::open(path.clone(), 10, 2).unwrap();
+        for i in 0..4 {
+            writer.write(&format!("event{}", i)).unwrap();
+        }
+
+        let read = |path: &Path| {
+            let mut contents = String::new();
+            File::open(path)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+        assert_eq!(read(&path), "event3\n");
+        assert_eq!(read(&writer.rotated_path(1)), "event2\n");
+        assert_eq!(read(&writer.rotated_path(2)), "event1\n");
+        assert!(!writer.rotated_path(3).exists());
+    }
+
+    #[test]
+    fn webhook_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let (bodies_tx, bodies_rx) = std_mpsc::channel();
+        let server = thread::spawn(move || {
+            for (i, stream) in listener.incoming().take(3).enumerate() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if header.starts_with("Content-Length: ") {
+                        content_length = header[16..].parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                stream.read_exact(&mut body).unwrap();
+                // The first request fails.
+                let response: &[u8] = if i == 0 {
+                    b"HTTP/1.1 503 Service Unavailable\r\n\r\n"
+                } else {
+                    bodies_tx.send(String::from_utf8(body).unwrap()).unwrap();
+                    b"HTTP/1.1 200 OK\r\n\r\n"
+                };
+                stream.get_mut().write_all(response).unwrap();
+            }
+        });
+
+        let mut writer = WebhookWriter::new(&url, 1, Duration::from_millis(100)).unwrap();
+        let (tx, rx) = std_mpsc::sync_channel(EVENT_QUEUE_SIZE);
+        tx.send("event1".to_string()).unwrap();
+        tx.send("event2".to_string()).unwrap();
+        drop(tx);
+        writer.run("sink-0", rx);
+        server.join().unwrap();
+
+        // The failed event doesn't hold back the next one.
+        let bodies: Vec<String> = bodies_rx.try_iter().collect();
+        assert_eq!(bodies, vec!["event2".to_string(), "event1".to_string()]);
+    }
+}
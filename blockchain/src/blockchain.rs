@@ -24,7 +24,6 @@
 use crate::awards::{Awards, ValidatorAwardState};
 use crate::block::*;
 use crate::config::*;
-use crate::election::mix;
 use crate::election::ElectionInfo;
 use crate::election::{self, ElectionResult};
 use crate::error::*;
@@ -38,6 +37,7 @@ use crate::transaction::{CoinbaseTransaction, ServiceAwardTransaction, Transacti
 use crate::view_changes::ViewChangeProof;
 use bit_vec::BitVec;
 use byteorder::{BigEndian, ByteOrder};
+use failure::format_err;
 use log::*;
 use rocksdb;
 use rocksdb::{ColumnFamily, Snapshot, WriteBatch};
@@ -165,7 +165,7 @@ pub(crate) struct Balance {
 }
 
 /// A special offset used to tore Macro Blocks on the disk.
-const MACRO_BLOCK_OFFSET: u32 = u32::max_value();
+pub(crate) const MACRO_BLOCK_OFFSET: u32 = u32::max_value();
 
 #[derive(Debug, Default, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct LSN(pub(crate) u64, pub(crate) u32); // use `struct` to disable explicit casts.
@@ -275,6 +275,32 @@ pub struct Blockchain {
     cache: VecDeque<Block>,
 }
 
+///
+/// Read-only access to blocks of a chain database.
+///
+/// Doesn't recover the state and doesn't take the database lock,
+/// so it can be used while a node is running on the same directory.
+///
+pub struct BlockReader {
+    database: rocksdb::DB,
+}
+
+impl BlockReader {
+    pub fn open(chain_dir: &Path) -> Result<BlockReader, BlockchainError> {
+        let database = Blockchain::open_database_read_only(chain_dir)?;
+        Ok(BlockReader { database })
+    }
+
+    /// Iterate over blocks starting from the specified position, in the order of the chain.
+    pub fn blocks_starting<'a>(
+        &'a self,
+        epoch: u64,
+        offset: u32,
+    ) -> impl Iterator<Item = Block> + 'a {
+        Blockchain::iter_blocks(&self.database, epoch, offset)
+    }
+}
+
 impl Blockchain {
     //----------------------------------------------------------------------------------------------
     // Constructors.
@@ -355,6 +381,7 @@ impl Blockchain {
         };
 
         blockchain.init(genesis, timestamp, consistency_check)?;
+        blockchain.check_checkpoints()?;
         Ok(blockchain)
     }
 
@@ -429,7 +456,7 @@ impl Blockchain {
         recover_map!(cf_output_by_hash, self.output_by_hash, lsn);
         let mut escrow = EscrowMap::new();
         recover_map!(cf_escrow, escrow, lsn);
-        self.escrow.escrow = escrow;
+        self.escrow.recover(escrow);
 
         let block = self.macro_block(lsn.0)?.into_owned();
 
@@ -632,6 +659,166 @@ impl Blockchain {
         Ok(())
     }
 
+    //----------------------------------------------------------------------------------------------
+    // Offline consistency check.
+    //----------------------------------------------------------------------------------------------
+
+    /// Open an existing chain database without modifying it.
+    pub(crate) fn open_database_read_only(
+        chain_dir: &Path,
+    ) -> Result<rocksdb::DB, BlockchainError> {
+        let opts = rocksdb::Options::default();
+        let database = rocksdb::DB::open_cf_for_read_only(&opts, chain_dir, COLON_FAMILIES, false)?;
+        Ok(database)
+    }
+
+    /// Returns LSN of the last macro block persisted into META, if any.
+    pub(crate) fn snapshot_lsn(database: &rocksdb::DB) -> Result<Option<LSN>, BlockchainError> {
+        let cf_meta = database.cf_handle(META).unwrap();
+        match database.get_cf(cf_meta, EPOCH.as_bytes())? {
+            Some(value) => Ok(Some(ProtoConvert::from_buffer(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse a key created by block_key().
+    pub(crate) fn lsn_from_key(key: &[u8]) -> Option<LSN> {
+        if key.len() != 12 {
+            return None;
+        }
+        Some(LSN(
+            BigEndian::read_u64(&key[0..8]),
+            BigEndian::read_u32(&key[8..12]),
+        ))
+    }
+
+    /// Check the block position and add it using the full validation.
+    pub(crate) fn replay_block(
+        &mut self,
+        lsn: LSN,
+        block: Block,
+        timestamp: Timestamp,
+    ) -> Result<(), BlockchainError> {
+        match block {
+            Block::MicroBlock(block) => {
+                if lsn != LSN(block.header.epoch, block.header.offset) {
+                    return Err(format_err!(
+                        "Micro block is stored under a wrong key: epoch={}, offset={}, key={:?}",
+                        block.header.epoch,
+                        block.header.offset,
+                        lsn
+                    )
+                    .into());
+                }
+                self.validate_micro_block(&block, timestamp, true)?;
+                self.push_micro_block(block, timestamp)?;
+            }
+            Block::MacroBlock(block) => {
+                if lsn != LSN(block.header.epoch, MACRO_BLOCK_OFFSET) {
+                    return Err(format_err!(
+                        "Macro block is stored under a wrong key: epoch={}, key={:?}",
+                        block.header.epoch,
+                        lsn
+                    )
+                    .into());
+                }
+                if block.header.epoch != self.epoch || self.offset != 0 {
+                    return Err(format_err!(
+                        "Out of order macro block: epoch={}, expected_epoch={}, offset={}",
+                        block.header.epoch,
+                        self.epoch,
+                        self.offset
+                    )
+                    .into());
+                }
+                self.validate_macro_block(&block, timestamp)?;
+                self.push_macro_block(block, timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the persisted information about `epoch` with `database`.
+    pub(crate) fn check_epoch_info(
+        &self,
+        database: &rocksdb::DB,
+        epoch: u64,
+    ) -> Result<(), BlockchainError> {
+        let key = Self::block_key(LSN(epoch, MACRO_BLOCK_OFFSET));
+        Self::compare_values(&self.database, database, EPOCH_INFOS, &key)
+    }
+
+    /// Compare META, escrow and indexes persisted by the last macro block with `database`.
+    pub(crate) fn check_snapshot(&self, database: &rocksdb::DB) -> Result<(), BlockchainError> {
+        for key in &[EPOCH, BALANCE, ELECTION_RESULT, AWARDS] {
+            Self::compare_values(&self.database, database, META, key.as_bytes())?;
+        }
+        for cf_name in &[ESCROW, OUTPUT_BY_HASH, BLOCK_BY_HASH] {
+            let cf_expected = self.database.cf_handle(cf_name).unwrap();
+            let cf_actual = database.cf_handle(cf_name).unwrap();
+            let mut expected = self
+                .database
+                .iterator_cf(cf_expected, rocksdb::IteratorMode::Start)?;
+            let mut actual = database.iterator_cf(cf_actual, rocksdb::IteratorMode::Start)?;
+            loop {
+                match (expected.next(), actual.next()) {
+                    (None, None) => break,
+                    (Some((key, _)), None) => {
+                        return Err(format_err!(
+                            "Missing {} record: key={}",
+                            cf_name,
+                            hex::encode(&key)
+                        )
+                        .into());
+                    }
+                    (None, Some((key, _))) => {
+                        return Err(format_err!(
+                            "Unexpected {} record: key={}",
+                            cf_name,
+                            hex::encode(&key)
+                        )
+                        .into());
+                    }
+                    (Some((expected_key, expected_value)), Some((actual_key, actual_value))) => {
+                        if expected_key != actual_key || expected_value != actual_value {
+                            return Err(format_err!(
+                                "Invalid {} record: expected_key={}, actual_key={}",
+                                cf_name,
+                                hex::encode(&expected_key),
+                                hex::encode(&actual_key)
+                            )
+                            .into());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compare_values(
+        expected: &rocksdb::DB,
+        actual: &rocksdb::DB,
+        cf_name: &'static str,
+        key: &[u8],
+    ) -> Result<(), BlockchainError> {
+        let expected_value = expected.get_cf(expected.cf_handle(cf_name).unwrap(), key)?;
+        let actual_value = actual.get_cf(actual.cf_handle(cf_name).unwrap(), key)?;
+        let expected_value = expected_value.as_ref().map(|v| &v[..]);
+        let actual_value = actual_value.as_ref().map(|v| &v[..]);
+        if expected_value != actual_value {
+            return Err(format_err!(
+                "Invalid {} record: key={}, expected={}, actual={}",
+                cf_name,
+                hex::encode(key),
+                expected_value.map(hex::encode).unwrap_or_default(),
+                actual_value.map(hex::encode).unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
     //
     // Info
     //
@@ -844,10 +1031,18 @@ impl Blockchain {
         &'a self,
         epoch: u64,
         offset: u32,
+    ) -> impl Iterator<Item = Block> + 'a {
+        Self::iter_blocks(&self.database, epoch, offset)
+    }
+
+    fn iter_blocks<'a>(
+        database: &'a rocksdb::DB,
+        epoch: u64,
+        offset: u32,
     ) -> impl Iterator<Item = Block> + 'a {
         let key = Self::block_key(LSN(epoch, offset));
         let mode = rocksdb::IteratorMode::From(&key, rocksdb::Direction::Forward);
-        self.database
+        database
             .iterator(mode)
             .map(|(_, v)| Block::from_buffer(&*v).expect("couldn't deserialize block."))
     }
@@ -982,7 +1177,9 @@ impl Blockchain {
     where
         OutputIter: Iterator<Item = &'a Output>,
     {
-        self.escrow.validate_stakes(inputs, outputs, self.epoch)
+        let allow_key_rotation = self.epoch >= self.cfg.key_rotation_epoch;
+        self.escrow
+            .validate_stakes(inputs, outputs, self.epoch, allow_key_rotation)
     }
 
     ///
@@ -996,6 +1193,19 @@ impl Blockchain {
         self.escrow.iter_validator_stakes(validator_pkey)
     }
 
+    ///
+    /// Iterate over stakes which can be confiscated for a misbehaviour of
+    /// the validator in the current epoch.
+    ///
+    #[inline]
+    pub fn iter_slashable_stakes<'a>(
+        &'a self,
+        validator_pkey: &'a pbc::PublicKey,
+    ) -> impl Iterator<Item = (&'a Hash, i64, u64)> + 'a {
+        self.escrow
+            .iter_slashable_stakes(validator_pkey, self.epoch)
+    }
+
     ///
     /// Return an account key by network key.
     ///
@@ -1146,6 +1356,24 @@ impl Blockchain {
         &self.cfg
     }
 
+    /// Returns the consistency check level.
+    pub fn consistency_check(&self) -> ConsistencyCheck {
+        self.consistency_check
+    }
+
+    /// Ensures that the stored chain doesn't contradict checkpoints.
+    fn check_checkpoints(&self) -> Result<(), BlockchainError> {
+        for checkpoint in &self.cfg.checkpoints {
+            if checkpoint.epoch >= self.epoch {
+                break;
+            }
+            let block_hash = Hash::digest(&*self.macro_block(checkpoint.epoch)?);
+            self.cfg
+                .validate_checkpoint(checkpoint.epoch, &block_hash)?;
+        }
+        Ok(())
+    }
+
     /// Returns number of total slots in current epoch.
     /// Internally always return cfg.max_slot_count
     pub fn total_slots(&self) -> i64 {
@@ -1244,15 +1472,13 @@ impl Blockchain {
         &self,
         view_change: u32,
         beneficiary_pkey: &scc::PublicKey,
-        network_skey: &pbc::SecretKey,
+        random: pbc::VRF,
         network_pkey: pbc::PublicKey,
         timestamp: Timestamp,
     ) -> (MacroBlock, Vec<Transaction>) {
         assert!(self.is_epoch_full());
         let epoch = self.epoch();
         let previous = self.last_macro_block_hash();
-        let seed = mix(self.last_macro_block_random(), view_change);
-        let random = pbc::make_VRF(network_skey, &seed);
         // Sic: difficulty is constant.
         let difficulty = self.difficulty();
 
@@ -1478,6 +1704,7 @@ impl Blockchain {
             &outputs,
             block.header.gamma,
             block.header.block_reward,
+            &HashMap::new(),
         );
 
         //
@@ -1702,6 +1929,7 @@ impl Blockchain {
         outputs: &HashMap<Hash, (Output, OutputKey)>,
         gamma: Fr,
         block_reward: i64,
+        rotations: &HashMap<Hash, pbc::PublicKey>,
     ) {
         let epoch = self.epoch;
 
@@ -1785,6 +2013,7 @@ impl Blockchain {
                         self.epoch,
                         self.cfg.stake_epochs,
                         o.amount,
+                        rotations.get(&output_hash).cloned(),
                     );
                     assert_eq!(self.escrow.current_lsn(), lsn);
                 }
@@ -1854,6 +2083,8 @@ impl Blockchain {
         let mut gamma = Fr::zero();
         let mut block_reward: i64 = 0;
         let mut txs: HashMap<Hash, Transaction> = HashMap::new();
+        // Stakes moved to a new validator key, new output => old key.
+        let mut rotations: HashMap<Hash, pbc::PublicKey> = HashMap::new();
         // Regular transactions.
         for (tx_id, tx) in block.transactions.into_iter().enumerate() {
             assert!(tx_id < std::u32::MAX as usize);
@@ -1888,7 +2119,26 @@ impl Blockchain {
                 Transaction::PaymentTransaction(tx) => {
                     gamma += tx.gamma;
                 }
-                Transaction::RestakeTransaction(_tx) => {}
+                Transaction::RestakeTransaction(_tx) if epoch < self.cfg.key_rotation_epoch => {}
+                Transaction::RestakeTransaction(tx) => {
+                    // Inputs are stakes of one validator, see RestakeTransaction::validate().
+                    let old_validator =
+                        tx.txins
+                            .iter()
+                            .find_map(|input_hash| match inputs.get(input_hash) {
+                                Some(Output::StakeOutput(o)) => Some(o.validator),
+                                _ => None,
+                            });
+                    if let Some(old_validator) = old_validator {
+                        for output in &tx.txouts {
+                            if let Output::StakeOutput(o) = output {
+                                if o.validator != old_validator {
+                                    rotations.insert(Hash::digest(output), old_validator);
+                                }
+                            }
+                        }
+                    }
+                }
                 Transaction::SlashingTransaction(tx) => {
                     info!(
                         "Found slashing transaction, removing validator, from list: cheater={}",
@@ -1946,7 +2196,15 @@ impl Blockchain {
         //
         // Register block.
         //
-        self.register_inputs_and_outputs(lsn, block_hash, &inputs, &outputs, gamma, block_reward);
+        self.register_inputs_and_outputs(
+            lsn,
+            block_hash,
+            &inputs,
+            &outputs,
+            gamma,
+            block_reward,
+            &rotations,
+        );
 
         //
         // Update metadata.
@@ -21,8 +21,11 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::error::BlockError;
+use failure::{format_err, Error};
 use serde_derive::{Deserialize, Serialize};
 use std::time::Duration;
+use stegos_crypto::hash::Hash;
 
 #[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 #[serde(rename_all = "snake_case")]
@@ -57,6 +60,101 @@ pub struct ChainConfig {
     pub service_award_per_epoch: i64,
     /// Maximal delta between block's timestamp and local timestamp.
     pub vetted_timestamp_delta: Duration,
+    /// Trusted macro blocks, sorted by epoch.
+    pub checkpoints: Vec<Checkpoint>,
+    /// The first epoch when locked stakes can be moved to a new validator key.
+    pub key_rotation_epoch: u64,
+}
+
+/// Trusted macro block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Epoch of the macro block.
+    pub epoch: u64,
+    /// Hash of the macro block.
+    pub hash: Hash,
+}
+
+impl ChainConfig {
+    ///
+    /// Returns the hash of trusted macro block for the epoch, if any.
+    ///
+    pub fn checkpoint(&self, epoch: u64) -> Option<&Hash> {
+        self.checkpoints
+            .binary_search_by_key(&epoch, |c| c.epoch)
+            .ok()
+            .map(|i| &self.checkpoints[i].hash)
+    }
+
+    ///
+    /// Returns the epoch of the latest trusted macro block, if any.
+    ///
+    pub fn last_checkpoint_epoch(&self) -> Option<u64> {
+        self.checkpoints.last().map(|c| c.epoch)
+    }
+
+    ///
+    /// Adds checkpoints, replacing existing ones for the same epochs.
+    ///
+    pub fn add_checkpoints(&mut self, checkpoints: &[Checkpoint]) {
+        for checkpoint in checkpoints {
+            match self
+                .checkpoints
+                .binary_search_by_key(&checkpoint.epoch, |c| c.epoch)
+            {
+                Ok(i) => self.checkpoints[i] = checkpoint.clone(),
+                Err(i) => self.checkpoints.insert(i, checkpoint.clone()),
+            }
+        }
+    }
+
+    ///
+    /// Checks that a macro block doesn't contradict checkpoints.
+    ///
+    pub fn validate_checkpoint(&self, epoch: u64, block_hash: &Hash) -> Result<(), BlockError> {
+        match self.checkpoint(epoch) {
+            Some(expected) if expected != block_hash => Err(BlockError::CheckpointMismatch(
+                epoch,
+                *block_hash,
+                *expected,
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+///
+/// Parses a list of checkpoints.
+///
+/// Each line contains an epoch and a hash of macro block, separated by whitespace.
+/// Empty lines and lines starting with '#' are ignored.
+///
+pub fn parse_checkpoints(data: &str) -> Result<Vec<Checkpoint>, Error> {
+    let mut checkpoints = Vec::new();
+    for (lineno, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (epoch, hash) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(epoch), Some(hash), None) => (epoch, hash),
+            _ => return Err(format_err!("Invalid checkpoint at line {}", lineno + 1)),
+        };
+        let epoch: u64 = epoch
+            .parse()
+            .map_err(|e| format_err!("Invalid checkpoint epoch at line {}: {}", lineno + 1, e))?;
+        let hash = Hash::try_from_hex(hash)
+            .map_err(|e| format_err!("Invalid checkpoint hash at line {}: {}", lineno + 1, e))?;
+        checkpoints.push(Checkpoint { epoch, hash });
+    }
+    checkpoints.sort_by_key(|c| c.epoch);
+    for pair in checkpoints.windows(2) {
+        if pair[0].epoch == pair[1].epoch {
+            return Err(format_err!("Duplicate checkpoint: epoch={}", pair[0].epoch));
+        }
+    }
+    Ok(checkpoints)
 }
 
 const STG: i64 = 1_000_000;
@@ -74,6 +172,67 @@ impl Default for ChainConfig {
             service_award_per_epoch: 12 * STG * (micro_blocks_in_epoch as i64 + 1), // 12 STG per block
             // Sic: synchronize this value with NodeConfig::{micro, macro}_block_timeout.
             vetted_timestamp_delta: Duration::from_secs(30),
+            checkpoints: Vec::new(),
+            // Disabled until scheduled for the network.
+            key_rotation_epoch: std::u64::MAX,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checkpoints() {
+        let hash1 = Hash::digest("block1");
+        let hash2 = Hash::digest("block2");
+        let data = format!(
+            "# Trusted checkpoints.\n\n20 {}\n  10   {}\n",
+            hash2.to_hex(),
+            hash1.to_hex()
+        );
+        let checkpoints = parse_checkpoints(&data).expect("valid checkpoints");
+        assert_eq!(
+            checkpoints,
+            vec![
+                Checkpoint {
+                    epoch: 10,
+                    hash: hash1
+                },
+                Checkpoint {
+                    epoch: 20,
+                    hash: hash2
+                }
+            ]
+        );
+        assert!(parse_checkpoints("10").is_err());
+        assert!(parse_checkpoints("x 00").is_err());
+        assert!(parse_checkpoints(&format!("1 {}\n1 {}", hash1.to_hex(), hash2.to_hex())).is_err());
+
+        let mut cfg = ChainConfig {
+            checkpoints,
+            ..Default::default()
+        };
+        assert_eq!(cfg.checkpoint(10), Some(&hash1));
+        assert_eq!(cfg.checkpoint(15), None);
+        assert_eq!(cfg.last_checkpoint_epoch(), Some(20));
+        assert!(cfg.validate_checkpoint(10, &hash1).is_ok());
+        assert!(cfg.validate_checkpoint(10, &hash2).is_err());
+        assert!(cfg.validate_checkpoint(15, &hash2).is_ok());
+
+        // Override and extend.
+        cfg.add_checkpoints(&[
+            Checkpoint {
+                epoch: 10,
+                hash: hash2,
+            },
+            Checkpoint {
+                epoch: 30,
+                hash: hash1,
+            },
+        ]);
+        assert_eq!(cfg.checkpoint(10), Some(&hash2));
+        assert_eq!(cfg.last_checkpoint_epoch(), Some(30));
+    }
+}
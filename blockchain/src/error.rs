@@ -332,6 +332,11 @@ pub enum BlockError {
     DifferentPublicKey(pbc::PublicKey, pbc::PublicKey),
     #[fail(display = "Invalid leader signature found: epoch={}, block={}", _0, _1)]
     InvalidLeaderSignature(u64, Hash),
+    #[fail(
+        display = "Macro block contradicts a checkpoint: epoch={}, block={}, checkpoint={}",
+        _0, _1, _2
+    )]
+    CheckpointMismatch(u64, Hash, Hash),
     #[fail(
         display = "Invalid block BLS multisignature: epoch={}, block={}, error={}",
         _1, _2, _0
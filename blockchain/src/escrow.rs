@@ -45,6 +45,9 @@ pub(crate) struct EscrowValue {
     pub(crate) account_pkey: scc::PublicKey,
     pub(crate) active_until_epoch: u64,
     pub(crate) amount: i64,
+    /// The previous validator key and the epoch of rotation,
+    /// if this stake has been moved by a key rotation.
+    pub(crate) rotated_from: Option<(pbc::PublicKey, u64)>,
 }
 
 use crate::LSN;
@@ -54,6 +57,9 @@ pub(crate) type EscrowMap = MultiVersionedMap<EscrowKey, EscrowValue, LSN>;
 pub struct Escrow {
     /// Stakes.
     pub(crate) escrow: EscrowMap,
+    /// Rotated stakes, by the previous validator key => the new validator key.
+    /// Derived from `escrow` and rebuilt on recovery and rollback.
+    rotations: BTreeMap<EscrowKey, pbc::PublicKey>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -85,7 +91,32 @@ impl Escrow {
     /// Create a new escrow.
     pub(crate) fn new() -> Self {
         let set = EscrowMap::new();
-        Escrow { escrow: set }
+        let rotations = BTreeMap::new();
+        Escrow {
+            escrow: set,
+            rotations,
+        }
+    }
+
+    ///
+    /// Replace stakes by ones recovered from the disk.
+    ///
+    pub(crate) fn recover(&mut self, escrow: EscrowMap) {
+        self.escrow = escrow;
+        self.reindex_rotations();
+    }
+
+    fn reindex_rotations(&mut self) {
+        self.rotations.clear();
+        for (key, value) in self.escrow.iter() {
+            if let Some((old_validator_pkey, _rotation_epoch)) = value.rotated_from {
+                let old_key = EscrowKey {
+                    validator_pkey: old_validator_pkey,
+                    output_hash: key.output_hash,
+                };
+                self.rotations.insert(old_key, key.validator_pkey);
+            }
+        }
     }
 
     ///
@@ -100,6 +131,7 @@ impl Escrow {
         epoch: u64,
         stakes_epoch: u64,
         amount: i64,
+        rotated_from: Option<pbc::PublicKey>,
     ) {
         let active_until_epoch = epoch + stakes_epoch;
         let key = EscrowKey {
@@ -110,6 +142,7 @@ impl Escrow {
             account_pkey,
             active_until_epoch,
             amount,
+            rotated_from: rotated_from.map(|pkey| (pkey, epoch)),
         };
 
         if let Some(v) = self.escrow.insert(lsn, key, value) {
@@ -119,6 +152,17 @@ impl Escrow {
             );
         }
 
+        if let Some(old_validator_pkey) = rotated_from {
+            let old_key = EscrowKey {
+                validator_pkey: old_validator_pkey,
+                output_hash,
+            };
+            self.rotations.insert(old_key, validator_pkey);
+            debug!(
+                "Rotated stake: utxo={}, old_validator={}, new_validator={}",
+                output_hash, old_validator_pkey, validator_pkey
+            );
+        }
         let (active_balance, expired_balance) = self.validator_balance(&validator_pkey, epoch);
         debug!(
             "Staked: utxo={}, validator={}, amount={}, active_until_epoch={}, active_balance={}, expired_balance={}",
@@ -141,6 +185,13 @@ impl Escrow {
             output_hash,
         };
         let val = self.escrow.remove(lsn, &key).expect("stake exists");
+        if let Some((old_validator_pkey, _rotation_epoch)) = val.rotated_from {
+            let old_key = EscrowKey {
+                validator_pkey: old_validator_pkey,
+                output_hash,
+            };
+            self.rotations.remove(&old_key);
+        }
 
         let (active_balance, expired_balance) = self.validator_balance(&validator_pkey, epoch);
         debug!(
@@ -156,16 +207,7 @@ impl Escrow {
         &self,
         validator_pkey: &pbc::PublicKey,
     ) -> impl Iterator<Item = (&Hash, i64, &scc::PublicKey, u64)> {
-        let (hash_min, hash_max) = Hash::bounds();
-        let key_min = EscrowKey {
-            validator_pkey: validator_pkey.clone(),
-            output_hash: hash_min,
-        };
-        let key_max = EscrowKey {
-            validator_pkey: validator_pkey.clone(),
-            output_hash: hash_max,
-        };
-
+        let (key_min, key_max) = validator_bounds(validator_pkey);
         self.escrow.range(&key_min..=&key_max).map(|(key, value)| {
             (
                 &key.output_hash,
@@ -176,6 +218,42 @@ impl Escrow {
         })
     }
 
+    ///
+    /// Iterate over stakes which can be confiscated for a misbehaviour of
+    /// the validator during the specified epoch.
+    ///
+    /// In addition to the own stakes of the validator, this includes stakes
+    /// which were moved from the validator to a new key during the epoch.
+    ///
+    pub fn iter_slashable_stakes<'a>(
+        &'a self,
+        validator_pkey: &'a pbc::PublicKey,
+        epoch: u64,
+    ) -> impl Iterator<Item = (&'a Hash, i64, u64)> + 'a {
+        let own_stakes = self.iter_validator_stakes(validator_pkey).map(
+            |(output_hash, amount, _account_pkey, active_until_epoch)| {
+                (output_hash, amount, active_until_epoch)
+            },
+        );
+        let (key_min, key_max) = validator_bounds(validator_pkey);
+        let rotated_stakes = self.rotations.range(key_min..=key_max).filter_map(
+            move |(old_key, new_validator_pkey)| {
+                let key = EscrowKey {
+                    validator_pkey: *new_validator_pkey,
+                    output_hash: old_key.output_hash,
+                };
+                let value = self.escrow.get(&key).expect("rotated stake exists");
+                match value.rotated_from {
+                    Some((_, rotation_epoch)) if rotation_epoch == epoch => {
+                        Some((&old_key.output_hash, value.amount, value.active_until_epoch))
+                    }
+                    _ => None,
+                }
+            },
+        );
+        own_stakes.chain(rotated_stakes)
+    }
+
     ///
     /// Get staked value for validator.
     ///
@@ -253,16 +331,24 @@ impl Escrow {
     /// # Arguments
     ///
     /// * - `inputs` - UTXOs referred by self.txins, in the same order as in self.txins.
+    /// * - `allow_key_rotation` - allow to move locked stakes to a new validator key.
     ///
     pub fn validate_stakes<'a, OutputIter>(
         &self,
         inputs: OutputIter,
         outputs: OutputIter,
         epoch: u64,
+        allow_key_rotation: bool,
     ) -> Result<(), BlockchainError>
     where
         OutputIter: Iterator<Item = &'a Output>,
     {
+        let inputs: Vec<&Output> = inputs.collect();
+        let outputs: Vec<&Output> = outputs.collect();
+        if allow_key_rotation && self.is_key_rotation(&inputs, &outputs, epoch) {
+            return Ok(());
+        }
+
         let mut staking_balance: HashMap<pbc::PublicKey, i64> = HashMap::new();
         for input in inputs {
             match input {
@@ -313,6 +399,94 @@ impl Escrow {
         Ok(())
     }
 
+    ///
+    /// Check if the transaction moves the whole locked stake of one validator
+    /// to a new network key, which is allowed without waiting for unlock.
+    ///
+    /// Stakes which were rotated during this epoch can't be rotated again,
+    /// so that they stay reachable for slashing of the previous key.
+    ///
+    fn is_key_rotation(&self, inputs: &[&Output], outputs: &[&Output], epoch: u64) -> bool {
+        let mut old_validator: Option<(pbc::PublicKey, scc::PublicKey)> = None;
+        let mut input_amount: i64 = 0;
+        let mut input_hashes: Vec<Hash> = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let o = match input {
+                Output::StakeOutput(o) => o,
+                _ => return false,
+            };
+            match old_validator {
+                Some((validator, _)) if validator != o.validator => return false,
+                _ => old_validator = Some((o.validator, o.recipient)),
+            }
+            input_amount += o.amount;
+            input_hashes.push(Hash::digest(*input));
+        }
+        let (old_validator, account_pkey) = match old_validator {
+            Some(v) => v,
+            None => return false,
+        };
+
+        let mut new_validator: Option<pbc::PublicKey> = None;
+        let mut output_amount: i64 = 0;
+        for output in outputs {
+            let o = match output {
+                Output::StakeOutput(o) => o,
+                _ => return false,
+            };
+            if o.validator == old_validator || o.recipient != account_pkey {
+                return false;
+            }
+            if let Some(other_account_pkey) = self.account_by_network_key(&o.validator) {
+                if other_account_pkey != account_pkey {
+                    return false;
+                }
+            }
+            match new_validator {
+                Some(validator) if validator != o.validator => return false,
+                _ => new_validator = Some(o.validator),
+            }
+            output_amount += o.amount;
+        }
+        if new_validator.is_none() || input_amount != output_amount {
+            return false;
+        }
+
+        // All stakes of the validator must be moved at once.
+        let mut stakes_count = 0;
+        for (output_hash, _amount, _account_pkey, _active_until_epoch) in
+            self.iter_validator_stakes(&old_validator)
+        {
+            if !input_hashes.contains(output_hash) {
+                return false;
+            }
+            stakes_count += 1;
+        }
+        if stakes_count != input_hashes.len() {
+            return false;
+        }
+
+        // Don't allow to chain rotations within one epoch.
+        for output_hash in input_hashes {
+            let key = EscrowKey {
+                validator_pkey: old_validator,
+                output_hash,
+            };
+            match self.escrow.get(&key) {
+                Some(value) => {
+                    if let Some((_, rotation_epoch)) = value.rotated_from {
+                        if rotation_epoch == epoch {
+                            return false;
+                        }
+                    }
+                }
+                None => return false,
+            }
+        }
+
+        true
+    }
+
     /// Returns an object that represent printable part of the state.
     pub fn info(&self, epoch: u64) -> EscrowInfo {
         let mut validators: HashMap<pbc::PublicKey, ValidatorInfo> = HashMap::new();
@@ -355,8 +529,140 @@ impl Escrow {
         self.escrow.checkpoint()
     }
 
-    #[inline]
     pub(crate) fn rollback_to_lsn(&mut self, to_lsn: LSN) {
         self.escrow.rollback_to_lsn(to_lsn);
+        self.reindex_rotations();
+    }
+}
+
+/// Range of escrow keys which belong to the validator.
+fn validator_bounds(validator_pkey: &pbc::PublicKey) -> (EscrowKey, EscrowKey) {
+    let (hash_min, hash_max) = Hash::bounds();
+    let key_min = EscrowKey {
+        validator_pkey: validator_pkey.clone(),
+        output_hash: hash_min,
+    };
+    let key_max = EscrowKey {
+        validator_pkey: validator_pkey.clone(),
+        output_hash: hash_max,
+    };
+    (key_min, key_max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_rotation() {
+        let (_skey, account_pkey) = scc::make_random_keys();
+        let (old_skey, old_pkey) = pbc::make_random_keys();
+        let (new_skey, new_pkey) = pbc::make_random_keys();
+        let epoch = 10;
+        let stake_epochs = 5;
+        let amount = 1_000;
+
+        let mut escrow = Escrow::new();
+        let input1 = Output::new_stake(&account_pkey, &old_skey, &old_pkey, amount).unwrap();
+        let input2 = Output::new_stake(&account_pkey, &old_skey, &old_pkey, amount).unwrap();
+        for (lsn, input) in [&input1, &input2].iter().enumerate() {
+            escrow.stake(
+                LSN(lsn as u64, 0),
+                old_pkey,
+                account_pkey,
+                Hash::digest(*input),
+                epoch,
+                stake_epochs,
+                amount,
+                None,
+            );
+        }
+
+        // Locked stake can be moved to a new key as a whole.
+        let output = Output::new_stake(&account_pkey, &new_skey, &new_pkey, 2 * amount).unwrap();
+        escrow
+            .validate_stakes(
+                [&input1, &input2].iter().cloned(),
+                [&output].iter().cloned(),
+                epoch,
+                true,
+            )
+            .expect("rotation is allowed");
+
+        // Not activated yet.
+        match escrow
+            .validate_stakes(
+                [&input1, &input2].iter().cloned(),
+                [&output].iter().cloned(),
+                epoch,
+                false,
+            )
+            .unwrap_err()
+        {
+            BlockchainError::StakeIsLocked(..) => {}
+            e => panic!("{:?}", e),
+        }
+
+        // Partial rotation.
+        let output = Output::new_stake(&account_pkey, &new_skey, &new_pkey, amount).unwrap();
+        match escrow
+            .validate_stakes(
+                [&input1].iter().cloned(),
+                [&output].iter().cloned(),
+                epoch,
+                true,
+            )
+            .unwrap_err()
+        {
+            BlockchainError::StakeIsLocked(..) => {}
+            e => panic!("{:?}", e),
+        }
+
+        // Different account.
+        let (_skey2, account_pkey2) = scc::make_random_keys();
+        let output = Output::new_stake(&account_pkey2, &new_skey, &new_pkey, 2 * amount).unwrap();
+        assert!(escrow
+            .validate_stakes(
+                [&input1, &input2].iter().cloned(),
+                [&output].iter().cloned(),
+                epoch,
+                true
+            )
+            .is_err());
+
+        // Rotated stakes are slashable for the old key during the epoch.
+        let output = Output::new_stake(&account_pkey, &new_skey, &new_pkey, 2 * amount).unwrap();
+        for input in &[&input1, &input2] {
+            escrow.unstake(LSN(2, 0), old_pkey, Hash::digest(*input), epoch);
+        }
+        escrow.stake(
+            LSN(3, 0),
+            new_pkey,
+            account_pkey,
+            Hash::digest(&output),
+            epoch,
+            stake_epochs,
+            2 * amount,
+            Some(old_pkey),
+        );
+        let slashable: Vec<_> = escrow.iter_slashable_stakes(&old_pkey, epoch).collect();
+        assert_eq!(slashable.len(), 1);
+        assert_eq!(*slashable[0].0, Hash::digest(&output));
+        assert_eq!(
+            escrow.iter_slashable_stakes(&old_pkey, epoch + 1).count(),
+            0
+        );
+
+        // The second rotation within the same epoch is not allowed.
+        let (new_skey2, new_pkey2) = pbc::make_random_keys();
+        let output2 = Output::new_stake(&account_pkey, &new_skey2, &new_pkey2, 2 * amount).unwrap();
+        assert!(escrow
+            .validate_stakes(
+                [&output].iter().cloned(),
+                [&output2].iter().cloned(),
+                epoch,
+                true
+            )
+            .is_err());
     }
 }
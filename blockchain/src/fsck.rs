@@ -0,0 +1,269 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Offline integrity check of a chain database.
+//!
+//! All blocks are replayed into a scratch database with the full validation
+//! (signatures, VRF, VDF, bulletproofs and range hashes). Persisted epoch
+//! information, escrow, balance, awards and indexes are compared with
+//! the replay afterwards.
+//!
+
+use crate::block::{Block, MacroBlock};
+use crate::blockchain::{Blockchain, MACRO_BLOCK_OFFSET};
+use crate::config::{ChainConfig, ConsistencyCheck};
+use crate::error::BlockchainError;
+use crate::timestamp::Timestamp;
+use failure::format_err;
+use log::*;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use stegos_crypto::hash::Hash;
+use stegos_serialization::traits::ProtoConvert;
+
+/// The first inconsistency found in the database.
+#[derive(Debug)]
+pub struct Inconsistency {
+    pub epoch: u64,
+    /// None for macro blocks and persisted state.
+    pub offset: Option<u32>,
+    pub error: BlockchainError,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "epoch={}, offset={}: {}", self.epoch, offset, self.error),
+            None => write!(f, "epoch={}: {}", self.epoch, self.error),
+        }
+    }
+}
+
+/// Result of check_chain().
+#[derive(Debug)]
+pub struct FsckReport {
+    /// The number of successfully checked blocks.
+    pub blocks: u64,
+    /// Epoch of the last persisted macro block.
+    pub snapshot_epoch: Option<u64>,
+    pub inconsistency: Option<Inconsistency>,
+}
+
+///
+/// Check the chain database in `chain_dir`, which is opened read-only.
+///
+/// `work_dir` must be an empty directory for the replayed database.
+///
+pub fn check_chain(
+    cfg: ChainConfig,
+    chain_dir: &Path,
+    work_dir: &Path,
+    genesis: MacroBlock,
+    timestamp: Timestamp,
+) -> Result<FsckReport, BlockchainError> {
+    if work_dir.exists()
+        && fs::read_dir(work_dir)
+            .map_err(failure::Error::from)?
+            .next()
+            .is_some()
+    {
+        return Err(format_err!("Directory is not empty: {:?}", work_dir).into());
+    }
+    let source = Blockchain::open_database_read_only(chain_dir)?;
+    let snapshot_lsn = Blockchain::snapshot_lsn(&source)?;
+    let snapshot_epoch = snapshot_lsn.map(|lsn| lsn.0);
+    info!(
+        "Checking blockchain: chain_dir={:?}, snapshot_epoch={:?}",
+        chain_dir, snapshot_epoch
+    );
+
+    let genesis_hash = Hash::digest(&genesis);
+    // LoadChain skips the double validation in push_*_block().
+    let mut replay = Blockchain::new(
+        cfg,
+        work_dir,
+        ConsistencyCheck::LoadChain,
+        genesis,
+        timestamp,
+    )?;
+
+    let mut report = FsckReport {
+        blocks: 0,
+        snapshot_epoch,
+        inconsistency: None,
+    };
+    for (key, value) in source.iterator(rocksdb::IteratorMode::Start) {
+        let lsn = match Blockchain::lsn_from_key(&key) {
+            Some(lsn) => lsn,
+            None => {
+                report.inconsistency = Some(Inconsistency {
+                    epoch: replay.epoch(),
+                    offset: Some(replay.offset()),
+                    error: format_err!("Invalid block key: {}", hex::encode(&key)).into(),
+                });
+                break;
+            }
+        };
+        let epoch = lsn.0;
+        let offset = if lsn.1 == MACRO_BLOCK_OFFSET {
+            None
+        } else {
+            Some(lsn.1)
+        };
+        let result = Block::from_buffer(&value)
+            .map_err(BlockchainError::from)
+            .and_then(|block| {
+                if report.blocks == 0 {
+                    // Genesis has been already added by Blockchain::new().
+                    let block_hash = Hash::digest(&block);
+                    if block_hash != genesis_hash {
+                        return Err(BlockchainError::IncompatibleGenesis(
+                            genesis_hash,
+                            block_hash,
+                        ));
+                    }
+                } else {
+                    replay.replay_block(lsn, block, timestamp)?;
+                }
+                if offset.is_none() {
+                    replay.check_epoch_info(&source, epoch)?;
+                    if Some(lsn) == snapshot_lsn {
+                        replay.check_snapshot(&source)?;
+                    }
+                }
+                Ok(())
+            });
+        if let Err(error) = result {
+            report.inconsistency = Some(Inconsistency {
+                epoch,
+                offset,
+                error,
+            });
+            break;
+        }
+        report.blocks += 1;
+        if offset.is_none() {
+            info!("Checked macro block: epoch={}", epoch);
+        }
+    }
+
+    if report.inconsistency.is_none() && report.blocks == 0 {
+        report.inconsistency = Some(Inconsistency {
+            epoch: 0,
+            offset: None,
+            error: format_err!("No blocks found").into(),
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    /// Create a chain with two full epochs in `chain_dir`.
+    fn create_chain(cfg: &ChainConfig, chain_dir: &Path) -> (MacroBlock, Timestamp) {
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir,
+            ConsistencyCheck::Full,
+            genesis.clone(),
+            timestamp,
+        )
+        .expect("Failed to create blockchain");
+        for _epoch in 0..2 {
+            for _offset in 0..cfg.micro_blocks_in_epoch {
+                timestamp += Duration::from_millis(1);
+                let (block, _, _) = test::create_fake_micro_block(&chain, &keychains, timestamp);
+                chain.push_micro_block(block, timestamp).unwrap();
+            }
+            timestamp += Duration::from_millis(1);
+            let (block, _) = test::create_fake_macro_block(&chain, &keychains, timestamp);
+            while chain.offset() > 0 {
+                chain.pop_micro_block().unwrap();
+            }
+            chain.push_macro_block(block, timestamp).unwrap();
+        }
+        (genesis, timestamp)
+    }
+
+    #[test]
+    fn clean_chain() {
+        let cfg = ChainConfig {
+            micro_blocks_in_epoch: 2,
+            ..Default::default()
+        };
+        let chain_dir = TempDir::new("chain").unwrap();
+        let work_dir = TempDir::new("fsck").unwrap();
+        let (genesis, timestamp) = create_chain(&cfg, chain_dir.path());
+
+        let report = check_chain(cfg, chain_dir.path(), work_dir.path(), genesis, timestamp)
+            .expect("no I/O errors");
+        assert!(report.inconsistency.is_none(), "{:?}", report.inconsistency);
+        // Genesis and two macro blocks.
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.snapshot_epoch, Some(2));
+    }
+
+    #[test]
+    fn corrupted_chain() {
+        let cfg = ChainConfig {
+            micro_blocks_in_epoch: 2,
+            ..Default::default()
+        };
+        let chain_dir = TempDir::new("chain").unwrap();
+        let work_dir = TempDir::new("fsck").unwrap();
+        let (genesis, timestamp) = create_chain(&cfg, chain_dir.path());
+
+        // Damage the first macro block after genesis.
+        {
+            let opts = rocksdb::Options::default();
+            let cfs = rocksdb::DB::list_cf(&opts, chain_dir.path()).unwrap();
+            let database = rocksdb::DB::open_cf(&opts, chain_dir.path(), &cfs).unwrap();
+            let (key, _value) = database
+                .iterator(rocksdb::IteratorMode::Start)
+                .nth(1)
+                .expect("block exists");
+            database.put(&key, b"corrupted").unwrap();
+        }
+
+        let report = check_chain(cfg, chain_dir.path(), work_dir.path(), genesis, timestamp)
+            .expect("no I/O errors");
+        let inconsistency = report.inconsistency.expect("corruption is found");
+        assert_eq!(inconsistency.epoch, 1);
+        assert_eq!(inconsistency.offset, None);
+        assert_eq!(report.blocks, 1);
+    }
+}
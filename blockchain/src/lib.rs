@@ -31,6 +31,7 @@ mod config;
 pub mod election;
 mod error;
 mod escrow;
+mod fsck;
 mod merkle;
 mod metrics;
 mod multisignature;
@@ -51,6 +52,7 @@ pub use crate::config::*;
 pub use crate::election::{mix, ElectionInfo, ElectionResult};
 pub use crate::error::*;
 pub use crate::escrow::*;
+pub use crate::fsck::{check_chain, FsckReport, Inconsistency};
 pub use crate::merkle::*;
 pub use crate::multisignature::*;
 pub use crate::output::*;
@@ -72,23 +74,27 @@ pub fn chain_to_prefix(network: &str) -> &'static str {
 }
 
 pub fn initialize_chain(chain: &str) -> Result<(MacroBlock, ChainConfig), Error> {
-    let (genesis, chain_cfg): (&[u8], ChainConfig) = match chain {
+    let (genesis, checkpoints, mut chain_cfg): (&[u8], &str, ChainConfig) = match chain {
         "dev" => (
             include_bytes!("../../chains/dev/genesis.bin"),
+            include_str!("../../chains/dev/checkpoints.txt"),
             ChainConfig {
                 awards_difficulty: 3,
                 stake_epochs: 1,
+                key_rotation_epoch: 0,
                 ..Default::default()
             },
         ),
         "testnet" => (
             include_bytes!("../../chains/testnet/genesis.bin"),
+            include_str!("../../chains/testnet/checkpoints.txt"),
             ChainConfig {
                 ..Default::default()
             },
         ),
         "mainnet" => (
             include_bytes!("../../chains/mainnet/genesis.bin"),
+            include_str!("../../chains/mainnet/checkpoints.txt"),
             ChainConfig {
                 ..Default::default()
             },
@@ -99,5 +105,6 @@ pub fn initialize_chain(chain: &str) -> Result<(MacroBlock, ChainConfig), Error>
     };
     let genesis = Block::from_buffer(genesis).expect("Invalid genesis");
     let genesis = genesis.unwrap_macro();
+    chain_cfg.checkpoints = parse_checkpoints(checkpoints).expect("Invalid checkpoints");
     Ok((genesis, chain_cfg))
 }
@@ -150,6 +150,10 @@ impl ProtoConvert for EscrowValue {
         msg.set_account_pkey(self.account_pkey.into_proto());
         msg.set_active_until_epoch(self.active_until_epoch);
         msg.set_amount(self.amount);
+        if let Some((rotated_from, rotation_epoch)) = &self.rotated_from {
+            msg.set_rotated_from(rotated_from.into_proto());
+            msg.set_rotation_epoch(*rotation_epoch);
+        }
         msg
     }
 
@@ -157,11 +161,18 @@ impl ProtoConvert for EscrowValue {
         let account_pkey = ProtoConvert::from_proto(proto.get_account_pkey())?;
         let active_until_epoch = proto.get_active_until_epoch();
         let amount = proto.get_amount();
+        let rotated_from = if proto.has_rotated_from() {
+            let rotated_from = ProtoConvert::from_proto(proto.get_rotated_from())?;
+            Some((rotated_from, proto.get_rotation_epoch()))
+        } else {
+            None
+        };
 
         Ok(Self {
             account_pkey,
             active_until_epoch,
             amount,
+            rotated_from,
         })
     }
 }
@@ -1323,6 +1334,15 @@ mod tests {
             account_pkey: scc::PublicKey::from(scc::Pt::random()),
             active_until_epoch: 324,
             amount: 55,
+            rotated_from: None,
+        };
+        roundtrip_eq(&value);
+
+        let value = EscrowValue {
+            account_pkey: scc::PublicKey::from(scc::Pt::random()),
+            active_until_epoch: 324,
+            amount: 55,
+            rotated_from: Some((pbc::PublicKey::dum(), 323)),
         };
         roundtrip_eq(&value);
     }
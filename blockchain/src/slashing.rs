@@ -104,9 +104,9 @@ pub fn confiscate_tx(
     assert_eq!(proof.block1.header.pkey, proof.block2.header.pkey);
     let ref cheater = proof.block1.header.pkey;
     let epoch = chain.epoch();
-    let (inputs, stake) = chain.iter_validator_stakes(cheater).fold(
+    let (inputs, stake) = chain.iter_slashable_stakes(cheater).fold(
         (Vec::<Hash>::new(), 0i64),
-        |(mut result, mut stake), (hash, amount, _, active_until_epoch)| {
+        |(mut result, mut stake), (hash, amount, active_until_epoch)| {
             if active_until_epoch >= epoch {
                 stake += amount;
                 result.push(hash.clone());
@@ -289,10 +289,12 @@ pub fn create_fake_macro_block(
     let view_change = chain.view_change();
     let key = chain.select_leader(view_change);
     let keys = keychains.iter().find(|p| p.network_pkey == key).unwrap();
+    let seed = mix(chain.last_macro_block_random(), view_change);
+    let random = pbc::make_VRF(&keys.network_skey, &seed);
     let (mut block, extra_transactions) = chain.create_macro_block(
         view_change,
         &keys.account_pkey,
-        &keys.network_skey,
+        random,
         keys.network_pkey,
         timestamp,
     );
@@ -454,6 +456,61 @@ pub fn create_fake_micro_block(
     (block, input_hashes, output_hashes)
 }
 
+///
+/// Creates a payment transaction which sends all payments of the account to itself.
+///
+pub fn create_payment_transaction(
+    chain: &Blockchain,
+    keychain: &KeyChain,
+    fee: i64,
+) -> PaymentTransaction {
+    let accounts_recovery = recover_account(
+        chain,
+        &keychain.account_skey,
+        &keychain.account_pkey,
+        0,
+        HashMap::new(),
+    )
+    .unwrap();
+    let unspent = accounts_recovery
+        .commited
+        .into_iter()
+        .chain(accounts_recovery.prepared);
+    let mut inputs: Vec<Output> = Vec::new();
+    let mut balance: i64 = 0;
+    for (_, OutputRecovery { output, .. }) in unspent {
+        match output {
+            Output::PaymentOutput(ref o) => {
+                let payload = o
+                    .decrypt_payload(&keychain.account_pkey, &keychain.account_skey)
+                    .unwrap();
+                balance += payload.amount;
+                inputs.push(output);
+            }
+            Output::PublicPaymentOutput(ref o) => {
+                balance += o.amount;
+                inputs.push(output);
+            }
+            Output::StakeOutput(_) => {}
+        }
+    }
+    assert!(balance > fee, "Not enough money");
+
+    let (output, outputs_gamma) =
+        PaymentOutput::new(&keychain.account_pkey, balance - fee).expect("keys are valid");
+    let outputs: Vec<Output> = vec![output.into()];
+    let tx = PaymentTransaction::new(
+        &keychain.account_skey,
+        &inputs,
+        &outputs,
+        &outputs_gamma,
+        fee,
+    )
+    .expect("Invalid keys");
+    tx.validate(&inputs).expect("Invalid transaction");
+    tx
+}
+
 pub fn create_micro_block_with_coinbase(
     chain: &Blockchain,
     keychains: &[KeyChain],
@@ -89,6 +89,11 @@ pub enum ConsensusError {
     InvalidValidatorId(u32),
     #[fail(display = "Failed to check view change message signature.")]
     InvalidViewChangeSignature,
+    #[fail(
+        display = "Can't restore consensus state: state={}, saved_epoch={}, our_epoch={}",
+        _0, _1, _2
+    )]
+    InvalidSnapshot(&'static str, u64, u64),
 }
 
 impl ConsensusError {
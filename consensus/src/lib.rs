@@ -28,9 +28,13 @@ mod message;
 pub mod metrics;
 pub mod optimistic;
 pub mod protos;
+mod recorder;
+mod signer;
 mod state;
 
 pub use crate::error::*;
 pub use crate::message::*;
 pub use crate::optimistic::ViewChangeCollector;
+pub use crate::recorder::*;
+pub use crate::signer::*;
 pub use crate::state::*;
@@ -22,6 +22,8 @@
 // SOFTWARE.
 
 use crate::error::*;
+use crate::signer::Signer;
+use failure::Error;
 use stegos_blockchain::{MacroBlockHeader, Transaction};
 use stegos_crypto::hash::{Hash, Hashable, Hasher};
 use stegos_crypto::pbc;
@@ -111,12 +113,7 @@ impl ConsensusMessage {
         pkey: &pbc::PublicKey,
         body: ConsensusMessageBody,
     ) -> ConsensusMessage {
-        let mut hasher = Hasher::new();
-        epoch.hash(&mut hasher);
-        round.hash(&mut hasher);
-        block_hash.hash(&mut hasher);
-        body.hash(&mut hasher);
-        let hash = hasher.result();
+        let hash = Self::signing_hash(epoch, round, &block_hash, &body);
         let sig = pbc::sign_hash(&hash, skey);
         ConsensusMessage {
             epoch,
@@ -128,16 +125,49 @@ impl ConsensusMessage {
         }
     }
 
+    ///
+    /// Create and sign a new consensus message using a signer.
+    ///
+    pub fn new_signed(
+        epoch: u64,
+        round: u32,
+        block_hash: Hash,
+        signer: &dyn Signer,
+        body: ConsensusMessageBody,
+    ) -> Result<ConsensusMessage, Error> {
+        let sig = signer.sign_consensus_message(epoch, round, &block_hash, &body)?;
+        Ok(ConsensusMessage {
+            epoch,
+            round,
+            block_hash,
+            body,
+            pkey: signer.pkey(),
+            sig,
+        })
+    }
+
+    ///
+    /// Returns the hash covered by the signature of the message.
+    ///
+    pub fn signing_hash(
+        epoch: u64,
+        round: u32,
+        block_hash: &Hash,
+        body: &ConsensusMessageBody,
+    ) -> Hash {
+        let mut hasher = Hasher::new();
+        epoch.hash(&mut hasher);
+        round.hash(&mut hasher);
+        block_hash.hash(&mut hasher);
+        body.hash(&mut hasher);
+        hasher.result()
+    }
+
     ///
     /// Validate signature of the message.
     ///
     pub fn validate(&self) -> Result<(), ConsensusError> {
-        let mut hasher = Hasher::new();
-        self.epoch.hash(&mut hasher);
-        self.round.hash(&mut hasher);
-        self.block_hash.hash(&mut hasher);
-        self.body.hash(&mut hasher);
-        let hash = hasher.result();
+        let hash = Self::signing_hash(self.epoch, self.round, &self.block_hash, &self.body);
         if let Err(_e) = pbc::check_hash(&hash, &self.sig, &self.pkey) {
             return Err(ConsensusError::InvalidMessageSignature);
         }
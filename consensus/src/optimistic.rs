@@ -24,8 +24,11 @@
 //!
 
 use crate::error::ConsensusError;
+use crate::signer::Signer;
+use failure::Error;
 use log::*;
 use std::collections::HashMap;
+use std::sync::Arc;
 use stegos_blockchain::view_changes::*;
 use stegos_blockchain::{check_supermajority, Blockchain, ChainInfo, ValidatorId};
 use stegos_crypto::hash::{Hash, Hashable, Hasher};
@@ -68,6 +71,19 @@ impl ViewChangeMessage {
             signature,
         }
     }
+
+    pub fn new_signed(
+        chain: ChainInfo,
+        validator_id: ValidatorId,
+        signer: &dyn Signer,
+    ) -> Result<Self, Error> {
+        let signature = signer.sign_view_change(&chain)?;
+        Ok(ViewChangeMessage {
+            chain,
+            validator_id,
+            signature,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -79,15 +95,12 @@ pub struct ViewChangeCollector {
     /// validator_id of current node.
     validator_id: ValidatorId,
     pkey: pbc::PublicKey,
-    skey: pbc::SecretKey,
+    signer: Arc<dyn Signer>,
 }
 
 impl ViewChangeCollector {
-    pub fn new(
-        blockchain: &Blockchain,
-        pkey: pbc::PublicKey,
-        skey: pbc::SecretKey,
-    ) -> ViewChangeCollector {
+    pub fn new(blockchain: &Blockchain, signer: Arc<dyn Signer>) -> ViewChangeCollector {
+        let pkey = signer.pkey();
         // get validator id, by public_key
         let validator_id = blockchain
             .validators()
@@ -98,7 +111,7 @@ impl ViewChangeCollector {
             .expect("Node is not validator");
         ViewChangeCollector {
             pkey,
-            skey,
+            signer,
             collected_slots: 0,
             validator_id,
             actual_view_changes: Default::default(),
@@ -195,8 +208,8 @@ impl ViewChangeCollector {
     }
 
     /// Handle block timeout, starting mooving to the next view change.
-    pub fn handle_timeout(&self, chain_info: ChainInfo) -> ViewChangeMessage {
+    pub fn handle_timeout(&self, chain_info: ChainInfo) -> Result<ViewChangeMessage, Error> {
         // on timeout, create view change message.
-        ViewChangeMessage::new(chain_info, self.validator_id, &self.skey)
+        ViewChangeMessage::new_signed(chain_info, self.validator_id, &*self.signer)
     }
 }
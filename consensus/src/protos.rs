@@ -26,6 +26,9 @@ use stegos_serialization::traits::*;
 
 use crate::message::*;
 use crate::optimistic::*;
+use crate::recorder::{Direction, MessageRecord, RecordedMessage};
+use crate::state::{ConsensusSnapshot, LockedRound};
+use std::collections::BTreeMap;
 use stegos_blockchain::view_changes::ViewChangeProof;
 use stegos_blockchain::*;
 use stegos_crypto::hash::Hash;
@@ -36,18 +39,37 @@ use stegos_blockchain::protos::*;
 use stegos_crypto::protos::*;
 include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 
+impl ProtoConvert for MacroBlockProposal {
+    type Proto = consensus::MacroBlockProposal;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = consensus::MacroBlockProposal::new();
+        proto.set_header(self.header.into_proto());
+        for transaction in &self.transactions {
+            proto.transactions.push(transaction.into_proto());
+        }
+        proto
+    }
+
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let header = MacroBlockHeader::from_proto(proto.get_header())?;
+        let mut transactions = Vec::<Transaction>::with_capacity(proto.transactions.len());
+        for transaction in proto.transactions.iter() {
+            transactions.push(Transaction::from_proto(transaction)?);
+        }
+        Ok(MacroBlockProposal {
+            header,
+            transactions,
+        })
+    }
+}
+
 impl ProtoConvert for ConsensusMessageBody {
     type Proto = consensus::ConsensusMessageBody;
     fn into_proto(&self) -> Self::Proto {
         let mut proto = consensus::ConsensusMessageBody::new();
         match self {
             ConsensusMessageBody::Proposal(block_proposal) => {
-                let mut proposal = consensus::MacroBlockProposal::new();
-                proposal.set_header(block_proposal.header.into_proto());
-                for transaction in &block_proposal.transactions {
-                    proposal.transactions.push(transaction.into_proto());
-                }
-                proto.set_macro_block_proposal(proposal);
+                proto.set_macro_block_proposal(block_proposal.into_proto());
             }
             ConsensusMessageBody::Prevote => {
                 proto.set_prevote(consensus::Prevote::new());
@@ -64,15 +86,7 @@ impl ProtoConvert for ConsensusMessageBody {
     fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
         let msg = match proto.body {
             Some(consensus::ConsensusMessageBody_oneof_body::macro_block_proposal(ref msg)) => {
-                let header = MacroBlockHeader::from_proto(msg.get_header())?;
-                let mut transactions = Vec::<Transaction>::with_capacity(msg.transactions.len());
-                for transaction in msg.transactions.iter() {
-                    transactions.push(Transaction::from_proto(transaction)?);
-                }
-                let block_proposal = MacroBlockProposal {
-                    header,
-                    transactions,
-                };
+                let block_proposal = MacroBlockProposal::from_proto(msg)?;
                 ConsensusMessageBody::Proposal(block_proposal)
             }
             Some(consensus::ConsensusMessageBody_oneof_body::prevote(ref _msg)) => {
@@ -178,6 +192,129 @@ impl ProtoConvert for AddressedViewChangeProof {
     }
 }
 
+impl ProtoConvert for LockedRound {
+    type Proto = consensus::LockedRound;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = consensus::LockedRound::new();
+        proto.set_block(self.block.into_proto());
+        proto.set_block_proposal(self.block_proposal.into_proto());
+        for (pkey, sig) in &self.precommits {
+            let mut precommit = consensus::LockedPrecommit::new();
+            precommit.set_pkey(pkey.into_proto());
+            precommit.set_sig(sig.into_proto());
+            proto.precommits.push(precommit);
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let block = MacroBlock::from_proto(proto.get_block())?;
+        let block_proposal = MacroBlockProposal::from_proto(proto.get_block_proposal())?;
+        let mut precommits = BTreeMap::new();
+        for precommit in proto.precommits.iter() {
+            let pkey = pbc::PublicKey::from_proto(precommit.get_pkey())?;
+            let sig = pbc::Signature::from_proto(precommit.get_sig())?;
+            precommits.insert(pkey, sig);
+        }
+        Ok(LockedRound {
+            block,
+            block_proposal,
+            precommits,
+        })
+    }
+}
+
+impl ProtoConvert for ConsensusSnapshot {
+    type Proto = consensus::ConsensusSnapshot;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = consensus::ConsensusSnapshot::new();
+        proto.set_epoch(self.epoch);
+        proto.set_round(self.round);
+        if let Some(locked_round) = &self.locked_round {
+            proto.set_locked_round(locked_round.into_proto());
+        }
+        for msg in &self.messages {
+            proto.messages.push(msg.into_proto());
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let epoch = proto.get_epoch();
+        let round = proto.get_round();
+        let locked_round = if proto.has_locked_round() {
+            Some(LockedRound::from_proto(proto.get_locked_round())?)
+        } else {
+            None
+        };
+        let mut messages = Vec::with_capacity(proto.messages.len());
+        for msg in proto.messages.iter() {
+            messages.push(ConsensusMessage::from_proto(msg)?);
+        }
+        Ok(ConsensusSnapshot {
+            epoch,
+            round,
+            locked_round,
+            messages,
+        })
+    }
+}
+
+impl ProtoConvert for MessageRecord {
+    type Proto = consensus::MessageRecord;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = consensus::MessageRecord::new();
+        proto.set_timestamp(self.timestamp.into());
+        proto.set_outgoing(self.direction == Direction::Outgoing);
+        match &self.message {
+            RecordedMessage::Started(pkey, chain) => {
+                let mut started = consensus::StartedRecord::new();
+                started.set_pkey(pkey.into_proto());
+                started.set_chain(chain.into_proto());
+                proto.set_started(started)
+            }
+            RecordedMessage::Consensus(msg) => proto.set_consensus_message(msg.into_proto()),
+            RecordedMessage::ViewChange(msg) => proto.set_view_change_message(msg.into_proto()),
+            RecordedMessage::ViewChangeProof(proof) => {
+                proto.set_view_change_proof(proof.into_proto())
+            }
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let timestamp = proto.get_timestamp().into();
+        let direction = if proto.get_outgoing() {
+            Direction::Outgoing
+        } else {
+            Direction::Incoming
+        };
+        let message = match proto.message {
+            Some(consensus::MessageRecord_oneof_message::started(ref started)) => {
+                let pkey = pbc::PublicKey::from_proto(started.get_pkey())?;
+                let chain = ChainInfo::from_proto(started.get_chain())?;
+                RecordedMessage::Started(pkey, chain)
+            }
+            Some(consensus::MessageRecord_oneof_message::consensus_message(ref msg)) => {
+                RecordedMessage::Consensus(ConsensusMessage::from_proto(msg)?)
+            }
+            Some(consensus::MessageRecord_oneof_message::view_change_message(ref msg)) => {
+                RecordedMessage::ViewChange(ViewChangeMessage::from_proto(msg)?)
+            }
+            Some(consensus::MessageRecord_oneof_message::view_change_proof(ref proof)) => {
+                RecordedMessage::ViewChangeProof(SealedViewChangeProof::from_proto(proof)?)
+            }
+            None => {
+                return Err(
+                    ProtoError::MissingField("message".to_string(), "message".to_string()).into(),
+                );
+            }
+        };
+        Ok(MessageRecord {
+            timestamp,
+            direction,
+            message,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,6 +372,31 @@ mod tests {
         roundtrip(&msg);
     }
 
+    #[test]
+    fn consensus_snapshot() {
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let msg = ConsensusMessage::new(
+            1,
+            2,
+            Hash::digest(&1u64),
+            &network_skey,
+            &network_pkey,
+            ConsensusMessageBody::Prevote {},
+        );
+        let snapshot = ConsensusSnapshot {
+            epoch: 1,
+            round: 2,
+            locked_round: None,
+            messages: vec![msg.clone()],
+        };
+        let snapshot2 = ConsensusSnapshot::from_buffer(&snapshot.into_buffer().unwrap()).unwrap();
+        assert_eq!(snapshot2.epoch, 1);
+        assert_eq!(snapshot2.round, 2);
+        assert!(snapshot2.locked_round.is_none());
+        assert_eq!(snapshot2.messages.len(), 1);
+        assert_eq!(Hash::digest(&snapshot2.messages[0]), Hash::digest(&msg));
+    }
+
     #[test]
     fn macro_blocks() {
         let (skey, pkey) = scc::make_random_keys();
@@ -0,0 +1,331 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Binary log of consensus messages.
+//!
+//! Every record is a protobuf-encoded MessageRecord prefixed by its length
+//! as a big-endian u32. The log is append-only: each start of the node
+//! writes a `Started` record with the network key of the recording node
+//! and the state of its chain. When the log grows over the size limit,
+//! it is moved to `<path>.1` and a new log is started with a `Started` record.
+//!
+
+use crate::message::ConsensusMessage;
+use crate::optimistic::{SealedViewChangeProof, ViewChangeMessage};
+use failure::{bail, format_err, Error};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use stegos_blockchain::{ChainInfo, Timestamp};
+use stegos_crypto::pbc;
+use stegos_serialization::traits::ProtoConvert;
+
+/// Records larger than this are treated as corruption.
+const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Clone, Debug)]
+pub enum RecordedMessage {
+    /// The node has started recording or switched to this network key,
+    /// with the state of its chain at this moment.
+    Started(pbc::PublicKey, ChainInfo),
+    Consensus(ConsensusMessage),
+    ViewChange(ViewChangeMessage),
+    ViewChangeProof(SealedViewChangeProof),
+}
+
+impl RecordedMessage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordedMessage::Started(..) => "Started",
+            RecordedMessage::Consensus(msg) => msg.name(),
+            RecordedMessage::ViewChange(_) => "ViewChange",
+            RecordedMessage::ViewChangeProof(_) => "ViewChangeProof",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MessageRecord {
+    pub timestamp: Timestamp,
+    pub direction: Direction,
+    pub message: RecordedMessage,
+}
+
+///
+/// Appends messages to the log.
+///
+#[derive(Debug)]
+pub struct MessageRecorder {
+    path: PathBuf,
+    file: File,
+    /// The current size of the log.
+    size: u64,
+    /// The size to rotate the log at, zero means unlimited.
+    max_size: u64,
+}
+
+impl MessageRecorder {
+    ///
+    /// Open the log for appending and write the `Started` record.
+    ///
+    pub fn open(
+        path: &Path,
+        max_size: u64,
+        pkey: pbc::PublicKey,
+        chain: ChainInfo,
+    ) -> Result<Self, Error> {
+        let file = open_log(path)?;
+        let size = file
+            .metadata()
+            .map_err(|e| format_err!("Failed to open {:?}: {}", path, e))?
+            .len();
+        let mut recorder = MessageRecorder {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+        };
+        recorder.record(Direction::Outgoing, RecordedMessage::Started(pkey, chain))?;
+        Ok(recorder)
+    }
+
+    ///
+    /// Returns true if the log has reached the size limit and should be rotated.
+    ///
+    pub fn is_full(&self) -> bool {
+        self.max_size > 0 && self.size >= self.max_size
+    }
+
+    ///
+    /// Move the log to `<path>.1`, replacing the previous one, and start a new log
+    /// with the `Started` record, so every file can be replayed on its own.
+    ///
+    pub fn rotate(&mut self, pkey: pbc::PublicKey, chain: ChainInfo) -> Result<(), Error> {
+        let rotated = rotated_path(&self.path);
+        fs::rename(&self.path, &rotated)
+            .map_err(|e| format_err!("Failed to rename {:?}: {}", self.path, e))?;
+        self.file = open_log(&self.path)?;
+        self.size = 0;
+        self.record(Direction::Outgoing, RecordedMessage::Started(pkey, chain))
+    }
+
+    pub fn record(&mut self, direction: Direction, message: RecordedMessage) -> Result<(), Error> {
+        let record = MessageRecord {
+            timestamp: Timestamp::now(),
+            direction,
+            message,
+        };
+        let data = record.into_buffer()?;
+        // A single write keeps records whole if the node crashes.
+        let mut buf = Vec::with_capacity(4 + data.len());
+        buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&data);
+        self.file
+            .write_all(&buf)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", self.path, e))?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn open_log(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format_err!("Failed to open {:?}: {}", path, e))
+}
+
+/// The previous part of a rotated log.
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+///
+/// Reads records from the log.
+///
+pub struct MessageLog {
+    file: BufReader<File>,
+    failed: bool,
+}
+
+impl MessageLog {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|e| format_err!("Failed to open {:?}: {}", path, e))?;
+        Ok(MessageLog {
+            file: BufReader::new(file),
+            failed: false,
+        })
+    }
+
+    fn read_record(&mut self) -> Result<Option<MessageRecord>, Error> {
+        let mut len = [0u8; 4];
+        match self.file.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_RECORD_SIZE {
+            bail!("Invalid record size: {}", len);
+        }
+        let mut data = vec![0u8; len];
+        self.file
+            .read_exact(&mut data)
+            .map_err(|e| format_err!("Truncated record: {}", e))?;
+        Ok(Some(MessageRecord::from_buffer(&data)?))
+    }
+}
+
+impl Iterator for MessageLog {
+    type Item = Result<MessageRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.read_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                // The rest of the file can't be framed.
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ConsensusMessageBody;
+    use std::fs;
+    use stegos_crypto::hash::Hash;
+    use tempdir::TempDir;
+
+    fn chain_info(offset: u32) -> ChainInfo {
+        ChainInfo {
+            epoch: 1,
+            offset,
+            view_change: 0,
+            last_block: Hash::digest(&offset),
+        }
+    }
+
+    #[test]
+    fn record_and_read() {
+        let dir = TempDir::new("consensus").unwrap();
+        let path = dir.path().join("consensus.log");
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let msg = ConsensusMessage::new(
+            1,
+            2,
+            Hash::digest(&1u64),
+            &network_skey,
+            &network_pkey,
+            ConsensusMessageBody::Prevote {},
+        );
+        {
+            let mut recorder =
+                MessageRecorder::open(&path, 0, network_pkey, chain_info(0)).unwrap();
+            recorder
+                .record(Direction::Incoming, RecordedMessage::Consensus(msg.clone()))
+                .unwrap();
+        }
+        // Reopening appends a new session.
+        MessageRecorder::open(&path, 0, network_pkey, chain_info(1)).unwrap();
+
+        let records: Vec<MessageRecord> = MessageLog::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        match &records[0].message {
+            RecordedMessage::Started(pkey, chain) => {
+                assert_eq!(pkey, &network_pkey);
+                assert_eq!(chain, &chain_info(0));
+            }
+            _ => panic!("expected Started"),
+        }
+        assert_eq!(records[1].direction, Direction::Incoming);
+        match &records[1].message {
+            RecordedMessage::Consensus(msg2) => assert_eq!(Hash::digest(msg2), Hash::digest(&msg)),
+            _ => panic!("expected ConsensusMessage"),
+        }
+
+        // A truncated tail is reported once.
+        let len = fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 1).unwrap();
+        let mut log = MessageLog::open(&path).unwrap();
+        assert!(log.next().unwrap().is_ok());
+        assert!(log.next().unwrap().is_ok());
+        assert!(log.next().unwrap().is_err());
+        assert!(log.next().is_none());
+    }
+
+    #[test]
+    fn rotate() {
+        let dir = TempDir::new("consensus").unwrap();
+        let path = dir.path().join("consensus.log");
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let msg = ConsensusMessage::new(
+            1,
+            0,
+            Hash::digest(&1u64),
+            &network_skey,
+            &network_pkey,
+            ConsensusMessageBody::Prevote {},
+        );
+
+        let mut recorder = MessageRecorder::open(&path, 1, network_pkey, chain_info(0)).unwrap();
+        assert!(recorder.is_full());
+        recorder.rotate(network_pkey, chain_info(1)).unwrap();
+        recorder
+            .record(Direction::Incoming, RecordedMessage::Consensus(msg))
+            .unwrap();
+        assert!(recorder.is_full());
+        recorder.rotate(network_pkey, chain_info(2)).unwrap();
+
+        // Every part starts with its own state.
+        let started = |path: &Path| -> (ChainInfo, usize) {
+            let records: Vec<MessageRecord> = MessageLog::open(path)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            match &records[0].message {
+                RecordedMessage::Started(_pkey, chain) => (*chain, records.len()),
+                _ => panic!("expected Started"),
+            }
+        };
+        assert_eq!(started(&rotated_path(&path)), (chain_info(1), 2));
+        assert_eq!(started(&path), (chain_info(2), 1));
+    }
+}
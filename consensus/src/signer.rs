@@ -0,0 +1,198 @@
+//! Network key signer.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::message::{ConsensusMessage, ConsensusMessageBody};
+use failure::{bail, Error};
+use std::fmt::Debug;
+use stegos_blockchain::{
+    ChainInfo, MacroBlockHeader, MicroBlockHeader, Output, RestakeTransaction,
+};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+
+///
+/// Produces all signatures made by the network key of a validator.
+///
+/// Every method describes what is signed, so implementations can
+/// refuse to sign conflicting messages.
+///
+pub trait Signer: Debug + Send + Sync {
+    /// Returns the network public key.
+    fn pkey(&self) -> pbc::PublicKey;
+
+    /// Signs a micro block header.
+    fn sign_micro_block(&self, header: &MicroBlockHeader) -> Result<pbc::Signature, Error>;
+
+    /// Signs a macro block, used for the pre-commit multisignature.
+    fn sign_macro_block(
+        &self,
+        round: u32,
+        header: &MacroBlockHeader,
+    ) -> Result<pbc::Signature, Error>;
+
+    /// Generates a VRF for the seed of a micro block.
+    fn make_micro_block_vrf(
+        &self,
+        epoch: u64,
+        offset: u32,
+        view_change: u32,
+        seed: &Hash,
+    ) -> Result<pbc::VRF, Error>;
+
+    /// Generates a VRF for the seed of a macro block.
+    fn make_macro_block_vrf(&self, epoch: u64, round: u32, seed: &Hash) -> Result<pbc::VRF, Error>;
+
+    /// Signs a view change message.
+    fn sign_view_change(&self, chain: &ChainInfo) -> Result<pbc::Signature, Error>;
+
+    /// Signs a consensus message.
+    fn sign_consensus_message(
+        &self,
+        epoch: u64,
+        round: u32,
+        block_hash: &Hash,
+        body: &ConsensusMessageBody,
+    ) -> Result<pbc::Signature, Error>;
+
+    /// Moves stakes of this validator into a new one, see create_restake_transaction().
+    fn restake(&self, inputs: &[Output]) -> Result<RestakeTransaction, Error>;
+
+    /// Moves stakes of this validator to a new network key.
+    fn rotate_stake(
+        &self,
+        inputs: &[Output],
+        new_skey: &pbc::SecretKey,
+        new_pkey: &pbc::PublicKey,
+    ) -> Result<RestakeTransaction, Error>;
+}
+
+///
+/// Creates a transaction which moves all `inputs` into a single stake
+/// of the same account, bound to `validator_pkey`.
+///
+pub fn create_restake_transaction(
+    skey: &pbc::SecretKey,
+    pkey: &pbc::PublicKey,
+    inputs: &[Output],
+    validator_skey: &pbc::SecretKey,
+    validator_pkey: &pbc::PublicKey,
+) -> Result<RestakeTransaction, Error> {
+    let mut account_pkey = None;
+    let mut amount: i64 = 0;
+    for input in inputs {
+        match input {
+            Output::StakeOutput(o) => {
+                account_pkey.get_or_insert(o.recipient);
+                amount += o.amount;
+            }
+            _ => bail!("Only stakes can be restaked: utxo={}", Hash::digest(input)),
+        }
+    }
+    let account_pkey = match account_pkey {
+        Some(account_pkey) => account_pkey,
+        None => bail!("Nothing to restake"),
+    };
+    let output = Output::new_stake(&account_pkey, validator_skey, validator_pkey, amount)?;
+    RestakeTransaction::new(skey, pkey, inputs, &[output])
+}
+
+/// Signer which keeps the network secret key in process memory.
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    skey: pbc::SecretKey,
+    pkey: pbc::PublicKey,
+}
+
+impl LocalSigner {
+    pub fn new(skey: pbc::SecretKey, pkey: pbc::PublicKey) -> Self {
+        LocalSigner { skey, pkey }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn pkey(&self) -> pbc::PublicKey {
+        self.pkey
+    }
+
+    fn sign_micro_block(&self, header: &MicroBlockHeader) -> Result<pbc::Signature, Error> {
+        let block_hash = Hash::digest(header);
+        Ok(pbc::sign_hash(&block_hash, &self.skey))
+    }
+
+    fn sign_macro_block(
+        &self,
+        _round: u32,
+        header: &MacroBlockHeader,
+    ) -> Result<pbc::Signature, Error> {
+        let block_hash = Hash::digest(header);
+        Ok(pbc::sign_hash(&block_hash, &self.skey))
+    }
+
+    fn make_micro_block_vrf(
+        &self,
+        _epoch: u64,
+        _offset: u32,
+        _view_change: u32,
+        seed: &Hash,
+    ) -> Result<pbc::VRF, Error> {
+        Ok(pbc::make_VRF(&self.skey, seed))
+    }
+
+    fn make_macro_block_vrf(
+        &self,
+        _epoch: u64,
+        _round: u32,
+        seed: &Hash,
+    ) -> Result<pbc::VRF, Error> {
+        Ok(pbc::make_VRF(&self.skey, seed))
+    }
+
+    fn sign_view_change(&self, chain: &ChainInfo) -> Result<pbc::Signature, Error> {
+        let hash = Hash::digest(chain);
+        Ok(pbc::sign_hash(&hash, &self.skey))
+    }
+
+    fn sign_consensus_message(
+        &self,
+        epoch: u64,
+        round: u32,
+        block_hash: &Hash,
+        body: &ConsensusMessageBody,
+    ) -> Result<pbc::Signature, Error> {
+        let hash = ConsensusMessage::signing_hash(epoch, round, block_hash, body);
+        Ok(pbc::sign_hash(&hash, &self.skey))
+    }
+
+    fn restake(&self, inputs: &[Output]) -> Result<RestakeTransaction, Error> {
+        create_restake_transaction(&self.skey, &self.pkey, inputs, &self.skey, &self.pkey)
+    }
+
+    fn rotate_stake(
+        &self,
+        inputs: &[Output],
+        new_skey: &pbc::SecretKey,
+        new_pkey: &pbc::PublicKey,
+    ) -> Result<RestakeTransaction, Error> {
+        create_restake_transaction(&self.skey, &self.pkey, inputs, new_skey, new_pkey)
+    }
+}
@@ -24,20 +24,32 @@
 use crate::error::*;
 use crate::message::*;
 use crate::metrics;
+use crate::signer::Signer;
 use log::*;
 use std::collections::BTreeMap;
 use std::mem;
+use std::sync::Arc;
 use stegos_blockchain::{
     check_supermajority, create_multi_signature, ElectionResult, MacroBlock, Timestamp,
 };
 use stegos_crypto::hash::Hash;
 use stegos_crypto::pbc;
 
-#[derive(Debug)]
-struct LockedRound {
-    precommits: BTreeMap<pbc::PublicKey, pbc::Signature>,
-    block: MacroBlock,
-    block_proposal: MacroBlockProposal,
+#[derive(Debug, Clone)]
+pub struct LockedRound {
+    pub(crate) precommits: BTreeMap<pbc::PublicKey, pbc::Signature>,
+    pub(crate) block: MacroBlock,
+    pub(crate) block_proposal: MacroBlockProposal,
+}
+
+/// The part of consensus state which survives restarts.
+#[derive(Debug, Clone)]
+pub struct ConsensusSnapshot {
+    pub epoch: u64,
+    pub round: u32,
+    pub(crate) locked_round: Option<LockedRound>,
+    /// Messages sent by this node in the current round.
+    pub(crate) messages: Vec<ConsensusMessage>,
 }
 
 #[derive(Debug, Eq, Copy, PartialEq, Clone)]
@@ -78,8 +90,8 @@ pub struct Consensus {
     //
     // Network node keys
     //
-    /// Public key of current node.
-    skey: pbc::SecretKey,
+    /// Signer for messages of current node.
+    signer: Arc<dyn Signer>,
     /// Public key of current node.
     pkey: pbc::PublicKey,
     //
@@ -117,6 +129,10 @@ pub struct Consensus {
     prevotes: BTreeMap<pbc::PublicKey, pbc::Signature>,
     /// Collected Precommits.
     precommits: BTreeMap<pbc::PublicKey, pbc::Signature>,
+    /// Messages sent by this node in the current round.
+    sent: Vec<ConsensusMessage>,
+    /// True if the state must be saved, see take_snapshot().
+    changed: bool,
 
     /// Consensus start time (used for metrics).
     start_time: Timestamp,
@@ -137,18 +153,17 @@ impl Consensus {
     /// # Arguments
     ///
     /// * `epoch` - current consensus epoch.
-    /// * `skey` - BLS Secret Key of this node.
-    /// * `pkey` - BLS Public Key of this node.
+    /// * `signer` - BLS signer of this node.
     /// * `starting_view_change` - blockchain view_change number.
     /// * `election_result` - result of the previous election.
     /// * `validators` - voting members of consensus.
     pub fn new(
         epoch: u64,
-        skey: pbc::SecretKey,
-        pkey: pbc::PublicKey,
+        signer: Arc<dyn Signer>,
         election_result: ElectionResult,
         validators: BTreeMap<pbc::PublicKey, i64>,
     ) -> Self {
+        let pkey = signer.pkey();
         assert!(validators.contains_key(&pkey));
         let state = ConsensusState::Propose;
         metrics::CONSENSUS_STATE.set(metrics::ConsensusState::Propose as i64);
@@ -161,13 +176,15 @@ impl Consensus {
         let block_proposal = None;
         let locked_round = None;
         let round = 0;
+        let sent: Vec<ConsensusMessage> = Vec::new();
+        let changed = false;
         let inbox: Vec<ConsensusMessage> = Vec::new();
         let outbox: Vec<ConsensusMessage> = Vec::new();
         let start_time = Timestamp::now();
         metrics::PRECOMMITS_AMOUNT.set(0);
         metrics::PREVOTES_AMOUNT.set(0);
         Consensus {
-            skey,
+            signer,
             pkey,
             validators,
             total_slots,
@@ -181,6 +198,8 @@ impl Consensus {
             locked_round,
             prevotes,
             precommits,
+            sent,
+            changed,
             inbox,
             outbox,
             start_time,
@@ -244,6 +263,8 @@ impl Consensus {
             self.round
         );
         self.round += 1;
+        self.sent.clear();
+        self.changed = true;
         // if our last state was Precommit, keep lock in the state.
         if self.state == ConsensusState::Precommit {
             self.lock()
@@ -282,11 +303,7 @@ impl Consensus {
             &block_hash
         );
         let body = ConsensusMessageBody::Proposal(block_proposal);
-        let msg = ConsensusMessage::new(
-            self.epoch, self.round, block_hash, &self.skey, &self.pkey, body,
-        );
-        self.outbox.push(msg.clone());
-        self.feed_message(msg).expect("message is valid");
+        self.send(block_hash, body);
     }
 
     ///
@@ -332,11 +349,7 @@ impl Consensus {
             block_hash,
         );
         let body = ConsensusMessageBody::Prevote;
-        let msg = ConsensusMessage::new(
-            self.epoch, self.round, block_hash, &self.skey, &self.pkey, body,
-        );
-        self.outbox.push(msg.clone());
-        self.feed_message(msg).expect("message is valid");
+        self.send(block_hash, body);
     }
 
     ///
@@ -353,15 +366,133 @@ impl Consensus {
             self.round,
             block_hash
         );
-        let block_hash_sig = pbc::sign_hash(&block_hash, &self.skey);
+        let header = &self.block.as_ref().unwrap().header;
+        let block_hash_sig = match self.signer.sign_macro_block(self.round, header) {
+            Ok(sig) => sig,
+            Err(e) => {
+                error!(
+                    "{}({}:{}): failed to sign block={:?}: {}",
+                    self.state.name(),
+                    self.epoch,
+                    self.round,
+                    block_hash,
+                    e
+                );
+                return;
+            }
+        };
         let body = ConsensusMessageBody::Precommit(block_hash_sig);
-        let msg = ConsensusMessage::new(
-            self.epoch, self.round, block_hash, &self.skey, &self.pkey, body,
-        );
+        self.send(block_hash, body);
+    }
+
+    ///
+    /// Sign a message from this node and send it to the network.
+    ///
+    fn send(&mut self, block_hash: Hash, body: ConsensusMessageBody) {
+        let msg = match ConsensusMessage::new_signed(
+            self.epoch,
+            self.round,
+            block_hash,
+            &*self.signer,
+            body,
+        ) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!(
+                    "{}({}:{}): failed to sign a message: block={:?}, error={}",
+                    self.state.name(),
+                    self.epoch,
+                    self.round,
+                    block_hash,
+                    e
+                );
+                return;
+            }
+        };
+        self.sent.push(msg.clone());
+        self.changed = true;
         self.outbox.push(msg.clone());
         self.feed_message(msg).expect("message is valid");
     }
 
+    ///
+    /// Returns the state to save if it has been changed since the last call.
+    /// The state must be saved before messages from outbox are sent.
+    ///
+    pub fn take_snapshot(&mut self) -> Option<ConsensusSnapshot> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+        Some(ConsensusSnapshot {
+            epoch: self.epoch,
+            round: self.round,
+            locked_round: self.locked_round.clone(),
+            messages: self.sent.clone(),
+        })
+    }
+
+    ///
+    /// Restore the state saved before restart.
+    ///
+    /// Own prevotes and precommits are sent again as is.
+    /// A new prevote is created only after the proposal is validated again,
+    /// and it is the same as before because it is signed for the same block.
+    ///
+    /// The state is left untouched if the snapshot doesn't match the current epoch
+    /// or the consensus has already moved past the initial state.
+    ///
+    pub fn restore(&mut self, snapshot: ConsensusSnapshot) -> Result<(), ConsensusError> {
+        if self.state != ConsensusState::Propose || snapshot.epoch != self.epoch {
+            return Err(ConsensusError::InvalidSnapshot(
+                self.state.name(),
+                snapshot.epoch,
+                self.epoch,
+            ));
+        }
+        info!(
+            "{}({}:{}): restored state: round={}, locked={}, sent={}",
+            self.state.name(),
+            self.epoch,
+            self.round,
+            snapshot.round,
+            snapshot.locked_round.is_some(),
+            snapshot.messages.len()
+        );
+        self.round = snapshot.round;
+        self.locked_round = snapshot.locked_round;
+        let messages: Vec<ConsensusMessage> = snapshot
+            .messages
+            .into_iter()
+            .filter(|msg| msg.round == self.round && msg.pkey == self.pkey)
+            .collect();
+        let proposal = messages.iter().find_map(|msg| match msg.body {
+            ConsensusMessageBody::Proposal(_) => Some(msg.clone()),
+            _ => None,
+        });
+        if proposal.is_none() {
+            // Re-propose the locked block if this node is the leader.
+            self.reset();
+        }
+        for msg in messages {
+            self.sent.push(msg.clone());
+            self.outbox.push(msg);
+        }
+        // Never propose a different block in the same round.
+        if let Some(proposal) = proposal {
+            if let Err(e) = self.feed_message(proposal) {
+                warn!(
+                    "{}({}:{}): failed to restore proposal: error={:?}",
+                    self.state.name(),
+                    self.epoch,
+                    self.round,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
     ///
     /// Feed incoming message into the state machine.
     ///
@@ -779,3 +910,189 @@ impl Drop for Consensus {
         metrics::CONSENSUS_STATE.set(metrics::ConsensusState::NotInConsensus as i64);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::LocalSigner;
+    use bit_vec::BitVec;
+
+    const EPOCH: u64 = 1;
+
+    /// A validator which pre-committed a block in the first round.
+    struct Precommitted {
+        keys: Vec<(pbc::SecretKey, pbc::PublicKey)>,
+        signer: Arc<dyn Signer>,
+        election_result: ElectionResult,
+        consensus: Consensus,
+        block: MacroBlock,
+        proposal: ConsensusMessage,
+    }
+
+    impl Precommitted {
+        /// Creates a fresh consensus for the same validator.
+        fn restart(&self) -> Consensus {
+            Consensus::new(
+                EPOCH,
+                self.signer.clone(),
+                self.election_result.clone(),
+                self.consensus.validators().clone(),
+            )
+        }
+    }
+
+    /// Creates a block and the proposal message sent by the leader of `round`.
+    fn proposal(
+        keys: &[(pbc::SecretKey, pbc::PublicKey)],
+        election_result: &ElectionResult,
+        round: u32,
+    ) -> (MacroBlock, ConsensusMessage) {
+        let leader = election_result.select_leader(round);
+        let (skey, pkey) = keys.iter().find(|(_, p)| *p == leader).unwrap();
+        let block = MacroBlock::empty(
+            Hash::digest("previous"),
+            EPOCH,
+            round,
+            *pkey,
+            pbc::make_VRF(skey, &Hash::digest("random")),
+            0,
+            Timestamp::now(),
+            0,
+            BitVec::new(),
+            election_result.validators.clone(),
+        );
+        let block_hash = Hash::digest(&block);
+        let block_proposal = MacroBlockProposal {
+            header: block.header.clone(),
+            transactions: Vec::new(),
+        };
+        let body = ConsensusMessageBody::Proposal(block_proposal);
+        let msg = ConsensusMessage::new(EPOCH, round, block_hash, skey, pkey, body);
+        (block, msg)
+    }
+
+    fn votes(msgs: &[ConsensusMessage]) -> Vec<(&'static str, Hash, Hash)> {
+        msgs.iter()
+            .map(|msg| (msg.name(), msg.block_hash, Hash::digest(&msg.sig)))
+            .collect()
+    }
+
+    /// Drives a validator, which doesn't lead the first two rounds, to pre-commit.
+    fn precommitted() -> Precommitted {
+        let keys: Vec<_> = (0..4).map(|_| pbc::make_random_keys()).collect();
+        let stakers: Vec<(pbc::PublicKey, i64)> = keys.iter().map(|(_, p)| (*p, 1)).collect();
+        let election_result = ElectionResult {
+            validators: stakers.clone(),
+            ..Default::default()
+        };
+        let leaders = [
+            election_result.select_leader(0),
+            election_result.select_leader(1),
+        ];
+        let (skey, pkey) = keys
+            .iter()
+            .find(|(_, p)| !leaders.contains(p))
+            .cloned()
+            .unwrap();
+        let signer: Arc<dyn Signer> = Arc::new(LocalSigner::new(skey, pkey));
+        let validators = stakers.into_iter().collect();
+        let mut consensus =
+            Consensus::new(EPOCH, signer.clone(), election_result.clone(), validators);
+
+        let (block, proposal) = proposal(&keys, &election_result, 0);
+        consensus.feed_message(proposal.clone()).unwrap();
+        assert!(consensus.should_prevote());
+        consensus.prevote(block.clone());
+        let snapshot = consensus.take_snapshot().expect("changed");
+        assert_eq!(votes(&snapshot.messages), votes(&consensus.outbox));
+        assert_eq!(snapshot.messages.len(), 1);
+        assert!(consensus.take_snapshot().is_none());
+
+        let block_hash = Hash::digest(&block);
+        for (skey, pkey) in keys.iter().filter(|(_, p)| *p != pkey).take(2) {
+            let msg = ConsensusMessage::new(
+                EPOCH,
+                0,
+                block_hash,
+                skey,
+                pkey,
+                ConsensusMessageBody::Prevote,
+            );
+            consensus.feed_message(msg).unwrap();
+        }
+        assert_eq!(consensus.state, ConsensusState::Precommit);
+        let names: Vec<_> = consensus.outbox.iter().map(|msg| msg.name()).collect();
+        assert_eq!(names, vec!["Prevote", "Precommit"]);
+
+        Precommitted {
+            keys,
+            signer,
+            election_result,
+            consensus,
+            block,
+            proposal,
+        }
+    }
+
+    #[test]
+    fn restore_after_precommit() {
+        let mut s = precommitted();
+        let snapshot = s.consensus.take_snapshot().expect("changed");
+        let sent = s.consensus.outbox.clone();
+        assert_eq!(votes(&snapshot.messages), votes(&sent));
+
+        let mut restored = s.restart();
+        restored.restore(snapshot).unwrap();
+        assert_eq!(restored.round(), 0);
+        assert_eq!(restored.state, ConsensusState::Propose);
+        assert!(restored.locked_round.is_none());
+        // Own votes are sent again as is.
+        assert_eq!(votes(&restored.outbox), votes(&sent));
+        assert_eq!(votes(&restored.sent), votes(&sent));
+
+        // The proposal is validated again and gives exactly the same pre-vote.
+        restored.outbox.clear();
+        restored.feed_message(s.proposal.clone()).unwrap();
+        assert!(restored.should_prevote());
+        restored.prevote(s.block.clone());
+        assert_eq!(votes(&restored.outbox), votes(&sent[..1]));
+    }
+
+    #[test]
+    fn restore_locked_round() {
+        let mut s = precommitted();
+        s.consensus.next_round();
+        let snapshot = s.consensus.take_snapshot().expect("changed");
+        assert_eq!(snapshot.round, 1);
+        assert!(snapshot.messages.is_empty());
+
+        let mut restored = s.restart();
+        restored.restore(snapshot).unwrap();
+        assert_eq!(restored.round(), 1);
+        assert_eq!(restored.state, ConsensusState::Propose);
+        let locked_round = restored.locked_round.as_ref().expect("locked");
+        assert_eq!(Hash::digest(&locked_round.block), Hash::digest(&s.block));
+        assert_eq!(locked_round.precommits.len(), 1);
+        assert!(restored.outbox.is_empty());
+
+        // Never pre-vote for a block different from the locked one.
+        let (block, proposal) = proposal(&s.keys, &s.election_result, 1);
+        assert_ne!(Hash::digest(&block), Hash::digest(&s.block));
+        restored.feed_message(proposal).unwrap();
+        assert!(restored.should_prevote());
+        restored.prevote(block);
+        assert!(restored.outbox.is_empty());
+        assert!(restored.locked_round.is_some());
+    }
+
+    #[test]
+    fn restore_other_epoch() {
+        let mut s = precommitted();
+        let mut snapshot = s.consensus.take_snapshot().expect("changed");
+        snapshot.epoch = EPOCH + 1;
+        let mut restored = s.restart();
+        assert!(restored.restore(snapshot).is_err());
+        assert_eq!(restored.round(), 0);
+        assert!(restored.outbox.is_empty());
+    }
+}
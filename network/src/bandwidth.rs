@@ -0,0 +1,273 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Bandwidth accounting.
+//!
+//! Bytes and messages are counted per protocol and per pubsub topic by codecs,
+//! bytes per peer by `CountingMuxer` on the level of multiplexed substreams.
+//! Counters of a peer are removed when it disconnects.
+//! All counters are Prometheus metrics, `stats()` collects them for the API.
+//!
+
+use crate::metrics;
+use crate::pubsub::metrics as pubsub_metrics;
+use futures::prelude::*;
+use libp2p_core::muxing::StreamMuxer;
+use libp2p_core::PeerId;
+use prometheus::core::Collector;
+use prometheus::{IntCounter, IntCounterVec};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Traffic of a protocol, topic or peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    /// Protocol, topic or base58 PeerId.
+    pub name: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+}
+
+/// Traffic since the start of the node.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthStats {
+    /// Per network protocol (pubsub, delivery, ncp, gatekeeper, kademlia, replication).
+    pub protocols: Vec<TrafficStats>,
+    /// Per pubsub topic, payload only.
+    pub topics: Vec<TrafficStats>,
+    /// Per connected peer, payload of all protocols without multiplexing
+    /// and encryption overhead. Messages are not counted per peer.
+    pub peers: Vec<TrafficStats>,
+}
+
+/// Collects the current values of bandwidth counters.
+pub fn stats() -> BandwidthStats {
+    BandwidthStats {
+        protocols: merge(
+            &metrics::INCOMING_TRAFFIC,
+            &metrics::OUTGOING_TRAFFIC,
+            Some((&metrics::INCOMING_MESSAGES, &metrics::OUTGOING_MESSAGES)),
+        ),
+        topics: merge(
+            &pubsub_metrics::INCOMING_PUBSUB_TRAFFIC,
+            &pubsub_metrics::OUTGOING_PUBSUB_TRAFFIC,
+            Some((
+                &pubsub_metrics::INCOMING_PUBSUB_MESSAGES,
+                &pubsub_metrics::OUTGOING_PUBSUB_MESSAGES,
+            )),
+        ),
+        peers: merge(
+            &metrics::PEER_INCOMING_TRAFFIC,
+            &metrics::PEER_OUTGOING_TRAFFIC,
+            None,
+        ),
+    }
+}
+
+/// Removes counters of a disconnected peer.
+pub fn remove_peer(peer_id: &PeerId) {
+    let peer_id = peer_id.to_base58();
+    for counter in &[
+        &*metrics::PEER_INCOMING_TRAFFIC,
+        &*metrics::PEER_OUTGOING_TRAFFIC,
+    ] {
+        // Fails only if the peer has never been counted.
+        let _ = counter.remove_label_values(&[&peer_id]);
+    }
+}
+
+/// Merges counters with a single label into stats, the busiest first.
+fn merge(
+    bytes_in: &IntCounterVec,
+    bytes_out: &IntCounterVec,
+    messages: Option<(&IntCounterVec, &IntCounterVec)>,
+) -> Vec<TrafficStats> {
+    let mut stats: BTreeMap<String, TrafficStats> = BTreeMap::new();
+    let mut update = |counter: &IntCounterVec, f: fn(&mut TrafficStats, u64)| {
+        for (name, value) in values(counter) {
+            let entry = stats.entry(name.clone()).or_insert_with(|| TrafficStats {
+                name,
+                ..Default::default()
+            });
+            f(entry, value);
+        }
+    };
+    update(bytes_in, |s, v| s.bytes_in = v);
+    update(bytes_out, |s, v| s.bytes_out = v);
+    if let Some((messages_in, messages_out)) = messages {
+        update(messages_in, |s, v| s.messages_in = v);
+        update(messages_out, |s, v| s.messages_out = v);
+    }
+    let mut stats: Vec<TrafficStats> = stats.into_iter().map(|(_, s)| s).collect();
+    stats.sort_by_key(|s| std::cmp::Reverse(s.bytes_in + s.bytes_out));
+    stats
+}
+
+/// Returns values of a counter by its first label.
+fn values(counter: &IntCounterVec) -> Vec<(String, u64)> {
+    let mut values = Vec::new();
+    for family in counter.collect() {
+        for metric in family.get_metric() {
+            if let Some(label) = metric.get_label().first() {
+                let value = metric.get_counter().get_value() as u64;
+                values.push((label.get_value().to_string(), value));
+            }
+        }
+    }
+    values
+}
+
+/// Wrapper of a connection multiplexer, which counts bytes of the remote peer.
+pub struct CountingMuxer<M> {
+    inner: M,
+    bytes_in: IntCounter,
+    bytes_out: IntCounter,
+}
+
+impl<M> CountingMuxer<M> {
+    pub fn new(inner: M, peer_id: &PeerId) -> Self {
+        let peer_id = peer_id.to_base58();
+        CountingMuxer {
+            inner,
+            bytes_in: metrics::PEER_INCOMING_TRAFFIC.with_label_values(&[&peer_id]),
+            bytes_out: metrics::PEER_OUTGOING_TRAFFIC.with_label_values(&[&peer_id]),
+        }
+    }
+}
+
+impl<M: StreamMuxer> StreamMuxer for CountingMuxer<M> {
+    type Substream = M::Substream;
+    type OutboundSubstream = M::OutboundSubstream;
+    type Error = M::Error;
+
+    fn poll_inbound(&self) -> Poll<Self::Substream, Self::Error> {
+        self.inner.poll_inbound()
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.inner.open_outbound()
+    }
+
+    fn poll_outbound(
+        &self,
+        substream: &mut Self::OutboundSubstream,
+    ) -> Poll<Self::Substream, Self::Error> {
+        self.inner.poll_outbound(substream)
+    }
+
+    fn destroy_outbound(&self, substream: Self::OutboundSubstream) {
+        self.inner.destroy_outbound(substream)
+    }
+
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+
+    fn read_substream(
+        &self,
+        substream: &mut Self::Substream,
+        buf: &mut [u8],
+    ) -> Poll<usize, Self::Error> {
+        let result = self.inner.read_substream(substream, buf);
+        if let Ok(Async::Ready(n)) = result {
+            self.bytes_in.inc_by(n as i64);
+        }
+        result
+    }
+
+    fn write_substream(
+        &self,
+        substream: &mut Self::Substream,
+        buf: &[u8],
+    ) -> Poll<usize, Self::Error> {
+        let result = self.inner.write_substream(substream, buf);
+        if let Ok(Async::Ready(n)) = result {
+            self.bytes_out.inc_by(n as i64);
+        }
+        result
+    }
+
+    fn flush_substream(&self, substream: &mut Self::Substream) -> Poll<(), Self::Error> {
+        self.inner.flush_substream(substream)
+    }
+
+    fn shutdown_substream(&self, substream: &mut Self::Substream) -> Poll<(), Self::Error> {
+        self.inner.shutdown_substream(substream)
+    }
+
+    fn destroy_substream(&self, substream: Self::Substream) {
+        self.inner.destroy_substream(substream)
+    }
+
+    fn is_remote_acknowledged(&self) -> bool {
+        self.inner.is_remote_acknowledged()
+    }
+
+    fn close(&self) -> Poll<(), Self::Error> {
+        self.inner.close()
+    }
+
+    fn flush_all(&self) -> Poll<(), Self::Error> {
+        self.inner.flush_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_counters() {
+        metrics::incoming("bandwidth_test_a", 100);
+        metrics::incoming("bandwidth_test_a", 50);
+        metrics::outgoing("bandwidth_test_a", 10);
+        metrics::outgoing("bandwidth_test_b", 1000);
+
+        let stats = stats();
+        let a = stats
+            .protocols
+            .iter()
+            .position(|s| s.name == "bandwidth_test_a")
+            .unwrap();
+        let b = stats
+            .protocols
+            .iter()
+            .position(|s| s.name == "bandwidth_test_b")
+            .unwrap();
+        assert!(b < a);
+        assert_eq!(
+            stats.protocols[a],
+            TrafficStats {
+                name: "bandwidth_test_a".to_string(),
+                bytes_in: 150,
+                bytes_out: 10,
+                messages_in: 2,
+                messages_out: 1,
+            }
+        );
+        assert_eq!(stats.protocols[b].messages_out, 1);
+    }
+}
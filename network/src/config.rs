@@ -21,7 +21,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use serde::{Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 /// Network configuration.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -43,10 +46,170 @@ pub struct NetworkConfig {
     pub max_connections: usize,
     /// Connection monitoring tick interval (secs)
     pub monitoring_interval: u64,
-    /// Handshake puzzle difficulty (VDF complexity)
+    /// Initial handshake puzzle difficulty (VDF complexity)
     pub hanshake_puzzle_difficulty: u64,
+    /// Adjustment of the handshake puzzle difficulty to the load.
+    pub puzzle_difficulty: PuzzleDifficulty,
     /// Network readiness threshold (number of handshake-enabled established connections)
     pub readiness_threshold: usize,
+    /// Routing of pubsub messages.
+    pub pubsub_mode: PubsubMode,
+    /// Desired number of mesh peers per topic, used in the mesh mode.
+    pub pubsub_mesh_degree: usize,
+    /// Peers (base58 PeerId) which are never accepted.
+    pub banned_peers: Vec<String>,
+    /// Peers (base58 PeerId) which are never penalized or banned.
+    pub allowed_peers: Vec<String>,
+    /// Priority classes of pubsub topics and unicast protocols, normal by default.
+    /// Configured entries override the built-in ones.
+    #[serde(deserialize_with = "deserialize_topic_priorities")]
+    pub topic_priorities: HashMap<String, MessagePriority>,
+    /// Maximum number of outgoing messages queued per peer and priority class.
+    pub priority_queue_size: usize,
+    /// File to keep known peers between restarts, not persisted if empty.
+    pub peer_store_file: PathBuf,
+    /// File to keep the Kademlia routing table between restarts, not persisted if empty.
+    pub routing_table_file: PathBuf,
+    /// File to keep bans and allows made via API, not persisted if empty.
+    pub reputation_file: PathBuf,
+    /// Outbound bandwidth limits.
+    pub rate_limits: RateLimits,
+    /// Proxy for outgoing connections.
+    pub proxy: ProxyConfig,
+}
+
+/// Proxy for outgoing connections.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// SOCKS5 proxy endpoint (e.g. a local Tor daemon), connect directly if empty.
+    /// Host names and seed_pool are resolved through the proxy.
+    pub socks5: String,
+    /// Don't accept incoming connections.
+    pub disable_listener: bool,
+}
+
+/// Outbound bandwidth limits in bytes per second, 0 means unlimited.
+/// High priority pubsub topics, consensus, view change and block topics are never limited.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RateLimits {
+    /// Total of limited traffic.
+    pub global: u64,
+    /// Limited traffic to a single peer.
+    pub per_peer: u64,
+    /// Blocks sent to replication downstreams.
+    pub replication: u64,
+    /// Pubsub messages of normal and low priority.
+    pub pubsub: u64,
+}
+
+/// Adjustment of the handshake puzzle difficulty to the load.
+/// The load is the rate of inbound connections and the number of connections
+/// relative to max_connections.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PuzzleDifficulty {
+    /// Lowest difficulty, used when the node is idle.
+    pub min: u64,
+    /// Highest difficulty, used under a connection flood.
+    pub max: u64,
+    /// Inbound connections per minute considered normal.
+    pub normal_attempt_rate: u64,
+    /// Maximum pending inbound handshakes from a single IP address, 0 means unlimited.
+    pub max_pending_per_ip: usize,
+}
+
+impl Default for PuzzleDifficulty {
+    fn default() -> PuzzleDifficulty {
+        PuzzleDifficulty {
+            min: 25,
+            max: 1600,
+            normal_attempt_rate: 60,
+            max_pending_per_ip: 4,
+        }
+    }
+}
+
+/// Routing of pubsub messages.
+#[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PubsubMode {
+    /// Forward every message to all peers subscribed to the topic.
+    Flood,
+    /// Forward messages to a mesh of peers per topic and gossip ids of
+    /// recent messages to other peers (gossipsub).
+    Mesh,
+}
+
+/// Priority class of outgoing messages.
+/// Messages of a class are sent only when all higher classes are empty.
+#[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MessagePriority {
+    /// Consensus and block propagation.
+    High,
+    Normal,
+    /// Transactions and other bulk traffic.
+    Low,
+}
+
+impl MessagePriority {
+    /// Label for metrics.
+    pub fn label(self) -> &'static str {
+        match self {
+            MessagePriority::High => "high",
+            MessagePriority::Normal => "normal",
+            MessagePriority::Low => "low",
+        }
+    }
+}
+
+impl Default for MessagePriority {
+    fn default() -> Self {
+        MessagePriority::Normal
+    }
+}
+
+fn default_topic_priorities() -> HashMap<String, MessagePriority> {
+    let high = [
+        "consensus",
+        "view_changes",
+        "view_changes_proofs",
+        "view_changes_direct",
+        "unicast_ack",
+        "block",
+        "compact_block",
+        "compact_block_txs",
+    ];
+    let low = [
+        "tx",
+        "tx_stem",
+        "txpool_join",
+        "txpool_announce",
+        "snowball",
+    ];
+    let mut priorities = HashMap::new();
+    for topic in high.iter() {
+        priorities.insert(topic.to_string(), MessagePriority::High);
+    }
+    for topic in low.iter() {
+        priorities.insert(topic.to_string(), MessagePriority::Low);
+    }
+    priorities
+}
+
+/// Merges configured topic priorities over the built-in ones.
+fn deserialize_topic_priorities<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, MessagePriority>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let overrides: HashMap<String, MessagePriority> = HashMap::deserialize(deserializer)?;
+    let mut priorities = default_topic_priorities();
+    priorities.extend(overrides);
+    Ok(priorities)
 }
 
 /// Default values for network configuration.
@@ -62,7 +225,36 @@ impl Default for NetworkConfig {
             max_connections: 32,
             monitoring_interval: 60,
             hanshake_puzzle_difficulty: 100,
+            puzzle_difficulty: Default::default(),
             readiness_threshold: 2,
+            pubsub_mode: PubsubMode::Flood,
+            pubsub_mesh_degree: 6,
+            banned_peers: vec![],
+            allowed_peers: vec![],
+            topic_priorities: default_topic_priorities(),
+            priority_queue_size: 1024,
+            peer_store_file: PathBuf::new(),
+            routing_table_file: PathBuf::new(),
+            reputation_file: PathBuf::new(),
+            rate_limits: Default::default(),
+            proxy: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_priorities_override() {
+        let json = r#"{"topic_priorities": {"tx": "normal", "custom": "high"}}"#;
+        let config: NetworkConfig = serde_json::from_str(json).unwrap();
+        let defaults = default_topic_priorities();
+        assert_eq!(config.topic_priorities.len(), defaults.len() + 1);
+        assert_eq!(config.topic_priorities["tx"], MessagePriority::Normal);
+        assert_eq!(config.topic_priorities["custom"], MessagePriority::High);
+        assert_eq!(config.topic_priorities["consensus"], MessagePriority::High);
+        assert_eq!(config.topic_priorities["tx_stem"], MessagePriority::Low);
+    }
+}
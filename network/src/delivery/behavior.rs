@@ -24,12 +24,13 @@
 use super::handler::{DeliveryHandler, DeliveryRecvEvent, DeliverySendEvent};
 pub use super::protocol::{DeliveryMessage, Unicast};
 
-use crate::utils::ExpiringQueue;
+use crate::config::NetworkConfig;
+use crate::metrics;
+use crate::utils::{ExpiringQueue, PriorityQueue};
 use futures::prelude::*;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
 use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolsHandler};
 use log::{debug, error};
-use smallvec::SmallVec;
 use std::{
     collections::{hash_map::HashMap, hash_set::HashSet, VecDeque},
     marker::PhantomData,
@@ -53,8 +54,11 @@ pub struct Delivery<TSubstream> {
     // Pending peers, peers we are trying to dial
     dial_queue: ExpiringQueue<PeerId, ()>,
 
-    // Sending queue
-    send_queue: HashMap<PeerId, SmallVec<[DeliveryMessage; 16]>>,
+    // Sending queue, bounded per priority class
+    send_queue: HashMap<PeerId, PriorityQueue<DeliveryMessage>>,
+
+    /// Maximum number of outgoing messages per peer and priority class.
+    priority_queue_size: usize,
 
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
@@ -62,12 +66,13 @@ pub struct Delivery<TSubstream> {
 
 impl<TSubstream> Delivery<TSubstream> {
     /// Creates a `Delivery`.
-    pub fn new() -> Self {
+    pub fn new(config: &NetworkConfig) -> Self {
         Delivery {
             events: VecDeque::new(),
             connected_peers: HashSet::new(),
             dial_queue: ExpiringQueue::new(DIAL_TIMEOUT),
             send_queue: HashMap::new(),
+            priority_queue_size: config.priority_queue_size,
             marker: PhantomData,
         }
     }
@@ -91,10 +96,19 @@ impl<TSubstream> Delivery<TSubstream> {
                 peer_id: next_hop.clone(),
             });
         }
-        self.send_queue
+        let priority = message.priority;
+        let priority_queue_size = self.priority_queue_size;
+        let dropped = self
+            .send_queue
             .entry(next_hop.clone())
-            .or_insert(SmallVec::new())
-            .push(DeliveryMessage::UnicastMessage(message));
+            .or_insert_with(|| PriorityQueue::new(priority_queue_size))
+            .push(priority, DeliveryMessage::UnicastMessage(message));
+        if dropped.is_some() {
+            debug!(target: "stegos_network::delivery", "dial queue is full, dropped the oldest message: peer_id={}, priority={:?}", next_hop, priority);
+            metrics::DROPPED_MESSAGES
+                .with_label_values(&["delivery", priority.label()])
+                .inc();
+        }
     }
 }
 
@@ -106,7 +120,7 @@ where
     type OutEvent = DeliveryEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        DeliveryHandler::new()
+        DeliveryHandler::new(self.priority_queue_size)
     }
 
     fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
@@ -120,7 +134,7 @@ where
             self.dial_queue.remove(&id);
             if let Some(queue) = self.send_queue.get_mut(&id) {
                 debug!(target: "stegos_network::delivery", "delivering queued messages: peer_id={}, queue_len={}", id, queue.len());
-                for m in queue.drain() {
+                while let Some(m) = queue.pop() {
                     self.events.push_back(NetworkBehaviourAction::SendEvent {
                         peer_id: id.clone(),
                         event: DeliverySendEvent::Deliver(m),
@@ -24,19 +24,25 @@
 use super::protocol::{DeliveryCodec, DeliveryConfig, DeliveryMessage};
 
 use futures::prelude::*;
+use futures::task;
 use libp2p_core::upgrade::{InboundUpgrade, Negotiated, OutboundUpgrade};
 use libp2p_swarm::protocols_handler::{
     KeepAlive, ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr, SubstreamProtocol,
 };
 use log::{debug, trace};
-use smallvec::SmallVec;
 use std::collections::VecDeque;
 use std::{fmt, io, time::Instant};
 use tokio::codec::Framed;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use crate::config::MessagePriority;
+use crate::metrics;
+use crate::utils::PriorityQueue;
 use crate::NETWORK_IDLE_TIMEOUT;
 
+/// Messages are queued while so many outbound substreams are being sent.
+const MAX_OUTBOUND_SUBSTREAMS: usize = 4;
+
 /// Protocol handler that handles communication with the remote for the Delivery protocol.
 ///
 /// The handler will automatically open a substream with the remote for each request we make.
@@ -57,7 +63,10 @@ where
     keep_alive: KeepAlive,
 
     /// Queue of values that we want to send to the remote.
-    send_queue: SmallVec<[DeliveryMessage; 16]>,
+    send_queue: PriorityQueue<DeliveryMessage>,
+
+    /// Outbound substreams requested, but not negotiated yet.
+    pending_outbound: usize,
 
     /// Events to send upstream
     out_events: VecDeque<DeliveryRecvEvent>,
@@ -101,15 +110,36 @@ where
     TSubstream: AsyncRead + AsyncWrite,
 {
     /// Builds a new `DeliveryHandler`.
-    pub fn new() -> Self {
+    pub fn new(queue_size: usize) -> Self {
         DeliveryHandler {
             config: DeliveryConfig::new(),
             substreams: Vec::new(),
             keep_alive: KeepAlive::Yes,
-            send_queue: SmallVec::new(),
+            send_queue: PriorityQueue::new(queue_size),
+            pending_outbound: 0,
             out_events: VecDeque::new(),
         }
     }
+
+    /// Number of outbound substreams which haven't sent their message yet.
+    fn outbound_substreams(&self) -> usize {
+        let sending = self
+            .substreams
+            .iter()
+            .filter(|substream| match substream {
+                SubstreamState::PendingSend(..) | SubstreamState::PendingFlush(..) => true,
+                _ => false,
+            })
+            .count();
+        self.pending_outbound + sending
+    }
+
+    /// An outbound substream has sent its message, schedule the next one.
+    fn outbound_finished(&self) {
+        if !self.send_queue.is_empty() {
+            task::current().notify();
+        }
+    }
 }
 
 impl<TSubstream> ProtocolsHandler for DeliveryHandler<TSubstream>
@@ -141,6 +171,7 @@ where
         protocol: <Self::OutboundProtocol as OutboundUpgrade<TSubstream>>::Output,
         message: Self::OutboundOpenInfo,
     ) {
+        self.pending_outbound -= 1;
         self.substreams
             .push(SubstreamState::PendingSend(protocol, message))
     }
@@ -149,7 +180,16 @@ where
     fn inject_event(&mut self, event: Self::InEvent) {
         match event {
             DeliverySendEvent::Deliver(message) => {
-                self.send_queue.push(message);
+                let priority = match &message {
+                    DeliveryMessage::UnicastMessage(unicast) => unicast.priority,
+                    DeliveryMessage::BroadcastMessage(_) => MessagePriority::Normal,
+                };
+                if self.send_queue.push(priority, message).is_some() {
+                    debug!(target: "stegos_network::delivery", "send queue is full, dropped the oldest message: priority={:?}", priority);
+                    metrics::DROPPED_MESSAGES
+                        .with_label_values(&["delivery", priority.label()])
+                        .inc();
+                }
             }
         }
     }
@@ -162,6 +202,7 @@ where
             <Self::OutboundProtocol as OutboundUpgrade<Self::Substream>>::Error,
         >,
     ) {
+        self.pending_outbound -= 1;
     }
 
     #[inline]
@@ -180,14 +221,16 @@ where
             return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(message)));
         }
 
-        if !self.send_queue.is_empty() {
-            let message = self.send_queue.remove(0);
-            return Ok(Async::Ready(
-                ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                    info: message,
-                    protocol: SubstreamProtocol::new(self.config.clone()),
-                },
-            ));
+        if self.outbound_substreams() < MAX_OUTBOUND_SUBSTREAMS {
+            if let Some(message) = self.send_queue.pop() {
+                self.pending_outbound += 1;
+                return Ok(Async::Ready(
+                    ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                        info: message,
+                        protocol: SubstreamProtocol::new(self.config.clone()),
+                    },
+                ));
+            }
         }
 
         for n in (0..self.substreams.len()).rev() {
@@ -223,13 +266,17 @@ where
                             }
                             Err(e) => {
                                 debug!(target: "stegos_network::delivery", "error sending to substream: error={}", e);
+                                self.outbound_finished();
                                 SubstreamState::Closing(substream)
                             }
                         }
                     }
                     SubstreamState::PendingFlush(mut substream) => {
                         match substream.poll_complete() {
-                            Ok(Async::Ready(())) => SubstreamState::Closing(substream),
+                            Ok(Async::Ready(())) => {
+                                self.outbound_finished();
+                                SubstreamState::Closing(substream)
+                            }
                             Ok(Async::NotReady) => {
                                 self.substreams
                                     .push(SubstreamState::PendingFlush(substream));
@@ -237,6 +284,7 @@ where
                             }
                             Err(e) => {
                                 debug!(target: "stegos_network::delivery", "error flushing substream: error={}", e);
+                                self.outbound_finished();
                                 SubstreamState::Closing(substream)
                             }
                         }
@@ -259,7 +307,7 @@ where
             }
         }
 
-        if self.substreams.is_empty() {
+        if self.substreams.is_empty() && self.send_queue.is_empty() && self.pending_outbound == 0 {
             self.keep_alive = KeepAlive::Until(Instant::now() + NETWORK_IDLE_TIMEOUT);
         } else {
             self.keep_alive = KeepAlive::Yes;
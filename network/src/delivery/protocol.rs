@@ -21,6 +21,7 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::config::MessagePriority;
 use crate::metrics;
 
 use bytes::{BufMut, BytesMut};
@@ -119,6 +120,11 @@ impl Encoder for DeliveryCodec {
                 unicast_proto.set_to(unicast.to.to_bytes().to_vec());
                 unicast_proto.set_payload(unicast.payload);
                 unicast_proto.set_dont_route(unicast.dont_route);
+                unicast_proto.set_priority(match unicast.priority {
+                    MessagePriority::High => delivery_proto::Priority::HIGH,
+                    MessagePriority::Normal => delivery_proto::Priority::NORMAL,
+                    MessagePriority::Low => delivery_proto::Priority::LOW,
+                });
                 msg.set_unicast(unicast_proto);
                 msg
             }
@@ -140,9 +146,7 @@ impl Encoder for DeliveryCodec {
         // Reserve enough space for the data and the length. The length has a maximum of 32 bits,
         // which means that 5 bytes is enough for the variable-length integer.
         dst.reserve(msg_size as usize + 5);
-        metrics::OUTGOING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(msg_size as i64);
+        metrics::outgoing(PROTOCOL_LABEL, msg_size as usize);
 
         proto
             .write_length_delimited_to_writer(&mut dst.by_ref().writer())
@@ -163,9 +167,7 @@ impl Decoder for DeliveryCodec {
             Some(p) => p,
             None => return Ok(None),
         };
-        metrics::INCOMING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(packet.len() as i64);
+        metrics::incoming(PROTOCOL_LABEL, packet.len());
         let message: delivery_proto::Message = protobuf::parse_from_bytes(&packet)?;
 
         let seq_no = message.get_seqno().to_vec();
@@ -180,11 +182,17 @@ impl Decoder for DeliveryCodec {
                 })?;
                 let payload = msg.get_payload().to_vec();
                 let dont_route = msg.get_dont_route();
+                let priority = match msg.get_priority() {
+                    delivery_proto::Priority::HIGH => MessagePriority::High,
+                    delivery_proto::Priority::NORMAL => MessagePriority::Normal,
+                    delivery_proto::Priority::LOW => MessagePriority::Low,
+                };
                 return Ok(Some(DeliveryMessage::UnicastMessage(Unicast {
                     to,
                     payload,
                     dont_route,
                     seq_no,
+                    priority,
                 })));
             }
             Some(delivery_proto::Message_oneof_typ::broadcast(msg)) => {
@@ -229,6 +237,8 @@ pub struct Unicast {
     pub payload: Vec<u8>,
     pub dont_route: bool,
     pub seq_no: Vec<u8>,
+    /// Priority class of the message, relays derive it from the protocol id.
+    pub priority: MessagePriority,
 }
 
 #[derive(Debug, Clone, PartialEq, Hash)]
@@ -258,6 +268,7 @@ impl Broadcast {
 #[cfg(test)]
 mod tests {
     use super::{Broadcast, DeliveryCodec, DeliveryMessage, Unicast};
+    use crate::config::MessagePriority;
     use futures::{future, Future, Sink, Stream};
     use rand;
     use stegos_crypto::pbc;
@@ -273,6 +284,7 @@ mod tests {
             payload: random_vec(1024),
             dont_route: false,
             seq_no: rand::random::<[u8; 20]>().to_vec(),
+            priority: MessagePriority::High,
         });
 
         test_one(msg);
@@ -21,6 +21,8 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use super::routing_store::RoutingStore;
+use crate::config::{MessagePriority, NetworkConfig};
 use crate::delivery::Unicast;
 use crate::kad::{kbucket::KBucketsPeerId, Kademlia, KademliaOut, NodeInfo};
 use crate::utils::LruBimap;
@@ -75,17 +77,35 @@ pub struct Discovery<TSubstream> {
     delay_between_queries: Duration,
     /// Delay to next monitoring check
     next_connection_check: Delay,
+    /// Persistent copy of the routing table.
+    routing_store: RoutingStore,
 }
 
 impl<TSubstream> Discovery<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
 {
-    pub fn new(local_node_id: pbc::PublicKey) -> Self {
+    pub fn new(config: &NetworkConfig, local_node_id: pbc::PublicKey) -> Self {
+        let mut kademlia = Kademlia::without_init(local_node_id.clone());
+        let mut known_nodes = LruBimap::<pbc::PublicKey, PeerId>::with_expiry_duration(NODES_TTL);
+        let routing_store = match RoutingStore::load(&config.routing_table_file) {
+            Ok((routing_store, nodes)) => {
+                debug!(target: "stegos_network::discovery", "Restored routing table: count={}", nodes.len());
+                for (node_id, peer_id, addresses) in nodes {
+                    known_nodes.insert(node_id.clone(), peer_id.clone());
+                    kademlia.restore_node(&node_id, peer_id, addresses);
+                }
+                routing_store
+            }
+            Err(e) => {
+                error!(target: "stegos_network::discovery", "Failed to load routing table: {}", e);
+                RoutingStore::new(&config.routing_table_file)
+            }
+        };
         Discovery {
-            my_id: local_node_id.clone(),
-            kademlia: Kademlia::without_init(local_node_id),
-            known_nodes: LruBimap::<pbc::PublicKey, PeerId>::with_expiry_duration(NODES_TTL),
+            my_id: local_node_id,
+            kademlia,
+            known_nodes,
             out_events: VecDeque::new(),
             connected_peers: HashSet::new(),
             next_query: Delay::new(Instant::now() + Duration::from_secs(30)),
@@ -97,6 +117,7 @@ where
                 Duration::from_secs(60 * 15),
                 100_000,
             ),
+            routing_store,
         }
     }
 
@@ -125,12 +146,23 @@ where
         self.known_nodes.insert(node_id, peer_id);
     }
 
-    pub fn deliver_unicast(&mut self, to: &pbc::PublicKey, payload: Vec<u8>) {
+    /// Returns PeerId of a known node.
+    pub fn peer_id(&mut self, node_id: &pbc::PublicKey) -> Option<PeerId> {
+        self.known_nodes.get_by_key(node_id).cloned()
+    }
+
+    pub fn deliver_unicast(
+        &mut self,
+        to: &pbc::PublicKey,
+        payload: Vec<u8>,
+        priority: MessagePriority,
+    ) {
         let mut message = Unicast {
             to: to.clone(),
             payload,
             dont_route: false,
             seq_no: rand::random::<[u8; 20]>().to_vec(),
+            priority,
         };
 
         // Guard against very unlikely event of Hash collision
@@ -355,6 +387,9 @@ where
                 Ok(Async::Ready(_)) => {
                     self.next_connection_check
                         .reset(Instant::now() + Duration::from_secs(MONITORING_INTERVAL));
+                    if let Err(e) = self.routing_store.save(self.kademlia.routing_table()) {
+                        error!(target: "stegos_network::discovery", "Failed to save routing table: {}", e);
+                    }
                     let my_id = self.kademlia.my_id().clone();
                     let closest_nodes: Vec<pbc::PublicKey> = self
                         .kademlia
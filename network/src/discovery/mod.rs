@@ -23,5 +23,6 @@
 
 mod behavior;
 mod metrics;
+mod routing_store;
 
 pub use behavior::{Discovery, DiscoveryOutEvent};
@@ -0,0 +1,147 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Persistent copy of the Kademlia routing table.
+//!
+//! The table is saved to `NetworkConfig::routing_table_file`, so that DHT lookups
+//! work right after restart, before the table is refilled from the network.
+//!
+
+use failure::{format_err, Error};
+use libp2p_core::{Multiaddr, PeerId};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use stegos_crypto::pbc;
+
+/// Saved entry of the routing table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StoredNode {
+    /// Network key.
+    node_id: pbc::PublicKey,
+    /// Base58 PeerId.
+    peer_id: String,
+    /// Known addresses.
+    addresses: Vec<Multiaddr>,
+}
+
+/// Routing table, backed by a JSON file.
+pub struct RoutingStore {
+    /// Path to the file, empty if the table is not persistent.
+    path: PathBuf,
+    /// The last saved table.
+    saved: Vec<StoredNode>,
+}
+
+impl RoutingStore {
+    /// Creates an empty store.
+    pub fn new(path: &Path) -> Self {
+        RoutingStore {
+            path: path.to_path_buf(),
+            saved: Vec::new(),
+        }
+    }
+
+    /// Loads the table from the file, returns an empty table if the file doesn't exist.
+    pub fn load(
+        path: &Path,
+    ) -> Result<(Self, Vec<(pbc::PublicKey, PeerId, Vec<Multiaddr>)>), Error> {
+        let mut store = RoutingStore::new(path);
+        if path.as_os_str().is_empty() || !path.exists() {
+            return Ok((store, Vec::new()));
+        }
+        let data = fs::read(path).map_err(|e| format_err!("Failed to read {:?}: {}", path, e))?;
+        store.saved = serde_json::from_slice(&data)
+            .map_err(|e| format_err!("Failed to parse {:?}: {}", path, e))?;
+        let nodes = store
+            .saved
+            .iter()
+            .filter_map(|node| {
+                let peer_id = PeerId::from_str(&node.peer_id).ok()?;
+                Some((node.node_id, peer_id, node.addresses.clone()))
+            })
+            .collect();
+        Ok((store, nodes))
+    }
+
+    /// Saves the table to the file if it was changed.
+    pub fn save(
+        &mut self,
+        nodes: Vec<(pbc::PublicKey, PeerId, Vec<Multiaddr>)>,
+    ) -> Result<(), Error> {
+        if self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let mut nodes: Vec<StoredNode> = nodes
+            .into_iter()
+            .map(|(node_id, peer_id, addresses)| StoredNode {
+                node_id,
+                peer_id: peer_id.to_base58(),
+                addresses,
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        if nodes == self.saved {
+            return Ok(());
+        }
+        let data = serde_json::to_vec_pretty(&nodes)?;
+        // Write to a temporary file first to never leave a truncated table.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &data).map_err(|e| format_err!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, &self.path)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", self.path, e))?;
+        self.saved = nodes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+    use tempdir::TempDir;
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("routing_store").unwrap();
+        let path = dir.path().join("routing_table.json");
+        let (mut store, nodes) = RoutingStore::load(&path).unwrap();
+        assert!(nodes.is_empty());
+
+        let (_skey, node_id) = pbc::make_random_keys();
+        let peer_id = identity::Keypair::generate_ed25519()
+            .public()
+            .into_peer_id();
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let table = vec![(node_id, peer_id.clone(), vec![addr.clone()])];
+        store.save(table.clone()).unwrap();
+
+        let (_store, nodes) = RoutingStore::load(&path).unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].0, node_id);
+        assert_eq!(nodes[0].1, peer_id);
+        assert_eq!(nodes[0].2, vec![addr]);
+    }
+}
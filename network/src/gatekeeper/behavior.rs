@@ -23,6 +23,7 @@
 
 use futures::prelude::*;
 use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use libp2p_core::multiaddr::Protocol;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
 use libp2p_swarm::{
     protocols_handler::ProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
@@ -32,20 +33,22 @@ use lru_time_cache::LruCache;
 use rand::{seq::SliceRandom, thread_rng};
 use std::cmp::max;
 use std::error;
-use std::time::{Duration, SystemTime};
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime};
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     marker::PhantomData,
     thread,
 };
 use stegos_crypto::vdf::VDF;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use super::difficulty::DifficultyController;
 use super::handler::{GatekeeperHandler, GatekeeperSendEvent};
+use super::metrics;
 use super::protocol::{GatekeeperMessage, VDFProof};
 use crate::config::NetworkConfig;
-use crate::utils::{socket_to_multi_addr, ExpiringQueue, PeerIdKey};
-use std::net::SocketAddr;
+use crate::utils::{seed_to_multi_addr, ExpiringQueue, PeerIdKey};
 
 // Dialout timeout
 const DIAL_TIMEOUT: Duration = Duration::from_secs(60);
@@ -88,8 +91,12 @@ pub struct Gatekeeper<TSubstream> {
     solved_vdfs: LruCache<PeerIdKey, (VDFChallenge, Option<Vec<u8>>)>,
     /// Queue of VDF challenges from remote peers, waiting to be solved
     challenges_queue: VecDeque<PeerId>,
-    /// VDF complexity
-    hanshake_puzzle_difficulty: u64,
+    /// VDF complexity of our puzzles
+    difficulty: DifficultyController,
+    /// Maximum pending inbound handshakes from a single IP address (0 - unlimited)
+    max_pending_per_ip: usize,
+    /// IP addresses of incoming peers
+    inbound_ips: HashMap<PeerId, IpAddr>,
     /// Netwrok readyness threshold
     readiness_threshold: usize,
     /// Marker to pin the generics.
@@ -109,8 +116,7 @@ impl<TSubstream> Gatekeeper<TSubstream> {
         addrs.shuffle(&mut rng);
 
         for addr in addrs.iter() {
-            let addr = addr.parse::<SocketAddr>().expect("Invalid seed_node");
-            let addr = socket_to_multi_addr(&addr);
+            let addr = seed_to_multi_addr(addr).expect("Invalid seed_node");
             debug!(target: "stegos_network::gatekeeper", "dialing peer with address {}", addr);
             events.push_back(NetworkBehaviourAction::DialAddress {
                 address: addr.clone(),
@@ -142,7 +148,14 @@ impl<TSubstream> Gatekeeper<TSubstream> {
             solvers: HashSet::new(),
             solver_threads,
             challenges_queue: VecDeque::new(),
-            hanshake_puzzle_difficulty: config.hanshake_puzzle_difficulty,
+            difficulty: DifficultyController::new(
+                config.hanshake_puzzle_difficulty,
+                &config.puzzle_difficulty,
+                config.max_connections,
+                Instant::now(),
+            ),
+            max_pending_per_ip: config.puzzle_difficulty.max_pending_per_ip,
+            inbound_ips: HashMap::new(),
             readiness_threshold: config.readiness_threshold,
             marker: PhantomData,
         }
@@ -168,13 +181,44 @@ impl<TSubstream> Gatekeeper<TSubstream> {
         self.protocol_updates.push_back(event);
     }
 
+    /// Checks if the peer's IP address has too many pending inbound handshakes.
+    fn too_many_pending(&self, peer_id: &PeerId) -> bool {
+        let ip = match self.inbound_ips.get(peer_id) {
+            Some(ip) => ip,
+            None => return false,
+        };
+        let pending = self
+            .pending_in_peers
+            .keys()
+            .filter(|p| self.inbound_ips.get(*p) == Some(ip))
+            .count();
+        self.max_pending_per_ip > 0 && pending >= self.max_pending_per_ip
+    }
+
     fn send_new_challenge(&mut self, peer_id: PeerId) {
+        if !self.pending_in_peers.contains_key(&peer_id) && self.too_many_pending(&peer_id) {
+            debug!(target: "stegos_network::gatekeeper", "too many pending handshakes from the same address, disconnecting: peer_id={}", peer_id);
+            metrics::REJECTED_HANDSHAKES.inc();
+            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                GatekeeperOutEvent::Rejected { peer_id },
+            ));
+            return;
+        }
+        let previous = self.difficulty.current();
+        let difficulty = self
+            .difficulty
+            .difficulty(self.connected_peers.len(), Instant::now());
+        if difficulty != previous {
+            info!(target: "stegos_network::gatekeeper", "Adjusted puzzle difficulty: difficulty={}, previous={}, attempt_rate={}, connections={}", difficulty, previous, self.difficulty.attempt_rate(), self.connected_peers.len());
+        }
+        metrics::PUZZLE_DIFFICULTY.set(difficulty as i64);
+        metrics::PUZZLES_ISSUED.inc();
         let challenge = generate_challenge(&peer_id);
         self.our_challenges.insert(
             peer_id.clone().into(),
             VDFChallenge {
                 challenge: challenge.clone(),
-                difficulty: self.hanshake_puzzle_difficulty,
+                difficulty,
             },
         );
         self.pending_in_peers
@@ -183,7 +227,7 @@ impl<TSubstream> Gatekeeper<TSubstream> {
             peer_id,
             event: GatekeeperSendEvent::Send(GatekeeperMessage::ChallengeReply {
                 challenge,
-                difficulty: self.hanshake_puzzle_difficulty,
+                difficulty,
             }),
         })
     }
@@ -233,6 +277,7 @@ impl<TSubstream> Gatekeeper<TSubstream> {
             && local_check_proof(&proof, challenge.difficulty)
         {
             debug!(target: "stegos_network::gatekeeper", "unlock request with valid proof, peer_id={}", peer_id);
+            metrics::PUZZLES_SOLVED.inc();
             self.unlocked_peers.insert(peer_id.clone().into(), ());
             self.pending_in_peers
                 .insert(peer_id.clone(), ListenerPeerState::WaitingDialer);
@@ -306,6 +351,13 @@ where
     fn inject_connected(&mut self, id: PeerId, cp: ConnectedPoint) {
         debug!(target: "stegos_network::gatekeeper", "peer connected: peer_id={}, endpoint={}", id, cp.display());
         self.connected_peers.insert(id.clone());
+        if let ConnectedPoint::Listener { send_back_addr, .. } = &cp {
+            if let Some(ip) = multiaddr_ip(send_back_addr) {
+                self.inbound_ips.insert(id.clone(), ip);
+            }
+            self.difficulty.attempt(Instant::now());
+            metrics::INBOUND_ATTEMPT_RATE.set(self.difficulty.attempt_rate() as i64);
+        }
         // FIXME: use LRU cache for dialing addresses/peers
         if let ConnectedPoint::Dialer { address } = cp {
             if self.desired_addesses.contains(&address) {
@@ -331,6 +383,7 @@ where
     fn inject_disconnected(&mut self, id: &PeerId, cp: ConnectedPoint) {
         debug!(target: "stegos_network::gatekeeper", "peer disconnected: peer_id={}, endpoint={}", id, cp.display());
         self.connected_peers.remove(id);
+        self.inbound_ips.remove(id);
         self.pending_out_peers.remove(&id.clone().into());
         self.pending_in_peers.remove(&id.clone().into());
         self.events.push_back(NetworkBehaviourAction::GenerateEvent(
@@ -426,6 +479,7 @@ where
         match self.solution_stream.poll() {
             Ok(Async::Ready(Some((peer_id, proof, duration)))) => {
                 self.solvers.remove(&peer_id);
+                metrics::LOCAL_PUZZLES_SOLVED.inc();
                 debug!(target: "stegos_network::gatekeeper", "solved puzzle: peer_id={}, duration={}.{}sec", peer_id, duration.as_secs(), duration.subsec_millis());
                 self.protocol_updates.push_back(PeerEvent::VDFSolved {
                     peer_id: peer_id.clone(),
@@ -594,6 +648,14 @@ fn local_check_proof(proof: &VDFProof, difficulty: u64) -> bool {
     true
 }
 
+fn multiaddr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(ip.into()),
+        Protocol::Ip6(ip) => Some(ip.into()),
+        _ => None,
+    }
+}
+
 fn generate_challenge(_peer_id: &PeerId) -> Vec<u8> {
     let key = (0..256).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
     key
@@ -621,6 +683,10 @@ pub enum GatekeeperOutEvent {
     Finished {
         peer_id: PeerId,
     },
+    /// The handshake is refused, the peer should be disconnected.
+    Rejected {
+        peer_id: PeerId,
+    },
     NetworkReady,
 }
 
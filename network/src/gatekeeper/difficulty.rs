@@ -0,0 +1,207 @@
+//
+// MIT License
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Adaptive difficulty of handshake puzzles.
+//!
+//! The load is the largest of the inbound connection rate relative to
+//! `normal_attempt_rate` and the number of connections relative to
+//! `max_connections`. The difficulty doubles when the load is above
+//! `HIGH_LOAD` and halves when it is below `LOW_LOAD`, at most once per
+//! `ADJUSTMENT_INTERVAL`, staying within `[min, max]`.
+//!
+
+use crate::config::PuzzleDifficulty;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Window to measure the rate of inbound connections.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Maximal number of remembered inbound connections, relative to `normal_attempt_rate`.
+/// The load is high anyway above `HIGH_LOAD`, so a flood doesn't grow the window.
+const MAX_ATTEMPTS_FACTOR: u64 = 4;
+/// Minimal time between adjustments.
+const ADJUSTMENT_INTERVAL: Duration = Duration::from_secs(10);
+/// Raise the difficulty above this load.
+const HIGH_LOAD: f64 = 1.0;
+/// Lower the difficulty below this load.
+const LOW_LOAD: f64 = 0.5;
+
+pub(crate) struct DifficultyController {
+    min: u64,
+    max: u64,
+    /// Inbound connections per minute considered normal.
+    normal_attempt_rate: u64,
+    max_connections: usize,
+    difficulty: u64,
+    /// Recent inbound connections.
+    attempts: VecDeque<Instant>,
+    adjusted: Instant,
+}
+
+impl DifficultyController {
+    pub fn new(
+        initial: u64,
+        config: &PuzzleDifficulty,
+        max_connections: usize,
+        now: Instant,
+    ) -> Self {
+        let lower = min(config.min, config.max);
+        let upper = max(config.min, config.max);
+        DifficultyController {
+            min: lower,
+            max: upper,
+            normal_attempt_rate: config.normal_attempt_rate,
+            max_connections,
+            difficulty: min(max(initial, lower), upper),
+            attempts: VecDeque::new(),
+            adjusted: now,
+        }
+    }
+
+    /// Accounts an inbound connection.
+    pub fn attempt(&mut self, now: Instant) {
+        self.attempts.push_back(now);
+        self.expire(now);
+        let max_attempts = max(self.normal_attempt_rate * MAX_ATTEMPTS_FACTOR, 1) as usize;
+        while self.attempts.len() > max_attempts {
+            self.attempts.pop_front();
+        }
+    }
+
+    /// Inbound connections in the last `RATE_WINDOW`.
+    pub fn attempt_rate(&self) -> usize {
+        self.attempts.len()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(first) = self.attempts.front() {
+            if now.duration_since(*first) < RATE_WINDOW {
+                break;
+            }
+            self.attempts.pop_front();
+        }
+    }
+
+    fn load(&self, connections: usize) -> f64 {
+        let rate_load = if self.normal_attempt_rate > 0 {
+            self.attempts.len() as f64 / self.normal_attempt_rate as f64
+        } else {
+            0.0
+        };
+        let connections_load = if self.max_connections > 0 {
+            connections as f64 / self.max_connections as f64
+        } else {
+            0.0
+        };
+        rate_load.max(connections_load)
+    }
+
+    /// Returns the difficulty of a new puzzle, adjusted to the current load.
+    pub fn difficulty(&mut self, connections: usize, now: Instant) -> u64 {
+        self.expire(now);
+        if now.duration_since(self.adjusted) < ADJUSTMENT_INTERVAL {
+            return self.difficulty;
+        }
+        let load = self.load(connections);
+        let difficulty = if load > HIGH_LOAD {
+            min(self.difficulty.saturating_mul(2), self.max)
+        } else if load < LOW_LOAD {
+            max(self.difficulty / 2, self.min)
+        } else {
+            self.difficulty
+        };
+        if difficulty != self.difficulty {
+            self.difficulty = difficulty;
+            self.adjusted = now;
+        }
+        self.difficulty
+    }
+
+    /// Returns the current difficulty without adjustments.
+    pub fn current(&self) -> u64 {
+        self.difficulty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hysteresis() {
+        let config = PuzzleDifficulty {
+            min: 25,
+            max: 400,
+            normal_attempt_rate: 10,
+            max_pending_per_ip: 4,
+        };
+        let mut now = Instant::now();
+        let mut controller = DifficultyController::new(100, &config, 20, now);
+        assert_eq!(controller.difficulty(0, now), 100);
+
+        // Flood: raised once per interval up to the limit.
+        for _ in 0..20 {
+            controller.attempt(now);
+        }
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 200);
+        assert_eq!(controller.difficulty(0, now), 200);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 400);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 400);
+
+        // Moderate load keeps the difficulty.
+        now += RATE_WINDOW;
+        for _ in 0..7 {
+            controller.attempt(now);
+        }
+        assert_eq!(controller.difficulty(0, now), 400);
+        // As well as moderate number of connections.
+        assert_eq!(controller.difficulty(12, now), 400);
+
+        // Idle: lowered down to the limit.
+        now += RATE_WINDOW;
+        assert_eq!(controller.difficulty(0, now), 200);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 100);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 50);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 25);
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(0, now), 25);
+
+        // Connections close to the limit.
+        now += ADJUSTMENT_INTERVAL;
+        assert_eq!(controller.difficulty(21, now), 50);
+
+        // The window is bounded during a flood.
+        for _ in 0..1000 {
+            controller.attempt(now);
+        }
+        assert_eq!(controller.attempt_rate(), 40);
+    }
+}
@@ -0,0 +1,58 @@
+//
+// MIT License
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use lazy_static::lazy_static;
+use prometheus::*;
+
+lazy_static! {
+    pub static ref PUZZLE_DIFFICULTY: IntGauge = register_int_gauge!(
+        "stegos_gatekeeper_puzzle_difficulty",
+        "Difficulty of handshake puzzles issued by us"
+    )
+    .unwrap();
+    pub static ref PUZZLES_ISSUED: IntCounter = register_int_counter!(
+        "stegos_gatekeeper_puzzles_issued",
+        "Handshake puzzles sent to incoming peers"
+    )
+    .unwrap();
+    pub static ref PUZZLES_SOLVED: IntCounter = register_int_counter!(
+        "stegos_gatekeeper_puzzles_solved",
+        "Valid solutions of our puzzles received from peers"
+    )
+    .unwrap();
+    pub static ref LOCAL_PUZZLES_SOLVED: IntCounter = register_int_counter!(
+        "stegos_gatekeeper_local_puzzles_solved",
+        "Puzzles of remote peers solved by us"
+    )
+    .unwrap();
+    pub static ref INBOUND_ATTEMPT_RATE: IntGauge = register_int_gauge!(
+        "stegos_gatekeeper_inbound_attempt_rate",
+        "Inbound connections in the last minute"
+    )
+    .unwrap();
+    pub static ref REJECTED_HANDSHAKES: IntCounter = register_int_counter!(
+        "stegos_gatekeeper_rejected_handshakes",
+        "Inbound handshakes rejected by the per IP limit"
+    )
+    .unwrap();
+}
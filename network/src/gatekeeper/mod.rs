@@ -22,7 +22,9 @@
 // SOFTWARE.
 
 mod behavior;
+mod difficulty;
 mod handler;
+mod metrics;
 mod proto;
 mod protocol;
 
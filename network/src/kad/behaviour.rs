@@ -228,6 +228,34 @@ impl<TSubstream> Kademlia<TSubstream> {
         }
     }
 
+    /// Returns nodes of the routing table with known PeerId and addresses.
+    pub fn routing_table(&self) -> Vec<(pbc::PublicKey, PeerId, Vec<Multiaddr>)> {
+        self.kbuckets
+            .entries()
+            .filter_map(|(node_id, node_info)| {
+                let peer_id = node_info.peer_id.clone()?;
+                let addresses: Vec<Multiaddr> = node_info.addresses.iter().cloned().collect();
+                if addresses.is_empty() {
+                    return None;
+                }
+                Some((node_id.clone(), peer_id, addresses))
+            })
+            .collect()
+    }
+
+    /// Adds a node of the routing table saved before restart.
+    pub fn restore_node(
+        &mut self,
+        node_id: &pbc::PublicKey,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+    ) {
+        self.set_peer_id(node_id, peer_id);
+        for address in addresses {
+            self.add_not_connected_address(node_id, address);
+        }
+    }
+
     /// Inner implementation of the constructors.
     fn new_inner(local_node_id: pbc::PublicKey, initialize: bool) -> Self {
         let parallelism = 3;
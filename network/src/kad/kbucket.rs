@@ -283,6 +283,13 @@ where
         None
     }
 
+    /// Returns all nodes of the table with their values.
+    pub fn entries(&self) -> impl Iterator<Item = (&TPeerId, &TVal)> {
+        self.tables
+            .iter()
+            .flat_map(|table| table.nodes.iter().map(|node| (&node.id, &node.value)))
+    }
+
     /// Returns the value associated to a node if any is present. Otherwise, tries to add the
     /// node to the table in a disconnected state and return its value. Returns `None` if `id` is
     /// the local peer, or if the table is full.
@@ -196,18 +196,14 @@ where
                         .map_err(|err| IoError::new(IoErrorKind::InvalidData, err.to_string()));
                     match res {
                         Ok(bytes) => {
-                            metrics::OUTGOING_TRAFFIC
-                                .with_label_values(&[&PROTOCOL_LABEL])
-                                .inc_by(bytes.len() as i64);
+                            metrics::outgoing(PROTOCOL_LABEL, bytes.len());
                             Ok(bytes)
                         }
                         Err(e) => Err(e),
                     }
                 })
                 .and_then::<fn(_) -> _, _>(|bytes: BytesMut| {
-                    metrics::INCOMING_TRAFFIC
-                        .with_label_values(&[&PROTOCOL_LABEL])
-                        .inc_by(bytes.len() as i64);
+                    metrics::incoming(PROTOCOL_LABEL, bytes.len());
                     let request = protobuf::parse_from_bytes(&bytes)?;
                     proto_to_req_msg(request)
                 }),
@@ -235,18 +231,14 @@ where
                     let proto_struct = req_msg_to_proto(request);
                     match proto_struct.write_to_bytes() {
                         Ok(msg) => {
-                            metrics::OUTGOING_TRAFFIC
-                                .with_label_values(&[&PROTOCOL_LABEL])
-                                .inc_by(msg.len() as i64);
+                            metrics::outgoing(PROTOCOL_LABEL, msg.len());
                             Ok(msg)
                         }
                         Err(err) => Err(IoError::new(IoErrorKind::Other, err.to_string())),
                     }
                 })
                 .and_then::<fn(_) -> _, _>(|bytes: BytesMut| {
-                    metrics::INCOMING_TRAFFIC
-                        .with_label_values(&[&PROTOCOL_LABEL])
-                        .inc_by(bytes.len() as i64);
+                    metrics::incoming(PROTOCOL_LABEL, bytes.len());
                     let response = protobuf::parse_from_bytes(&bytes)?;
                     proto_to_resp_msg(response)
                 }),
@@ -24,6 +24,7 @@
 #![allow(dead_code)]
 #![deny(warnings)]
 
+mod bandwidth;
 mod config;
 mod delivery;
 mod discovery;
@@ -33,15 +34,20 @@ mod libp2p_network;
 pub mod loopback;
 mod metrics;
 mod ncp;
+mod network_key;
 mod pubsub;
 mod replication;
+mod reputation;
+pub mod simulation;
 mod utils;
 
 use failure::{Error, Fail};
 use futures::sync::{mpsc, oneshot};
 use std::fmt;
+use std::sync::Arc;
 use stegos_crypto::pbc;
 
+pub use self::bandwidth::{BandwidthStats, TrafficStats};
 pub use self::config::*;
 pub use self::kad::KBucketsPeerId;
 pub use self::libp2p_network::Libp2pNetwork;
@@ -51,7 +57,10 @@ pub use self::libp2p_network::NETWORK_IDLE_TIMEOUT;
 pub use self::libp2p_network::NETWORK_READY_TOKEN;
 pub use self::libp2p_network::NETWORK_STATUS_TOPIC;
 pub use self::ncp::NodeInfo;
+pub use self::network_key::{unicast_signing_hash, LocalNetworkKey, NetworkKey};
+pub use self::pubsub::MessageId;
 pub use self::replication::ReplicationEvent;
+pub use self::reputation::{Misbehavior, PeerReputation};
 pub use self::utils::IntoMultihash;
 
 pub type Network = Box<dyn NetworkProvider + Send>;
@@ -63,6 +72,16 @@ where
     /// Subscribe to topic, returns Stream<Vec<u8>> of messages incoming to topic
     fn subscribe(&self, topic: &str) -> Result<mpsc::UnboundedReceiver<Vec<u8>>, Error>;
 
+    /// Subscribe to topic, which messages are forwarded to other peers only
+    /// after the subscriber has accepted them by `validate()`.
+    fn subscribe_validated(
+        &self,
+        topic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<PubsubMessage>, Error>;
+
+    /// Report the result of validation of a message from `subscribe_validated()`.
+    fn validate(&self, id: MessageId, result: ValidationResult) -> Result<(), Error>;
+
     /// Published message to topic
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error>;
 
@@ -75,6 +94,15 @@ where
     /// Send unicast message to peer identified by network public key
     fn send(&self, dest: pbc::PublicKey, protocol_id: &str, data: Vec<u8>) -> Result<(), Error>;
 
+    /// Send unicast message, which is acknowledged by the recipient and resent until
+    /// acknowledged or out of attempts. The returned future resolves with the outcome.
+    fn send_reliable(
+        &self,
+        dest: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error>;
+
     /// Connect to a replication upstream.
     fn replication_connect(&self, peer_id: PeerId) -> Result<(), Error>;
 
@@ -83,15 +111,35 @@ where
     /// Request list of connected nodes
     fn list_connected_nodes(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error>;
 
+    /// Network keys of nodes connected by outbound connections, i.e. dialed by this node.
+    fn outbound_nodes(&self) -> Vec<pbc::PublicKey>;
+
+    /// Lower the reputation of a node which has sent an invalid message.
+    fn report_peer(&self, node_id: pbc::PublicKey, misbehavior: Misbehavior) -> Result<(), Error>;
+
+    /// Ban a peer until `unban_peer()`.
+    fn ban_peer(&self, peer_id: PeerId) -> Result<(), Error>;
+
+    /// Lift a permanent or temporary ban.
+    fn unban_peer(&self, peer_id: PeerId) -> Result<(), Error>;
+
+    /// Exempt a peer from reputation scoring and bans.
+    fn allow_peer(&self, peer_id: PeerId) -> Result<(), Error>;
+
+    /// Remove a peer from the allow list.
+    fn disallow_peer(&self, peer_id: PeerId) -> Result<(), Error>;
+
+    /// Request reputation of scored, banned and allowed peers
+    fn list_peer_reputation(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error>;
+
+    /// Request traffic per protocol, pubsub topic and peer
+    fn bandwidth_stats(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error>;
+
     /// Helper for cloning boxed object
     fn box_clone(&self) -> Network;
 
     /// Change network keys
-    fn change_network_keys(
-        &self,
-        _new_pkey: pbc::PublicKey,
-        _new_skey: pbc::SecretKey,
-    ) -> Result<(), Error>;
+    fn change_network_keys(&self, new_key: Arc<dyn NetworkKey>) -> Result<(), Error>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -100,9 +148,38 @@ pub struct UnicastMessage {
     pub data: Vec<u8>,
 }
 
+/// A broadcast message waiting for validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PubsubMessage {
+    pub id: MessageId,
+    pub data: Vec<u8>,
+}
+
+/// Result of validation of a broadcast message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationResult {
+    /// The message is valid and forwarded to other peers.
+    Accept,
+    /// The message is invalid, the sender is penalized.
+    Reject,
+    /// The message is dropped without penalty, e.g. when it can't be validated now.
+    Ignore,
+}
+
+/// Outcome of `send_reliable()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// The recipient has acknowledged the message.
+    Delivered,
+    /// The message has not been acknowledged after all attempts.
+    Failed,
+}
+
 #[derive(Debug, Clone)]
 pub enum NetworkResponse {
     ConnectedNodes { nodes: Vec<NodeInfo> },
+    PeerReputation { peers: Vec<PeerReputation> },
+    BandwidthStats { stats: BandwidthStats },
 }
 
 impl Clone for Network {
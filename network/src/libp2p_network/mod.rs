@@ -21,9 +21,11 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use byteorder::{BigEndian, ByteOrder};
 use failure::{format_err, Error};
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
+use futures::task::AtomicTask;
 use libp2p;
 pub use libp2p_core::multiaddr::Multiaddr;
 pub use libp2p_core::PeerId;
@@ -38,32 +40,87 @@ use protobuf::Message as ProtoMessage;
 use smallvec::SmallVec;
 use std::collections::{HashMap, HashSet};
 use std::error;
+use std::fmt;
 use std::io;
 use std::net::{SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use stegos_crypto::hash::{Hashable, Hasher};
 use stegos_crypto::pbc;
 use stegos_crypto::utils::u8v_to_hexstr;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::config::NetworkConfig;
+use crate::bandwidth::{self, CountingMuxer};
+use crate::config::{MessagePriority, NetworkConfig};
 use crate::delivery::{Delivery, DeliveryEvent, DeliveryMessage};
 use crate::discovery::{Discovery, DiscoveryOutEvent};
 use crate::gatekeeper::{Gatekeeper, GatekeeperOutEvent, PeerEvent};
+use crate::metrics;
 use crate::ncp::{Ncp, NcpOutEvent};
 use crate::pubsub::{Floodsub, FloodsubEvent};
 use crate::replication::{Replication, ReplicationEvent};
-use crate::{Network, NetworkProvider, NetworkResponse, UnicastMessage};
+use crate::reputation::{Reputation, Verdict};
+use crate::{
+    unicast_signing_hash, DeliveryStatus, MessageId, Misbehavior, Network, NetworkKey,
+    NetworkProvider, NetworkResponse, PubsubMessage, UnicastMessage, ValidationResult,
+};
 
 mod proto;
+mod reliable;
+mod socks5;
 use self::proto::unicast_proto;
-use crate::utils::socket_to_multi_addr;
+use self::reliable::{ReliableUnicast, ACK_PROTOCOL_ID};
+use crate::utils::{socket_to_multi_addr, PriorityQueue, RateLimiter};
 use std::str::FromStr;
 use trust_dns_resolver::config::{NameServerConfig, Protocol};
 
 #[derive(Clone, Debug)]
 pub struct Libp2pNetwork {
     control_tx: mpsc::UnboundedSender<ControlMessage>,
+    /// Outgoing messages of normal and low priority.
+    outbound: Arc<OutboundQueue>,
+    topic_priorities: Arc<HashMap<String, MessagePriority>>,
+    /// Nodes connected by outbound connections, updated by the network service.
+    outbound_nodes: Arc<Mutex<Vec<pbc::PublicKey>>>,
+}
+
+/// Outgoing messages of normal and low priority, waiting for the network service.
+/// High priority messages and requests go through the control channel and are never dropped.
+struct OutboundQueue {
+    queue: Mutex<PriorityQueue<ControlMessage>>,
+    task: AtomicTask,
+}
+
+impl OutboundQueue {
+    fn new(limit: usize) -> Self {
+        OutboundQueue {
+            queue: Mutex::new(PriorityQueue::new(limit)),
+            task: AtomicTask::new(),
+        }
+    }
+
+    /// Enqueue a message, the oldest message of the class is dropped if the queue is full.
+    fn push(&self, priority: MessagePriority, msg: ControlMessage) {
+        let dropped = self.queue.lock().unwrap().push(priority, msg);
+        if dropped.is_some() {
+            debug!(target: "stegos_network", "outbound queue is full, dropped the oldest message: priority={:?}", priority);
+            metrics::DROPPED_MESSAGES
+                .with_label_values(&["outbound", priority.label()])
+                .inc();
+        }
+        self.task.notify();
+    }
+
+    fn pop(&self) -> Option<ControlMessage> {
+        self.queue.lock().unwrap().pop()
+    }
+}
+
+impl fmt::Debug for OutboundQueue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OutboundQueue")
+            .field("len", &self.queue.lock().unwrap().len())
+            .finish()
+    }
 }
 
 // Allow connection to terminate after that much idle time
@@ -75,10 +132,16 @@ pub const NETWORK_READY_TOKEN: &'static [u8] = &[1, 0, 0, 0];
 const IBE_ID: &'static [u8] = &[105u8, 13, 185, 148, 68, 76, 69, 155];
 
 impl Libp2pNetwork {
+    ///
+    /// Creates a new network service.
+    ///
+    /// `peer_skey` is the secret of the libp2p identity of the node.
+    /// It's the network secret key, unless that one is kept by a remote signer.
+    ///
     pub fn new(
         mut config: NetworkConfig,
-        network_skey: pbc::SecretKey,
-        network_pkey: pbc::PublicKey,
+        network_key: Arc<dyn NetworkKey>,
+        peer_skey: &pbc::SecretKey,
     ) -> Result<
         (
             Network,
@@ -88,16 +151,51 @@ impl Libp2pNetwork {
         ),
         Error,
     > {
+        let proxy = parse_proxy(&config)?;
         // Resolve network.seed_pool.
-        config
-            .seed_nodes
-            .extend_from_slice(&resolve_seed_nodes(&config.seed_pool, &config.dns_servers)?);
+        let seed_nodes = match &proxy {
+            Some(proxy) => {
+                resolve_seed_nodes_via_proxy(&config.seed_pool, &config.dns_servers, proxy)
+            }
+            None => resolve_seed_nodes(&config.seed_pool, &config.dns_servers),
+        };
+        match seed_nodes {
+            Ok(seed_nodes) => config.seed_nodes.extend_from_slice(&seed_nodes),
+            // Known peers from the previous run are enough to join the network.
+            Err(e) if config.peer_store_file.exists() || config.routing_table_file.exists() => {
+                warn!("Failed to resolve seed nodes, using known peers: {}", e);
+            }
+            Err(e) => return Err(e),
+        }
 
-        let (service, control_tx, peer_id, replication_rx) =
-            new_service(&config, network_skey, network_pkey)?;
-        let network = Libp2pNetwork { control_tx };
+        let outbound = Arc::new(OutboundQueue::new(config.priority_queue_size));
+        let outbound_nodes = Arc::new(Mutex::new(Vec::new()));
+        let (service, control_tx, peer_id, replication_rx) = new_service(
+            &config,
+            proxy,
+            network_key,
+            peer_skey,
+            outbound.clone(),
+            outbound_nodes.clone(),
+        )?;
+        let topic_priorities = Arc::new(config.topic_priorities.clone());
+        let network = Libp2pNetwork {
+            control_tx,
+            outbound,
+            topic_priorities,
+            outbound_nodes,
+        };
         Ok((Box::new(network), service, peer_id, replication_rx))
     }
+
+    /// Passes an outgoing message to the network service according to the priority class.
+    fn send_outbound(&self, topic: &str, msg: ControlMessage) -> Result<(), Error> {
+        match topic_priority(&self.topic_priorities, topic) {
+            MessagePriority::High => self.control_tx.unbounded_send(msg)?,
+            priority => self.outbound.push(priority, msg),
+        }
+        Ok(())
+    }
 }
 
 impl NetworkProvider for Libp2pNetwork {
@@ -110,15 +208,30 @@ impl NetworkProvider for Libp2pNetwork {
         Ok(rx)
     }
 
+    fn subscribe_validated(
+        &self,
+        topic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<PubsubMessage>, Error> {
+        let topic: String = topic.into();
+        let (tx, rx) = mpsc::unbounded();
+        let msg = ControlMessage::SubscribeValidated { topic, handler: tx };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(rx)
+    }
+
+    fn validate(&self, id: MessageId, result: ValidationResult) -> Result<(), Error> {
+        let msg = ControlMessage::Validate { id, result };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
     /// Published message to topic
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
-        let topic: String = topic.clone().into();
         let msg = ControlMessage::Publish {
-            topic: topic.clone().into(),
+            topic: topic.into(),
             data,
         };
-        self.control_tx.unbounded_send(msg)?;
-        Ok(())
+        self.send_outbound(topic, msg)
     }
 
     // Subscribe to unicast messages
@@ -138,14 +251,31 @@ impl NetworkProvider for Libp2pNetwork {
 
     // Send direct message to public key
     fn send(&self, to: pbc::PublicKey, protocol_id: &str, data: Vec<u8>) -> Result<(), Error> {
-        let protocol_id: String = protocol_id.clone().into();
         let msg = ControlMessage::SendUnicast {
+            to,
+            protocol_id: protocol_id.into(),
+            data,
+        };
+        self.send_outbound(protocol_id, msg)
+    }
+
+    // Send direct message to public key and wait for acknowledgement
+    fn send_reliable(
+        &self,
+        to: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error> {
+        let protocol_id: String = protocol_id.clone().into();
+        let (tx, rx) = oneshot::channel::<DeliveryStatus>();
+        let msg = ControlMessage::SendReliableUnicast {
             to,
             protocol_id,
             data,
+            tx,
         };
         self.control_tx.unbounded_send(msg)?;
-        Ok(())
+        Ok(rx)
     }
 
     fn replication_connect(&self, peer_id: PeerId) -> Result<(), Error> {
@@ -167,27 +297,86 @@ impl NetworkProvider for Libp2pNetwork {
         Ok(rx)
     }
 
+    fn outbound_nodes(&self) -> Vec<pbc::PublicKey> {
+        self.outbound_nodes.lock().unwrap().clone()
+    }
+
+    fn report_peer(&self, node_id: pbc::PublicKey, misbehavior: Misbehavior) -> Result<(), Error> {
+        let msg = ControlMessage::ReportPeer {
+            node_id,
+            misbehavior,
+        };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn ban_peer(&self, peer_id: PeerId) -> Result<(), Error> {
+        let msg = ControlMessage::BanPeer { peer_id };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn unban_peer(&self, peer_id: PeerId) -> Result<(), Error> {
+        let msg = ControlMessage::UnbanPeer { peer_id };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn allow_peer(&self, peer_id: PeerId) -> Result<(), Error> {
+        let msg = ControlMessage::AllowPeer { peer_id };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn disallow_peer(&self, peer_id: PeerId) -> Result<(), Error> {
+        let msg = ControlMessage::DisallowPeer { peer_id };
+        self.control_tx.unbounded_send(msg)?;
+        Ok(())
+    }
+
+    fn list_peer_reputation(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        self.control_tx
+            .unbounded_send(ControlMessage::PeerReputationRequest { tx })?;
+        Ok(rx)
+    }
+
+    fn bandwidth_stats(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        // Counters are global, no need to ask the swarm.
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        let stats = bandwidth::stats();
+        if let Err(_v) = tx.send(NetworkResponse::BandwidthStats { stats }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
     // Clone self as a box
     fn box_clone(&self) -> Network {
         Box::new((*self).clone())
     }
 
     // Switch to new network keys
-    fn change_network_keys(
-        &self,
-        new_pkey: pbc::PublicKey,
-        new_skey: pbc::SecretKey,
-    ) -> Result<(), Error> {
-        let msg = ControlMessage::ChangeNetworkKeys { new_pkey, new_skey };
+    fn change_network_keys(&self, new_key: Arc<dyn NetworkKey>) -> Result<(), Error> {
+        let msg = ControlMessage::ChangeNetworkKeys { new_key };
         self.control_tx.unbounded_send(msg)?;
         Ok(())
     }
 }
 
+/// Priority class of a pubsub topic or an unicast protocol, normal by default.
+fn topic_priority(priorities: &HashMap<String, MessagePriority>, topic: &str) -> MessagePriority {
+    priorities.get(topic).cloned().unwrap_or_default()
+}
+
 fn new_service(
     config: &NetworkConfig,
-    network_skey: pbc::SecretKey,
-    network_pkey: pbc::PublicKey,
+    proxy: Option<SocketAddr>,
+    network_key: Arc<dyn NetworkKey>,
+    peer_skey: &pbc::SecretKey,
+    outbound: Arc<OutboundQueue>,
+    outbound_nodes: Arc<Mutex<Vec<pbc::PublicKey>>>,
 ) -> Result<
     (
         impl Future<Item = (), Error = ()>,
@@ -197,21 +386,21 @@ fn new_service(
     ),
     Error,
 > {
-    let keypair = ed25519_from_pbc(&network_skey);
+    let keypair = ed25519_from_pbc(peer_skey);
     let local_key = identity::Keypair::Ed25519(keypair);
     let local_pub_key = local_key.public();
     let peer_id = local_pub_key.clone().into_peer_id();
 
     // Set up a an encrypted DNS-enabled TCP Transport over the Mplex and Yamux protocols
-    let transport = build_tcp_ws_secio_yamux(local_key);
+    let transport = build_tcp_ws_secio_yamux(local_key, proxy);
 
     // Create a Swarm to manage peers and events
     let (behaviour, replication_rx) =
-        Libp2pBehaviour::new(config, network_skey, network_pkey, peer_id.clone());
+        Libp2pBehaviour::new(config, network_key, peer_id.clone(), outbound_nodes)?;
 
     let mut swarm = Swarm::new(transport, behaviour, peer_id.clone());
 
-    if config.endpoint != "" {
+    if config.endpoint != "" && !config.proxy.disable_listener {
         let endpoint = SocketAddr::from_str(&config.endpoint).expect("Invalid endpoint");
         let endpoint = socket_to_multi_addr(&endpoint);
         Swarm::listen_on(&mut swarm, endpoint).unwrap();
@@ -229,6 +418,14 @@ fn new_service(
             }
         }
 
+        // Register before draining to not miss a notification.
+        outbound.task.register();
+        while let Some(msg) = outbound.pop() {
+            swarm.process_event(msg);
+        }
+
+        swarm.resend_unicasts();
+
         loop {
             match swarm.poll().expect("Error while polling swarm") {
                 Async::Ready(Some(_)) => {}
@@ -260,15 +457,27 @@ pub struct Libp2pBehaviour<TSubstream: AsyncRead + AsyncWrite> {
     #[behaviour(ignore)]
     consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<Vec<u8>>; 3]>>,
     #[behaviour(ignore)]
+    validated_consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<PubsubMessage>; 3]>>,
+    #[behaviour(ignore)]
     unicast_consumers: HashMap<String, SmallVec<[mpsc::UnboundedSender<UnicastMessage>; 3]>>,
     #[behaviour(ignore)]
     replication_tx: mpsc::UnboundedSender<ReplicationEvent>,
     #[behaviour(ignore)]
     my_pkey: pbc::PublicKey,
     #[behaviour(ignore)]
-    my_skey: pbc::SecretKey,
+    my_key: Arc<dyn NetworkKey>,
     #[behaviour(ignore)]
     connected_peers: HashSet<PeerId>,
+    #[behaviour(ignore)]
+    reputation: Reputation,
+    #[behaviour(ignore)]
+    topic_priorities: HashMap<String, MessagePriority>,
+    #[behaviour(ignore)]
+    reliable: ReliableUnicast,
+    #[behaviour(ignore)]
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    #[behaviour(ignore)]
+    outbound_nodes: Arc<Mutex<Vec<pbc::PublicKey>>>,
 }
 
 impl<TSubstream> Libp2pBehaviour<TSubstream>
@@ -277,10 +486,11 @@ where
 {
     pub fn new(
         config: &NetworkConfig,
-        network_skey: pbc::SecretKey,
-        network_pkey: pbc::PublicKey,
+        network_key: Arc<dyn NetworkKey>,
         peer_id: PeerId,
-    ) -> (Self, mpsc::UnboundedReceiver<ReplicationEvent>) {
+        outbound_nodes: Arc<Mutex<Vec<pbc::PublicKey>>>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ReplicationEvent>), Error> {
+        let network_pkey = network_key.pkey();
         let relaying = if config.advertised_endpoint == "".to_string() {
             false
         } else {
@@ -288,22 +498,29 @@ where
         };
 
         let (replication_tx, replication_rx) = mpsc::unbounded::<ReplicationEvent>();
+        let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(&config.rate_limits)));
         let behaviour = Libp2pBehaviour {
-            floodsub: Floodsub::new(peer_id.clone(), relaying),
+            floodsub: Floodsub::new(config, peer_id.clone(), relaying, rate_limiter.clone()),
             ncp: Ncp::new(config, network_pkey.clone()),
             gatekeeper: Gatekeeper::new(config),
-            delivery: Delivery::new(),
-            discovery: Discovery::new(network_pkey.clone()),
-            replication: Replication::new(),
+            delivery: Delivery::new(config),
+            discovery: Discovery::new(config, network_pkey.clone()),
+            replication: Replication::new(rate_limiter.clone()),
             replication_tx,
             consumers: HashMap::new(),
+            validated_consumers: HashMap::new(),
             unicast_consumers: HashMap::new(),
             my_pkey: network_pkey.clone(),
-            my_skey: network_skey.clone(),
+            my_key: network_key,
             connected_peers: HashSet::new(),
+            reputation: Reputation::new(config)?,
+            topic_priorities: config.topic_priorities.clone(),
+            reliable: ReliableUnicast::new(),
+            rate_limiter,
+            outbound_nodes,
         };
         debug!(target: "stegos_network::delivery", "Network endpoints: node_id={}, peer_id={}", network_pkey, peer_id);
-        (behaviour, replication_rx)
+        Ok((behaviour, replication_rx))
     }
 
     fn process_event(&mut self, msg: ControlMessage) {
@@ -329,6 +546,16 @@ where
                     .or_insert(SmallVec::new())
                     .push(handler);
             }
+            ControlMessage::SubscribeValidated { topic, handler } => {
+                self.validated_consumers
+                    .entry(topic.clone())
+                    .or_insert(SmallVec::new())
+                    .push(handler);
+                self.floodsub.subscribe_validated(topic);
+            }
+            ControlMessage::Validate { id, result } => {
+                self.floodsub.validate(id, result);
+            }
             ControlMessage::Publish { topic, data } => {
                 debug!(target: "stegos_network::pubsub",
                     "Sending broadcast message: topic={}, size={}",
@@ -337,12 +564,13 @@ where
                 );
                 self.floodsub.publish(topic, data)
             }
-            ControlMessage::ChangeNetworkKeys { new_pkey, new_skey } => {
+            ControlMessage::ChangeNetworkKeys { new_key } => {
+                let new_pkey = new_key.pkey();
                 debug!(target: "stegos_network::libp2p_network","changing network key: from={}, to={}", self.my_pkey, new_pkey);
                 self.ncp.change_network_key(new_pkey.clone());
                 self.discovery.change_network_key(new_pkey.clone());
                 self.my_pkey = new_pkey;
-                self.my_skey = new_skey;
+                self.my_key = new_key;
             }
             ControlMessage::SubscribeUnicast {
                 protocol_id,
@@ -357,44 +585,22 @@ where
                 to,
                 protocol_id,
                 data,
+            } => self.send_unicast(to, protocol_id, data, 0),
+            ControlMessage::SendReliableUnicast {
+                to,
+                protocol_id,
+                data,
+                tx,
             } => {
-                debug!(target: "stegos_network::delivery",
-                    "Sending unicast message: to={}, from={}, protocol={}, size={}",
-                    to,
-                    self.my_pkey,
-                    protocol_id,
-                    data.len(),
-                );
-
                 if to == self.my_pkey {
-                    let msg = UnicastMessage {
-                        from: to.clone(),
-                        data,
-                    };
-                    self.unicast_consumers
-                        .entry(protocol_id)
-                        .or_insert(SmallVec::new())
-                        .retain({
-                            move |c| {
-                                if let Err(e) = c.unbounded_send(msg.clone()) {
-                                    error!("Error sending data to consumer: {}", e);
-                                    false
-                                } else {
-                                    true
-                                }
-                            }
-                        })
-                } else {
-                    let payload = UnicastPayload {
-                        from: self.my_pkey.clone(),
-                        to: to.clone(),
-                        protocol_id,
-                        data,
-                    };
-                    let msg = encode_unicast(payload, &self.my_skey);
-                    // self.floodsub.publish(floodsub_topic, msg);
-                    self.discovery.deliver_unicast(&to, msg);
+                    self.send_unicast(to, protocol_id, data, 0);
+                    let _ = tx.send(DeliveryStatus::Delivered);
+                    return;
                 }
+                let seq = self
+                    .reliable
+                    .send(to.clone(), protocol_id.clone(), data.clone(), tx);
+                self.send_unicast(to, protocol_id, data, seq);
             }
             ControlMessage::EnableReplicationUpstream { peer_id } => {
                 self.replication.connect(peer_id);
@@ -408,12 +614,139 @@ where
                     warn!(target: "stegos_network", "Failed send API response for connected nodes");
                 }
             }
+            ControlMessage::ReportPeer {
+                node_id,
+                misbehavior,
+            } => match self.discovery.peer_id(&node_id) {
+                Some(peer_id) => self.penalize(peer_id, misbehavior),
+                None => {
+                    debug!(target: "stegos_network::reputation", "reported unknown node: node_id={}, misbehavior={:?}", node_id, misbehavior);
+                }
+            },
+            ControlMessage::BanPeer { peer_id } => {
+                info!(target: "stegos_network::reputation", "Banning peer: peer_id={}", peer_id);
+                self.reputation.ban(peer_id.clone());
+                self.save_reputation();
+                if self.connected_peers.contains(&peer_id) {
+                    self.shutdown(&peer_id);
+                }
+            }
+            ControlMessage::UnbanPeer { peer_id } => {
+                info!(target: "stegos_network::reputation", "Unbanning peer: peer_id={}", peer_id);
+                self.reputation.unban(&peer_id);
+                self.save_reputation();
+            }
+            ControlMessage::AllowPeer { peer_id } => {
+                info!(target: "stegos_network::reputation", "Allowing peer: peer_id={}", peer_id);
+                self.reputation.allow(peer_id);
+                self.save_reputation();
+            }
+            ControlMessage::DisallowPeer { peer_id } => {
+                info!(target: "stegos_network::reputation", "Disallowing peer: peer_id={}", peer_id);
+                self.reputation.disallow(&peer_id);
+                self.save_reputation();
+            }
+            ControlMessage::PeerReputationRequest { tx } => {
+                let peers = self.reputation.list();
+                if let Err(_v) = tx.send(NetworkResponse::PeerReputation { peers }) {
+                    warn!(target: "stegos_network", "Failed send API response for peer reputation");
+                }
+            }
+        }
+    }
+
+    fn save_reputation(&self) {
+        if let Err(e) = self.reputation.save() {
+            error!(target: "stegos_network::reputation", "Failed to save bans and allows: {}", e);
         }
     }
 
     fn shutdown(&mut self, peer_id: &PeerId) {
         self.ncp.terminate(peer_id.clone());
     }
+
+    /// Publishes the current outbound nodes for `NetworkProvider::outbound_nodes()`.
+    fn update_outbound_nodes(&mut self) {
+        *self.outbound_nodes.lock().unwrap() = self.ncp.get_outbound_nodes();
+    }
+
+    /// Sends an unicast message, `seq` is non-zero for reliable messages.
+    fn send_unicast(&mut self, to: pbc::PublicKey, protocol_id: String, data: Vec<u8>, seq: u64) {
+        debug!(target: "stegos_network::delivery",
+            "Sending unicast message: to={}, from={}, protocol={}, size={}, seq={}",
+            to,
+            self.my_pkey,
+            protocol_id,
+            data.len(),
+            seq,
+        );
+
+        if to == self.my_pkey {
+            let msg = UnicastMessage {
+                from: to.clone(),
+                data,
+            };
+            self.unicast_consumers
+                .entry(protocol_id)
+                .or_insert(SmallVec::new())
+                .retain({
+                    move |c| {
+                        if let Err(e) = c.unbounded_send(msg.clone()) {
+                            error!("Error sending data to consumer: {}", e);
+                            false
+                        } else {
+                            true
+                        }
+                    }
+                })
+        } else {
+            let priority = self.topic_priority(&protocol_id);
+            let payload = UnicastPayload {
+                from: self.my_pkey.clone(),
+                to: to.clone(),
+                protocol_id,
+                data,
+                seq,
+            };
+            let msg = match encode_unicast(payload, &*self.my_key) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    error!(target: "stegos_network::delivery", "failed to sign unicast message: to={}, error={}", to, e);
+                    return;
+                }
+            };
+            self.discovery.deliver_unicast(&to, msg, priority);
+        }
+    }
+
+    /// Priority class of a pubsub topic or an unicast protocol.
+    fn topic_priority(&self, topic: &str) -> MessagePriority {
+        topic_priority(&self.topic_priorities, topic)
+    }
+
+    /// Resends reliable messages which haven't been acknowledged in time.
+    fn resend_unicasts(&mut self) {
+        for retry in self.reliable.poll() {
+            self.send_unicast(retry.to, retry.protocol_id, retry.data, retry.seq);
+        }
+    }
+
+    /// Lower the score of a peer, disconnect or ban it on threshold.
+    fn penalize(&mut self, peer_id: PeerId, misbehavior: Misbehavior) {
+        match self.reputation.report(&peer_id, misbehavior) {
+            Verdict::Keep => {
+                debug!(target: "stegos_network::reputation", "peer misbehaved: peer_id={}, misbehavior={:?}", peer_id, misbehavior);
+            }
+            Verdict::Disconnect => {
+                warn!(target: "stegos_network::reputation", "Disconnecting misbehaving peer: peer_id={}, misbehavior={:?}", peer_id, misbehavior);
+                self.shutdown(&peer_id);
+            }
+            Verdict::Ban => {
+                warn!(target: "stegos_network::reputation", "Banning misbehaving peer: peer_id={}, misbehavior={:?}", peer_id, misbehavior);
+                self.shutdown(&peer_id);
+            }
+        }
+    }
 }
 
 impl<TSubstream> NetworkBehaviourEventProcess<NcpOutEvent> for Libp2pBehaviour<TSubstream>
@@ -426,13 +759,23 @@ where
                 self.gatekeeper.dial_address(address);
             }
             NcpOutEvent::DialPeer { peer_id } => {
+                if self.reputation.is_banned(&peer_id) {
+                    return;
+                }
                 self.gatekeeper.dial_peer(peer_id);
             }
             NcpOutEvent::Connected { peer_id } => {
+                if self.reputation.is_banned(&peer_id) {
+                    debug!(target: "stegos_network::reputation", "disconnecting banned peer: peer_id={}", peer_id);
+                    self.shutdown(&peer_id);
+                }
                 self.connected_peers.insert(peer_id);
             }
             NcpOutEvent::Disconnected { peer_id } => {
+                self.rate_limiter.lock().unwrap().remove_peer(&peer_id);
+                bandwidth::remove_peer(&peer_id);
                 self.connected_peers.remove(&peer_id);
+                self.update_outbound_nodes();
             }
             NcpOutEvent::DiscoveredPeer {
                 node_id,
@@ -454,6 +797,7 @@ where
                         }
                     }
                 }
+                self.update_outbound_nodes();
             }
         }
     }
@@ -494,6 +838,36 @@ where
                     }
                 })
             }
+            FloodsubEvent::ValidationRequest { id, message } => {
+                debug!(target: "stegos_network::pubsub",
+                       "Received broadcast message for validation: topic={}, id={}, size={}",
+                       &message.topic,
+                       id,
+                       message.data.len(),
+                );
+                let consumers = self
+                    .validated_consumers
+                    .entry(message.topic)
+                    .or_insert(SmallVec::new());
+                let msg = PubsubMessage {
+                    id,
+                    data: message.data,
+                };
+                consumers.retain(move |c| {
+                    if let Err(e) = c.unbounded_send(msg.clone()) {
+                        error!(target: "stegos_network::pubsub", "Error sending data to consumer: {}", e);
+                        false
+                    } else {
+                        true
+                    }
+                })
+            }
+            FloodsubEvent::Misbehaving {
+                peer_id,
+                misbehavior,
+            } => {
+                self.penalize(peer_id, misbehavior);
+            }
             FloodsubEvent::Subscribed { .. } => {}
             FloodsubEvent::Unsubscribed { .. } => {}
         }
@@ -507,17 +881,28 @@ where
     fn inject_event(&mut self, event: GatekeeperOutEvent) {
         match event {
             GatekeeperOutEvent::PrepareListener { peer_id } => {
+                if self.reputation.is_banned(&peer_id) {
+                    self.shutdown(&peer_id);
+                    return;
+                }
                 self.floodsub.enable_incoming(&peer_id);
                 self.gatekeeper
                     .notify(PeerEvent::EnabledListener { peer_id });
             }
             GatekeeperOutEvent::PrepareDialer { peer_id } => {
+                if self.reputation.is_banned(&peer_id) {
+                    self.shutdown(&peer_id);
+                    return;
+                }
                 self.floodsub.enable_outgoing(&peer_id);
                 self.gatekeeper.notify(PeerEvent::EnabledDialer { peer_id });
             }
             GatekeeperOutEvent::Finished { peer_id } => {
                 self.floodsub.enable_outgoing(&peer_id);
             }
+            GatekeeperOutEvent::Rejected { peer_id } => {
+                self.shutdown(&peer_id);
+            }
             GatekeeperOutEvent::NetworkReady => {
                 debug!(target: "stegos_network::gatekeeper", "network is ready");
                 let consumers = self
@@ -540,6 +925,9 @@ where
     fn inject_event(&mut self, event: DiscoveryOutEvent) {
         match event {
             DiscoveryOutEvent::DialPeer { peer_id } => {
+                if self.reputation.is_banned(&peer_id) {
+                    return;
+                }
                 debug!(target: "stegos_network::kad", "connecting to closest peer: {}", peer_id);
                 self.gatekeeper.dial_peer(peer_id);
             }
@@ -574,7 +962,7 @@ where
                                 // send unicast message upstream
                                 if payload.to == self.my_pkey {
                                     let payload = match decrypt_message(
-                                        &self.my_skey,
+                                        &*self.my_key,
                                         payload,
                                         signature,
                                         rval,
@@ -586,11 +974,35 @@ where
                                         }
                                     };
                                     debug!(target: "stegos_network::delivery",
-                                        "Received unicast message: from={}, protocol={} size={}",
+                                        "Received unicast message: from={}, protocol={} size={}, seq={}",
                                         payload.from,
                                         payload.protocol_id,
-                                        payload.data.len()
+                                        payload.data.len(),
+                                        payload.seq,
                                     );
+                                    if payload.protocol_id == ACK_PROTOCOL_ID {
+                                        match decode_ack(&payload.data) {
+                                            Ok(seq) => self.reliable.ack(&payload.from, seq),
+                                            Err(e) => {
+                                                debug!(target: "stegos_network::delivery", "bad ack received: {}", e);
+                                            }
+                                        }
+                                        return;
+                                    }
+                                    if payload.seq != 0 {
+                                        // Acknowledge every copy, since previous acks might be lost.
+                                        let ack = encode_ack(payload.seq);
+                                        self.send_unicast(
+                                            payload.from.clone(),
+                                            ACK_PROTOCOL_ID.to_string(),
+                                            ack,
+                                            0,
+                                        );
+                                        if self.reliable.is_duplicate(&payload.from, payload.seq) {
+                                            debug!(target: "stegos_network::delivery", "got retransmitted unicast message: from={}, seq={}", payload.from, payload.seq);
+                                            return;
+                                        }
+                                    }
                                     let msg = UnicastMessage {
                                         from: payload.from,
                                         data: payload.data,
@@ -617,6 +1029,14 @@ where
                         return;
                     }
                     // Mesage to somebody else, try to route again...
+                    // The priority from the sender is not trusted, derive it from the topic.
+                    let mut unicast = unicast;
+                    unicast.priority = match decode_unicast(unicast.payload.clone()) {
+                        Ok((payload, _signature, _rval)) => {
+                            self.topic_priority(&payload.protocol_id)
+                        }
+                        Err(_e) => MessagePriority::Low,
+                    };
                     let dest = unicast.to.clone();
                     self.discovery.route(&dest, unicast);
                 }
@@ -644,6 +1064,14 @@ pub enum ControlMessage {
         topic: String,
         handler: mpsc::UnboundedSender<Vec<u8>>,
     },
+    SubscribeValidated {
+        topic: String,
+        handler: mpsc::UnboundedSender<PubsubMessage>,
+    },
+    Validate {
+        id: MessageId,
+        result: ValidationResult,
+    },
     Publish {
         topic: String,
         data: Vec<u8>,
@@ -658,8 +1086,7 @@ pub enum ControlMessage {
         consumer: mpsc::UnboundedSender<UnicastMessage>,
     },
     ChangeNetworkKeys {
-        new_pkey: pbc::PublicKey,
-        new_skey: pbc::SecretKey,
+        new_key: Arc<dyn NetworkKey>,
     },
     EnableReplicationUpstream {
         peer_id: PeerId,
@@ -670,6 +1097,25 @@ pub enum ControlMessage {
     ConnectedNodesRequest {
         tx: oneshot::Sender<NetworkResponse>,
     },
+    ReportPeer {
+        node_id: pbc::PublicKey,
+        misbehavior: Misbehavior,
+    },
+    BanPeer {
+        peer_id: PeerId,
+    },
+    UnbanPeer {
+        peer_id: PeerId,
+    },
+    AllowPeer {
+        peer_id: PeerId,
+    },
+    DisallowPeer {
+        peer_id: PeerId,
+    },
+    PeerReputationRequest {
+        tx: oneshot::Sender<NetworkResponse>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -678,24 +1124,24 @@ pub struct UnicastPayload {
     to: pbc::PublicKey,
     protocol_id: String,
     data: Vec<u8>,
+    /// Sequence number of a reliable message, zero otherwise.
+    seq: u64,
 }
 
 // Encode unicast message
-fn encode_unicast(payload: UnicastPayload, sign_key: &pbc::SecretKey) -> Vec<u8> {
+fn encode_unicast(payload: UnicastPayload, key: &dyn NetworkKey) -> Result<Vec<u8>, Error> {
     let mut msg = unicast_proto::Message::new();
 
     // NOTE: ibe_encrypt() can fail if payload.to is an invalid PublicKey
     // It should be checked ahead of this place, using PublicKey::decompress()?
     let enc_packet = pbc::ibe_encrypt(&payload.data, &payload.to, IBE_ID).expect("ok");
 
-    let mut hasher = Hasher::new();
-    payload.from.hash(&mut hasher);
-    payload.to.hash(&mut hasher);
-    payload.protocol_id.hash(&mut hasher);
-    enc_packet.rval().hash(&mut hasher);
-    enc_packet.cmsg().hash(&mut hasher);
-    let hash = hasher.result();
-    let sig = pbc::sign_hash(&hash, sign_key);
+    let sig = key.sign_unicast(
+        &payload.to,
+        &payload.protocol_id,
+        enc_packet.rval(),
+        enc_packet.cmsg(),
+    )?;
 
     msg.set_data(enc_packet.cmsg().to_vec());
     msg.set_rval(enc_packet.rval().to_bytes().to_vec());
@@ -703,9 +1149,11 @@ fn encode_unicast(payload: UnicastPayload, sign_key: &pbc::SecretKey) -> Vec<u8>
     msg.set_to(payload.to.to_bytes().to_vec());
     msg.set_protocol_id(payload.protocol_id.into_bytes().to_vec());
     msg.set_signature(sig.to_bytes().to_vec());
+    msg.set_seq(payload.seq);
 
-    msg.write_to_bytes()
-        .expect("protobuf encoding should never fail")
+    Ok(msg
+        .write_to_bytes()
+        .expect("protobuf encoding should never fail"))
 }
 
 fn decode_unicast(input: Vec<u8>) -> Result<(UnicastPayload, pbc::Signature, pbc::RVal), Error> {
@@ -718,38 +1166,50 @@ fn decode_unicast(input: Vec<u8>) -> Result<(UnicastPayload, pbc::Signature, pbc
     let protocol_id = String::from_utf8(protocol_id_bytes.to_vec())?;
     let data = msg.take_data().to_vec();
     let rval = pbc::RVal::try_from_bytes(&msg.take_rval().to_vec())?;
+    let seq = msg.get_seq();
 
     let payload = UnicastPayload {
         from,
         to,
         protocol_id,
         data,
+        seq,
     };
 
     Ok((payload, signature, rval))
 }
 
+fn encode_ack(seq: u64) -> Vec<u8> {
+    let mut data = vec![0u8; 8];
+    BigEndian::write_u64(&mut data, seq);
+    data
+}
+
+fn decode_ack(data: &[u8]) -> Result<u64, Error> {
+    if data.len() != 8 {
+        return Err(format_err!("Invalid ack length: {}", data.len()));
+    }
+    Ok(BigEndian::read_u64(data))
+}
+
 fn decrypt_message(
-    my_skey: &pbc::SecretKey,
+    key: &dyn NetworkKey,
     mut payload: UnicastPayload,
     signature: pbc::Signature,
     rval: pbc::RVal,
 ) -> Result<UnicastPayload, Error> {
-    let enc_packet = pbc::EncryptedPacket::new(&payload.to, IBE_ID, &rval, &payload.data);
-
-    let mut hasher = Hasher::new();
-    payload.from.hash(&mut hasher);
-    payload.to.hash(&mut hasher);
-    payload.protocol_id.hash(&mut hasher);
-    rval.hash(&mut hasher);
-    payload.data.hash(&mut hasher);
-    let hash = hasher.result();
-
+    let hash = unicast_signing_hash(
+        &payload.from,
+        &payload.to,
+        &payload.protocol_id,
+        &rval,
+        &payload.data,
+    );
     if let Err(_e) = pbc::check_hash(&hash, &signature, &payload.from) {
         return Err(format_err!("Bad packet signature."));
     }
 
-    if let Ok(data) = pbc::ibe_decrypt(&enc_packet, my_skey) {
+    if let Ok(data) = key.decrypt_unicast(IBE_ID, &rval, &payload.data) {
         // if decrypted fine, check the signature
         payload.data = data;
         Ok(payload)
@@ -762,10 +1222,12 @@ fn decrypt_message(
 ///
 /// The implementation supports TCP/IP, WebSockets over TCP/IP, secio as the encryption layer,
 /// and mplex or yamux as the multiplexing layer.
+/// Outgoing connections are made through the SOCKS5 `proxy` if set.
 ///
 /// > **Note**: If you ever need to express the type of this `Transport`.
 pub fn build_tcp_ws_secio_yamux(
     keypair: identity::Keypair,
+    proxy: Option<SocketAddr>,
 ) -> impl Transport<
     Output = (
         PeerId,
@@ -784,11 +1246,14 @@ pub fn build_tcp_ws_secio_yamux(
     let mut mplex_config = libp2p_mplex::MplexConfig::new();
     mplex_config.max_buffer_len_behaviour(libp2p_mplex::MaxBufferBehaviour::Block);
 
-    CommonTransport::new()
+    CommonTransport::new(proxy)
         .upgrade(libp2p_core::upgrade::Version::V1)
         .authenticate(secio::SecioConfig::new(keypair))
         .multiplex(mplex_config)
-        .map(|(peer, muxer), _| (peer, libp2p_core::muxing::StreamMuxerBox::new(muxer)))
+        .map(|(peer, muxer), _| {
+            let muxer = CountingMuxer::new(muxer, &peer);
+            (peer, libp2p_core::muxing::StreamMuxerBox::new(muxer))
+        })
         .timeout(Duration::from_secs(20))
 }
 
@@ -807,16 +1272,23 @@ type InnerImplementation = dns::DnsConfig<tcp::TcpConfig>;
 #[derive(Debug, Clone)]
 struct CommonTransportInner {
     inner: InnerImplementation,
+    tcp: tcp::TcpConfig,
+    proxy: Option<Multiaddr>,
 }
 
 impl CommonTransport {
     /// Initializes the `CommonTransport`.
-    pub fn new() -> CommonTransport {
+    pub fn new(proxy: Option<SocketAddr>) -> CommonTransport {
         let tcp = tcp::TcpConfig::new().nodelay(true);
-        let transport = dns::DnsConfig::new(tcp);
+        let transport = dns::DnsConfig::new(tcp.clone());
+        let proxy = proxy.map(|proxy| socket_to_multi_addr(&proxy));
 
         CommonTransport {
-            inner: CommonTransportInner { inner: transport },
+            inner: CommonTransportInner {
+                inner: transport,
+                tcp,
+                proxy,
+            },
         }
     }
 }
@@ -826,17 +1298,68 @@ impl Transport for CommonTransport {
     type Error = <InnerImplementation as Transport>::Error;
     type Listener = <InnerImplementation as Transport>::Listener;
     type ListenerUpgrade = <InnerImplementation as Transport>::ListenerUpgrade;
-    type Dial = <InnerImplementation as Transport>::Dial;
+    type Dial = Box<dyn Future<Item = Self::Output, Error = Self::Error> + Send>;
 
     fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
         self.inner.inner.listen_on(addr)
     }
 
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
-        self.inner.inner.dial(addr)
+        let proxy = match self.inner.proxy {
+            Some(proxy) => proxy,
+            None => return Ok(Box::new(self.inner.inner.dial(addr)?)),
+        };
+        let target = match socks5::Target::from_multiaddr(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        trace!("Dialing {} through SOCKS5 proxy {}", addr, proxy);
+        let dial = self
+            .inner
+            .tcp
+            .dial(proxy)
+            .map_err(|e| e.map(dns::DnsErr::Underlying))?
+            .and_then(move |stream| socks5::connect(stream, target))
+            .map_err(dns::DnsErr::Underlying);
+        Ok(Box::new(dial))
     }
 }
 
+fn parse_proxy(config: &NetworkConfig) -> Result<Option<SocketAddr>, Error> {
+    if config.proxy.socks5 == "" {
+        return Ok(None);
+    }
+    let proxy = SocketAddr::from_str(&config.proxy.socks5).map_err(|e| {
+        format_err!(
+            "Invalid network.proxy.socks5 '{}': {}",
+            config.proxy.socks5,
+            e
+        )
+    })?;
+    info!("Using SOCKS5 proxy {} for outgoing connections", proxy);
+    Ok(Some(proxy))
+}
+
+/// Resolves seed_pool without local DNS queries, addresses of seeds are resolved by the proxy.
+fn resolve_seed_nodes_via_proxy(
+    seed_pool: &str,
+    dns_servers: &[String],
+    proxy: &SocketAddr,
+) -> Result<Vec<String>, Error> {
+    if seed_pool == "" {
+        return Ok(Vec::new());
+    }
+    let dns_server = dns_servers.first().ok_or_else(|| {
+        format_err!("network.dns_servers is required to resolve seed_pool through the proxy")
+    })?;
+    let dns_server = dns_server.parse::<SocketAddr>()?;
+    info!("Trying to resolve seed nodes SRV records through the proxy.");
+    let seed_nodes = socks5::lookup_srv(proxy, &dns_server, seed_pool)
+        .map_err(|e| format_err!("Failed to resolve seed_pool: {}", e))?;
+    debug!("Resolved seed_nodes addresses = {:?}.", seed_nodes);
+    Ok(seed_nodes)
+}
+
 fn resolve_seed_nodes(seed_pool: &str, dns_servers: &[String]) -> Result<Vec<String>, Error> {
     use trust_dns_resolver::{
         config::{ResolverConfig, ResolverOpts},
@@ -898,6 +1421,7 @@ fn ed25519_from_pbc(source: &pbc::SecretKey) -> ed25519::Keypair {
 #[cfg(test)]
 mod tests {
     use super::UnicastPayload;
+    use crate::LocalNetworkKey;
     use stegos_crypto::pbc;
 
     #[test]
@@ -912,17 +1436,21 @@ mod tests {
             to,
             protocol_id,
             data,
+            seq: 1,
         };
 
-        let encoded = super::encode_unicast(payload.clone(), &from_skey);
+        let from_key = LocalNetworkKey::new(from_skey, from);
+        let to_key = LocalNetworkKey::new(to_skey, to);
+        let encoded = super::encode_unicast(payload.clone(), &from_key).unwrap();
         let (enc_payload, signature, rval) = super::decode_unicast(encoded).unwrap();
         let enc_data = enc_payload.data.clone();
-        let payload_2 = super::decrypt_message(&to_skey, enc_payload, signature, rval).unwrap();
+        let payload_2 = super::decrypt_message(&to_key, enc_payload, signature, rval).unwrap();
 
         assert_eq!(payload.from, payload_2.from);
         assert_eq!(payload.to, payload_2.to);
         assert_eq!(payload.protocol_id, payload_2.protocol_id);
         assert_eq!(payload.data, payload_2.data);
+        assert_eq!(payload.seq, payload_2.seq);
         assert_ne!(payload.data, enc_data);
     }
 
@@ -0,0 +1,287 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Reliable unicast.
+//!
+//! Reliable messages carry a sequence number and are acknowledged by the
+//! recipient with an `ACK_PROTOCOL_ID` unicast. Unacknowledged messages are
+//! resent with exponential backoff until `MAX_ATTEMPTS` is reached.
+//! At most `MAX_PENDING` messages are waiting for acknowledgement, the oldest
+//! ones fail first.
+//!
+
+use crate::DeliveryStatus;
+use futures::prelude::*;
+use futures::sync::oneshot;
+use log::*;
+use lru_time_cache::LruCache;
+use rand::{thread_rng, Rng};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use stegos_crypto::pbc;
+use tokio_timer::Interval;
+
+/// Protocol id of acknowledgements.
+pub const ACK_PROTOCOL_ID: &str = "unicast_ack";
+/// Timeout of the first attempt, doubled on every retry.
+const INITIAL_TIMEOUT: Duration = Duration::from_secs(2);
+/// Total number of attempts before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+/// How often check for expired attempts.
+const RETRY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How long remember received sequence numbers to drop retransmissions.
+const RECEIVED_TTL: Duration = Duration::from_secs(10 * 60);
+/// Maximal number of remembered sequence numbers.
+const RECEIVED_CAPACITY: usize = 100_000;
+/// Maximal number of messages waiting for acknowledgement.
+const MAX_PENDING: usize = 1024;
+
+struct PendingUnicast {
+    to: pbc::PublicKey,
+    protocol_id: String,
+    data: Vec<u8>,
+    attempts: u32,
+    deadline: Instant,
+    tx: oneshot::Sender<DeliveryStatus>,
+}
+
+/// A message to send again.
+pub struct Retry {
+    pub seq: u64,
+    pub to: pbc::PublicKey,
+    pub protocol_id: String,
+    pub data: Vec<u8>,
+}
+
+pub struct ReliableUnicast {
+    /// Sequence number of the next message.
+    next_seq: u64,
+    /// Messages waiting for acknowledgement by sequence number, oldest first.
+    pending: BTreeMap<u64, PendingUnicast>,
+    /// Recently received (sender, sequence number).
+    received: LruCache<(Vec<u8>, u64), ()>,
+    /// Timer to check for expired attempts.
+    retry_timer: Interval,
+}
+
+impl ReliableUnicast {
+    pub fn new() -> Self {
+        ReliableUnicast {
+            // Random start to not clash with sequence numbers used before restart.
+            next_seq: thread_rng().gen_range(1, u64::max_value() / 2),
+            pending: BTreeMap::new(),
+            received: LruCache::with_expiry_duration_and_capacity(RECEIVED_TTL, RECEIVED_CAPACITY),
+            retry_timer: Interval::new_interval(RETRY_CHECK_INTERVAL),
+        }
+    }
+
+    /// Registers a new message, returns its sequence number.
+    pub fn send(
+        &mut self,
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        tx: oneshot::Sender<DeliveryStatus>,
+    ) -> u64 {
+        self.send_at(to, protocol_id, data, tx, Instant::now())
+    }
+
+    fn send_at(
+        &mut self,
+        to: pbc::PublicKey,
+        protocol_id: String,
+        data: Vec<u8>,
+        tx: oneshot::Sender<DeliveryStatus>,
+        now: Instant,
+    ) -> u64 {
+        if self.pending.len() >= MAX_PENDING {
+            let oldest = *self.pending.keys().next().expect("not empty");
+            let pending = self.pending.remove(&oldest).unwrap();
+            debug!(target: "stegos_network::delivery", "too many pending unicasts, giving up: to={}, seq={}", pending.to, oldest);
+            let _ = pending.tx.send(DeliveryStatus::Failed);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let pending = PendingUnicast {
+            to,
+            protocol_id,
+            data,
+            attempts: 1,
+            deadline: now + INITIAL_TIMEOUT,
+            tx,
+        };
+        self.pending.insert(seq, pending);
+        seq
+    }
+
+    /// Processes an acknowledgement.
+    pub fn ack(&mut self, from: &pbc::PublicKey, seq: u64) {
+        match self.pending.get(&seq) {
+            Some(pending) if pending.to == *from => {}
+            _ => {
+                debug!(target: "stegos_network::delivery", "unexpected ack: from={}, seq={}", from, seq);
+                return;
+            }
+        }
+        let pending = self.pending.remove(&seq).unwrap();
+        debug!(target: "stegos_network::delivery", "unicast delivered: to={}, seq={}, attempts={}", from, seq, pending.attempts);
+        let _ = pending.tx.send(DeliveryStatus::Delivered);
+    }
+
+    /// Returns true if the message has already been received.
+    pub fn is_duplicate(&mut self, from: &pbc::PublicKey, seq: u64) -> bool {
+        let key = (from.to_bytes().to_vec(), seq);
+        if self.received.contains_key(&key) {
+            return true;
+        }
+        self.received.insert(key, ());
+        false
+    }
+
+    /// Returns messages to send again, fails messages without attempts left.
+    pub fn poll(&mut self) -> Vec<Retry> {
+        let mut expired = false;
+        loop {
+            match self.retry_timer.poll() {
+                Ok(Async::Ready(Some(_))) => expired = true,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                Err(e) => {
+                    error!(target: "stegos_network::delivery", "Retry timer error: {}", e);
+                    break;
+                }
+            }
+        }
+        if !expired {
+            return Vec::new();
+        }
+        self.retries_at(Instant::now())
+    }
+
+    fn retries_at(&mut self, now: Instant) -> Vec<Retry> {
+        let mut retries = Vec::new();
+        let mut failed = Vec::new();
+        for (seq, pending) in self.pending.iter_mut() {
+            if pending.deadline > now {
+                continue;
+            }
+            if pending.attempts >= MAX_ATTEMPTS {
+                failed.push(*seq);
+                continue;
+            }
+            pending.attempts += 1;
+            pending.deadline = now + INITIAL_TIMEOUT * 2u32.pow(pending.attempts - 1);
+            debug!(target: "stegos_network::delivery", "resending unicast: to={}, seq={}, attempt={}", pending.to, seq, pending.attempts);
+            retries.push(Retry {
+                seq: *seq,
+                to: pending.to.clone(),
+                protocol_id: pending.protocol_id.clone(),
+                data: pending.data.clone(),
+            });
+        }
+        for seq in failed {
+            let pending = self.pending.remove(&seq).unwrap();
+            debug!(target: "stegos_network::delivery", "unicast not delivered: to={}, seq={}", pending.to, seq);
+            let _ = pending.tx.send(DeliveryStatus::Failed);
+        }
+        retries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_and_retries() {
+        let mut reliable = ReliableUnicast::new();
+        let (_, pkey1) = pbc::make_random_keys();
+        let (_, pkey2) = pbc::make_random_keys();
+        let now = Instant::now();
+
+        let (tx, mut delivered) = oneshot::channel();
+        let seq1 = reliable.send_at(pkey1, "test".to_string(), vec![1], tx, now);
+        let (tx, mut failed) = oneshot::channel();
+        let seq2 = reliable.send_at(pkey2, "test".to_string(), vec![2], tx, now);
+        assert_ne!(seq1, seq2);
+        assert!(reliable.retries_at(now).is_empty());
+
+        let retries = reliable.retries_at(now + INITIAL_TIMEOUT);
+        assert_eq!(retries.len(), 2);
+
+        // Acknowledgement from a wrong node is ignored.
+        reliable.ack(&pkey2, seq1);
+        assert_eq!(delivered.try_recv().unwrap(), None);
+        reliable.ack(&pkey1, seq1);
+        assert_eq!(
+            delivered.try_recv().unwrap(),
+            Some(DeliveryStatus::Delivered)
+        );
+
+        let mut now = now + INITIAL_TIMEOUT;
+        for attempt in 2..MAX_ATTEMPTS {
+            now += INITIAL_TIMEOUT * 2u32.pow(attempt - 1);
+            let retries = reliable.retries_at(now);
+            assert_eq!(retries.len(), 1);
+            assert_eq!(retries[0].seq, seq2);
+            assert_eq!(retries[0].data, vec![2]);
+        }
+        assert_eq!(failed.try_recv().unwrap(), None);
+        now += INITIAL_TIMEOUT * 2u32.pow(MAX_ATTEMPTS - 1);
+        assert!(reliable.retries_at(now).is_empty());
+        assert_eq!(failed.try_recv().unwrap(), Some(DeliveryStatus::Failed));
+        assert!(reliable.pending.is_empty());
+    }
+
+    #[test]
+    fn max_pending() {
+        let mut reliable = ReliableUnicast::new();
+        let (_, pkey) = pbc::make_random_keys();
+        let now = Instant::now();
+        let mut receivers = Vec::new();
+        for i in 0..MAX_PENDING + 1 {
+            let (tx, rx) = oneshot::channel();
+            reliable.send_at(pkey, "test".to_string(), vec![i as u8], tx, now);
+            receivers.push(rx);
+        }
+        assert_eq!(reliable.pending.len(), MAX_PENDING);
+        assert_eq!(
+            receivers[0].try_recv().unwrap(),
+            Some(DeliveryStatus::Failed)
+        );
+        for rx in &mut receivers[1..] {
+            assert_eq!(rx.try_recv().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut reliable = ReliableUnicast::new();
+        let (_, pkey1) = pbc::make_random_keys();
+        let (_, pkey2) = pbc::make_random_keys();
+        assert!(!reliable.is_duplicate(&pkey1, 1));
+        assert!(reliable.is_duplicate(&pkey1, 1));
+        assert!(!reliable.is_duplicate(&pkey1, 2));
+        assert!(!reliable.is_duplicate(&pkey2, 1));
+    }
+}
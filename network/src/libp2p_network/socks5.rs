@@ -0,0 +1,430 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! SOCKS5 client (RFC 1928).
+//!
+//! Outgoing connections are tunnelled through a proxy (e.g. a local Tor daemon)
+//! using CONNECT without authentication. Domain names are passed to the proxy
+//! unresolved, so no DNS queries leave the host.
+//!
+
+use failure::{format_err, Error};
+use futures::prelude::*;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use rand::{thread_rng, Rng};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_io::io::{read_exact, write_all};
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, Query};
+use trust_dns_resolver::proto::rr::{Name, RData, RecordType};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+
+/// Timeout of blocking requests through the proxy.
+const BLOCKING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Destination of a CONNECT request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl Target {
+    /// Extracts the destination from `/ip4|ip6|dns4|dns6/<host>/tcp/<port>`.
+    pub fn from_multiaddr(addr: &Multiaddr) -> Option<Target> {
+        let mut iter = addr.iter();
+        let host = iter.next()?;
+        let port = match iter.next()? {
+            Protocol::Tcp(port) => port,
+            _ => return None,
+        };
+        if iter.next().is_some() {
+            return None;
+        }
+        match host {
+            Protocol::Ip4(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+            Protocol::Ip6(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+            Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                Some(Target::Domain(name.into_owned(), port))
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes ATYP, DST.ADDR and DST.PORT.
+    fn encode(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        let port = match self {
+            Target::Ip(SocketAddr::V4(addr)) => {
+                buf.push(ATYP_IPV4);
+                buf.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Ip(SocketAddr::V6(addr)) => {
+                buf.push(ATYP_IPV6);
+                buf.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Domain(name, port) => {
+                if name.is_empty() || name.len() > 255 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid domain name: {}", name),
+                    ));
+                }
+                buf.push(ATYP_DOMAIN);
+                buf.push(name.len() as u8);
+                buf.extend_from_slice(name.as_bytes());
+                *port
+            }
+        };
+        buf.extend_from_slice(&port.to_be_bytes());
+        Ok(())
+    }
+}
+
+fn protocol_error(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+fn check_method_reply(reply: &[u8]) -> io::Result<()> {
+    if reply[0] != VERSION {
+        return Err(protocol_error(format!(
+            "Invalid SOCKS version: {}",
+            reply[0]
+        )));
+    }
+    if reply[1] != NO_AUTHENTICATION {
+        return Err(protocol_error(
+            "SOCKS proxy requires authentication".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the first five bytes of the reply and returns the length of the rest.
+fn check_connect_reply(reply: &[u8]) -> io::Result<usize> {
+    if reply[0] != VERSION {
+        return Err(protocol_error(format!(
+            "Invalid SOCKS version: {}",
+            reply[0]
+        )));
+    }
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(protocol_error(format!(
+            "SOCKS connect failed: reply={}",
+            reply[1]
+        )));
+    }
+    // The rest of BND.ADDR, plus BND.PORT.
+    match reply[3] {
+        ATYP_IPV4 => Ok(4 - 1 + 2),
+        ATYP_IPV6 => Ok(16 - 1 + 2),
+        ATYP_DOMAIN => Ok(reply[4] as usize + 2),
+        atyp => Err(protocol_error(format!(
+            "Invalid SOCKS address type: {}",
+            atyp
+        ))),
+    }
+}
+
+/// Performs the SOCKS5 handshake over a stream connected to the proxy.
+/// Resolves to the stream tunnelled to `target`.
+pub fn connect<S>(stream: S, target: Target) -> impl Future<Item = S, Error = io::Error>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    let encoded = target.encode(&mut request);
+    futures::future::result(encoded)
+        .and_then(move |()| write_all(stream, [VERSION, 1, NO_AUTHENTICATION]))
+        .and_then(|(stream, _)| read_exact(stream, [0u8; 2]))
+        .and_then(|(stream, reply)| {
+            check_method_reply(&reply)?;
+            Ok(stream)
+        })
+        .and_then(move |stream| write_all(stream, request))
+        // VER, REP, RSV, ATYP and the first byte of BND.ADDR.
+        .and_then(|(stream, _)| read_exact(stream, [0u8; 5]))
+        .and_then(|(stream, reply)| {
+            let remaining = check_connect_reply(&reply)?;
+            Ok((stream, remaining))
+        })
+        .and_then(|(stream, remaining)| read_exact(stream, vec![0u8; remaining]))
+        .map(|(stream, _)| stream)
+}
+
+/// Opens a blocking connection to `target` through the proxy.
+pub fn connect_blocking(proxy: &SocketAddr, target: Target) -> io::Result<TcpStream> {
+    connect_blocking_with_timeout(proxy, target, BLOCKING_TIMEOUT)
+}
+
+/// Performs the same handshake as `connect` using blocking I/O,
+/// every read and write fails after `timeout`.
+fn connect_blocking_with_timeout(
+    proxy: &SocketAddr,
+    target: Target,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    target.encode(&mut request)?;
+    let mut stream = TcpStream::connect_timeout(proxy, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    stream.write_all(&[VERSION, 1, NO_AUTHENTICATION])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    check_method_reply(&reply)?;
+
+    stream.write_all(&request)?;
+    // VER, REP, RSV, ATYP and the first byte of BND.ADDR.
+    let mut reply = [0u8; 5];
+    stream.read_exact(&mut reply)?;
+    let remaining = check_connect_reply(&reply)?;
+    let mut bound = vec![0u8; remaining];
+    stream.read_exact(&mut bound)?;
+    Ok(stream)
+}
+
+/// Looks up SRV records using DNS over TCP through the proxy.
+/// Returns "host:port" of targets, which are left for the proxy to resolve.
+pub fn lookup_srv(
+    proxy: &SocketAddr,
+    dns_server: &SocketAddr,
+    name: &str,
+) -> Result<Vec<String>, Error> {
+    let name = Name::from_ascii(name).map_err(|e| format_err!("Invalid name {}: {}", name, e))?;
+    let mut query = Message::new();
+    query
+        .set_id(thread_rng().gen())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, RecordType::SRV));
+    let query = query
+        .to_vec()
+        .map_err(|e| format_err!("Failed to encode DNS query: {}", e))?;
+
+    let mut stream = connect_blocking(proxy, Target::Ip(*dns_server))?;
+    stream.write_all(&(query.len() as u16).to_be_bytes())?;
+    stream.write_all(&query)?;
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut response)?;
+    let response = Message::from_vec(&response)
+        .map_err(|e| format_err!("Failed to decode DNS response: {}", e))?;
+
+    let mut targets = Vec::new();
+    for record in response.answers() {
+        if let RData::SRV(srv) = record.rdata() {
+            let host = srv.target().to_utf8();
+            let host = host.trim_end_matches('.');
+            targets.push(format!("{}:{}", host, srv.port()));
+        }
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::CommonTransport;
+    use super::*;
+    use libp2p_core::Transport;
+    use std::net::TcpListener;
+    use std::thread;
+    use trust_dns_resolver::proto::rr::rdata::SRV;
+    use trust_dns_resolver::proto::rr::Record;
+
+    /// Minimal SOCKS5 server which accepts a single CONNECT and passes the tunnel to `serve`.
+    /// Returns ATYP, DST.ADDR and DST.PORT of the request.
+    fn stand_in_proxy<F>(serve: F) -> (SocketAddr, thread::JoinHandle<Vec<u8>>)
+    where
+        F: FnOnce(&mut TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [VERSION, 1, NO_AUTHENTICATION]);
+            stream.write_all(&[VERSION, NO_AUTHENTICATION]).unwrap();
+
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).unwrap();
+            assert_eq!(&header[..3], &[VERSION, CMD_CONNECT, 0]);
+            let remaining = match header[3] {
+                ATYP_IPV4 => 4 - 1 + 2,
+                ATYP_IPV6 => 16 - 1 + 2,
+                ATYP_DOMAIN => header[4] as usize + 2,
+                atyp => panic!("Invalid address type: {}", atyp),
+            };
+            let mut request = header[3..].to_vec();
+            let mut rest = vec![0u8; remaining];
+            stream.read_exact(&mut rest).unwrap();
+            request.extend_from_slice(&rest);
+            stream
+                .write_all(&[VERSION, REPLY_SUCCEEDED, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 80])
+                .unwrap();
+
+            serve(&mut stream);
+            request
+        });
+        (addr, handle)
+    }
+
+    fn echo(stream: &mut TcpStream) {
+        let mut data = [0u8; 4];
+        stream.read_exact(&mut data).unwrap();
+        stream.write_all(&data).unwrap();
+    }
+
+    fn domain_request(name: &str, port: u16) -> Vec<u8> {
+        let mut request = vec![ATYP_DOMAIN, name.len() as u8];
+        request.extend_from_slice(name.as_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+        request
+    }
+
+    #[test]
+    fn target_from_multiaddr() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        assert_eq!(
+            Target::from_multiaddr(&addr),
+            Some(Target::Ip("10.0.0.1:10203".parse().unwrap()))
+        );
+        let addr: Multiaddr = "/dns4/seed.stegos.com/tcp/10203".parse().unwrap();
+        assert_eq!(
+            Target::from_multiaddr(&addr),
+            Some(Target::Domain("seed.stegos.com".to_string(), 10203))
+        );
+        let addr: Multiaddr = "/ip4/10.0.0.1/udp/10203".parse().unwrap();
+        assert_eq!(Target::from_multiaddr(&addr), None);
+    }
+
+    #[test]
+    fn connect_through_proxy() {
+        let (proxy, handle) = stand_in_proxy(echo);
+        let target = Target::Domain("seed.stegos.com".to_string(), 10203);
+        let mut stream = connect_blocking(&proxy, target).unwrap();
+        stream.write_all(b"ping").unwrap();
+        let mut data = [0u8; 4];
+        stream.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"ping");
+
+        let request = handle.join().unwrap();
+        assert_eq!(request, domain_request("seed.stegos.com", 10203));
+    }
+
+    #[test]
+    fn connect_timeout() {
+        // Connections are accepted by the kernel, but nobody answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let target = Target::Domain("seed.stegos.com".to_string(), 10203);
+        let e =
+            connect_blocking_with_timeout(&proxy, target, Duration::from_millis(100)).unwrap_err();
+        assert!(
+            e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut,
+            "unexpected error: {}",
+            e
+        );
+    }
+
+    #[test]
+    fn dial_through_proxy() {
+        let (proxy, handle) = stand_in_proxy(echo);
+        let transport = CommonTransport::new(Some(proxy));
+        let addr: Multiaddr = "/dns4/seed.stegos.com/tcp/10203".parse().unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let stream = runtime.block_on(transport.dial(addr).unwrap()).unwrap();
+        let (stream, _) = runtime.block_on(write_all(stream, *b"ping")).unwrap();
+        let (_stream, data) = runtime.block_on(read_exact(stream, [0u8; 4])).unwrap();
+        assert_eq!(&data, b"ping");
+
+        let request = handle.join().unwrap();
+        assert_eq!(request, domain_request("seed.stegos.com", 10203));
+    }
+
+    #[test]
+    fn lookup_srv_through_proxy() {
+        let (proxy, handle) = stand_in_proxy(|stream| {
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let query = Message::from_vec(&query).unwrap();
+            assert_eq!(query.queries().len(), 1);
+            assert_eq!(query.queries()[0].query_type(), RecordType::SRV);
+
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_op_code(OpCode::Query)
+                .add_query(query.queries()[0].clone());
+            for (i, host) in ["seed1.stegos.com.", "seed2.stegos.com."]
+                .iter()
+                .enumerate()
+            {
+                let srv = SRV::new(0, 0, 10203 + i as u16, Name::from_ascii(host).unwrap());
+                let mut record = Record::new();
+                record
+                    .set_name(query.queries()[0].name().clone())
+                    .set_ttl(60)
+                    .set_rr_type(RecordType::SRV)
+                    .set_rdata(RData::SRV(srv));
+                response.add_answer(record);
+            }
+            let response = response.to_vec().unwrap();
+            stream
+                .write_all(&(response.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&response).unwrap();
+        });
+        let dns_server: SocketAddr = "8.8.8.8:53".parse().unwrap();
+        let targets = lookup_srv(&proxy, &dns_server, "_seed._tcp.stegos.com").unwrap();
+        assert_eq!(
+            targets,
+            vec![
+                "seed1.stegos.com:10203".to_string(),
+                "seed2.stegos.com:10204".to_string()
+            ]
+        );
+
+        // The DNS server is reached through the proxy.
+        let request = handle.join().unwrap();
+        assert_eq!(request, vec![ATYP_IPV4, 8, 8, 8, 8, 0, 53]);
+    }
+}
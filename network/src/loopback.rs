@@ -21,8 +21,12 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 #![allow(dead_code)]
+use crate::pubsub::message_id;
 use crate::replication::ReplicationEvent;
-use crate::{Network, NetworkProvider, NetworkResponse, UnicastMessage};
+use crate::{
+    DeliveryStatus, MessageId, Misbehavior, Network, NetworkKey, NetworkProvider, NetworkResponse,
+    PubsubMessage, UnicastMessage, ValidationResult,
+};
 use failure::{format_err, Error};
 use futures::sync::{mpsc, oneshot};
 use libp2p_core::identity::ed25519;
@@ -54,6 +58,31 @@ impl NetworkProvider for LoopbackNetwork {
         Ok(rx)
     }
 
+    fn subscribe_validated(
+        &self,
+        stopic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<PubsubMessage>, Error> {
+        let topic: String = stopic.to_string();
+        let (tx, rx) = mpsc::unbounded();
+        self.state
+            .lock()
+            .unwrap()
+            .validated_consumers
+            .entry(topic)
+            .or_default()
+            .push(tx);
+        Ok(rx)
+    }
+
+    fn validate(&self, id: MessageId, result: ValidationResult) -> Result<(), Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .validation_results
+            .push((id, result));
+        Ok(())
+    }
+
     fn subscribe_unicast(
         &self,
         stopic: &str,
@@ -80,6 +109,22 @@ impl NetworkProvider for LoopbackNetwork {
         Ok(())
     }
 
+    /// Queued as a regular unicast, which is always delivered by the loopback.
+    fn send_reliable(
+        &self,
+        to: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error> {
+        self.send(to, protocol_id, data)?;
+        let (tx, rx) = oneshot::channel::<DeliveryStatus>();
+        if let Err(_v) = tx.send(DeliveryStatus::Delivered) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
     fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
         trace!("Received publish for topic = {}", topic);
         let topic: String = topic.to_string();
@@ -105,11 +150,55 @@ impl NetworkProvider for LoopbackNetwork {
         }
     }
 
-    fn change_network_keys(
-        &self,
-        _new_pkey: pbc::PublicKey,
-        _new_skey: pbc::SecretKey,
-    ) -> Result<(), Error> {
+    fn outbound_nodes(&self) -> Vec<pbc::PublicKey> {
+        self.state.lock().unwrap().outbound_nodes.clone()
+    }
+
+    fn report_peer(&self, node_id: pbc::PublicKey, misbehavior: Misbehavior) -> Result<(), Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .reported_peers
+            .push((node_id, misbehavior));
+        Ok(())
+    }
+
+    fn ban_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn unban_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn allow_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn disallow_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn list_peer_reputation(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        if let Err(_v) = tx.send(NetworkResponse::PeerReputation { peers: vec![] }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn bandwidth_stats(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        let stats = Default::default();
+        if let Err(_v) = tx.send(NetworkResponse::BandwidthStats { stats }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn change_network_keys(&self, _new_key: Arc<dyn NetworkKey>) -> Result<(), Error> {
         Ok(())
     }
 
@@ -122,9 +211,16 @@ impl NetworkProvider for LoopbackNetwork {
 #[derive(Debug, Clone)]
 struct LoopbackState {
     consumers: HashMap<String, Vec<mpsc::UnboundedSender<Vec<u8>>>>,
+    validated_consumers: HashMap<String, Vec<mpsc::UnboundedSender<PubsubMessage>>>,
+    /// Results reported by validate().
+    validation_results: Vec<(MessageId, ValidationResult)>,
+    /// Nodes reported by report_peer().
+    reported_peers: Vec<(pbc::PublicKey, Misbehavior)>,
     unicast_consumers: HashMap<String, Vec<mpsc::UnboundedSender<UnicastMessage>>>,
     queue: VecDeque<MessageFromNode>,
     replication_tx: mpsc::UnboundedSender<ReplicationEvent>,
+    /// Nodes returned by outbound_nodes().
+    outbound_nodes: Vec<pbc::PublicKey>,
 }
 
 #[derive(Debug, Clone)]
@@ -149,9 +245,13 @@ impl Loopback {
         let queue = VecDeque::new();
         let state = LoopbackState {
             consumers,
+            validated_consumers: HashMap::new(),
+            validation_results: Vec::new(),
+            reported_peers: Vec::new(),
             unicast_consumers,
             replication_tx,
             queue,
+            outbound_nodes: Vec::new(),
         };
         let state = Arc::new(Mutex::new(state));
         let network = LoopbackNetwork {
@@ -161,6 +261,11 @@ impl Loopback {
         (service, Box::new(network), peer_id, replication_rx)
     }
 
+    /// Sets the nodes this node has dialed.
+    pub fn set_outbound_nodes(&mut self, nodes: Vec<pbc::PublicKey>) {
+        self.state.lock().unwrap().outbound_nodes = nodes;
+    }
+
     pub fn assert_empty_queue(&self) {
         let ref mut state = self.state.lock().unwrap();
         let mut result = Vec::new();
@@ -303,6 +408,14 @@ impl Loopback {
 
     pub fn receive_broadcast_raw(&mut self, topic: &str, data: Vec<u8>) {
         let ref mut state = self.state.lock().unwrap();
+        if let Some(nodes) = state.validated_consumers.get_mut(topic) {
+            let msg = PubsubMessage {
+                id: message_id(topic, &data),
+                data,
+            };
+            nodes.retain(move |tx| tx.unbounded_send(msg.clone()).is_ok());
+            return;
+        }
         let ref mut nodes = state
             .consumers
             .get_mut(topic)
@@ -311,6 +424,19 @@ impl Loopback {
         nodes.retain(move |tx| tx.unbounded_send(data.clone()).is_ok());
     }
 
+    /// Take results reported by validate().
+    pub fn take_validation_results(&mut self) -> Vec<(MessageId, ValidationResult)> {
+        mem::replace(
+            &mut self.state.lock().unwrap().validation_results,
+            Vec::new(),
+        )
+    }
+
+    /// Take nodes reported by report_peer().
+    pub fn take_reported_peers(&mut self) -> Vec<(pbc::PublicKey, Misbehavior)> {
+        mem::replace(&mut self.state.lock().unwrap().reported_peers, Vec::new())
+    }
+
     pub fn receive_broadcast<M: ProtoConvert>(&mut self, topic: &str, msg: M) {
         self.receive_broadcast_raw(topic, msg.into_buffer().unwrap());
     }
@@ -35,4 +35,50 @@ lazy_static! {
         &["protocol"]
     )
     .unwrap();
+    pub static ref INCOMING_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "stegos_network_incoming_messages",
+        "Incoming messages per protocol",
+        &["protocol"]
+    )
+    .unwrap();
+    pub static ref OUTGOING_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "stegos_network_outgoing_messages",
+        "Outgoing messages per protocol",
+        &["protocol"]
+    )
+    .unwrap();
+    pub static ref PEER_INCOMING_TRAFFIC: IntCounterVec = register_int_counter_vec!(
+        "stegos_network_incoming_traffic_per_peer",
+        "Incoming bytes per peer",
+        &["peer"]
+    )
+    .unwrap();
+    pub static ref PEER_OUTGOING_TRAFFIC: IntCounterVec = register_int_counter_vec!(
+        "stegos_network_outgoing_traffic_per_peer",
+        "Outgoing bytes per peer",
+        &["peer"]
+    )
+    .unwrap();
+    pub static ref DROPPED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "stegos_network_dropped_messages",
+        "Outgoing messages dropped on a full priority queue",
+        &["protocol", "priority"]
+    )
+    .unwrap();
+}
+
+/// Accounts an incoming message of the protocol.
+pub fn incoming(protocol: &str, size: usize) {
+    INCOMING_TRAFFIC
+        .with_label_values(&[protocol])
+        .inc_by(size as i64);
+    INCOMING_MESSAGES.with_label_values(&[protocol]).inc();
+}
+
+/// Accounts an outgoing message of the protocol.
+pub fn outgoing(protocol: &str, size: usize) {
+    OUTGOING_TRAFFIC
+        .with_label_values(&[protocol])
+        .inc_by(size as i64);
+    OUTGOING_MESSAGES.with_label_values(&[protocol]).inc();
 }
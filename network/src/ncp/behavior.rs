@@ -32,7 +32,7 @@ use rand::{thread_rng, Rng};
 use serde_derive::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     marker::PhantomData,
     net::Ipv4Addr,
     time::{Duration, Instant},
@@ -43,8 +43,9 @@ use tokio_timer::Delay;
 
 use crate::config::NetworkConfig;
 use crate::ncp::handler::NcpHandler;
+use crate::ncp::peer_store::PeerStore;
 use crate::ncp::protocol::{GetPeersResponse, NcpMessage, PeerInfo};
-use crate::utils::{socket_to_multi_addr, ExpiringQueue};
+use crate::utils::{seed_to_multi_addr, socket_to_multi_addr, ExpiringQueue};
 use std::net::SocketAddr;
 use std::str::FromStr;
 
@@ -70,6 +71,8 @@ pub struct Ncp<TSubstream> {
     out_events: VecDeque<NcpOutEvent>,
     /// List of connected peers (including disabled)
     connected_peers: ExpiringQueue<PeerId, Instant>,
+    /// Connected peers dialed by this node.
+    outbound_peers: HashSet<PeerId>,
     /// Known peers
     known_peers: LruCache<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>,
     /// Maximum connections allowd
@@ -82,6 +85,8 @@ pub struct Ncp<TSubstream> {
     delay_between_monitor_events: Duration,
     /// Seed nodes (we keep them in case we were too long offline and need to restart the net)
     seed_nodes: Vec<Multiaddr>,
+    /// Known peers saved between restarts
+    peer_store: PeerStore,
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
@@ -97,16 +102,33 @@ pub struct NodeInfo {
     addresses: Vec<Multiaddr>,
 }
 
+impl NodeInfo {
+    pub(crate) fn new(
+        peer_id: &PeerId,
+        network_pkey: pbc::PublicKey,
+        addresses: Vec<Multiaddr>,
+    ) -> Self {
+        NodeInfo {
+            peer_id: peer_id.to_base58(),
+            network_pkey,
+            addresses,
+        }
+    }
+}
+
 impl<TSubstream> Ncp<TSubstream> {
     /// Creates a NetworkBehaviour for NCP.
     pub fn new(config: &NetworkConfig, network_pkey: pbc::PublicKey) -> Self {
         let mut seed_nodes: Vec<Multiaddr> = config
             .seed_nodes
             .iter()
-            .map(|a| socket_to_multi_addr(&SocketAddr::from_str(a).expect("Invalid seed_nodes")))
+            .map(|a| seed_to_multi_addr(a).expect("Invalid seed_nodes"))
             .collect();
 
-        let advertised_endpoint = if config.advertised_endpoint != "" {
+        // Nothing to advertise if incoming connections are not accepted.
+        let advertised_endpoint = if config.advertised_endpoint != ""
+            && !config.proxy.disable_listener
+        {
             let endpoint = SocketAddr::from_str(&config.advertised_endpoint)
                 .expect("Invalid advertised_endpoint");
             let endpoint = socket_to_multi_addr(&endpoint);
@@ -123,16 +145,53 @@ impl<TSubstream> Ncp<TSubstream> {
         } else {
             None
         };
+
+        let peer_store = match PeerStore::load(&config.peer_store_file, KNOWN_PEERS_TABLE_SIZE) {
+            Ok(peer_store) => peer_store,
+            Err(e) => {
+                error!(target: "stegos_network::ncp", "Failed to load known peers: {}", e);
+                PeerStore::new(&config.peer_store_file, KNOWN_PEERS_TABLE_SIZE)
+            }
+        };
+        let mut known_peers =
+            LruCache::<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>::with_capacity(
+                KNOWN_PEERS_TABLE_SIZE,
+            );
+        let mut out_events = VecDeque::new();
+        // Restore known peers and try to reach the best of them right away.
+        for peer in peer_store.ranked() {
+            let peer_id = PeerId::from_str(&peer.peer_id).expect("checked by PeerStore");
+            let addresses: SmallVec<[Multiaddr; 16]> = peer
+                .addresses
+                .into_iter()
+                .filter(|a| a.iter().next().is_some() && !is_localhost(a))
+                .collect();
+            if addresses.is_empty() {
+                continue;
+            }
+            known_peers.insert(
+                peer_id.clone().into_bytes(),
+                (peer.node_id.clone(), addresses.clone()),
+            );
+            out_events.push_back(NcpOutEvent::DiscoveredPeer {
+                peer_id: peer_id.clone(),
+                node_id: peer.node_id,
+                addresses: addresses.to_vec(),
+            });
+            if known_peers.len() <= config.min_connections {
+                out_events.push_back(NcpOutEvent::DialPeer { peer_id });
+            }
+        }
+        debug!(target: "stegos_network::ncp", "Restored known peers: count={}", known_peers.len());
+
         Ncp {
             node_id: network_pkey,
             advertised_endpoint,
             events: VecDeque::new(),
-            out_events: VecDeque::new(),
+            out_events,
             connected_peers: ExpiringQueue::new(IDLE_TIMEOUT),
-            known_peers:
-                LruCache::<Vec<u8>, (pbc::PublicKey, SmallVec<[Multiaddr; 16]>)>::with_capacity(
-                    KNOWN_PEERS_TABLE_SIZE,
-                ),
+            outbound_peers: HashSet::new(),
+            known_peers,
             max_connections: config.max_connections,
             min_connections: config.min_connections,
             monitor_delay: Delay::new(
@@ -142,6 +201,7 @@ impl<TSubstream> Ncp<TSubstream> {
             ),
             delay_between_monitor_events: Duration::from_secs(config.monitoring_interval),
             seed_nodes,
+            peer_store,
             marker: PhantomData,
         }
     }
@@ -174,6 +234,14 @@ impl<TSubstream> Ncp<TSubstream> {
         }
         nodes
     }
+
+    /// Network keys of connected peers dialed by this node.
+    pub fn get_outbound_nodes(&self) -> Vec<pbc::PublicKey> {
+        self.outbound_peers
+            .iter()
+            .filter_map(|p| self.known_peers.peek(p.as_bytes()).map(|info| info.0))
+            .collect()
+    }
 }
 
 impl<TSubstream> NetworkBehaviour for Ncp<TSubstream>
@@ -197,7 +265,7 @@ where
         addresses
     }
 
-    fn inject_connected(&mut self, id: PeerId, _: ConnectedPoint) {
+    fn inject_connected(&mut self, id: PeerId, endpoint: ConnectedPoint) {
         debug!(target: "stegos_network::ncp", "peer connected: peer_id={}", id.to_base58());
         self.events.push_back(NcpEvent::RequestPeers {
             peer_id: id.clone(),
@@ -205,12 +273,21 @@ where
         self.out_events.push_back(NcpOutEvent::Connected {
             peer_id: id.clone(),
         });
+        self.peer_store.connected(&id);
+        if let ConnectedPoint::Dialer { .. } = endpoint {
+            self.outbound_peers.insert(id.clone());
+        }
         self.connected_peers.insert(id, Instant::now());
     }
 
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        self.peer_store.dial_failed(peer_id);
+    }
+
     fn inject_disconnected(&mut self, id: &PeerId, _: ConnectedPoint) {
         debug!(target: "stegos_network::ncp", "peer disconnected: peer_id={}", id.to_base58());
         self.connected_peers.remove(id);
+        self.outbound_peers.remove(id);
         self.known_peers.remove(id.as_bytes());
         self.out_events.push_back(NcpOutEvent::Disconnected {
             peer_id: id.clone(),
@@ -282,6 +359,9 @@ where
                             .push_back(NcpEvent::RequestPeers { peer_id: p.clone() });
                         let _ = self.known_peers.get(p.as_bytes());
                     }
+                    if let Err(e) = self.peer_store.save() {
+                        error!(target: "stegos_network::ncp", "Failed to save known peers: {}", e);
+                    }
                     if self.connected_peers.len() >= self.max_connections {
                         // Already have max connected_peers
                         continue;
@@ -373,18 +453,21 @@ where
                                         .push(addr)
                                 }
                             }
+                            let addresses: Vec<Multiaddr> = self
+                                .known_peers
+                                .get(id.as_bytes())
+                                .unwrap()
+                                .1
+                                .iter()
+                                .map(|v| v.clone())
+                                .filter(|v| !is_localhost(v))
+                                .collect();
+                            self.peer_store
+                                .update(&id, peer.node_id.clone(), addresses.clone());
                             self.out_events.push_back(NcpOutEvent::DiscoveredPeer {
                                 peer_id: peer.peer_id.clone(),
                                 node_id: peer.node_id.clone(),
-                                addresses: self
-                                    .known_peers
-                                    .get(id.as_bytes())
-                                    .unwrap()
-                                    .1
-                                    .iter()
-                                    .map(|v| v.clone())
-                                    .filter(|v| !is_localhost(v))
-                                    .collect(),
+                                addresses,
                             });
                         }
                     }
@@ -23,6 +23,7 @@
 
 mod behavior;
 mod handler;
+mod peer_store;
 mod proto;
 mod protocol;
 
@@ -0,0 +1,308 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Persistent store of known peers.
+//!
+//! Peers learned via NCP are saved to `NetworkConfig::peer_store_file` together
+//! with connection statistics, so that the node can reconnect to the network
+//! after restart even if seed nodes are unavailable.
+//!
+//! Peers we have never connected to are learned second-hand and can be made up
+//! by other peers. They always rank below connected peers and never evict them.
+//!
+
+use failure::{format_err, Error};
+use libp2p_core::{Multiaddr, PeerId};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use stegos_crypto::pbc;
+
+/// Peers not seen for so long are forgotten.
+const PEER_TTL: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Stored information about a peer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredPeer {
+    /// Base58 PeerId.
+    pub peer_id: String,
+    /// Network key.
+    pub node_id: pbc::PublicKey,
+    /// Known addresses.
+    pub addresses: Vec<Multiaddr>,
+    /// Last successful connection (seconds since UNIX epoch), zero if never connected.
+    pub last_seen: u64,
+    /// Number of successful connections.
+    pub successes: u32,
+    /// Number of failed dials.
+    pub failures: u32,
+}
+
+impl StoredPeer {
+    /// Rank of the peer, higher is better.
+    fn rank(&self) -> f64 {
+        (self.successes as f64 + 1.0) / (self.successes as f64 + self.failures as f64 + 2.0)
+    }
+
+    /// True if the peer was learned from other peers and never connected.
+    fn is_second_hand(&self) -> bool {
+        self.successes == 0
+    }
+}
+
+/// Store of known peers, backed by a JSON file.
+pub struct PeerStore {
+    /// Path to the file, empty if the store is not persistent.
+    path: PathBuf,
+    /// Known peers by base58 PeerId.
+    peers: HashMap<String, StoredPeer>,
+    /// Maximum number of saved peers.
+    capacity: usize,
+    /// True if the store was changed since the last save.
+    dirty: bool,
+}
+
+impl PeerStore {
+    /// Creates an empty store.
+    pub fn new(path: &Path, capacity: usize) -> Self {
+        PeerStore {
+            path: path.to_path_buf(),
+            peers: HashMap::new(),
+            capacity,
+            dirty: false,
+        }
+    }
+
+    /// Loads the store from the file, starts with an empty store if the file doesn't exist.
+    pub fn load(path: &Path, capacity: usize) -> Result<Self, Error> {
+        let mut store = PeerStore::new(path, capacity);
+        if path.as_os_str().is_empty() || !path.exists() {
+            return Ok(store);
+        }
+        let data = fs::read(path).map_err(|e| format_err!("Failed to read {:?}: {}", path, e))?;
+        let peers: Vec<StoredPeer> = serde_json::from_slice(&data)
+            .map_err(|e| format_err!("Failed to parse {:?}: {}", path, e))?;
+        let expired = now().saturating_sub(PEER_TTL.as_secs());
+        for peer in peers {
+            // Peers which were never connected are evicted by rank instead.
+            if (peer.last_seen != 0 && peer.last_seen < expired)
+                || PeerId::from_str(&peer.peer_id).is_err()
+            {
+                continue;
+            }
+            store.peers.insert(peer.peer_id.clone(), peer);
+        }
+        store.evict();
+        Ok(store)
+    }
+
+    /// Saves the store to the file if it was changed.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.dirty || self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let peers = self.ranked();
+        let data = serde_json::to_vec_pretty(&peers)?;
+        // Write to a temporary file first to never leave a truncated store.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &data).map_err(|e| format_err!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, &self.path)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", self.path, e))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Returns known peers, best first. Connected peers go before second-hand ones.
+    pub fn ranked(&self) -> Vec<StoredPeer> {
+        let mut peers: Vec<StoredPeer> = self.peers.values().cloned().collect();
+        peers.sort_by(|a, b| {
+            a.is_second_hand()
+                .cmp(&b.is_second_hand())
+                .then(b.rank().partial_cmp(&a.rank()).unwrap())
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        peers
+    }
+
+    /// Removes the worst peers above capacity.
+    fn evict(&mut self) {
+        if self.peers.len() <= self.capacity {
+            return;
+        }
+        for peer in self.ranked().into_iter().skip(self.capacity) {
+            self.peers.remove(&peer.peer_id);
+        }
+        self.dirty = true;
+    }
+
+    /// Updates the network key and addresses of a peer learned from other peers.
+    /// The last seen time is updated only by a successful connection.
+    pub fn update(&mut self, peer_id: &PeerId, node_id: pbc::PublicKey, addresses: Vec<Multiaddr>) {
+        let key = peer_id.to_base58();
+        let is_new = !self.peers.contains_key(&key);
+        let peer = self.peers.entry(key).or_insert_with(|| StoredPeer {
+            peer_id: peer_id.to_base58(),
+            node_id,
+            addresses: Vec::new(),
+            last_seen: 0,
+            successes: 0,
+            failures: 0,
+        });
+        peer.node_id = node_id;
+        if !addresses.is_empty() {
+            peer.addresses = addresses;
+        }
+        self.dirty = true;
+        if is_new {
+            self.evict();
+        }
+    }
+
+    /// Records a successful connection to a peer.
+    pub fn connected(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(&peer_id.to_base58()) {
+            peer.successes = peer.successes.saturating_add(1);
+            peer.last_seen = now();
+            self.dirty = true;
+        }
+    }
+
+    /// Records a failed dial to a peer.
+    pub fn dial_failed(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(&peer_id.to_base58()) {
+            peer.failures = peer.failures.saturating_add(1);
+            self.dirty = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+    use tempdir::TempDir;
+
+    fn random_peer() -> (PeerId, pbc::PublicKey) {
+        let peer_id = identity::Keypair::generate_ed25519()
+            .public()
+            .into_peer_id();
+        let (_skey, pkey) = pbc::make_random_keys();
+        (peer_id, pkey)
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("peer_store").unwrap();
+        let path = dir.path().join("peers.json");
+        let mut store = PeerStore::load(&path, 2).unwrap();
+        assert_eq!(store.len(), 0);
+
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let (good, good_key) = random_peer();
+        let (bad, bad_key) = random_peer();
+        let (unknown, unknown_key) = random_peer();
+        store.update(&good, good_key, vec![addr.clone()]);
+        store.update(&bad, bad_key, vec![addr.clone()]);
+        store.connected(&good);
+        store.dial_failed(&bad);
+        store.dial_failed(&bad);
+        store.update(&unknown, unknown_key, vec![addr.clone()]);
+
+        let ranked = store.ranked();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].peer_id, good.to_base58());
+        assert_eq!(ranked[0].successes, 1);
+        assert_eq!(ranked[0].addresses, vec![addr]);
+        assert_ne!(ranked[0].last_seen, 0);
+        assert_eq!(ranked[1].peer_id, unknown.to_base58());
+        assert_eq!(ranked[1].last_seen, 0);
+
+        store.save().unwrap();
+        let loaded = PeerStore::load(&path, 2).unwrap();
+        assert_eq!(loaded.ranked(), ranked);
+    }
+
+    #[test]
+    fn evict_on_insert() {
+        let dir = TempDir::new("peer_store").unwrap();
+        let mut store = PeerStore::new(&dir.path().join("peers.json"), 2);
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let (good, good_key) = random_peer();
+        let (bad, bad_key) = random_peer();
+        store.update(&good, good_key, vec![addr.clone()]);
+        store.update(&bad, bad_key, vec![addr.clone()]);
+        store.connected(&good);
+        store.dial_failed(&bad);
+        for _ in 0..10 {
+            let (peer_id, node_id) = random_peer();
+            store.update(&peer_id, node_id, vec![addr.clone()]);
+            assert_eq!(store.len(), 2);
+        }
+        assert!(store.ranked().iter().any(|p| p.peer_id == good.to_base58()));
+        assert!(!store.ranked().iter().any(|p| p.peer_id == bad.to_base58()));
+
+        // Second-hand updates keep the last seen time.
+        let last_seen = store.ranked()[0].last_seen;
+        store.update(&good, good_key, vec![addr]);
+        assert_eq!(store.ranked()[0].last_seen, last_seen);
+    }
+
+    #[test]
+    fn second_hand_never_evicts_connected() {
+        let dir = TempDir::new("peer_store").unwrap();
+        let mut store = PeerStore::new(&dir.path().join("peers.json"), 2);
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/10203".parse().unwrap();
+        let (good, good_key) = random_peer();
+        let (flaky, flaky_key) = random_peer();
+        store.update(&good, good_key, vec![addr.clone()]);
+        store.update(&flaky, flaky_key, vec![addr.clone()]);
+        store.connected(&good);
+        store.connected(&flaky);
+        for _ in 0..10 {
+            store.dial_failed(&flaky);
+        }
+        for _ in 0..10 {
+            let (peer_id, node_id) = random_peer();
+            store.update(&peer_id, node_id, vec![addr.clone()]);
+            assert_eq!(store.len(), 2);
+        }
+        let ranked = store.ranked();
+        assert_eq!(ranked[0].peer_id, good.to_base58());
+        assert_eq!(ranked[1].peer_id, flaky.to_base58());
+    }
+}
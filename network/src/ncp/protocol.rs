@@ -150,9 +150,7 @@ impl Encoder for NcpCodec {
         // Reserve enough space for the data and the length. The length has a maximum of 32 bits,
         // which means that 5 bytes is enough for the variable-length integer.
         dst.reserve(msg_size as usize + 5);
-        metrics::OUTGOING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(msg_size as i64);
+        metrics::outgoing(PROTOCOL_LABEL, msg_size as usize);
 
         proto
             .write_length_delimited_to_writer(&mut dst.by_ref().writer())
@@ -174,9 +172,7 @@ impl Decoder for NcpCodec {
             None => return Ok(None),
         };
 
-        metrics::INCOMING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(packet.len() as i64);
+        metrics::incoming(PROTOCOL_LABEL, packet.len());
         let message: ncp_proto::Message = protobuf::parse_from_bytes(&packet)?;
 
         match message.get_field_type() {
@@ -0,0 +1,111 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Network key of the node.
+//!
+//! Unicast messages are signed by the network key of the sender and
+//! encrypted to the network key of the recipient. The secret key can
+//! be kept outside of the node, see `NetworkKey`.
+//!
+
+use failure::Error;
+use std::fmt;
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
+use stegos_crypto::pbc;
+
+///
+/// Holder of the network secret key.
+///
+pub trait NetworkKey: fmt::Debug + Send + Sync {
+    /// Returns the network public key.
+    fn pkey(&self) -> pbc::PublicKey;
+
+    /// Signs an outgoing unicast message, which data is already encrypted.
+    fn sign_unicast(
+        &self,
+        to: &pbc::PublicKey,
+        protocol_id: &str,
+        rval: &pbc::RVal,
+        data: &[u8],
+    ) -> Result<pbc::Signature, Error>;
+
+    /// Decrypts an incoming unicast message.
+    fn decrypt_unicast(&self, id: &[u8], rval: &pbc::RVal, data: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+///
+/// Returns the hash covered by the signature of a unicast message.
+/// The sequence number is not covered to keep compatibility with older peers.
+///
+pub fn unicast_signing_hash(
+    from: &pbc::PublicKey,
+    to: &pbc::PublicKey,
+    protocol_id: &str,
+    rval: &pbc::RVal,
+    data: &[u8],
+) -> Hash {
+    let mut hasher = Hasher::new();
+    from.hash(&mut hasher);
+    to.hash(&mut hasher);
+    protocol_id.hash(&mut hasher);
+    rval.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.result()
+}
+
+/// Network key which is kept in process memory.
+#[derive(Debug, Clone)]
+pub struct LocalNetworkKey {
+    skey: pbc::SecretKey,
+    pkey: pbc::PublicKey,
+}
+
+impl LocalNetworkKey {
+    pub fn new(skey: pbc::SecretKey, pkey: pbc::PublicKey) -> Self {
+        LocalNetworkKey { skey, pkey }
+    }
+}
+
+impl NetworkKey for LocalNetworkKey {
+    fn pkey(&self) -> pbc::PublicKey {
+        self.pkey
+    }
+
+    fn sign_unicast(
+        &self,
+        to: &pbc::PublicKey,
+        protocol_id: &str,
+        rval: &pbc::RVal,
+        data: &[u8],
+    ) -> Result<pbc::Signature, Error> {
+        let hash = unicast_signing_hash(&self.pkey, to, protocol_id, rval, data);
+        Ok(pbc::sign_hash(&hash, &self.skey))
+    }
+
+    fn decrypt_unicast(&self, id: &[u8], rval: &pbc::RVal, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let packet = pbc::EncryptedPacket::new(&self.pkey, id, rval, data);
+        let data = pbc::ibe_decrypt(&packet, &self.skey)?;
+        Ok(data)
+    }
+}
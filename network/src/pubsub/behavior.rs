@@ -18,11 +18,18 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use super::handler::rpc_priority;
 use super::handler::FloodsubHandler;
+use super::mesh::{self, Mesh};
 use super::metrics;
 use super::protocol::{
-    FloodsubMessage, FloodsubRpc, FloodsubSubscription, FloodsubSubscriptionAction,
+    ControlAction, FloodsubMessage, FloodsubRpc, FloodsubSubscription, FloodsubSubscriptionAction,
+    MessageId,
 };
+use crate::config::{MessagePriority, NetworkConfig, PubsubMode};
+use crate::metrics as network_metrics;
+use crate::utils::RateLimiter;
+use crate::{Misbehavior, ValidationResult};
 
 use futures::prelude::*;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
@@ -36,6 +43,7 @@ use std::time::{Duration, Instant};
 use std::{
     collections::{hash_map::HashMap, hash_set::HashSet, VecDeque},
     marker::PhantomData,
+    sync::{Arc, Mutex},
 };
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_timer::Delay;
@@ -45,6 +53,32 @@ use update_rate::{RateCounter, RollingRateCounter};
 const PUBSUB_SAMPLES: u64 = 100;
 const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
 const LRU_EXPIRE_TIME: Duration = Duration::from_secs(60); // 1 minute to allow transaction retransmit
+/// Messages which haven't been validated in this time are dropped.
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(30);
+/// Peers sending more messages per second are penalized.
+const MAX_INCOMING_RATE: f64 = 500.0;
+/// Label of pubsub in rate limits.
+const RATE_LIMIT_PROTOCOL: &str = "pubsub";
+/// Maximum number of messages held back by rate limits, the oldest are dropped.
+const MAX_THROTTLED_MESSAGES: usize = 4096;
+/// Consensus, view change and block topics are never rate limited,
+/// whatever priority is configured for them.
+const UNLIMITED_TOPICS: &[&str] = &[
+    "consensus",
+    "view_changes",
+    "view_changes_proofs",
+    "view_changes_direct",
+    "block",
+    "compact_block",
+    "compact_block_txs",
+];
+
+/// A message waiting for validation by the application.
+struct PendingMessage {
+    message: FloodsubMessage,
+    source: PeerId,
+    received: Instant,
+}
 
 /// Network behaviour that automatically identifies nodes periodically, and returns information
 /// about them.
@@ -81,13 +115,51 @@ pub struct Floodsub<TSubstream> {
     /// Do we relay (disabled on edge nodes)
     relaying: bool,
 
+    /// Routing of messages.
+    mode: PubsubMode,
+
+    /// Mesh peers and recent messages, used in the mesh mode.
+    mesh: Mesh,
+
+    /// Peers which route messages in the mesh mode, others get full messages.
+    mesh_remotes: HashSet<PeerId>,
+
+    /// Mesh maintenance and gossip interval.
+    heartbeat_delay: Delay,
+
+    /// Topics, which messages are forwarded only after validation by the application.
+    validated_topics: HashSet<String>,
+
+    /// Messages waiting for validation.
+    pending: HashMap<MessageId, PendingMessage>,
+
+    /// Priority classes of topics, used by handlers to order outgoing messages.
+    priorities: Arc<HashMap<String, MessagePriority>>,
+
+    /// Maximum number of outgoing messages per peer and priority class.
+    priority_queue_size: usize,
+
+    /// Outbound bandwidth limits, shared with other protocols.
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+
+    /// Messages held back by rate limits, in order of sending.
+    throttled: VecDeque<(PeerId, FloodsubRpc)>,
+
+    /// When to try to send throttled messages again.
+    throttle_delay: Delay,
+
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
 
 impl<TSubstream> Floodsub<TSubstream> {
     /// Creates a `Floodsub`.
-    pub fn new(local_peer_id: PeerId, relaying: bool) -> Self {
+    pub fn new(
+        config: &NetworkConfig,
+        local_peer_id: PeerId,
+        relaying: bool,
+        rate_limiter: Arc<Mutex<RateLimiter>>,
+    ) -> Self {
         Floodsub {
             events: VecDeque::new(),
             local_peer_id,
@@ -99,6 +171,17 @@ impl<TSubstream> Floodsub<TSubstream> {
             incoming_rates: HashMap::new(),
             metrics_update_delay: Delay::new(Instant::now() + METRICS_UPDATE_INTERVAL),
             relaying,
+            mode: config.pubsub_mode,
+            mesh: Mesh::new(config.pubsub_mesh_degree),
+            mesh_remotes: HashSet::new(),
+            heartbeat_delay: Delay::new(Instant::now() + mesh::HEARTBEAT_INTERVAL),
+            validated_topics: HashSet::new(),
+            pending: HashMap::new(),
+            priorities: Arc::new(config.topic_priorities.clone()),
+            priority_queue_size: config.priority_queue_size,
+            rate_limiter,
+            throttled: VecDeque::new(),
+            throttle_delay: Delay::new(Instant::now()),
             marker: PhantomData,
         }
     }
@@ -122,14 +205,105 @@ impl<TSubstream> Floodsub<TSubstream> {
                         topic: topic.clone(),
                         action: FloodsubSubscriptionAction::Subscribe,
                     }],
+                    control: Vec::new(),
                 }),
             });
         }
 
+        if self.mode == PubsubMode::Mesh {
+            let grafts = self.mesh.join(&topic, &self.mesh_capable_remotes());
+            self.send_control(grafts);
+        }
         self.subscribed_topics.push(topic);
         true
     }
 
+    /// Subscribes to a topic, which messages are forwarded to other peers
+    /// only after `validate()` has accepted them.
+    pub fn subscribe_validated(&mut self, topic: String) -> bool {
+        self.validated_topics.insert(topic.clone());
+        self.subscribe(topic)
+    }
+
+    /// Forward, drop or penalize a message received on a validated topic.
+    pub fn validate(&mut self, id: MessageId, result: ValidationResult) {
+        let pending = match self.pending.remove(&id) {
+            Some(pending) => pending,
+            None => {
+                trace!(target: "stegos_network::pubsub", "validated unknown or expired message: id={}", id);
+                return;
+            }
+        };
+        trace!(target: "stegos_network::pubsub", "message validated: id={}, result={:?}", id, result);
+        match result {
+            ValidationResult::Accept => {
+                if !self.relaying {
+                    return;
+                }
+                let peers = self.forward_peers(&pending.source, &pending.message);
+                for peer_id in peers {
+                    self.events.push_back(NetworkBehaviourAction::SendEvent {
+                        peer_id,
+                        event: FloodsubSendEvent::Publish(FloodsubRpc {
+                            subscriptions: Vec::new(),
+                            messages: vec![pending.message.clone()],
+                            control: Vec::new(),
+                        }),
+                    });
+                }
+            }
+            ValidationResult::Reject => {
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    FloodsubEvent::Misbehaving {
+                        peer_id: pending.source,
+                        misbehavior: Misbehavior::InvalidMessage,
+                    },
+                ));
+            }
+            ValidationResult::Ignore => {}
+        }
+    }
+
+    /// Drop messages which haven't been validated in time.
+    fn expire_pending(&mut self) {
+        let now = Instant::now();
+        self.pending
+            .retain(|_, pending| now.duration_since(pending.received) < VALIDATION_TIMEOUT);
+    }
+
+    /// Peers to forward a received message to.
+    fn forward_peers(&mut self, source: &PeerId, message: &FloodsubMessage) -> Vec<PeerId> {
+        let mut mesh_peers = None;
+        if self.mode == PubsubMode::Mesh {
+            self.mesh.cache_message(message);
+            // Flood until the mesh is formed.
+            mesh_peers = self
+                .mesh
+                .peers(&message.topic)
+                .filter(|peers| !peers.is_empty());
+        }
+        self.unlocked_remotes
+            .iter()
+            .filter(|(peer_id, subscr_topics)| {
+                *peer_id != source
+                    && subscr_topics.iter().any(|t| t == &message.topic)
+                    // Other mesh peers get the id by gossip, flood peers ignore it.
+                    && (mesh_peers.map_or(true, |peers| peers.contains(*peer_id))
+                        || !self.mesh_remotes.contains(*peer_id))
+            })
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect()
+    }
+
+    /// Topics of peers which route messages in the mesh mode.
+    fn mesh_capable_remotes(&self) -> mesh::RemoteTopics {
+        self.unlocked_remotes
+            .iter()
+            .filter(|(peer_id, _)| self.mesh_remotes.contains(*peer_id))
+            .map(|(peer_id, topics)| (peer_id.clone(), topics.clone()))
+            .collect()
+    }
+
     /// Publishes a message to the network.
     ///
     /// > **Note**: Doesn't do anything if we're not subscribed to the topic.
@@ -145,17 +319,94 @@ impl<TSubstream> Floodsub<TSubstream> {
         super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
 
         // Send to peers we know are subscribed to the topic.
-        for (peer_id, sub_topic) in self.unlocked_remotes.iter() {
-            if !sub_topic.iter().any(|t| t == &message.topic) {
-                continue;
-            }
-
+        let local_peer_id = self.local_peer_id.clone();
+        for peer_id in self.forward_peers(&local_peer_id, &message) {
             trace!(target: "stegos_network::pubsub", "sending message to peer: peer_id={}", peer_id);
             self.events.push_back(NetworkBehaviourAction::SendEvent {
-                peer_id: peer_id.clone(),
+                peer_id,
                 event: FloodsubSendEvent::Publish(FloodsubRpc {
                     subscriptions: Vec::new(),
                     messages: vec![message.clone()],
+                    control: Vec::new(),
+                }),
+            });
+        }
+    }
+
+    /// Send control messages, one RPC per peer.
+    fn send_control(&mut self, actions: Vec<(PeerId, ControlAction)>) {
+        let mut rpcs: HashMap<PeerId, FloodsubRpc> = HashMap::new();
+        for (peer_id, action) in actions {
+            rpcs.entry(peer_id)
+                .or_insert_with(|| FloodsubRpc {
+                    messages: Vec::new(),
+                    subscriptions: Vec::new(),
+                    control: Vec::new(),
+                })
+                .control
+                .push(action);
+        }
+        for (peer_id, rpc) in rpcs {
+            trace!(target: "stegos_network::pubsub", "sending control to peer: peer_id={}, control={:?}", peer_id, rpc.control);
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id,
+                event: FloodsubSendEvent::Publish(rpc),
+            });
+        }
+    }
+
+    /// Handle mesh control messages from the remote.
+    fn handle_control(&mut self, peer_id: &PeerId, control: Vec<ControlAction>) {
+        // Flood nodes never send control messages.
+        if self.mesh_remotes.insert(peer_id.clone()) {
+            debug!(target: "stegos_network::pubsub", "peer routes in the mesh mode: peer_id={}", peer_id);
+        }
+        let mut responses = Vec::new();
+        let mut messages = Vec::new();
+        for action in control {
+            match action {
+                ControlAction::IHave { topic, message_ids } => {
+                    if !self.subscribed_topics.iter().any(|t| t == &topic) {
+                        continue;
+                    }
+                    let message_ids: Vec<_> = message_ids
+                        .into_iter()
+                        .filter(|id| !self.received.contains_key(id))
+                        .take(mesh::MAX_IWANT_IDS)
+                        .collect();
+                    if !message_ids.is_empty() {
+                        responses.push((peer_id.clone(), ControlAction::IWant { message_ids }));
+                    }
+                }
+                ControlAction::IWant { message_ids } => {
+                    if message_ids.len() > mesh::MAX_IWANT_IDS {
+                        debug!(target: "stegos_network::pubsub", "too many ids in IWANT, ignoring the rest: peer_id={}, ids={}", peer_id, message_ids.len());
+                    }
+                    for id in message_ids.into_iter().take(mesh::MAX_IWANT_IDS) {
+                        if let Some(message) = self.mesh.cached_message(&id) {
+                            messages.push(message.clone());
+                        }
+                    }
+                }
+                ControlAction::Graft { topic } => {
+                    if let Some(prune) = self.mesh.handle_graft(peer_id, topic) {
+                        responses.push((peer_id.clone(), prune));
+                    }
+                }
+                ControlAction::Prune { topic } => {
+                    self.mesh.remove_peer_from_topic(peer_id, &topic);
+                }
+                ControlAction::Mesh => {}
+            }
+        }
+        self.send_control(responses);
+        if !messages.is_empty() {
+            self.events.push_back(NetworkBehaviourAction::SendEvent {
+                peer_id: peer_id.clone(),
+                event: FloodsubSendEvent::Publish(FloodsubRpc {
+                    messages,
+                    subscriptions: Vec::new(),
+                    control: Vec::new(),
                 }),
             });
         }
@@ -193,9 +444,15 @@ impl<TSubstream> Floodsub<TSubstream> {
                         topic: topic.clone(),
                         action: FloodsubSubscriptionAction::Subscribe,
                     }],
+                    control: Vec::new(),
                 }),
             });
         }
+
+        // Ask for gossip instead of full messages outside of the mesh.
+        if self.mode == PubsubMode::Mesh {
+            self.send_control(vec![(peer_id.clone(), ControlAction::Mesh)]);
+        }
     }
 
     pub fn enable_incoming(&mut self, peer_id: &PeerId) {
@@ -225,7 +482,7 @@ where
     type OutEvent = FloodsubEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        FloodsubHandler::new()
+        FloodsubHandler::new(self.priorities.clone(), self.priority_queue_size)
     }
 
     fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
@@ -244,6 +501,9 @@ where
         debug_assert!(was_in);
         self.allowed_remotes.remove(id);
         self.unlocked_remotes.remove(id);
+        self.mesh.remove_peer(id);
+        self.mesh_remotes.remove(id);
+        self.throttled.retain(|(peer_id, _)| peer_id != id);
         super::metrics::CONNECTED_PEERS.set(self.connected_peers.len() as i64);
         super::metrics::UNLOCKED_PEERS.set(self.unlocked_remotes.len() as i64);
     }
@@ -254,13 +514,13 @@ where
             .or_insert(RollingRateCounter::new(PUBSUB_SAMPLES))
             .update();
 
-        if !self.allowed_remotes.contains(&propagation_source) {
-            debug!(target: "stegos_network::pubsub", "event from unwanted peer, dropping: peer_id={}", propagation_source);
-            return;
-        }
-
         match event {
             FloodsubRecvEvent::Message(event) => {
+                if !self.allowed_remotes.contains(&propagation_source) {
+                    debug!(target: "stegos_network::pubsub", "event from unwanted peer, dropping: peer_id={}", propagation_source);
+                    return;
+                }
+
                 // Update connected peers topics
                 for subscription in event.subscriptions {
                     let remote_peer_topics = self
@@ -286,6 +546,8 @@ where
                             {
                                 remote_peer_topics.remove(pos);
                             }
+                            self.mesh
+                                .remove_peer_from_topic(&propagation_source, &subscription.topic);
                             self.events.push_back(NetworkBehaviourAction::GenerateEvent(
                                 FloodsubEvent::Unsubscribed {
                                     peer_id: propagation_source.clone(),
@@ -296,22 +558,46 @@ where
                     }
                 }
 
+                if self.mode == PubsubMode::Mesh && !event.control.is_empty() {
+                    self.handle_control(&propagation_source, event.control);
+                }
+
                 // List of messages we're going to propagate on the network.
                 let mut rpcs_to_dispatch: Vec<(PeerId, FloodsubRpc)> = Vec::new();
 
                 for message in event.messages {
                     // Use `self.received` to skip the messages that we have already received in the past.
                     // Note that this can false positive.
-                    if self.received.contains_key(&message.digest()) {
+                    let id = message.digest();
+                    if self.received.contains_key(&id) {
                         trace!(target: "stegos_network::pubsub", "LRU cache hit");
                         super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
                         continue;
                     } else {
-                        self.received.notify_insert(message.digest(), ());
+                        self.received.notify_insert(id, ());
                     }
                     super::metrics::LRU_CACHE_SIZE.set(self.received.len() as i64);
                     trace!(target: "stegos_network::pubsub", "processing message: peer_id={}", propagation_source);
 
+                    // Forward after validation.
+                    if self.validated_topics.contains(&message.topic) {
+                        let event = FloodsubEvent::ValidationRequest {
+                            id,
+                            message: message.clone(),
+                        };
+                        self.events
+                            .push_back(NetworkBehaviourAction::GenerateEvent(event));
+                        self.pending.insert(
+                            id,
+                            PendingMessage {
+                                message,
+                                source: propagation_source.clone(),
+                                received: Instant::now(),
+                            },
+                        );
+                        continue;
+                    }
+
                     // Add the message to be dispatched to the user.
                     if self.subscribed_topics.iter().any(|t| t == &message.topic) {
                         let event = FloodsubEvent::Message(message.clone());
@@ -326,23 +612,17 @@ where
                     }
 
                     // Propagate the message to everyone else who is subscribed to any of the topics.
-                    for (peer_id, subscr_topics) in self.unlocked_remotes.iter() {
-                        if peer_id == &propagation_source {
-                            continue;
-                        }
-
-                        if !subscr_topics.iter().any(|t| t == &message.topic) {
-                            continue;
-                        }
-
-                        if let Some(pos) = rpcs_to_dispatch.iter().position(|(p, _)| p == peer_id) {
+                    for peer_id in self.forward_peers(&propagation_source, &message) {
+                        if let Some(pos) = rpcs_to_dispatch.iter().position(|(p, _)| p == &peer_id)
+                        {
                             rpcs_to_dispatch[pos].1.messages.push(message.clone());
                         } else {
                             rpcs_to_dispatch.push((
-                                peer_id.clone(),
+                                peer_id,
                                 FloodsubRpc {
                                     subscriptions: Vec::new(),
                                     messages: vec![message.clone()],
+                                    control: Vec::new(),
                                 },
                             ));
                         }
@@ -356,6 +636,15 @@ where
                     });
                 }
             }
+            FloodsubRecvEvent::InvalidRpc => {
+                debug!(target: "stegos_network::pubsub", "invalid message received: peer_id={}", propagation_source);
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    FloodsubEvent::Misbehaving {
+                        peer_id: propagation_source,
+                        misbehavior: Misbehavior::InvalidRpc,
+                    },
+                ));
+            }
         }
     }
 
@@ -375,6 +664,15 @@ where
                         metrics::INCOMING_RATES
                             .with_label_values(&[&peer_id.clone().to_base58()])
                             .set(counter.rate());
+                        if counter.rate() > MAX_INCOMING_RATE {
+                            debug!(target: "stegos_network::pubsub", "peer is flooding: peer_id={}, rate={}", peer_id, counter.rate());
+                            self.events.push_back(NetworkBehaviourAction::GenerateEvent(
+                                FloodsubEvent::Misbehaving {
+                                    peer_id: peer_id.clone(),
+                                    misbehavior: Misbehavior::Flooding,
+                                },
+                            ));
+                        }
                     }
                     self.metrics_update_delay
                         .reset(Instant::now() + METRICS_UPDATE_INTERVAL);
@@ -387,20 +685,173 @@ where
             }
         }
 
-        if let Some(event) = self.events.pop_front() {
-            return Async::Ready(event);
+        loop {
+            match self.heartbeat_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    if self.mode == PubsubMode::Mesh {
+                        let remotes = self.mesh_capable_remotes();
+                        let actions = self.mesh.heartbeat(&remotes, self.relaying);
+                        self.send_control(actions);
+                        for (topic, size) in self.mesh.mesh_sizes() {
+                            metrics::MESH_PEERS
+                                .with_label_values(&[topic.as_str()])
+                                .set(size as i64);
+                        }
+                    }
+                    self.expire_pending();
+                    self.heartbeat_delay
+                        .reset(Instant::now() + mesh::HEARTBEAT_INTERVAL);
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => {
+                    debug!(target: "stegos_network::pubsub", "heartbeat timer error: error={}", e);
+                    break;
+                }
+            }
+        }
+
+        if let Some((peer_id, rpc)) = self.release_throttled() {
+            return Async::Ready(NetworkBehaviourAction::SendEvent {
+                peer_id,
+                event: FloodsubSendEvent::Publish(rpc),
+            });
+        }
+
+        while let Some(event) = self.events.pop_front() {
+            match event {
+                NetworkBehaviourAction::SendEvent {
+                    peer_id,
+                    event: FloodsubSendEvent::Publish(rpc),
+                } => {
+                    if let Some(rpc) = self.throttle(peer_id.clone(), rpc) {
+                        return Async::Ready(NetworkBehaviourAction::SendEvent {
+                            peer_id,
+                            event: FloodsubSendEvent::Publish(rpc),
+                        });
+                    }
+                }
+                event => return Async::Ready(event),
+            }
+        }
+
+        if !self.throttled.is_empty() {
+            // Wake up when rate limits allow to send again.
+            if let Err(e) = self.throttle_delay.poll() {
+                debug!(target: "stegos_network::pubsub", "throttle timer error: error={}", e);
+            }
         }
 
         Async::NotReady
     }
 }
 
+impl<TSubstream> Floodsub<TSubstream> {
+    /// Returns the RPC if it can be sent now, otherwise holds it back.
+    /// High priority (consensus) messages are never limited.
+    fn throttle(&mut self, peer_id: PeerId, rpc: FloodsubRpc) -> Option<FloodsubRpc> {
+        let priority = rpc_priority(&self.priorities, &rpc);
+        if priority == MessagePriority::High || is_unlimited(&rpc) {
+            return Some(rpc);
+        }
+        // Keep the order of messages to the same peer.
+        if !self.throttled.iter().any(|(p, _)| *p == peer_id) {
+            let result = self.rate_limiter.lock().unwrap().check(
+                RATE_LIMIT_PROTOCOL,
+                &peer_id,
+                rpc_size(&rpc),
+            );
+            match result {
+                Ok(()) => return Some(rpc),
+                Err(retry_at) => self.schedule_retry(retry_at),
+            }
+        }
+        trace!(target: "stegos_network::pubsub", "rate limited: peer_id={}", peer_id);
+        if self.throttled.len() >= MAX_THROTTLED_MESSAGES {
+            if let Some((_, dropped)) = self.throttled.pop_front() {
+                let priority = rpc_priority(&self.priorities, &dropped);
+                debug!(target: "stegos_network::pubsub", "too many rate limited messages, dropped the oldest: priority={:?}", priority);
+                network_metrics::DROPPED_MESSAGES
+                    .with_label_values(&[RATE_LIMIT_PROTOCOL, priority.label()])
+                    .inc();
+            }
+        }
+        self.throttled.push_back((peer_id, rpc));
+        None
+    }
+
+    /// Returns the first throttled RPC which can be sent now.
+    fn release_throttled(&mut self) -> Option<(PeerId, FloodsubRpc)> {
+        if self.throttled.is_empty() {
+            return None;
+        }
+        let mut limiter = self.rate_limiter.lock().unwrap();
+        let mut blocked: HashSet<&PeerId> = HashSet::new();
+        let mut retry_at: Option<Instant> = None;
+        let mut released: Option<usize> = None;
+        for (i, (peer_id, rpc)) in self.throttled.iter().enumerate() {
+            if blocked.contains(peer_id) {
+                continue;
+            }
+            match limiter.check(RATE_LIMIT_PROTOCOL, peer_id, rpc_size(rpc)) {
+                Ok(()) => {
+                    released = Some(i);
+                    break;
+                }
+                Err(at) => {
+                    blocked.insert(peer_id);
+                    retry_at = Some(retry_at.map_or(at, |r| r.min(at)));
+                }
+            }
+        }
+        drop(limiter);
+        if let Some(at) = retry_at {
+            self.schedule_retry(at);
+        }
+        released.and_then(|i| self.throttled.remove(i))
+    }
+
+    fn schedule_retry(&mut self, at: Instant) {
+        let deadline = self.throttle_delay.deadline();
+        if at < deadline || deadline <= Instant::now() {
+            self.throttle_delay.reset(at);
+        }
+    }
+}
+
+/// Returns true if all messages of the RPC belong to unlimited topics.
+fn is_unlimited(rpc: &FloodsubRpc) -> bool {
+    rpc.messages
+        .iter()
+        .all(|message| UNLIMITED_TOPICS.contains(&message.topic.as_str()))
+}
+
+/// Approximate size of the RPC for rate limits.
+fn rpc_size(rpc: &FloodsubRpc) -> usize {
+    rpc.messages
+        .iter()
+        .map(|message| message.data.len() + message.topic.len())
+        .sum()
+}
+
 /// Event that can happen on the floodsub behaviour.
 #[derive(Debug)]
 pub enum FloodsubEvent {
     /// A message has been received.
     Message(FloodsubMessage),
 
+    /// A message has been received on a validated topic.
+    /// It is forwarded after `Floodsub::validate()`.
+    ValidationRequest {
+        id: MessageId,
+        message: FloodsubMessage,
+    },
+
+    /// The remote has sent an invalid message or too many messages.
+    Misbehaving {
+        peer_id: PeerId,
+        misbehavior: Misbehavior,
+    },
+
     /// A remote subscribed to a topic.
     Subscribed {
         /// Remote that has subscribed.
@@ -429,4 +880,120 @@ pub enum FloodsubSendEvent {
 /// Event received from handler
 pub enum FloodsubRecvEvent {
     Message(FloodsubRpc),
+    /// The remote has sent a message which can't be decoded.
+    InvalidRpc,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use tokio::net::TcpStream;
+
+    fn floodsub(config: &NetworkConfig) -> Floodsub<TcpStream> {
+        let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(&config.rate_limits)));
+        Floodsub::new(config, PeerId::random(), true, rate_limiter)
+    }
+
+    fn rpc(topic: &str, data: Vec<u8>) -> FloodsubRpc {
+        FloodsubRpc {
+            messages: vec![FloodsubMessage {
+                topic: topic.to_string(),
+                data,
+            }],
+            subscriptions: Vec::new(),
+            control: Vec::new(),
+        }
+    }
+
+    fn add_peer(floodsub: &mut Floodsub<TcpStream>, topic: &str) -> PeerId {
+        let peer_id = PeerId::random();
+        let mut topics = SmallVec::new();
+        topics.push(topic.to_string());
+        floodsub.connected_peers.insert(peer_id.clone());
+        floodsub.allowed_remotes.insert(peer_id.clone());
+        floodsub.unlocked_remotes.insert(peer_id.clone(), topics);
+        peer_id
+    }
+
+    #[test]
+    fn flood_peers_in_mesh_mode() {
+        let topic = "topic";
+        let mut config = NetworkConfig::default();
+        config.pubsub_mode = PubsubMode::Mesh;
+        config.pubsub_mesh_degree = 1;
+        let mut floodsub = floodsub(&config);
+        let mesh_peers: Vec<PeerId> = (0..3).map(|_| add_peer(&mut floodsub, topic)).collect();
+        let flood_peer = add_peer(&mut floodsub, topic);
+        for peer_id in &mesh_peers {
+            floodsub.handle_control(peer_id, vec![ControlAction::Mesh]);
+        }
+        assert!(floodsub.subscribe(topic.to_string()));
+
+        // Only mesh nodes are grafted.
+        let grafted = floodsub.mesh.peers(topic).unwrap().clone();
+        assert_eq!(grafted.len(), 1);
+        assert!(!grafted.contains(&flood_peer));
+
+        let message = FloodsubMessage {
+            topic: topic.to_string(),
+            data: vec![1, 2, 3],
+        };
+        let source = floodsub.local_peer_id.clone();
+        let peers: HashSet<PeerId> = floodsub
+            .forward_peers(&source, &message)
+            .into_iter()
+            .collect();
+        let mut expected = grafted;
+        expected.insert(flood_peer.clone());
+        assert_eq!(peers, expected);
+
+        // Flood peers don't get gossip.
+        let remotes = floodsub.mesh_capable_remotes();
+        for (peer_id, action) in floodsub.mesh.heartbeat(&remotes, true) {
+            assert_ne!(peer_id, flood_peer);
+            if let ControlAction::IHave { message_ids, .. } = action {
+                assert_eq!(message_ids, vec![message.digest()]);
+            }
+        }
+    }
+
+    #[test]
+    fn throttle() {
+        let mut config = NetworkConfig::default();
+        config.rate_limits.pubsub = 100_000;
+        // Block topics are never limited, even if configured otherwise.
+        config
+            .topic_priorities
+            .insert("block".to_string(), MessagePriority::Low);
+        let mut floodsub = floodsub(&config);
+        let peer_id = PeerId::random();
+
+        // Exhaust the bucket, with 100ms of debt.
+        assert!(floodsub
+            .throttle(peer_id.clone(), rpc("tx", vec![0u8; 110_000]))
+            .is_some());
+        for i in 1..4u8 {
+            assert!(floodsub
+                .throttle(peer_id.clone(), rpc("tx", vec![i]))
+                .is_none());
+        }
+        assert_eq!(floodsub.throttled.len(), 3);
+
+        // High priority and unlimited topics bypass the limiter.
+        for topic in &["consensus", "view_changes", "block"] {
+            let sent = floodsub.throttle(peer_id.clone(), rpc(topic, vec![0u8; 1000]));
+            assert_eq!(sent.unwrap().messages[0].topic, *topic);
+        }
+        assert_eq!(floodsub.throttled.len(), 3);
+
+        // Throttled messages are released in order.
+        thread::sleep(Duration::from_millis(150));
+        for i in 1..4u8 {
+            let (released_peer, rpc) = floodsub.release_throttled().unwrap();
+            assert_eq!(released_peer, peer_id);
+            assert_eq!(rpc.messages[0].data, vec![i]);
+        }
+        assert!(floodsub.release_throttled().is_none());
+    }
 }
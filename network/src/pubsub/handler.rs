@@ -22,17 +22,25 @@ use super::behavior::{FloodsubRecvEvent, FloodsubSendEvent};
 use super::protocol::{FloodsubCodec, FloodsubConfig, FloodsubRpc};
 
 use futures::prelude::*;
+use futures::task;
 use libp2p_core::upgrade::{InboundUpgrade, Negotiated, OutboundUpgrade};
 use libp2p_swarm::protocols_handler::{
     KeepAlive, ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr, SubstreamProtocol,
 };
 use log::{debug, trace};
-use smallvec::SmallVec;
 use std::{fmt, io, time::Instant};
 use tokio::codec::Framed;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use crate::config::MessagePriority;
+use crate::metrics;
+use crate::utils::PriorityQueue;
 use crate::NETWORK_IDLE_TIMEOUT;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Messages are queued while so many outbound substreams are being sent.
+const MAX_OUTBOUND_SUBSTREAMS: usize = 4;
 
 /// Protocol handler that handles communication with the remote for the floodsub protocol.
 ///
@@ -51,7 +59,11 @@ where
     /// KeepAlive status
     keep_alive: KeepAlive,
     /// Queue of values that we want to send to the remote.
-    send_queue: SmallVec<[FloodsubRpc; 16]>,
+    send_queue: PriorityQueue<FloodsubRpc>,
+    /// Outbound substreams requested, but not negotiated yet.
+    pending_outbound: usize,
+    /// Priority classes of topics.
+    priorities: Arc<HashMap<String, MessagePriority>>,
 }
 
 /// State of an active substream, opened either by us or by the remote.
@@ -89,16 +101,51 @@ where
     TSubstream: AsyncRead + AsyncWrite,
 {
     /// Builds a new `FloodsubHandler`.
-    pub fn new() -> Self {
+    pub fn new(priorities: Arc<HashMap<String, MessagePriority>>, queue_size: usize) -> Self {
         FloodsubHandler {
             config: FloodsubConfig::new(),
             substreams: Vec::new(),
             keep_alive: KeepAlive::Yes,
-            send_queue: SmallVec::new(),
+            send_queue: PriorityQueue::new(queue_size),
+            pending_outbound: 0,
+            priorities,
+        }
+    }
+
+    /// Number of outbound substreams which haven't sent their message yet.
+    fn outbound_substreams(&self) -> usize {
+        let sending = self
+            .substreams
+            .iter()
+            .filter(|substream| match substream {
+                SubstreamState::PendingSend(..) | SubstreamState::PendingFlush(..) => true,
+                _ => false,
+            })
+            .count();
+        self.pending_outbound + sending
+    }
+
+    /// An outbound substream has sent its message, schedule the next one.
+    fn outbound_finished(&self) {
+        if !self.send_queue.is_empty() {
+            task::current().notify();
         }
     }
 }
 
+/// The highest priority of topics in the RPC.
+/// Subscriptions and control messages are always sent first.
+pub(crate) fn rpc_priority(
+    priorities: &HashMap<String, MessagePriority>,
+    rpc: &FloodsubRpc,
+) -> MessagePriority {
+    rpc.messages
+        .iter()
+        .map(|message| priorities.get(&message.topic).cloned().unwrap_or_default())
+        .min()
+        .unwrap_or(MessagePriority::High)
+}
+
 impl<TSubstream> ProtocolsHandler for FloodsubHandler<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
@@ -128,6 +175,7 @@ where
         protocol: <Self::OutboundProtocol as OutboundUpgrade<TSubstream>>::Output,
         message: Self::OutboundOpenInfo,
     ) {
+        self.pending_outbound -= 1;
         self.substreams
             .push(SubstreamState::PendingSend(protocol, message))
     }
@@ -136,7 +184,13 @@ where
     fn inject_event(&mut self, event: Self::InEvent) {
         match event {
             FloodsubSendEvent::Publish(message) => {
-                self.send_queue.push(message);
+                let priority = rpc_priority(&self.priorities, &message);
+                if self.send_queue.push(priority, message).is_some() {
+                    debug!(target: "stegos_network::pubsub", "send queue is full, dropped the oldest message: priority={:?}", priority);
+                    metrics::DROPPED_MESSAGES
+                        .with_label_values(&["pubsub", priority.label()])
+                        .inc();
+                }
             }
         }
     }
@@ -149,6 +203,7 @@ where
             <Self::OutboundProtocol as OutboundUpgrade<Self::Substream>>::Error,
         >,
     ) {
+        self.pending_outbound -= 1;
         trace!(target: "stegos_network::pubsub", "got dial outbound failure: {}", e);
     }
 
@@ -163,14 +218,16 @@ where
         ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent>,
         io::Error,
     > {
-        if !self.send_queue.is_empty() {
-            let message = self.send_queue.remove(0);
-            return Ok(Async::Ready(
-                ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                    info: message,
-                    protocol: SubstreamProtocol::new(self.config.clone()),
-                },
-            ));
+        if self.outbound_substreams() < MAX_OUTBOUND_SUBSTREAMS {
+            if let Some(message) = self.send_queue.pop() {
+                self.pending_outbound += 1;
+                return Ok(Async::Ready(
+                    ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                        info: message,
+                        protocol: SubstreamProtocol::new(self.config.clone()),
+                    },
+                ));
+            }
         }
 
         for n in (0..self.substreams.len()).rev() {
@@ -193,6 +250,12 @@ where
                         }
                         Err(e) => {
                             debug!(target: "stegos_network::pubsub", "error reading from substream: error={}", e);
+                            if e.kind() == io::ErrorKind::InvalidData {
+                                self.substreams.push(SubstreamState::Closing(substream));
+                                return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
+                                    FloodsubRecvEvent::InvalidRpc,
+                                )));
+                            }
                             SubstreamState::Closing(substream)
                         }
                     },
@@ -206,13 +269,17 @@ where
                             }
                             Err(e) => {
                                 debug!(target: "stegos_network::pubsub", "error sending to substream: error={}", e);
+                                self.outbound_finished();
                                 SubstreamState::Closing(substream)
                             }
                         }
                     }
                     SubstreamState::PendingFlush(mut substream) => {
                         match substream.poll_complete() {
-                            Ok(Async::Ready(())) => SubstreamState::Closing(substream),
+                            Ok(Async::Ready(())) => {
+                                self.outbound_finished();
+                                SubstreamState::Closing(substream)
+                            }
                             Ok(Async::NotReady) => {
                                 self.substreams
                                     .push(SubstreamState::PendingFlush(substream));
@@ -220,6 +287,7 @@ where
                             }
                             Err(e) => {
                                 debug!(target: "stegos_network::pubsub", "error flushing substream: error={}", e);
+                                self.outbound_finished();
                                 SubstreamState::Closing(substream)
                             }
                         }
@@ -242,7 +310,7 @@ where
             }
         }
 
-        if self.substreams.is_empty() {
+        if self.substreams.is_empty() && self.send_queue.is_empty() && self.pending_outbound == 0 {
             self.keep_alive = KeepAlive::Until(Instant::now() + NETWORK_IDLE_TIMEOUT);
         } else {
             self.keep_alive = KeepAlive::Yes;
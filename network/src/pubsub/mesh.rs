@@ -0,0 +1,365 @@
+//! Mesh Routing.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Mesh routing (gossipsub).
+//!
+//! Every subscribed topic has a mesh of `degree` peers which receive full
+//! messages. The mesh is kept between `degree_low` and `degree_high` peers
+//! by GRAFT and PRUNE on each heartbeat. Other peers subscribed to the topic
+//! receive only ids of recent messages (IHAVE) and request missing ones (IWANT).
+//! Messages are kept for IWANT during `HISTORY_LENGTH` heartbeats, and at most
+//! `MAX_IWANT_IDS` of them are served per request.
+//!
+//! Mesh nodes announce themselves by the MESH control flag. Peers which haven't
+//! announced it route messages by flooding, ignore gossip and always receive
+//! full messages, so the mesh is built only from mesh nodes.
+//!
+
+use super::protocol::{ControlAction, FloodsubMessage, MessageId};
+use libp2p_core::PeerId;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::thread_rng;
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of heartbeats to keep messages for IWANT.
+const HISTORY_LENGTH: usize = 5;
+/// Number of heartbeats to gossip message ids.
+const HISTORY_GOSSIP: usize = 3;
+/// Maximal number of message ids served per IWANT, the rest is ignored.
+pub const MAX_IWANT_IDS: usize = 512;
+
+/// Topics of remote peers.
+pub type RemoteTopics = HashMap<PeerId, SmallVec<[String; 8]>>;
+
+/// Recent messages, split by heartbeats.
+struct MessageCache {
+    messages: HashMap<MessageId, FloodsubMessage>,
+    /// The current heartbeat first.
+    history: VecDeque<Vec<(MessageId, String)>>,
+}
+
+impl MessageCache {
+    fn new() -> Self {
+        let mut history = VecDeque::with_capacity(HISTORY_LENGTH);
+        history.push_front(Vec::new());
+        MessageCache {
+            messages: HashMap::new(),
+            history,
+        }
+    }
+
+    fn put(&mut self, id: MessageId, message: FloodsubMessage) {
+        if self.messages.contains_key(&id) {
+            return;
+        }
+        self.history[0].push((id, message.topic.clone()));
+        self.messages.insert(id, message);
+    }
+
+    fn get(&self, id: &MessageId) -> Option<&FloodsubMessage> {
+        self.messages.get(id)
+    }
+
+    fn gossip_ids(&self, topic: &str) -> Vec<MessageId> {
+        self.history
+            .iter()
+            .take(HISTORY_GOSSIP)
+            .flat_map(|window| window.iter())
+            .filter(|(_, t)| t == topic)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn shift(&mut self) {
+        if self.history.len() == HISTORY_LENGTH {
+            for (id, _) in self.history.pop_back().unwrap() {
+                self.messages.remove(&id);
+            }
+        }
+        self.history.push_front(Vec::new());
+    }
+}
+
+pub struct Mesh {
+    degree: usize,
+    degree_low: usize,
+    degree_high: usize,
+    /// Mesh peers of subscribed topics.
+    topics: HashMap<String, HashSet<PeerId>>,
+    cache: MessageCache,
+}
+
+impl Mesh {
+    pub fn new(degree: usize) -> Self {
+        let degree = degree.max(1);
+        Mesh {
+            degree,
+            degree_low: (degree * 2 / 3).max(1),
+            degree_high: degree * 2,
+            topics: HashMap::new(),
+            cache: MessageCache::new(),
+        }
+    }
+
+    /// Mesh peers of the topic.
+    pub fn peers(&self, topic: &str) -> Option<&HashSet<PeerId>> {
+        self.topics.get(topic)
+    }
+
+    /// Create a mesh for the newly subscribed topic.
+    pub fn join(&mut self, topic: &str, remotes: &RemoteTopics) -> Vec<(PeerId, ControlAction)> {
+        let mesh = self.topics.entry(topic.to_string()).or_default();
+        let candidates = remotes
+            .iter()
+            .filter(|(peer_id, topics)| {
+                !mesh.contains(*peer_id) && topics.iter().any(|t| t == topic)
+            })
+            .map(|(peer_id, _)| peer_id.clone());
+        let mut actions = Vec::new();
+        for peer_id in candidates.choose_multiple(&mut thread_rng(), self.degree) {
+            mesh.insert(peer_id.clone());
+            actions.push((
+                peer_id,
+                ControlAction::Graft {
+                    topic: topic.to_string(),
+                },
+            ));
+        }
+        actions
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        for mesh in self.topics.values_mut() {
+            mesh.remove(peer_id);
+        }
+    }
+
+    pub fn remove_peer_from_topic(&mut self, peer_id: &PeerId, topic: &str) {
+        if let Some(mesh) = self.topics.get_mut(topic) {
+            mesh.remove(peer_id);
+        }
+    }
+
+    /// The remote has added us to its mesh. Returns PRUNE if we are not subscribed.
+    pub fn handle_graft(&mut self, peer_id: &PeerId, topic: String) -> Option<ControlAction> {
+        match self.topics.get_mut(&topic) {
+            Some(mesh) => {
+                mesh.insert(peer_id.clone());
+                None
+            }
+            None => Some(ControlAction::Prune { topic }),
+        }
+    }
+
+    /// Remember a message for IHAVE and IWANT.
+    pub fn cache_message(&mut self, message: &FloodsubMessage) {
+        self.cache.put(message.digest(), message.clone());
+    }
+
+    pub fn cached_message(&self, id: &MessageId) -> Option<&FloodsubMessage> {
+        self.cache.get(id)
+    }
+
+    ///
+    /// Keep the size of every mesh between `degree_low` and `degree_high`
+    /// and gossip ids of recent messages to peers out of the mesh.
+    ///
+    pub fn heartbeat(
+        &mut self,
+        remotes: &RemoteTopics,
+        gossip: bool,
+    ) -> Vec<(PeerId, ControlAction)> {
+        let mut rng = thread_rng();
+        let mut actions = Vec::new();
+        for (topic, mesh) in self.topics.iter_mut() {
+            // Peers might have unsubscribed without notice.
+            mesh.retain(|peer_id| {
+                remotes
+                    .get(peer_id)
+                    .map_or(false, |topics| topics.iter().any(|t| t == topic))
+            });
+
+            if mesh.len() < self.degree_low {
+                let candidates = remotes
+                    .iter()
+                    .filter(|(peer_id, topics)| {
+                        !mesh.contains(*peer_id) && topics.iter().any(|t| t == topic)
+                    })
+                    .map(|(peer_id, _)| peer_id.clone());
+                for peer_id in candidates.choose_multiple(&mut rng, self.degree - mesh.len()) {
+                    mesh.insert(peer_id.clone());
+                    actions.push((
+                        peer_id,
+                        ControlAction::Graft {
+                            topic: topic.clone(),
+                        },
+                    ));
+                }
+            } else if mesh.len() > self.degree_high {
+                let mut peers: Vec<PeerId> = mesh.iter().cloned().collect();
+                peers.shuffle(&mut rng);
+                for peer_id in peers.into_iter().skip(self.degree) {
+                    mesh.remove(&peer_id);
+                    actions.push((
+                        peer_id,
+                        ControlAction::Prune {
+                            topic: topic.clone(),
+                        },
+                    ));
+                }
+            }
+
+            if !gossip {
+                continue;
+            }
+            let message_ids = self.cache.gossip_ids(topic);
+            if message_ids.is_empty() {
+                continue;
+            }
+            let candidates = remotes
+                .iter()
+                .filter(|(peer_id, topics)| {
+                    !mesh.contains(*peer_id) && topics.iter().any(|t| t == topic)
+                })
+                .map(|(peer_id, _)| peer_id.clone());
+            for peer_id in candidates.choose_multiple(&mut rng, self.degree) {
+                actions.push((
+                    peer_id,
+                    ControlAction::IHave {
+                        topic: topic.clone(),
+                        message_ids: message_ids.clone(),
+                    },
+                ));
+            }
+        }
+        self.cache.shift();
+        actions
+    }
+
+    pub fn mesh_sizes(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.topics.iter().map(|(topic, mesh)| (topic, mesh.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remotes(count: usize, topic: &str) -> RemoteTopics {
+        (0..count)
+            .map(|_| {
+                let mut topics = SmallVec::new();
+                topics.push(topic.to_string());
+                (PeerId::random(), topics)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn maintain_degree() {
+        let topic = "topic";
+        let mut mesh = Mesh::new(3);
+        let mut remotes = remotes(20, topic);
+
+        let actions = mesh.join(topic, &remotes);
+        assert_eq!(actions.len(), 3);
+        assert_eq!(mesh.peers(topic).unwrap().len(), 3);
+
+        // Grafts from other peers grow the mesh over degree_high.
+        for peer_id in remotes.keys() {
+            assert_eq!(mesh.handle_graft(peer_id, topic.to_string()), None);
+        }
+        assert_eq!(mesh.peers(topic).unwrap().len(), 20);
+        let actions = mesh.heartbeat(&remotes, false);
+        assert_eq!(actions.len(), 17);
+        for (_, action) in &actions {
+            match action {
+                ControlAction::Prune { .. } => {}
+                _ => panic!("expected PRUNE"),
+            }
+        }
+        assert_eq!(mesh.peers(topic).unwrap().len(), 3);
+
+        // Disconnected peers are replaced.
+        let mesh_peers: Vec<PeerId> = mesh.peers(topic).unwrap().iter().cloned().collect();
+        for peer_id in &mesh_peers {
+            remotes.remove(peer_id);
+            mesh.remove_peer(peer_id);
+        }
+        let actions = mesh.heartbeat(&remotes, false);
+        assert_eq!(actions.len(), 3);
+        assert_eq!(mesh.peers(topic).unwrap().len(), 3);
+
+        // Not subscribed.
+        let peer_id = PeerId::random();
+        assert_eq!(
+            mesh.handle_graft(&peer_id, "other".to_string()),
+            Some(ControlAction::Prune {
+                topic: "other".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn gossip() {
+        let topic = "topic";
+        let mut mesh = Mesh::new(3);
+        let remotes = remotes(10, topic);
+        mesh.join(topic, &remotes);
+        let message = FloodsubMessage {
+            topic: topic.to_string(),
+            data: vec![1, 2, 3],
+        };
+        let id = message.digest();
+        mesh.cache_message(&message);
+        assert_eq!(mesh.cached_message(&id), Some(&message));
+
+        for _ in 0..HISTORY_GOSSIP {
+            let actions = mesh.heartbeat(&remotes, true);
+            let ihave: Vec<&PeerId> = actions
+                .iter()
+                .filter_map(|(peer_id, a)| match a {
+                    ControlAction::IHave { message_ids, .. } => {
+                        assert_eq!(message_ids, &vec![id]);
+                        Some(peer_id)
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(ihave.len(), 3);
+            assert!(ihave
+                .iter()
+                .all(|peer_id| !mesh.peers(topic).unwrap().contains(peer_id)));
+        }
+        assert!(mesh.heartbeat(&remotes, true).is_empty());
+        assert!(mesh.cached_message(&id).is_some());
+        for _ in HISTORY_GOSSIP + 1..HISTORY_LENGTH {
+            mesh.heartbeat(&remotes, true);
+        }
+        assert!(mesh.cached_message(&id).is_none());
+    }
+}
@@ -42,7 +42,19 @@ lazy_static! {
     .unwrap();
     pub static ref INCOMING_PUBSUB_TRAFFIC: IntCounterVec = register_int_counter_vec!(
         "stegos_pubsub_incoming_traffic_per_topic",
-        "Outgoing bytes per topic",
+        "Incoming bytes per topic",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref OUTGOING_PUBSUB_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "stegos_pubsub_outgoing_messages_per_topic",
+        "Outgoing messages per topic",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref INCOMING_PUBSUB_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "stegos_pubsub_incoming_messages_per_topic",
+        "Incoming messages per topic",
         &["topic"]
     )
     .unwrap();
@@ -51,6 +63,12 @@ lazy_static! {
         "Total count of connected peers"
     )
     .unwrap();
+    pub static ref MESH_PEERS: IntGaugeVec = register_int_gauge_vec!(
+        "stegos_pubsub_mesh_peers",
+        "Count of mesh peers per topic",
+        &["topic"]
+    )
+    .unwrap();
     pub static ref UNLOCKED_PEERS: IntGauge =
         register_int_gauge!("stegos_pubsub_unlocked_peers", "Count of unlocked peers").unwrap();
 }
@@ -20,13 +20,15 @@
 
 //! Implements the floodsub protocol, see also the:
 //! [spec](https://github.com/libp2p/specs/tree/master/pubsub).
+//! In the mesh mode, messages are routed like in gossipsub.
 
 pub mod handler;
 pub mod protocol;
 
 mod behavior;
-mod metrics;
+mod mesh;
+pub(crate) mod metrics;
 mod proto;
 
 pub use self::behavior::{Floodsub, FloodsubEvent};
-pub use self::protocol::{FloodsubMessage, FloodsubRpc};
+pub use self::protocol::{message_id, ControlAction, FloodsubMessage, FloodsubRpc, MessageId};
@@ -26,9 +26,8 @@ use bytes::{BufMut, BytesMut};
 use futures::future;
 use libp2p_core::{upgrade::Negotiated, InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use protobuf::Message as ProtobufMessage;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use std::{io, iter};
+use stegos_crypto::hash::{Hashable, Hasher};
 use tokio::codec::{Decoder, Encoder, Framed};
 use tokio::io::{AsyncRead, AsyncWrite};
 use unsigned_varint::codec;
@@ -113,6 +112,9 @@ impl Encoder for FloodsubCodec {
             pubsub_metrics::OUTGOING_PUBSUB_TRAFFIC
                 .with_label_values(&[&message.topic])
                 .inc_by(message.data.len() as i64);
+            pubsub_metrics::OUTGOING_PUBSUB_MESSAGES
+                .with_label_values(&[&message.topic])
+                .inc();
             let mut msg = rpc_proto::Message::new();
             msg.set_data(message.data);
             msg.set_topic(message.topic);
@@ -126,13 +128,41 @@ impl Encoder for FloodsubCodec {
             proto.mut_subscriptions().push(subscription);
         }
 
+        for action in item.control.into_iter() {
+            let control = proto.mut_control();
+            match action {
+                ControlAction::IHave { topic, message_ids } => {
+                    let mut ihave = rpc_proto::ControlIHave::new();
+                    ihave.set_topic(topic);
+                    ihave.set_message_ids(message_ids);
+                    control.mut_ihave().push(ihave);
+                }
+                ControlAction::IWant { message_ids } => {
+                    let mut iwant = rpc_proto::ControlIWant::new();
+                    iwant.set_message_ids(message_ids);
+                    control.mut_iwant().push(iwant);
+                }
+                ControlAction::Graft { topic } => {
+                    let mut graft = rpc_proto::ControlGraft::new();
+                    graft.set_topic(topic);
+                    control.mut_graft().push(graft);
+                }
+                ControlAction::Prune { topic } => {
+                    let mut prune = rpc_proto::ControlPrune::new();
+                    prune.set_topic(topic);
+                    control.mut_prune().push(prune);
+                }
+                ControlAction::Mesh => {
+                    control.set_mesh(true);
+                }
+            }
+        }
+
         let msg_size = proto.compute_size();
         // Reserve enough space for the data and the length. The length has a maximum of 32 bits,
         // which means that 5 bytes is enough for the variable-length integer.
         dst.reserve(msg_size as usize + 5);
-        metrics::OUTGOING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(msg_size as i64);
+        metrics::outgoing(PROTOCOL_LABEL, msg_size as usize);
 
         proto
             .write_length_delimited_to_writer(&mut dst.by_ref().writer())
@@ -154,11 +184,10 @@ impl Decoder for FloodsubCodec {
             None => return Ok(None),
         };
 
-        metrics::INCOMING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(packet.len() as i64);
+        metrics::incoming(PROTOCOL_LABEL, packet.len());
 
-        let mut rpc: rpc_proto::RPC = protobuf::parse_from_bytes(&packet)?;
+        let mut rpc: rpc_proto::RPC = protobuf::parse_from_bytes(&packet)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
         let mut messages = Vec::with_capacity(rpc.get_publish().len());
         for mut publish in rpc.take_publish().into_iter() {
@@ -167,9 +196,39 @@ impl Decoder for FloodsubCodec {
             pubsub_metrics::INCOMING_PUBSUB_TRAFFIC
                 .with_label_values(&[&topic])
                 .inc_by(data.len() as i64);
+            pubsub_metrics::INCOMING_PUBSUB_MESSAGES
+                .with_label_values(&[&topic])
+                .inc();
             messages.push(FloodsubMessage { data, topic });
         }
 
+        let mut control = Vec::new();
+        let mut proto_control = rpc.take_control();
+        for mut ihave in proto_control.take_ihave().into_iter() {
+            control.push(ControlAction::IHave {
+                topic: ihave.take_topic(),
+                message_ids: ihave.take_message_ids(),
+            });
+        }
+        for mut iwant in proto_control.take_iwant().into_iter() {
+            control.push(ControlAction::IWant {
+                message_ids: iwant.take_message_ids(),
+            });
+        }
+        for mut graft in proto_control.take_graft().into_iter() {
+            control.push(ControlAction::Graft {
+                topic: graft.take_topic(),
+            });
+        }
+        for mut prune in proto_control.take_prune().into_iter() {
+            control.push(ControlAction::Prune {
+                topic: prune.take_topic(),
+            });
+        }
+        if proto_control.get_mesh() {
+            control.push(ControlAction::Mesh);
+        }
+
         Ok(Some(FloodsubRpc {
             messages,
             subscriptions: rpc
@@ -184,6 +243,7 @@ impl Decoder for FloodsubCodec {
                     topic: sub.take_topic(),
                 })
                 .collect(),
+            control,
         }))
     }
 }
@@ -195,6 +255,8 @@ pub struct FloodsubRpc {
     pub messages: Vec<FloodsubMessage>,
     /// List of subscriptions.
     pub subscriptions: Vec<FloodsubSubscription>,
+    /// Mesh control messages.
+    pub control: Vec<ControlAction>,
 }
 
 /// A message received by the floodsub system.
@@ -207,14 +269,26 @@ pub struct FloodsubMessage {
     pub data: Vec<u8>,
 }
 
+/// Identifier of a message, the same on all nodes.
+pub type MessageId = u64;
+
 impl FloodsubMessage {
-    pub fn digest(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        self.hash(&mut hasher);
-        hasher.finish()
+    pub fn digest(&self) -> MessageId {
+        message_id(&self.topic, &self.data)
     }
 }
 
+/// Identifier of a message on the topic.
+pub fn message_id(topic: &str, data: &[u8]) -> MessageId {
+    let mut hasher = Hasher::new();
+    topic.hash(&mut hasher);
+    data.hash(&mut hasher);
+    let hash = hasher.result();
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&hash.base_vector()[..8]);
+    MessageId::from_be_bytes(id)
+}
+
 /// A subscription received by the floodsub system.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FloodsubSubscription {
@@ -224,6 +298,25 @@ pub struct FloodsubSubscription {
     pub topic: String,
 }
 
+/// Mesh control message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ControlAction {
+    /// The remote has recently seen these messages.
+    IHave {
+        topic: String,
+        message_ids: Vec<MessageId>,
+    },
+    /// The remote requests these messages.
+    IWant { message_ids: Vec<MessageId> },
+    /// The remote has added us to its mesh for the topic.
+    Graft { topic: String },
+    /// The remote has removed us from its mesh for the topic.
+    Prune { topic: String },
+    /// The remote routes messages in the mesh mode and expects gossip
+    /// instead of full messages outside of its mesh.
+    Mesh,
+}
+
 /// Action that a subscription wants to perform.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FloodsubSubscriptionAction {
@@ -232,3 +325,46 @@ pub enum FloodsubSubscriptionAction {
     /// The remote wants to unsubscribe from the given topic.
     Unsubscribe,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_roundtrip() {
+        let rpc = FloodsubRpc {
+            messages: vec![FloodsubMessage {
+                topic: "topic".to_string(),
+                data: vec![1, 2, 3],
+            }],
+            subscriptions: vec![FloodsubSubscription {
+                action: FloodsubSubscriptionAction::Subscribe,
+                topic: "topic".to_string(),
+            }],
+            control: vec![
+                ControlAction::IHave {
+                    topic: "topic".to_string(),
+                    message_ids: vec![1, 2],
+                },
+                ControlAction::IWant {
+                    message_ids: vec![3],
+                },
+                ControlAction::Graft {
+                    topic: "topic".to_string(),
+                },
+                ControlAction::Prune {
+                    topic: "other".to_string(),
+                },
+                ControlAction::Mesh,
+            ],
+        };
+        let mut codec = FloodsubCodec {
+            length_prefix: Default::default(),
+        };
+        let mut buf = BytesMut::new();
+        codec.encode(rpc.clone(), &mut buf).unwrap();
+        let rpc2 = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(rpc, rpc2);
+        assert!(buf.is_empty());
+    }
+}
@@ -20,6 +20,7 @@
 // SOFTWARE.
 
 use super::handler::{HandlerInEvent, HandlerOutEvent, ReplicationHandler};
+use crate::utils::RateLimiter;
 use futures::prelude::*;
 use futures::sync::mpsc;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
@@ -29,7 +30,15 @@ use libp2p_swarm::{
 use log::*;
 use std::collections::VecDeque;
 use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_timer::Delay;
+
+/// Label of replication in rate limits.
+const RATE_LIMIT_PROTOCOL: &str = "replication";
+/// Size of the channel between the node and the rate limiter.
+const THROTTLED_BUFFER_SIZE: usize = 1;
 
 /// Replication event.
 #[derive(Debug)]
@@ -58,20 +67,87 @@ pub enum ReplicationEvent {
     },
 }
 
+/// Outgoing blocks of a downstream, forwarded to the network within rate limits.
+struct ThrottledStream {
+    peer_id: PeerId,
+    /// Blocks from the node.
+    rx: mpsc::Receiver<Vec<u8>>,
+    /// Blocks to the network.
+    tx: mpsc::Sender<Vec<u8>>,
+    /// A block waiting for the rate limits.
+    pending: Option<Vec<u8>>,
+    /// When to try to send the pending block again.
+    delay: Delay,
+}
+
+impl ThrottledStream {
+    /// Forwards blocks until the rate limits are exceeded.
+    /// Returns Ready when any side of the stream is closed.
+    fn poll(&mut self, rate_limiter: &Mutex<RateLimiter>) -> Poll<(), ()> {
+        loop {
+            if self.pending.is_none() {
+                match self.rx.poll()? {
+                    Async::Ready(Some(data)) => self.pending = Some(data),
+                    Async::Ready(None) => return Ok(Async::Ready(())),
+                    Async::NotReady => break,
+                }
+            }
+            // Wait for room in the network channel before spending tokens.
+            match self.tx.poll_ready().map_err(drop)? {
+                Async::Ready(()) => {}
+                Async::NotReady => break,
+            }
+            let size = self.pending.as_ref().unwrap().len();
+            let result =
+                rate_limiter
+                    .lock()
+                    .unwrap()
+                    .check(RATE_LIMIT_PROTOCOL, &self.peer_id, size);
+            if let Err(retry_at) = result {
+                trace!("[{}] Rate limited: retry_at={:?}", self.peer_id, retry_at);
+                self.delay.reset(retry_at);
+                match self.delay.poll() {
+                    Ok(Async::NotReady) => break,
+                    Ok(Async::Ready(())) => continue,
+                    Err(e) => {
+                        error!("[{}] Throttle timer error: {}", self.peer_id, e);
+                        break;
+                    }
+                }
+            }
+            let data = self.pending.take().unwrap();
+            if let AsyncSink::NotReady(data) = self.tx.start_send(data).map_err(drop)? {
+                self.pending = Some(data);
+                break;
+            }
+        }
+        self.tx.poll_complete().map_err(drop)?;
+        Ok(Async::NotReady)
+    }
+}
+
 /// Replication protocol.
 pub struct Replication<TSubstream> {
     /// Events that need to be yielded to the outside when polling.
     events: VecDeque<NetworkBehaviourAction<HandlerInEvent, ReplicationEvent>>,
 
+    /// Outbound bandwidth limits, shared with other protocols.
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+
+    /// Rate limited downstreams.
+    throttled: Vec<ThrottledStream>,
+
     /// Marker to pin the generics.
     marker: PhantomData<TSubstream>,
 }
 
 impl<TSubstream> Replication<TSubstream> {
     /// Creates a `Replication`.
-    pub fn new() -> Self {
+    pub fn new(rate_limiter: Arc<Mutex<RateLimiter>>) -> Self {
         Replication {
             events: VecDeque::new(),
+            rate_limiter,
+            throttled: Vec::new(),
             marker: PhantomData,
         }
     }
@@ -127,6 +203,7 @@ where
             ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
         };
         debug!("[{}] Disconnected: multiaddr={}", peer_id, multiaddr);
+        self.throttled.retain(|s| &s.peer_id != peer_id);
         let event = ReplicationEvent::Unregistered {
             peer_id: peer_id.clone(),
             multiaddr,
@@ -144,7 +221,30 @@ where
             HandlerOutEvent::ConnectionFailed { error } => {
                 ReplicationEvent::ConnectionFailed { peer_id, error }
             }
-            HandlerOutEvent::Accepted { tx, rx } => ReplicationEvent::Accepted { peer_id, tx, rx },
+            HandlerOutEvent::Accepted { tx, rx } => {
+                let is_limited = self
+                    .rate_limiter
+                    .lock()
+                    .unwrap()
+                    .is_limited(RATE_LIMIT_PROTOCOL);
+                if is_limited {
+                    let (node_tx, node_rx) = mpsc::channel(THROTTLED_BUFFER_SIZE);
+                    self.throttled.push(ThrottledStream {
+                        peer_id: peer_id.clone(),
+                        rx: node_rx,
+                        tx,
+                        pending: None,
+                        delay: Delay::new(Instant::now()),
+                    });
+                    ReplicationEvent::Accepted {
+                        peer_id,
+                        tx: node_tx,
+                        rx,
+                    }
+                } else {
+                    ReplicationEvent::Accepted { peer_id, tx, rx }
+                }
+            }
         };
         let event = NetworkBehaviourAction::GenerateEvent(event);
         self.events.push_back(event);
@@ -161,6 +261,17 @@ where
     > {
         trace!("Poll");
 
+        let mut i = 0;
+        while i < self.throttled.len() {
+            match self.throttled[i].poll(&self.rate_limiter) {
+                Ok(Async::NotReady) => i += 1,
+                Ok(Async::Ready(())) | Err(()) => {
+                    debug!("[{}] Rate limited stream closed", self.throttled[i].peer_id);
+                    self.throttled.swap_remove(i);
+                }
+            }
+        }
+
         if let Some(event) = self.events.pop_front() {
             trace!("Generated event: {:?}", event);
             return Async::Ready(event);
@@ -169,3 +280,49 @@ where
         Async::NotReady
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RateLimits;
+    use futures::{future, stream};
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn throttled_stream() {
+        let limits = RateLimits {
+            replication: 100_000,
+            ..Default::default()
+        };
+        let rate_limiter = Mutex::new(RateLimiter::new(&limits));
+        let (node_tx, node_rx) = mpsc::channel(THROTTLED_BUFFER_SIZE);
+        let (tx, rx) = mpsc::channel(16);
+        let mut throttled = ThrottledStream {
+            peer_id: PeerId::random(),
+            rx: node_rx,
+            tx,
+            pending: None,
+            delay: Delay::new(Instant::now()),
+        };
+        let blocks: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 60_000]).collect();
+
+        let mut runtime = Runtime::new().unwrap();
+        let started = Instant::now();
+        runtime.spawn(
+            stream::iter_ok(blocks.clone())
+                .forward(node_tx)
+                .map(drop)
+                .map_err(drop),
+        );
+        runtime
+            .block_on(future::poll_fn(|| throttled.poll(&rate_limiter)))
+            .unwrap();
+        // 240KB at 100KB/s with a burst of 100KB.
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        drop(throttled);
+        let received = runtime.block_on(rx.collect()).unwrap();
+        assert_eq!(received, blocks);
+    }
+}
@@ -104,9 +104,7 @@ impl Encoder for ReplicationCodec {
     type Error = io::Error;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        metrics::OUTGOING_TRAFFIC
-            .with_label_values(&[&PROTOCOL_LABEL])
-            .inc_by(item.len() as i64);
+        metrics::outgoing(PROTOCOL_LABEL, item.len());
 
         let msg = self.length_prefix.encode(item.into(), dst);
         match msg {
@@ -123,9 +121,7 @@ impl Decoder for ReplicationCodec {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         match self.length_prefix.decode(src)? {
             Some(bytes) => {
-                metrics::INCOMING_TRAFFIC
-                    .with_label_values(&[&PROTOCOL_LABEL])
-                    .inc_by(bytes.len() as i64);
+                metrics::incoming(PROTOCOL_LABEL, bytes.len());
                 Ok(Some(bytes.to_vec()))
             }
             None => Ok(None),
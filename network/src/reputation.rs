@@ -0,0 +1,397 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Peer reputation.
+//!
+//! Every peer has a score, which is lowered on misbehavior and decays back
+//! to zero over time. Peers are disconnected when their score drops below
+//! `DISCONNECT_THRESHOLD` and banned for `BAN_DURATION` below `BAN_THRESHOLD`.
+//! Peers from `NetworkConfig::banned_peers` are banned until unbanned,
+//! peers from `NetworkConfig::allowed_peers` are never scored or banned.
+//! Permanent bans and allows made via API are saved to
+//! `NetworkConfig::reputation_file` and restored on startup.
+//!
+
+use crate::config::NetworkConfig;
+use failure::{format_err, Error};
+use libp2p_core::PeerId;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Scores are halved with this interval.
+const SCORE_HALF_LIFE: Duration = Duration::from_secs(5 * 60);
+/// Peers are disconnected below this score.
+const DISCONNECT_THRESHOLD: f64 = -50.0;
+/// Peers are temporarily banned below this score.
+const BAN_THRESHOLD: f64 = -100.0;
+/// Duration of a temporary ban.
+const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+/// Scores closer to zero are forgotten.
+const SCORE_EPSILON: f64 = 0.1;
+
+/// Misbehavior of a remote peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Misbehavior {
+    /// The peer has sent a message which can't be decoded.
+    InvalidRpc,
+    /// The peer has sent a message which has been rejected by the application.
+    InvalidMessage,
+    /// The peer sends messages faster than allowed.
+    Flooding,
+}
+
+impl Misbehavior {
+    fn penalty(self) -> f64 {
+        match self {
+            Misbehavior::InvalidRpc => 20.0,
+            Misbehavior::InvalidMessage => 10.0,
+            Misbehavior::Flooding => 30.0,
+        }
+    }
+}
+
+/// What to do with a peer after misbehavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Keep,
+    Disconnect,
+    Ban,
+}
+
+/// Reputation of a peer for passing to API.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerReputation {
+    /// Libp2p PeerId of the node
+    pub peer_id: String,
+    /// Current score, zero for well-behaved peers.
+    pub score: f64,
+    /// The peer is banned.
+    pub banned: bool,
+    /// Remaining time of a temporary ban (secs), None for permanent bans.
+    pub ban_expires_in: Option<u64>,
+    /// The peer is never scored or banned.
+    pub allowed: bool,
+}
+
+struct Score {
+    value: f64,
+    updated: Instant,
+}
+
+impl Score {
+    fn decayed(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.value * 0.5f64.powf(elapsed / SCORE_HALF_LIFE.as_secs_f64())
+    }
+}
+
+/// Permanent bans and allows, as saved to the file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredLists {
+    banned: Vec<String>,
+    allowed: Vec<String>,
+}
+
+pub struct Reputation {
+    /// Path to the file, empty if bans and allows are not persistent.
+    path: PathBuf,
+    scores: HashMap<PeerId, Score>,
+    /// Banned peers with the expiration time of the ban, None for permanent bans.
+    banned: HashMap<PeerId, Option<Instant>>,
+    allowed: HashSet<PeerId>,
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, Error> {
+    PeerId::from_str(peer_id).map_err(|e| format_err!("Invalid peer_id '{}': {:?}", peer_id, e))
+}
+
+impl Reputation {
+    pub fn new(config: &NetworkConfig) -> Result<Self, Error> {
+        let mut reputation = Reputation {
+            path: config.reputation_file.clone(),
+            scores: HashMap::new(),
+            banned: HashMap::new(),
+            allowed: HashSet::new(),
+        };
+        let stored = load_lists(&config.reputation_file)?;
+        for peer_id in stored.banned.iter().chain(&config.banned_peers) {
+            reputation.ban(parse_peer_id(peer_id)?);
+        }
+        for peer_id in stored.allowed.iter().chain(&config.allowed_peers) {
+            reputation.allow(parse_peer_id(peer_id)?);
+        }
+        Ok(reputation)
+    }
+
+    /// Saves permanent bans and allows to the file.
+    pub fn save(&self) -> Result<(), Error> {
+        if self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let mut stored = StoredLists::default();
+        for (peer_id, expires) in &self.banned {
+            if expires.is_none() {
+                stored.banned.push(peer_id.to_base58());
+            }
+        }
+        stored.banned.sort();
+        stored.allowed = self.allowed.iter().map(PeerId::to_base58).collect();
+        stored.allowed.sort();
+        let data = serde_json::to_vec_pretty(&stored)?;
+        // Write to a temporary file first to never leave a truncated file.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &data).map_err(|e| format_err!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, &self.path)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", self.path, e))?;
+        Ok(())
+    }
+
+    /// Forget decayed scores and expired bans.
+    fn prune(&mut self, now: Instant) {
+        self.banned
+            .retain(|_, expires| expires.map_or(true, |expires| expires > now));
+        self.scores
+            .retain(|_, score| score.decayed(now).abs() >= SCORE_EPSILON);
+    }
+
+    /// Lower the score of a peer.
+    pub fn report(&mut self, peer_id: &PeerId, misbehavior: Misbehavior) -> Verdict {
+        self.report_at(peer_id, misbehavior, Instant::now())
+    }
+
+    fn report_at(&mut self, peer_id: &PeerId, misbehavior: Misbehavior, now: Instant) -> Verdict {
+        if self.allowed.contains(peer_id) {
+            return Verdict::Keep;
+        }
+        if !self.scores.contains_key(peer_id) {
+            self.prune(now);
+        }
+        let score = self.scores.entry(peer_id.clone()).or_insert(Score {
+            value: 0.0,
+            updated: now,
+        });
+        score.value = score.decayed(now) - misbehavior.penalty();
+        score.updated = now;
+        if score.value < BAN_THRESHOLD {
+            self.scores.remove(peer_id);
+            self.banned
+                .entry(peer_id.clone())
+                .or_insert(Some(now + BAN_DURATION));
+            Verdict::Ban
+        } else if score.value < DISCONNECT_THRESHOLD {
+            Verdict::Disconnect
+        } else {
+            Verdict::Keep
+        }
+    }
+
+    pub fn is_banned(&mut self, peer_id: &PeerId) -> bool {
+        self.is_banned_at(peer_id, Instant::now())
+    }
+
+    fn is_banned_at(&mut self, peer_id: &PeerId, now: Instant) -> bool {
+        match self.banned.get(peer_id) {
+            Some(Some(expires)) if *expires <= now => {
+                self.banned.remove(peer_id);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    /// Ban a peer until `unban()`.
+    pub fn ban(&mut self, peer_id: PeerId) {
+        self.allowed.remove(&peer_id);
+        self.scores.remove(&peer_id);
+        self.banned.insert(peer_id, None);
+    }
+
+    pub fn unban(&mut self, peer_id: &PeerId) {
+        self.banned.remove(peer_id);
+    }
+
+    /// Exempt a peer from scoring and bans.
+    pub fn allow(&mut self, peer_id: PeerId) {
+        self.banned.remove(&peer_id);
+        self.scores.remove(&peer_id);
+        self.allowed.insert(peer_id);
+    }
+
+    pub fn disallow(&mut self, peer_id: &PeerId) {
+        self.allowed.remove(peer_id);
+    }
+
+    /// Reputation of scored, banned and allowed peers.
+    pub fn list(&mut self) -> Vec<PeerReputation> {
+        let now = Instant::now();
+        self.prune(now);
+        let mut peers: HashSet<&PeerId> = self.scores.keys().collect();
+        peers.extend(self.banned.keys());
+        peers.extend(self.allowed.iter());
+        peers
+            .into_iter()
+            .map(|peer_id| {
+                let ban = self.banned.get(peer_id);
+                PeerReputation {
+                    peer_id: peer_id.to_base58(),
+                    score: self.scores.get(peer_id).map_or(0.0, |s| s.decayed(now)),
+                    banned: ban.is_some(),
+                    ban_expires_in: ban
+                        .and_then(|expires| *expires)
+                        .map(|expires| expires.duration_since(now).as_secs()),
+                    allowed: self.allowed.contains(peer_id),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Loads permanent bans and allows, nothing if the file doesn't exist.
+fn load_lists(path: &Path) -> Result<StoredLists, Error> {
+    if path.as_os_str().is_empty() || !path.exists() {
+        return Ok(StoredLists::default());
+    }
+    let data = fs::read(path).map_err(|e| format_err!("Failed to read {:?}: {}", path, e))?;
+    serde_json::from_slice(&data).map_err(|e| format_err!("Failed to parse {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+    use tempdir::TempDir;
+
+    fn random_peer_id() -> PeerId {
+        identity::Keypair::generate_ed25519()
+            .public()
+            .into_peer_id()
+    }
+
+    #[test]
+    fn score_and_ban() {
+        let mut reputation = Reputation::new(&NetworkConfig::default()).unwrap();
+        let peer_id = random_peer_id();
+        let now = Instant::now();
+
+        let mut verdicts = Vec::new();
+        for _ in 0..11 {
+            verdicts.push(reputation.report_at(&peer_id, Misbehavior::InvalidMessage, now));
+        }
+        assert_eq!(verdicts[0], Verdict::Keep);
+        assert_eq!(verdicts[5], Verdict::Disconnect);
+        assert_eq!(verdicts[10], Verdict::Ban);
+        assert!(reputation.is_banned_at(&peer_id, now));
+        assert!(!reputation.is_banned_at(&peer_id, now + BAN_DURATION));
+
+        // The score decays between reports.
+        for i in 0..20 {
+            let verdict = reputation.report_at(
+                &peer_id,
+                Misbehavior::InvalidMessage,
+                now + SCORE_HALF_LIFE * i,
+            );
+            assert_eq!(verdict, Verdict::Keep);
+        }
+    }
+
+    #[test]
+    fn ban_and_allow_lists() {
+        let banned = random_peer_id();
+        let allowed = random_peer_id();
+        let mut config = NetworkConfig::default();
+        config.banned_peers = vec![banned.to_base58()];
+        config.allowed_peers = vec![allowed.to_base58()];
+        let mut reputation = Reputation::new(&config).unwrap();
+
+        assert!(reputation.is_banned_at(&banned, Instant::now() + BAN_DURATION * 2));
+        for _ in 0..20 {
+            assert_eq!(
+                reputation.report(&allowed, Misbehavior::Flooding),
+                Verdict::Keep
+            );
+        }
+        assert!(!reputation.is_banned(&allowed));
+        assert_eq!(reputation.list().len(), 2);
+
+        reputation.unban(&banned);
+        assert!(!reputation.is_banned(&banned));
+        reputation.ban(allowed.clone());
+        assert!(reputation.is_banned(&allowed));
+        assert_eq!(reputation.list().len(), 1);
+
+        config.banned_peers = vec!["garbage".to_string()];
+        assert!(Reputation::new(&config).is_err());
+    }
+
+    #[test]
+    fn prune_scores() {
+        let mut reputation = Reputation::new(&NetworkConfig::default()).unwrap();
+        let now = Instant::now();
+        for _ in 0..10 {
+            reputation.report_at(&random_peer_id(), Misbehavior::InvalidRpc, now);
+        }
+        assert_eq!(reputation.scores.len(), 10);
+        let later = now + SCORE_HALF_LIFE * 20;
+        reputation.report_at(&random_peer_id(), Misbehavior::InvalidRpc, later);
+        assert_eq!(reputation.scores.len(), 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("reputation").unwrap();
+        let mut config = NetworkConfig::default();
+        config.reputation_file = dir.path().join("reputation.json");
+        let banned = random_peer_id();
+        let allowed = random_peer_id();
+        let reported = random_peer_id();
+
+        let mut reputation = Reputation::new(&config).unwrap();
+        reputation.ban(banned.clone());
+        reputation.allow(allowed.clone());
+        for _ in 0..11 {
+            reputation.report(&reported, Misbehavior::InvalidMessage);
+        }
+        assert!(reputation.is_banned(&reported));
+        reputation.save().unwrap();
+
+        // Temporary bans are not saved.
+        let mut reputation = Reputation::new(&config).unwrap();
+        assert!(reputation.is_banned(&banned));
+        assert!(!reputation.is_banned(&reported));
+        assert!(reputation.allowed.contains(&allowed));
+
+        reputation.unban(&banned);
+        reputation.disallow(&allowed);
+        reputation.save().unwrap();
+        let mut reputation = Reputation::new(&config).unwrap();
+        assert!(!reputation.is_banned(&banned));
+        assert!(reputation.list().is_empty());
+    }
+}
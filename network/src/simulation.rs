@@ -0,0 +1,954 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! In-process network of many nodes for reproducible tests.
+//!
+//! Messages are queued with a delivery time on a virtual clock, which is
+//! moved forward by the test harness. Link latency, jitter and losses are
+//! sampled from a PRNG with a fixed seed, so the same scenario always
+//! produces the same sequence of deliveries.
+//!
+//! Replication streams are reliable and ordered, but subject to latency
+//! and partitions: a partition closes all streams which cross it.
+//!
+
+use crate::ncp::NodeInfo;
+use crate::pubsub::message_id;
+use crate::replication::ReplicationEvent;
+use crate::{
+    DeliveryStatus, MessageId, Misbehavior, Network, NetworkKey, NetworkProvider, NetworkResponse,
+    PubsubMessage, UnicastMessage, ValidationResult,
+};
+use failure::{bail, format_err, Error};
+use futures::executor::{self, Notify, NotifyHandle, Spawn};
+use futures::sync::{mpsc, oneshot};
+use futures::{Async, AsyncSink};
+use libp2p_core::identity::ed25519;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use libp2p_core::{identity, PeerId};
+use log::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use stegos_crypto::pbc;
+
+/// Capacity of replication channels, in messages.
+const REPLICATION_BUFFER: usize = 16;
+
+/// Properties of a directed link between two nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConfig {
+    /// Minimal delivery delay.
+    pub latency: Duration,
+    /// Random extra delay in [0, jitter], which reorders messages.
+    /// Replication streams are never reordered.
+    pub jitter: Duration,
+    /// Probability to lose a broadcast or unicast message, [0.0, 1.0].
+    pub drop_rate: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        LinkConfig {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(0),
+            drop_rate: 0.0,
+        }
+    }
+}
+
+/// Counters of the simulated network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimulationStats {
+    pub sent: u64,
+    pub delivered: u64,
+    /// Lost on links, cut by partitions or sent to unknown peers.
+    pub dropped: u64,
+}
+
+#[derive(Debug)]
+enum Payload {
+    Publish {
+        topic: String,
+        data: Vec<u8>,
+    },
+    Unicast {
+        protocol_id: String,
+        data: Vec<u8>,
+        reliable: bool,
+    },
+    Replication {
+        stream: u64,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug)]
+struct Delivery {
+    at: Duration,
+    /// Keeps FIFO order for the same delivery time.
+    seq: u64,
+    from: usize,
+    to: usize,
+    payload: Payload,
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, the earliest delivery goes first.
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+struct SimulatedNode {
+    pkey: pbc::PublicKey,
+    peer_id: PeerId,
+    multiaddr: Multiaddr,
+    /// Nodes in different groups can't communicate.
+    group: usize,
+    consumers: HashMap<String, Vec<mpsc::UnboundedSender<Vec<u8>>>>,
+    validated_consumers: HashMap<String, Vec<mpsc::UnboundedSender<PubsubMessage>>>,
+    unicast_consumers: HashMap<String, Vec<mpsc::UnboundedSender<UnicastMessage>>>,
+    replication_tx: mpsc::UnboundedSender<ReplicationEvent>,
+}
+
+/// One direction of a replication connection.
+struct StreamEnd {
+    node: usize,
+    /// Messages written by the node.
+    rx: Spawn<mpsc::Receiver<Vec<u8>>>,
+    /// Messages read by the node.
+    tx: Spawn<mpsc::Sender<Vec<u8>>>,
+    /// Delivered messages which don't fit into `tx` yet.
+    pending: VecDeque<Vec<u8>>,
+}
+
+struct ReplicationStream {
+    ends: [StreamEnd; 2],
+}
+
+impl ReplicationStream {
+    fn connects(&self, a: usize, b: usize) -> bool {
+        (self.ends[0].node == a && self.ends[1].node == b)
+            || (self.ends[0].node == b && self.ends[1].node == a)
+    }
+}
+
+#[derive(Clone)]
+struct NoopNotify;
+
+impl Notify for NoopNotify {
+    fn notify(&self, _id: usize) {}
+}
+
+struct SimulationState {
+    rng: StdRng,
+    now: Duration,
+    seq: u64,
+    nodes: Vec<SimulatedNode>,
+    default_link: LinkConfig,
+    links: HashMap<(usize, usize), LinkConfig>,
+    queue: BinaryHeap<Delivery>,
+    /// Ordered by id to keep the simulation deterministic.
+    streams: BTreeMap<u64, ReplicationStream>,
+    next_stream: u64,
+    stats: SimulationStats,
+    notify: NotifyHandle,
+}
+
+impl SimulationState {
+    fn node_by_pkey(&self, pkey: &pbc::PublicKey) -> Option<usize> {
+        self.nodes.iter().position(|node| &node.pkey == pkey)
+    }
+
+    fn node_by_peer_id(&self, peer_id: &PeerId) -> Option<usize> {
+        self.nodes.iter().position(|node| &node.peer_id == peer_id)
+    }
+
+    fn link(&self, from: usize, to: usize) -> LinkConfig {
+        self.links
+            .get(&(from, to))
+            .cloned()
+            .unwrap_or(self.default_link)
+    }
+
+    fn is_reachable(&self, from: usize, to: usize) -> bool {
+        self.nodes[from].group == self.nodes[to].group
+    }
+
+    /// Queue a message, applying losses and delays of the link.
+    fn send(&mut self, from: usize, to: usize, payload: Payload) {
+        self.stats.sent += 1;
+        let link = self.link(from, to);
+        let reliable = match payload {
+            Payload::Replication { .. } => true,
+            Payload::Unicast { reliable, .. } => reliable,
+            _ => false,
+        };
+        if !reliable && link.drop_rate > 0.0 && self.rng.gen::<f64>() < link.drop_rate {
+            trace!("Lost a message: from={}, to={}", from, to);
+            self.stats.dropped += 1;
+            return;
+        }
+        let mut delay = link.latency;
+        if !reliable && link.jitter > Duration::from_millis(0) {
+            let jitter = link.jitter.as_micros() as u64;
+            delay += Duration::from_micros(self.rng.gen_range(0, jitter + 1));
+        }
+        self.seq += 1;
+        self.queue.push(Delivery {
+            at: self.now + delay,
+            seq: self.seq,
+            from,
+            to,
+            payload,
+        });
+    }
+
+    fn deliver(&mut self, delivery: Delivery) {
+        if !self.is_reachable(delivery.from, delivery.to) {
+            trace!(
+                "Message cut by partition: from={}, to={}",
+                delivery.from,
+                delivery.to
+            );
+            self.stats.dropped += 1;
+            return;
+        }
+        let from_pkey = self.nodes[delivery.from].pkey;
+        let node = &mut self.nodes[delivery.to];
+        match delivery.payload {
+            Payload::Publish { topic, data } => {
+                if let Some(consumers) = node.validated_consumers.get_mut(&topic) {
+                    let msg = PubsubMessage {
+                        id: message_id(&topic, &data),
+                        data: data.clone(),
+                    };
+                    consumers.retain(|tx| tx.unbounded_send(msg.clone()).is_ok());
+                }
+                if let Some(consumers) = node.consumers.get_mut(&topic) {
+                    consumers.retain(|tx| tx.unbounded_send(data.clone()).is_ok());
+                }
+            }
+            Payload::Unicast {
+                protocol_id, data, ..
+            } => {
+                if let Some(consumers) = node.unicast_consumers.get_mut(&protocol_id) {
+                    let msg = UnicastMessage {
+                        from: from_pkey,
+                        data,
+                    };
+                    consumers.retain(|tx| tx.unbounded_send(msg.clone()).is_ok());
+                }
+            }
+            Payload::Replication { stream, data } => {
+                let to = delivery.to;
+                if let Some(stream) = self.streams.get_mut(&stream) {
+                    let end = stream
+                        .ends
+                        .iter_mut()
+                        .find(|end| end.node == to)
+                        .expect("stream end");
+                    end.pending.push_back(data);
+                }
+            }
+        }
+        self.stats.delivered += 1;
+    }
+
+    /// Move messages between replication channels and the queue.
+    fn pump_streams(&mut self) {
+        let mut closed = Vec::new();
+        let mut outgoing = Vec::new();
+        let notify = self.notify.clone();
+        for (id, stream) in self.streams.iter_mut() {
+            let nodes = [stream.ends[0].node, stream.ends[1].node];
+            for (i, end) in stream.ends.iter_mut().enumerate() {
+                // Read messages written by the node.
+                loop {
+                    match end.rx.poll_stream_notify(&notify, 0) {
+                        Ok(Async::Ready(Some(data))) => {
+                            outgoing.push((*id, nodes[i], nodes[1 - i], data))
+                        }
+                        Ok(Async::Ready(None)) | Err(()) => {
+                            closed.push(*id);
+                            break;
+                        }
+                        Ok(Async::NotReady) => break,
+                    }
+                }
+                // Write delivered messages to the node.
+                while let Some(data) = end.pending.pop_front() {
+                    match end.tx.start_send_notify(data, &notify, 0) {
+                        Ok(AsyncSink::Ready) => {}
+                        Ok(AsyncSink::NotReady(data)) => {
+                            end.pending.push_front(data);
+                            break;
+                        }
+                        Err(_) => {
+                            closed.push(*id);
+                            break;
+                        }
+                    }
+                }
+                if end.tx.poll_flush_notify(&notify, 0).is_err() {
+                    closed.push(*id);
+                }
+            }
+        }
+        for (stream, from, to, data) in outgoing {
+            self.send(from, to, Payload::Replication { stream, data });
+        }
+        for id in closed {
+            self.close_stream(id);
+        }
+    }
+
+    fn close_stream(&mut self, id: u64) {
+        if let Some(stream) = self.streams.remove(&id) {
+            debug!(
+                "Closed replication stream: id={}, nodes=({}, {})",
+                id, stream.ends[0].node, stream.ends[1].node
+            );
+            // Dropping the channels ends the streams on both nodes.
+        }
+    }
+
+    fn connect(&mut self, from: usize, peer_id: PeerId) {
+        let to = match self.node_by_peer_id(&peer_id) {
+            Some(to) if self.is_reachable(from, to) => to,
+            _ => {
+                let error = io::Error::new(io::ErrorKind::ConnectionRefused, "Unreachable");
+                let event = ReplicationEvent::ConnectionFailed { peer_id, error };
+                self.nodes[from].replication_tx.unbounded_send(event).ok();
+                return;
+            }
+        };
+        let (from_tx, from_rx) = mpsc::channel::<Vec<u8>>(REPLICATION_BUFFER);
+        let (from_sim_tx, from_sim_rx) = mpsc::channel::<Vec<u8>>(REPLICATION_BUFFER);
+        let (to_tx, to_rx) = mpsc::channel::<Vec<u8>>(REPLICATION_BUFFER);
+        let (to_sim_tx, to_sim_rx) = mpsc::channel::<Vec<u8>>(REPLICATION_BUFFER);
+        let id = self.next_stream;
+        self.next_stream += 1;
+        self.streams.insert(
+            id,
+            ReplicationStream {
+                ends: [
+                    StreamEnd {
+                        node: from,
+                        rx: executor::spawn(from_rx),
+                        tx: executor::spawn(from_sim_tx),
+                        pending: VecDeque::new(),
+                    },
+                    StreamEnd {
+                        node: to,
+                        rx: executor::spawn(to_rx),
+                        tx: executor::spawn(to_sim_tx),
+                        pending: VecDeque::new(),
+                    },
+                ],
+            },
+        );
+        debug!(
+            "Opened replication stream: id={}, nodes=({}, {})",
+            id, from, to
+        );
+        let connected = ReplicationEvent::Connected {
+            peer_id: self.nodes[to].peer_id.clone(),
+            tx: from_tx,
+            rx: from_sim_rx,
+        };
+        self.nodes[from]
+            .replication_tx
+            .unbounded_send(connected)
+            .ok();
+        let accepted = ReplicationEvent::Accepted {
+            peer_id: self.nodes[from].peer_id.clone(),
+            tx: to_tx,
+            rx: to_sim_rx,
+        };
+        self.nodes[to].replication_tx.unbounded_send(accepted).ok();
+    }
+
+    fn disconnect(&mut self, from: usize, peer_id: &PeerId) {
+        if let Some(to) = self.node_by_peer_id(peer_id) {
+            let ids: Vec<u64> = self
+                .streams
+                .iter()
+                .filter(|(_, stream)| stream.connects(from, to))
+                .map(|(id, _)| *id)
+                .collect();
+            for id in ids {
+                self.close_stream(id);
+            }
+        }
+    }
+
+    /// Close all replication streams crossing a partition.
+    fn cut_streams(&mut self) {
+        let ids: Vec<u64> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| !self.is_reachable(stream.ends[0].node, stream.ends[1].node))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.close_stream(id);
+        }
+    }
+}
+
+///
+/// In-process network connecting many nodes.
+///
+#[derive(Clone)]
+pub struct Simulation {
+    state: Arc<Mutex<SimulationState>>,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        let state = SimulationState {
+            rng: StdRng::seed_from_u64(seed),
+            now: Duration::from_millis(0),
+            seq: 0,
+            nodes: Vec::new(),
+            default_link: LinkConfig::default(),
+            links: HashMap::new(),
+            queue: BinaryHeap::new(),
+            streams: BTreeMap::new(),
+            next_stream: 0,
+            stats: SimulationStats::default(),
+            notify: NotifyHandle::from(Arc::new(NoopNotify)),
+        };
+        Simulation {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    ///
+    /// Connect a new node, returning arguments for NodeService::new().
+    ///
+    pub fn add_node(
+        &self,
+        network_pkey: pbc::PublicKey,
+    ) -> (Network, PeerId, mpsc::UnboundedReceiver<ReplicationEvent>) {
+        let mut state = self.state.lock().unwrap();
+        // Derive the identity from the seed to keep the simulation reproducible.
+        let mut raw: [u8; 32] = state.rng.gen();
+        let secret = ed25519::SecretKey::from_bytes(&mut raw).expect("32 bytes");
+        let keypair = ed25519::Keypair::from(secret);
+        let peer_id = identity::Keypair::Ed25519(keypair).public().into_peer_id();
+        let (replication_tx, replication_rx) = mpsc::unbounded::<ReplicationEvent>();
+        let index = state.nodes.len();
+        let multiaddr = Multiaddr::empty().with(Protocol::Memory(index as u64));
+        // Every node is a replication peer of every other one.
+        for node in &state.nodes {
+            let event = ReplicationEvent::Registered {
+                peer_id: node.peer_id.clone(),
+                multiaddr: node.multiaddr.clone(),
+            };
+            replication_tx.unbounded_send(event).ok();
+            let event = ReplicationEvent::Registered {
+                peer_id: peer_id.clone(),
+                multiaddr: multiaddr.clone(),
+            };
+            node.replication_tx.unbounded_send(event).ok();
+        }
+        state.nodes.push(SimulatedNode {
+            pkey: network_pkey,
+            peer_id: peer_id.clone(),
+            multiaddr,
+            group: 0,
+            consumers: HashMap::new(),
+            validated_consumers: HashMap::new(),
+            unicast_consumers: HashMap::new(),
+            replication_tx,
+        });
+        let network = SimulatedNetwork {
+            index,
+            state: self.state.clone(),
+        };
+        (Box::new(network), peer_id, replication_rx)
+    }
+
+    /// Set properties of all links without explicit configuration.
+    pub fn set_default_link(&self, link: LinkConfig) {
+        self.state.lock().unwrap().default_link = link;
+    }
+
+    /// Set properties of the link from `from` to `to`.
+    pub fn set_link(
+        &self,
+        from: &pbc::PublicKey,
+        to: &pbc::PublicKey,
+        link: LinkConfig,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let from = state
+            .node_by_pkey(from)
+            .ok_or_else(|| format_err!("Unknown node: {}", from))?;
+        let to = state
+            .node_by_pkey(to)
+            .ok_or_else(|| format_err!("Unknown node: {}", to))?;
+        state.links.insert((from, to), link);
+        Ok(())
+    }
+
+    ///
+    /// Split the network into isolated groups.
+    /// Nodes which aren't listed form one more group.
+    ///
+    pub fn partition(&self, groups: &[&[pbc::PublicKey]]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut assignment = vec![0; state.nodes.len()];
+        for (group, pkeys) in groups.iter().enumerate() {
+            for pkey in pkeys.iter() {
+                match state.node_by_pkey(pkey) {
+                    Some(index) => assignment[index] = group + 1,
+                    None => bail!("Unknown node: {}", pkey),
+                }
+            }
+        }
+        for (node, group) in state.nodes.iter_mut().zip(assignment) {
+            node.group = group;
+        }
+        info!("Network partitioned: groups={}", groups.len());
+        state.cut_streams();
+        Ok(())
+    }
+
+    /// Remove all partitions.
+    pub fn heal(&self) {
+        let mut state = self.state.lock().unwrap();
+        for node in state.nodes.iter_mut() {
+            node.group = 0;
+        }
+        info!("Network healed");
+    }
+
+    /// Current virtual time since the start of simulation.
+    pub fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    /// The number of messages on the wire.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    pub fn stats(&self) -> SimulationStats {
+        self.state.lock().unwrap().stats
+    }
+
+    ///
+    /// Move the virtual clock by `duration`, delivering all messages
+    /// which are due. Returns the number of delivered messages.
+    ///
+    pub fn advance(&self, duration: Duration) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let deadline = state.now + duration;
+        state.pump_streams();
+        let mut delivered = 0;
+        loop {
+            match state.queue.peek() {
+                Some(delivery) if delivery.at <= deadline => {}
+                _ => break,
+            }
+            let delivery = state.queue.pop().unwrap();
+            state.now = delivery.at;
+            state.deliver(delivery);
+            delivered += 1;
+        }
+        state.now = deadline;
+        state.pump_streams();
+        delivered
+    }
+
+    ///
+    /// Move the virtual clock to the next message and deliver it.
+    /// Returns false if there are no messages on the wire.
+    ///
+    pub fn deliver_next(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.pump_streams();
+        let delivery = match state.queue.pop() {
+            Some(delivery) => delivery,
+            None => return false,
+        };
+        state.now = delivery.at;
+        state.deliver(delivery);
+        state.pump_streams();
+        true
+    }
+}
+
+///
+/// NetworkProvider of a single node in Simulation.
+///
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    index: usize,
+    state: Arc<Mutex<SimulationState>>,
+}
+
+impl std::fmt::Debug for SimulatedNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SimulatedNetwork({})", self.index)
+    }
+}
+
+impl NetworkProvider for SimulatedNetwork {
+    fn subscribe(&self, topic: &str) -> Result<mpsc::UnboundedReceiver<Vec<u8>>, Error> {
+        let (tx, rx) = mpsc::unbounded();
+        self.state.lock().unwrap().nodes[self.index]
+            .consumers
+            .entry(topic.to_string())
+            .or_default()
+            .push(tx);
+        Ok(rx)
+    }
+
+    fn subscribe_validated(
+        &self,
+        topic: &str,
+    ) -> Result<mpsc::UnboundedReceiver<PubsubMessage>, Error> {
+        let (tx, rx) = mpsc::unbounded();
+        self.state.lock().unwrap().nodes[self.index]
+            .validated_consumers
+            .entry(topic.to_string())
+            .or_default()
+            .push(tx);
+        Ok(rx)
+    }
+
+    fn validate(&self, _id: MessageId, _result: ValidationResult) -> Result<(), Error> {
+        // Messages are published to all nodes directly, nothing to forward.
+        Ok(())
+    }
+
+    fn publish(&self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        // Own messages are not delivered back, like in floodsub.
+        for to in 0..state.nodes.len() {
+            if to == self.index {
+                continue;
+            }
+            let payload = Payload::Publish {
+                topic: topic.to_string(),
+                data: data.clone(),
+            };
+            state.send(self.index, to, payload);
+        }
+        Ok(())
+    }
+
+    fn subscribe_unicast(
+        &self,
+        protocol_id: &str,
+    ) -> Result<mpsc::UnboundedReceiver<UnicastMessage>, Error> {
+        let (tx, rx) = mpsc::unbounded();
+        self.state.lock().unwrap().nodes[self.index]
+            .unicast_consumers
+            .entry(protocol_id.to_string())
+            .or_default()
+            .push(tx);
+        Ok(rx)
+    }
+
+    fn send(&self, dest: pbc::PublicKey, protocol_id: &str, data: Vec<u8>) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.node_by_pkey(&dest) {
+            Some(to) => {
+                let payload = Payload::Unicast {
+                    protocol_id: protocol_id.to_string(),
+                    data,
+                    reliable: false,
+                };
+                state.send(self.index, to, payload);
+            }
+            None => {
+                trace!("Unicast to unknown node: to={}", dest);
+                state.stats.sent += 1;
+                state.stats.dropped += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Retransmissions are not simulated: reliable messages aren't lost on links,
+    /// but fail if the recipient is unknown or cut by a partition.
+    fn send_reliable(
+        &self,
+        dest: pbc::PublicKey,
+        protocol_id: &str,
+        data: Vec<u8>,
+    ) -> Result<oneshot::Receiver<DeliveryStatus>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let status = match state.node_by_pkey(&dest) {
+            Some(to) if state.is_reachable(self.index, to) => {
+                let payload = Payload::Unicast {
+                    protocol_id: protocol_id.to_string(),
+                    data,
+                    reliable: true,
+                };
+                state.send(self.index, to, payload);
+                DeliveryStatus::Delivered
+            }
+            _ => {
+                trace!("Reliable unicast to unreachable node: to={}", dest);
+                DeliveryStatus::Failed
+            }
+        };
+        let (tx, rx) = oneshot::channel::<DeliveryStatus>();
+        if let Err(_v) = tx.send(status) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn replication_connect(&self, peer_id: PeerId) -> Result<(), Error> {
+        self.state.lock().unwrap().connect(self.index, peer_id);
+        Ok(())
+    }
+
+    fn replication_disconnect(&self, peer_id: PeerId) -> Result<(), Error> {
+        self.state.lock().unwrap().disconnect(self.index, &peer_id);
+        Ok(())
+    }
+
+    fn list_connected_nodes(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let state = self.state.lock().unwrap();
+        let nodes = (0..state.nodes.len())
+            .filter(|to| *to != self.index && state.is_reachable(self.index, *to))
+            .map(|to| {
+                let node = &state.nodes[to];
+                NodeInfo::new(&node.peer_id, node.pkey, vec![node.multiaddr.clone()])
+            })
+            .collect();
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        if let Err(_v) = tx.send(NetworkResponse::ConnectedNodes { nodes }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn outbound_nodes(&self) -> Vec<pbc::PublicKey> {
+        let state = self.state.lock().unwrap();
+        (0..state.nodes.len())
+            .filter(|to| *to != self.index && state.is_reachable(self.index, *to))
+            .map(|to| state.nodes[to].pkey)
+            .collect()
+    }
+
+    fn report_peer(
+        &self,
+        _node_id: pbc::PublicKey,
+        _misbehavior: Misbehavior,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn ban_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn unban_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn allow_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn disallow_peer(&self, _peer_id: PeerId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn list_peer_reputation(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        if let Err(_v) = tx.send(NetworkResponse::PeerReputation { peers: vec![] }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn bandwidth_stats(&self) -> Result<oneshot::Receiver<NetworkResponse>, Error> {
+        let (tx, rx) = oneshot::channel::<NetworkResponse>();
+        let stats = Default::default();
+        if let Err(_v) = tx.send(NetworkResponse::BandwidthStats { stats }) {
+            Err(format_err!("Failed to send reply to oneshot channel"))
+        } else {
+            Ok(rx)
+        }
+    }
+
+    fn box_clone(&self) -> Network {
+        Box::new(self.clone())
+    }
+
+    fn change_network_keys(&self, new_key: Arc<dyn NetworkKey>) -> Result<(), Error> {
+        self.state.lock().unwrap().nodes[self.index].pkey = new_key.pkey();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{Future, Sink, Stream};
+
+    fn drain<T>(mut rx: mpsc::UnboundedReceiver<T>) -> Vec<T> {
+        rx.close();
+        rx.wait().map(|item| item.unwrap()).collect()
+    }
+
+    fn deliveries(seed: u64) -> Vec<Vec<u8>> {
+        let simulation = Simulation::new(seed);
+        simulation.set_default_link(LinkConfig {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(50),
+            drop_rate: 0.3,
+        });
+        let (_, pkey1) = pbc::make_random_keys();
+        let (_, pkey2) = pbc::make_random_keys();
+        let (network1, _, _) = simulation.add_node(pkey1);
+        let (network2, _, _) = simulation.add_node(pkey2);
+        let rx = network2.subscribe("test").unwrap();
+        for i in 0..100u8 {
+            network1.publish("test", vec![i]).unwrap();
+        }
+        while simulation.deliver_next() {}
+        drain(rx)
+    }
+
+    #[test]
+    fn deterministic() {
+        let first = deliveries(42);
+        assert!(first.len() > 0 && first.len() < 100, "some are lost");
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_ne!(first, sorted, "some are reordered");
+        assert_eq!(deliveries(42), first);
+    }
+
+    #[test]
+    fn latency_and_partitions() {
+        let simulation = Simulation::new(1);
+        let (_, pkey1) = pbc::make_random_keys();
+        let (_, pkey2) = pbc::make_random_keys();
+        let (network1, _, _) = simulation.add_node(pkey1);
+        let (network2, _, _) = simulation.add_node(pkey2);
+        let rx = network2.subscribe_unicast("test").unwrap();
+
+        network1.send(pkey2, "test", vec![1]).unwrap();
+        assert_eq!(simulation.advance(Duration::from_millis(5)), 0);
+        assert_eq!(simulation.advance(Duration::from_millis(5)), 1);
+        assert_eq!(simulation.now(), Duration::from_millis(10));
+
+        simulation.partition(&[&[pkey1]]).unwrap();
+        network1.send(pkey2, "test", vec![2]).unwrap();
+        network1.send(pkey2, "test", vec![3]).unwrap();
+        simulation.heal();
+        network1.send(pkey2, "test", vec![4]).unwrap();
+        // In-flight messages are still delivered after heal.
+        simulation.partition(&[&[pkey1]]).unwrap();
+        network1.send(pkey2, "test", vec![5]).unwrap();
+        simulation.heal();
+        while simulation.deliver_next() {}
+
+        let received: Vec<Vec<u8>> = drain(rx).into_iter().map(|msg| msg.data).collect();
+        assert_eq!(received, vec![vec![1], vec![2], vec![3], vec![4], vec![5]]);
+        assert_eq!(simulation.stats().dropped, 0);
+
+        simulation.partition(&[&[pkey1]]).unwrap();
+        let rx = network2.subscribe_unicast("test").unwrap();
+        network1.send(pkey2, "test", vec![6]).unwrap();
+        while simulation.deliver_next() {}
+        assert!(drain(rx).is_empty());
+        assert_eq!(simulation.stats().dropped, 1);
+    }
+
+    #[test]
+    fn replication() {
+        let simulation = Simulation::new(1);
+        let (_, pkey1) = pbc::make_random_keys();
+        let (_, pkey2) = pbc::make_random_keys();
+        let (network1, _, events1) = simulation.add_node(pkey1);
+        let (_network2, peer_id2, events2) = simulation.add_node(pkey2);
+
+        network1.replication_connect(peer_id2).unwrap();
+        let mut events1 = drain(events1).into_iter();
+        let mut events2 = drain(events2).into_iter();
+        match events1.next() {
+            Some(ReplicationEvent::Registered { .. }) => {}
+            e => panic!("unexpected event: {:?}", e),
+        }
+        let (tx1, rx1) = match events1.next() {
+            Some(ReplicationEvent::Connected { tx, rx, .. }) => (tx, rx),
+            e => panic!("unexpected event: {:?}", e),
+        };
+        match events2.next() {
+            Some(ReplicationEvent::Registered { .. }) => {}
+            e => panic!("unexpected event: {:?}", e),
+        }
+        let (tx2, rx2) = match events2.next() {
+            Some(ReplicationEvent::Accepted { tx, rx, .. }) => (tx, rx),
+            e => panic!("unexpected event: {:?}", e),
+        };
+
+        let _tx1 = tx1.send(vec![1]).wait().unwrap();
+        let tx2 = tx2.send(vec![2]).wait().unwrap();
+        simulation.advance(Duration::from_millis(10));
+        let (msg, rx2) = rx2.into_future().wait().ok().unwrap();
+        assert_eq!(msg, Some(vec![1]));
+        let (msg, _rx1) = rx1.into_future().wait().ok().unwrap();
+        assert_eq!(msg, Some(vec![2]));
+
+        // A partition closes the stream.
+        simulation.partition(&[&[pkey1]]).unwrap();
+        assert!(tx2.send(vec![3]).wait().is_err());
+        let (msg, _) = rx2.into_future().wait().ok().unwrap();
+        assert_eq!(msg, None);
+    }
+}
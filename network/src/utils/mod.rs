@@ -25,11 +25,15 @@ mod expiring_queue;
 mod lru_bimap;
 mod multihash;
 mod peer_id_key;
+mod priority_queue;
+mod rate_limiter;
 
 pub use self::expiring_queue::ExpiringQueue;
 pub use self::lru_bimap::LruBimap;
 pub use self::multihash::IntoMultihash;
 pub use self::peer_id_key::PeerIdKey;
+pub use self::priority_queue::PriorityQueue;
+pub use self::rate_limiter::RateLimiter;
 use libp2p_core::multiaddr::{Multiaddr, Protocol};
 use std::net::SocketAddr;
 
@@ -38,3 +42,20 @@ pub fn socket_to_multi_addr(addr: &SocketAddr) -> Multiaddr {
     maddr.push(Protocol::Tcp(addr.port()));
     maddr
 }
+
+/// Converts "ip:port" or "host:port" to a multiaddr, host names are resolved on dial.
+pub fn seed_to_multi_addr(addr: &str) -> Option<Multiaddr> {
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Some(socket_to_multi_addr(&addr));
+    }
+    let mut parts = addr.rsplitn(2, ':');
+    let port = parts.next()?.parse::<u16>().ok()?;
+    let host = parts.next()?;
+    if host.is_empty() {
+        return None;
+    }
+    let mut maddr = Multiaddr::empty();
+    maddr.push(Protocol::Dns4(host.into()));
+    maddr.push(Protocol::Tcp(port));
+    Some(maddr)
+}
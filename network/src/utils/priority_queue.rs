@@ -0,0 +1,103 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::config::MessagePriority;
+use std::collections::VecDeque;
+
+/// Bounded FIFO queues of outgoing messages per priority class.
+pub struct PriorityQueue<T> {
+    high: VecDeque<T>,
+    normal: VecDeque<T>,
+    low: VecDeque<T>,
+    /// Maximum length of each queue.
+    limit: usize,
+}
+
+impl<T> PriorityQueue<T> {
+    pub fn new(limit: usize) -> Self {
+        PriorityQueue {
+            high: VecDeque::new(),
+            normal: VecDeque::new(),
+            low: VecDeque::new(),
+            limit,
+        }
+    }
+
+    fn queue(&mut self, priority: MessagePriority) -> &mut VecDeque<T> {
+        match priority {
+            MessagePriority::High => &mut self.high,
+            MessagePriority::Normal => &mut self.normal,
+            MessagePriority::Low => &mut self.low,
+        }
+    }
+
+    /// Enqueue an item, returns the oldest item of the class if its queue is full.
+    pub fn push(&mut self, priority: MessagePriority, item: T) -> Option<T> {
+        let limit = self.limit;
+        let queue = self.queue(priority);
+        let dropped = if queue.len() >= limit {
+            queue.pop_front()
+        } else {
+            None
+        };
+        queue.push_back(item);
+        dropped
+    }
+
+    /// Dequeue the oldest item of the highest non-empty class.
+    pub fn pop(&mut self) -> Option<T> {
+        self.high
+            .pop_front()
+            .or_else(|| self.normal.pop_front())
+            .or_else(|| self.low.pop_front())
+    }
+
+    pub fn len(&self) -> usize {
+        self.high.len() + self.normal.len() + self.low.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priorities() {
+        let mut queue = PriorityQueue::new(2);
+        assert_eq!(queue.push(MessagePriority::Low, 1), None);
+        assert_eq!(queue.push(MessagePriority::Normal, 2), None);
+        assert_eq!(queue.push(MessagePriority::Low, 3), None);
+        assert_eq!(queue.push(MessagePriority::High, 4), None);
+        // The oldest low priority item is dropped.
+        assert_eq!(queue.push(MessagePriority::Low, 5), Some(1));
+        assert_eq!(queue.len(), 4);
+
+        let order: Vec<i32> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, vec![4, 2, 3, 5]);
+        assert!(queue.is_empty());
+    }
+}
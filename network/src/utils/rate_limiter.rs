@@ -0,0 +1,194 @@
+//
+// MIT License
+//
+// Copyright (c) 2018-2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::config::RateLimits;
+use libp2p_core::PeerId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Token bucket of bytes, which allows bursts of one second of traffic.
+/// Messages are sent while the bucket is not empty and may leave it in debt.
+struct TokenBucket {
+    /// Bytes per second.
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now > self.updated {
+            let elapsed = (now - self.updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.updated = now;
+        }
+    }
+
+    /// When the bucket is not in debt anymore.
+    fn ready_at(&self, now: Instant) -> Instant {
+        if self.tokens >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Outbound rate limits: global, per peer and per protocol.
+pub struct RateLimiter {
+    global: Option<TokenBucket>,
+    per_peer: u64,
+    peers: HashMap<PeerId, TokenBucket>,
+    protocols: HashMap<&'static str, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        let now = Instant::now();
+        let mut protocols = HashMap::new();
+        for (protocol, rate) in &[
+            ("replication", limits.replication),
+            ("pubsub", limits.pubsub),
+        ] {
+            if *rate > 0 {
+                protocols.insert(*protocol, TokenBucket::new(*rate, now));
+            }
+        }
+        RateLimiter {
+            global: if limits.global > 0 {
+                Some(TokenBucket::new(limits.global, now))
+            } else {
+                None
+            },
+            per_peer: limits.per_peer,
+            peers: HashMap::new(),
+            protocols,
+        }
+    }
+
+    /// Accounts `size` bytes sent by `protocol` to `peer_id`.
+    /// Returns the time to try again if the traffic is over the limits.
+    pub fn check(&mut self, protocol: &str, peer_id: &PeerId, size: usize) -> Result<(), Instant> {
+        self.check_at(protocol, peer_id, size, Instant::now())
+    }
+
+    fn check_at(
+        &mut self,
+        protocol: &str,
+        peer_id: &PeerId,
+        size: usize,
+        now: Instant,
+    ) -> Result<(), Instant> {
+        let per_peer = self.per_peer;
+        let mut buckets: Vec<&mut TokenBucket> = Vec::with_capacity(3);
+        if let Some(global) = self.global.as_mut() {
+            buckets.push(global);
+        }
+        if per_peer > 0 {
+            let peer = self
+                .peers
+                .entry(peer_id.clone())
+                .or_insert_with(|| TokenBucket::new(per_peer, now));
+            buckets.push(peer);
+        }
+        if let Some(bucket) = self.protocols.get_mut(protocol) {
+            buckets.push(bucket);
+        }
+
+        let mut ready_at = now;
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            ready_at = ready_at.max(bucket.ready_at(now));
+        }
+        if ready_at > now {
+            return Err(ready_at);
+        }
+        for bucket in buckets {
+            bucket.tokens -= size as f64;
+        }
+        Ok(())
+    }
+
+    /// Returns true if traffic of the protocol is limited.
+    pub fn is_limited(&self, protocol: &str) -> bool {
+        self.global.is_some() || self.per_peer > 0 || self.protocols.contains_key(protocol)
+    }
+
+    /// Forgets the bucket of a disconnected peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+
+    #[test]
+    fn limits() {
+        let limits = RateLimits {
+            global: 3000,
+            per_peer: 1000,
+            replication: 0,
+            pubsub: 2000,
+        };
+        let mut limiter = RateLimiter::new(&limits);
+        let peer1 = identity::Keypair::generate_ed25519()
+            .public()
+            .into_peer_id();
+        let peer2 = identity::Keypair::generate_ed25519()
+            .public()
+            .into_peer_id();
+        let now = Instant::now();
+
+        // Per peer limit, a message may exceed the bucket.
+        assert!(limiter.check_at("replication", &peer1, 1500, now).is_ok());
+        let ready_at = limiter.check_at("replication", &peer1, 1, now).unwrap_err();
+        assert_eq!(ready_at, now + Duration::from_millis(500));
+        assert!(limiter.check_at("replication", &peer2, 1000, now).is_ok());
+        assert!(limiter
+            .check_at("replication", &peer1, 1, now + Duration::from_millis(500))
+            .is_ok());
+
+        // Global limit.
+        let now = now + Duration::from_millis(500);
+        limiter.per_peer = 0;
+        assert!(limiter.check_at("replication", &peer2, 2000, now).is_ok());
+        assert!(limiter.check_at("replication", &peer2, 1, now).is_err());
+
+        // Protocol limit.
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.check_at("pubsub", &peer1, 2500, now).is_ok());
+        assert!(limiter.check_at("pubsub", &peer1, 1, now).is_err());
+        assert!(limiter.check_at("replication", &peer1, 1, now).is_ok());
+    }
+}
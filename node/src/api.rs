@@ -30,11 +30,11 @@ use stegos_blockchain::{
 };
 
 use stegos_crypto::hash::{Hash, Hashable, Hasher};
-use stegos_crypto::scc;
 use stegos_crypto::utils::{
     deserialize_protobuf_array_from_hex, deserialize_protobuf_from_hex,
     serialize_protobuf_array_to_hex, serialize_protobuf_to_hex,
 };
+use stegos_crypto::{pbc, scc};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "output_type")]
@@ -85,6 +85,9 @@ pub enum NodeRequest {
         #[serde(deserialize_with = "deserialize_protobuf_array_from_hex")]
         unspent_list: Vec<Output>,
     },
+    /// Move the stake of this validator to new network keys, generated by the node
+    /// and used starting from the next epoch.
+    RotateNetworkKeys {},
     ValidateCertificate {
         output_hash: Hash,
         spender: scc::PublicKey,
@@ -146,6 +149,11 @@ pub enum NodeResponse {
         timestamp: Timestamp,
         amount: i64,
     },
+    NetworkKeysRotated {
+        network_pkey: pbc::PublicKey,
+        hash: Hash,
+        status: TransactionStatus,
+    },
     RestakingEnabled,
     RestakingDisabled,
     UpstreamChanged,
@@ -0,0 +1,483 @@
+//! Compact Micro Blocks.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! A compact micro block carries the header, the leader's signature and
+//! short identifiers of transactions. Receivers rebuild the full block
+//! from their mempool and fetch missing transactions from the leader.
+//!
+
+use crate::{metrics, BlockStatus, NodeService};
+use failure::{format_err, Error};
+use log::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use stegos_blockchain::{BlockError, MicroBlock, MicroBlockHeader, Transaction};
+use stegos_crypto::hash::{Hash, Hashable, Hasher};
+use stegos_crypto::pbc;
+use stegos_serialization::traits::ProtoConvert;
+use tokio_timer::clock;
+
+/// Topic used for sending compact micro blocks.
+pub const COMPACT_BLOCK_TOPIC: &'static str = "compact_block";
+/// Unicast topic for fetching missing transactions of compact micro blocks.
+pub const COMPACT_BLOCK_TXS_TOPIC: &'static str = "compact_block_txs";
+/// The maximal number of incomplete compact micro blocks.
+const MAX_PENDING_MICRO_BLOCKS: usize = 16;
+/// How long to wait for missing transactions before downloading the full block.
+const PENDING_MICRO_BLOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often to check for incomplete compact micro blocks.
+pub(crate) const PENDING_MICRO_BLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Short transaction identifier.
+pub type ShortTxId = u64;
+
+///
+/// Calculate a short transaction identifier.
+/// The identifier is salted by the block hash to make collisions unpredictable.
+///
+pub fn short_tx_id(block_hash: &Hash, tx_hash: &Hash) -> ShortTxId {
+    let mut hasher = Hasher::new();
+    "ShortTxId".hash(&mut hasher);
+    block_hash.hash(&mut hasher);
+    tx_hash.hash(&mut hasher);
+    let hash = hasher.result();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash.base_vector()[0..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Micro block with short transaction identifiers instead of transactions.
+#[derive(Debug, Clone)]
+pub struct CompactMicroBlock {
+    /// Header.
+    pub header: MicroBlockHeader,
+    /// BLS signature by leader.
+    pub sig: pbc::Signature,
+    /// Short identifiers of transactions.
+    pub short_ids: Vec<ShortTxId>,
+    /// Transactions which are never in mempool, with their indexes.
+    pub prefilled: Vec<(u32, Transaction)>,
+}
+
+impl CompactMicroBlock {
+    pub fn from_block(block: &MicroBlock) -> CompactMicroBlock {
+        let block_hash = Hash::digest(block);
+        let short_ids = block
+            .transactions
+            .iter()
+            .map(|tx| short_tx_id(&block_hash, &Hash::digest(tx)))
+            .collect();
+        let prefilled = block
+            .transactions
+            .iter()
+            .enumerate()
+            .filter(|(_index, tx)| match tx {
+                Transaction::CoinbaseTransaction(_) | Transaction::ServiceAwardTransaction(_) => {
+                    true
+                }
+                _ => false,
+            })
+            .map(|(index, tx)| (index as u32, tx.clone()))
+            .collect();
+        CompactMicroBlock {
+            header: block.header.clone(),
+            sig: block.sig.clone(),
+            short_ids,
+            prefilled,
+        }
+    }
+}
+
+impl Hashable for CompactMicroBlock {
+    fn hash(&self, state: &mut Hasher) {
+        self.header.hash(state);
+        self.sig.hash(state);
+        for short_id in &self.short_ids {
+            short_id.hash(state);
+        }
+        for (index, tx) in &self.prefilled {
+            index.hash(state);
+            tx.hash(state);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestTransactions {
+    pub epoch: u64,
+    pub offset: u32,
+    pub block_hash: Hash,
+    pub indexes: Vec<u32>,
+}
+
+impl Hashable for RequestTransactions {
+    fn hash(&self, state: &mut Hasher) {
+        self.epoch.hash(state);
+        self.offset.hash(state);
+        self.block_hash.hash(state);
+        for index in &self.indexes {
+            index.hash(state);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResponseTransactions {
+    pub block_hash: Hash,
+    pub transactions: Vec<Transaction>,
+}
+
+impl Hashable for ResponseTransactions {
+    fn hash(&self, state: &mut Hasher) {
+        self.block_hash.hash(state);
+        for tx in &self.transactions {
+            tx.hash(state);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CompactBlockMessage {
+    Request(RequestTransactions),
+    Response(ResponseTransactions),
+}
+
+impl Hashable for CompactBlockMessage {
+    fn hash(&self, state: &mut Hasher) {
+        match self {
+            CompactBlockMessage::Request(r) => {
+                "request".hash(state);
+                r.hash(state)
+            }
+            CompactBlockMessage::Response(r) => {
+                "response".hash(state);
+                r.hash(state)
+            }
+        }
+    }
+}
+
+/// A compact micro block waiting for missing transactions.
+pub(crate) struct PendingMicroBlock {
+    header: MicroBlockHeader,
+    sig: pbc::Signature,
+    short_ids: Vec<ShortTxId>,
+    transactions: Vec<Option<Transaction>>,
+    missing: Vec<u32>,
+    /// When missing transactions have been requested.
+    requested: Instant,
+}
+
+impl PendingMicroBlock {
+    fn into_block(self) -> MicroBlock {
+        let transactions = self
+            .transactions
+            .into_iter()
+            .map(|tx| tx.expect("all transactions are resolved"))
+            .collect();
+        MicroBlock {
+            header: self.header,
+            sig: self.sig,
+            transactions,
+        }
+    }
+}
+
+impl NodeService {
+    /// Send a micro block to the network in the compact form.
+    pub(crate) fn send_compact_micro_block(&mut self, block: &MicroBlock) -> Result<(), Error> {
+        let compact = CompactMicroBlock::from_block(block);
+        self.network
+            .publish(&COMPACT_BLOCK_TOPIC, compact.into_buffer()?)
+    }
+
+    /// Handle a compact micro block from the network.
+    pub(crate) fn handle_compact_micro_block(
+        &mut self,
+        compact: CompactMicroBlock,
+    ) -> Result<BlockStatus, Error> {
+        let block_hash = Hash::digest(&compact.header);
+        let epoch = compact.header.epoch;
+        let offset = compact.header.offset;
+        if epoch != self.chain.epoch() || offset > self.chain.offset() {
+            debug!(
+                "Ignore a compact micro block: block={}, epoch={}, offset={}, our_epoch={}, our_offset={}",
+                block_hash,
+                epoch,
+                offset,
+                self.chain.epoch(),
+                self.chain.offset()
+            );
+            return Ok(BlockStatus::Ignored);
+        }
+        if self.pending_micro_blocks.contains_key(&block_hash) {
+            return Ok(BlockStatus::Ignored);
+        }
+        if compact.short_ids.len() != compact.header.transactions_len as usize {
+            return Err(format_err!(
+                "Invalid number of transactions in a compact micro block: block={}, expected={}, got={}",
+                block_hash,
+                compact.header.transactions_len,
+                compact.short_ids.len()
+            ));
+        }
+        // Check that block is created by legitimate validator.
+        let election_result = self.chain.election_result_by_offset(offset)?;
+        let leader = election_result.select_leader(compact.header.view_change);
+        if leader != compact.header.pkey {
+            return Err(BlockError::DifferentPublicKey(leader, compact.header.pkey).into());
+        }
+        if let Err(_e) = pbc::check_hash(&block_hash, &compact.sig, &leader) {
+            return Err(BlockError::InvalidLeaderSignature(epoch, block_hash).into());
+        }
+        metrics::COMPACT_MICRO_BLOCKS.inc();
+
+        // Resolve transactions using the mempool.
+        let mut known: HashMap<ShortTxId, Option<Hash>> = HashMap::new();
+        for (tx_hash, _tx) in self.mempool.iter() {
+            let short_id = short_tx_id(&block_hash, tx_hash);
+            known
+                .entry(short_id)
+                .and_modify(|e| *e = None) // collision - fetch from the leader.
+                .or_insert(Some(*tx_hash));
+        }
+        let mut transactions: Vec<Option<Transaction>> = compact
+            .short_ids
+            .iter()
+            .map(|short_id| match known.get(short_id) {
+                Some(Some(tx_hash)) => self.mempool.get_tx(tx_hash).cloned(),
+                _ => None,
+            })
+            .collect();
+        for (index, tx) in compact.prefilled {
+            let index = index as usize;
+            if index >= transactions.len()
+                || short_tx_id(&block_hash, &Hash::digest(&tx)) != compact.short_ids[index]
+            {
+                return Err(format_err!(
+                    "Invalid prefilled transaction in a compact micro block: block={}, index={}",
+                    block_hash,
+                    index
+                ));
+            }
+            transactions[index] = Some(tx);
+        }
+        let missing: Vec<u32> = transactions
+            .iter()
+            .enumerate()
+            .filter(|(_index, tx)| tx.is_none())
+            .map(|(index, _tx)| index as u32)
+            .collect();
+
+        let pending = PendingMicroBlock {
+            header: compact.header,
+            sig: compact.sig,
+            short_ids: compact.short_ids,
+            transactions,
+            missing,
+            requested: clock::now(),
+        };
+        if pending.missing.is_empty() {
+            debug!(
+                "Reconstructed a compact micro block from mempool: block={}, epoch={}, offset={}",
+                block_hash, epoch, offset
+            );
+            return self.handle_micro_block(pending.into_block());
+        }
+
+        // Fetch missing transactions from the leader.
+        if self.pending_micro_blocks.len() >= MAX_PENDING_MICRO_BLOCKS {
+            self.prune_pending_micro_blocks();
+            if self.pending_micro_blocks.len() >= MAX_PENDING_MICRO_BLOCKS {
+                warn!(
+                    "Too many incomplete compact micro blocks, ignoring: block={}",
+                    block_hash
+                );
+                return Ok(BlockStatus::Ignored);
+            }
+        }
+        let request = RequestTransactions {
+            epoch,
+            offset,
+            block_hash,
+            indexes: pending.missing.clone(),
+        };
+        debug!(
+            "Requesting missing transactions: block={}, epoch={}, offset={}, missing={}, total={}, leader={}",
+            block_hash,
+            epoch,
+            offset,
+            pending.missing.len(),
+            pending.transactions.len(),
+            leader
+        );
+        metrics::COMPACT_MICRO_BLOCK_MISSING_TRANSACTIONS.inc_by(pending.missing.len() as i64);
+        self.pending_micro_blocks.insert(block_hash, pending);
+        let msg = CompactBlockMessage::Request(request);
+        self.network
+            .send(leader, COMPACT_BLOCK_TXS_TOPIC, msg.into_buffer()?)?;
+        Ok(BlockStatus::Processed)
+    }
+
+    fn handle_request_transactions(
+        &mut self,
+        from: pbc::PublicKey,
+        request: RequestTransactions,
+    ) -> Result<(), Error> {
+        if request.epoch != self.chain.epoch() || request.offset >= self.chain.offset() {
+            debug!(
+                "Requested transactions for an unknown block: from={}, block={}, epoch={}, offset={}",
+                from, request.block_hash, request.epoch, request.offset
+            );
+            return Ok(());
+        }
+        let block = self.chain.micro_block(request.epoch, request.offset)?;
+        if Hash::digest(&*block) != request.block_hash {
+            debug!(
+                "Requested transactions for a different block: from={}, block={}, epoch={}, offset={}",
+                from, request.block_hash, request.epoch, request.offset
+            );
+            return Ok(());
+        }
+        let mut transactions = Vec::with_capacity(request.indexes.len());
+        for index in request.indexes {
+            match block.transactions.get(index as usize) {
+                Some(tx) => transactions.push(tx.clone()),
+                None => {
+                    return Err(format_err!(
+                        "Invalid transaction index requested: from={}, block={}, index={}",
+                        from,
+                        request.block_hash,
+                        index
+                    ));
+                }
+            }
+        }
+        debug!(
+            "Sending missing transactions: to={}, block={}, num_transactions={}",
+            from,
+            request.block_hash,
+            transactions.len()
+        );
+        let response = ResponseTransactions {
+            block_hash: request.block_hash,
+            transactions,
+        };
+        let msg = CompactBlockMessage::Response(response);
+        self.network
+            .send(from, COMPACT_BLOCK_TXS_TOPIC, msg.into_buffer()?)
+    }
+
+    fn handle_response_transactions(
+        &mut self,
+        from: pbc::PublicKey,
+        response: ResponseTransactions,
+    ) -> Result<(), Error> {
+        let block_hash = response.block_hash;
+        let mut pending = match self.pending_micro_blocks.remove(&block_hash) {
+            Some(pending) => pending,
+            None => {
+                debug!(
+                    "Received transactions for an unknown block: from={}, block={}",
+                    from, block_hash
+                );
+                return Ok(());
+            }
+        };
+        if response.transactions.len() != pending.missing.len() {
+            return Err(format_err!(
+                "Invalid number of missing transactions: from={}, block={}, expected={}, got={}",
+                from,
+                block_hash,
+                pending.missing.len(),
+                response.transactions.len()
+            ));
+        }
+        let missing = std::mem::replace(&mut pending.missing, Vec::new());
+        for (index, tx) in missing.into_iter().zip(response.transactions) {
+            let index = index as usize;
+            let short_id = short_tx_id(&block_hash, &Hash::digest(&tx));
+            if short_id != pending.short_ids[index] {
+                return Err(format_err!(
+                    "Received an unexpected transaction: from={}, block={}, index={}",
+                    from,
+                    block_hash,
+                    index
+                ));
+            }
+            pending.transactions[index] = Some(tx);
+        }
+        debug!(
+            "Reconstructed a compact micro block: block={}, epoch={}, offset={}",
+            block_hash, pending.header.epoch, pending.header.offset
+        );
+        self.handle_micro_block(pending.into_block())?;
+        Ok(())
+    }
+
+    pub(crate) fn handle_compact_block_message(
+        &mut self,
+        from: pbc::PublicKey,
+        msg: CompactBlockMessage,
+    ) -> Result<(), Error> {
+        match msg {
+            CompactBlockMessage::Request(r) => self.handle_request_transactions(from, r),
+            CompactBlockMessage::Response(r) => self.handle_response_transactions(from, r),
+        }
+    }
+
+    /// Download full blocks if the leader doesn't send missing transactions in time.
+    pub(crate) fn handle_pending_micro_blocks_timer(&mut self) -> Result<(), Error> {
+        let now = clock::now();
+        let expired: Vec<Hash> = self
+            .pending_micro_blocks
+            .iter()
+            .filter(|(_block_hash, pending)| {
+                now.duration_since(pending.requested) >= PENDING_MICRO_BLOCK_TIMEOUT
+            })
+            .map(|(block_hash, _pending)| *block_hash)
+            .collect();
+        for block_hash in expired {
+            let pending = self
+                .pending_micro_blocks
+                .remove(&block_hash)
+                .expect("pending block exists");
+            warn!(
+                "Missing transactions haven't been received in time: block={}, epoch={}, offset={}, leader={}",
+                block_hash, pending.header.epoch, pending.header.offset, pending.header.pkey
+            );
+            self.request_history_from(pending.header.pkey, "compact block timeout")?;
+        }
+        Ok(())
+    }
+
+    /// Remove compact micro blocks which can't be applied anymore.
+    pub(crate) fn prune_pending_micro_blocks(&mut self) {
+        let epoch = self.chain.epoch();
+        let offset = self.chain.offset();
+        self.pending_micro_blocks.retain(|_block_hash, pending| {
+            pending.header.epoch == epoch && pending.header.offset <= offset
+        });
+    }
+}
@@ -22,6 +22,7 @@
 // SOFTWARE.
 
 use serde_derive::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::time::Duration;
 
 /// Node configuration.
@@ -32,6 +33,10 @@ pub struct NodeConfig {
     pub micro_block_timeout: Duration,
     /// How long wait for the key blocks.
     pub macro_block_timeout: Duration,
+    /// Increase block timeouts on consecutive view changes and high block latency.
+    pub adaptive_timeouts: bool,
+    /// The upper bound for adaptive block timeouts.
+    pub max_block_timeout: Duration,
     /// When change is_synchronized to false.
     pub sync_timeout: Duration,
     /// Timeout to check is_synchronized status.
@@ -52,6 +57,63 @@ pub struct NodeConfig {
     pub min_payment_fee: i64,
     /// Minimal fee for the stake transactions.
     pub min_stake_fee: i64,
+    /// How to propagate new transactions to the network.
+    pub tx_propagation: TxPropagation,
+    /// Probability (in percents) to switch from the stem to the fluff phase on each hop.
+    pub dandelion_fluff_probability: u32,
+    /// How long wait for a stem transaction to be fluffed by others.
+    pub dandelion_embargo_timeout: Duration,
+    /// Maximal number of transactions in the stem phase.
+    pub max_stempool_size: usize,
+    /// Send micro blocks in the compact form, relying on mempools of peers.
+    pub compact_micro_blocks: bool,
+    /// Operation mode.
+    pub mode: NodeMode,
+    /// Where to keep the last signed messages for double-sign protection.
+    /// Empty value keeps them in memory.
+    pub last_signed_file: PathBuf,
+    /// Unix socket of a stegos-signer process holding the network key.
+    /// Empty value signs blocks and consensus messages locally.
+    pub remote_signer: PathBuf,
+    /// Directory with network.skey and network.pkey, updated on key rotation.
+    /// Empty value keeps rotated keys in memory.
+    pub network_keys_dir: PathBuf,
+    /// Where to keep the macro block consensus state across restarts.
+    /// Empty value keeps it in memory.
+    pub consensus_state_file: PathBuf,
+    /// Where to record incoming and outgoing consensus messages for stegos-replay.
+    /// Empty value disables recording.
+    pub consensus_log_file: PathBuf,
+    /// The consensus log is moved to `<consensus_log_file>.1` when it grows over this size in bytes.
+    /// Zero disables rotation.
+    pub consensus_log_max_size: u64,
+}
+
+/// Node operation mode.
+#[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeMode {
+    /// Participate in consensus if the node has enough stake.
+    Validator,
+    /// Validate and serve the blockchain, but never participate in consensus,
+    /// re-stake or act as a facilitator.
+    Observer,
+}
+
+impl Default for NodeMode {
+    fn default() -> Self {
+        NodeMode::Validator
+    }
+}
+
+/// Transaction propagation mode.
+#[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxPropagation {
+    /// Broadcast transactions to all peers immediately.
+    Flood,
+    /// Relay transactions via a random path of peers before broadcasting (Dandelion++).
+    Dandelion,
 }
 
 impl Default for NodeConfig {
@@ -60,6 +122,9 @@ impl Default for NodeConfig {
             // Sic: synchronize this value with ChainConfig::vetted_timeout.
             micro_block_timeout: Duration::from_secs(30),
             macro_block_timeout: Duration::from_secs(30),
+            adaptive_timeouts: false,
+            // Sic: clamped to 2 * ChainConfig::vetted_timestamp_delta.
+            max_block_timeout: Duration::from_secs(60),
             sync_timeout: Duration::from_secs(5 * 60), // should >= block_timeout.
             sync_change_timeout: Duration::from_secs(30), // should >= block_timeout.
             max_inputs_in_tx: 100,
@@ -70,6 +135,18 @@ impl Default for NodeConfig {
             max_outputs_in_mempool: 10000,
             min_payment_fee: 1_000, // 0.001 STG
             min_stake_fee: 0,       // free
+            tx_propagation: TxPropagation::Flood,
+            dandelion_fluff_probability: 10,
+            dandelion_embargo_timeout: Duration::from_secs(30),
+            max_stempool_size: 1000,
+            compact_micro_blocks: false,
+            mode: NodeMode::default(),
+            last_signed_file: PathBuf::new(),
+            remote_signer: PathBuf::new(),
+            network_keys_dir: PathBuf::new(),
+            consensus_state_file: PathBuf::new(),
+            consensus_log_file: PathBuf::new(),
+            consensus_log_max_size: 100 * 1024 * 1024,
         }
     }
 }
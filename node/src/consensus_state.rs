@@ -0,0 +1,102 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Persistence of the macro block consensus state across restarts.
+//!
+//! The round, the locked proposal and messages sent by this node are saved
+//! before the messages leave the node and restored when the consensus for
+//! the same epoch is started again.
+//!
+
+use crate::NodeService;
+use failure::Error;
+use log::*;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use stegos_consensus::{Consensus, ConsensusSnapshot};
+use stegos_serialization::traits::ProtoConvert;
+
+fn write_snapshot(path: &Path, snapshot: &ConsensusSnapshot) -> Result<(), Error> {
+    let data = snapshot.into_buffer()?;
+    let tmp_path = path.with_extension("tmp");
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(&data)?;
+    f.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn read_snapshot(path: &Path) -> Result<Option<ConsensusSnapshot>, Error> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(ConsensusSnapshot::from_buffer(&data)?)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///
+/// Save the consensus state if it has been changed.
+/// Must be called before messages from the consensus outbox are sent.
+///
+pub(crate) fn save_consensus_state(path: &Path, consensus: &mut Consensus) -> Result<(), Error> {
+    if path.as_os_str().is_empty() {
+        return Ok(());
+    }
+    if let Some(snapshot) = consensus.take_snapshot() {
+        write_snapshot(path, &snapshot)?;
+        trace!(
+            "Saved consensus state: epoch={}, round={}",
+            snapshot.epoch,
+            snapshot.round
+        );
+    }
+    Ok(())
+}
+
+impl NodeService {
+    ///
+    /// Restore the consensus state of the current epoch, if any.
+    ///
+    pub(crate) fn restore_consensus_state(&self, consensus: &mut Consensus) {
+        let path = &self.cfg.consensus_state_file;
+        if path.as_os_str().is_empty() {
+            return;
+        }
+        match read_snapshot(path) {
+            Ok(Some(snapshot)) if snapshot.epoch == consensus.epoch() => {
+                if let Err(e) = consensus.restore(snapshot) {
+                    warn!("Failed to restore consensus state, starting fresh: {}", e);
+                }
+            }
+            Ok(Some(snapshot)) => {
+                debug!(
+                    "Ignored consensus state from another epoch: saved_epoch={}, epoch={}",
+                    snapshot.epoch,
+                    consensus.epoch()
+                );
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to load consensus state from {:?}: {}", path, e),
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//! Dandelion++ - private transaction propagation.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! A new transaction is first relayed over a random path of peers (the stem phase)
+//! and only then broadcasted to the network (the fluff phase). Every stem hop
+//! decides to fluff with `NodeConfig::dandelion_fluff_probability`. Each node
+//! on the path keeps an embargo timer and broadcasts the transaction itself
+//! if it hasn't been fluffed by others in time.
+//!
+
+use crate::config::TxPropagation;
+use crate::error::*;
+use crate::{metrics, NodeService};
+use failure::Error;
+use log::*;
+use rand::{self, Rng};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use stegos_blockchain::{Transaction, TransactionError};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_serialization::traits::ProtoConvert;
+use tokio_timer::clock;
+
+/// Unicast topic for transactions in the stem phase.
+pub const TX_STEM_TOPIC: &'static str = "tx_stem";
+
+/// How often check embargo timers.
+pub(crate) const STEM_EMBARGO_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transactions in the stem phase.
+pub(crate) struct StemPool {
+    /// Transactions with their embargo deadlines.
+    pool: HashMap<Hash, (Transaction, Instant)>,
+    /// Inputs of transactions => tx_hash.
+    inputs: HashMap<Hash, Hash>,
+    /// Outputs of transactions => tx_hash.
+    outputs: HashMap<Hash, Hash>,
+    /// The next hop for stem transactions.
+    relay: Option<pbc::PublicKey>,
+    /// The epoch when the relay was selected.
+    relay_epoch: u64,
+    /// Maximal number of transactions.
+    max_size: usize,
+}
+
+impl StemPool {
+    ///
+    /// Creates a new stem pool.
+    ///
+    pub fn new(max_size: usize) -> Self {
+        StemPool {
+            pool: HashMap::new(),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+            relay: None,
+            relay_epoch: 0,
+            max_size,
+        }
+    }
+
+    ///
+    /// Checks if the stem pool contains a transaction.
+    ///
+    pub fn contains_tx(&self, tx_hash: &Hash) -> bool {
+        self.pool.contains_key(tx_hash)
+    }
+
+    ///
+    /// Checks if the stem pool has a transaction spending the input.
+    ///
+    pub fn contains_input(&self, input_hash: &Hash) -> bool {
+        self.inputs.contains_key(input_hash)
+    }
+
+    ///
+    /// Checks if the stem pool has a transaction creating the output.
+    ///
+    pub fn contains_output(&self, output_hash: &Hash) -> bool {
+        self.outputs.contains_key(output_hash)
+    }
+
+    ///
+    /// Adds a transaction to the stem pool.
+    /// If the pool is full, removes and returns the transaction with the closest deadline.
+    ///
+    pub fn push_tx(
+        &mut self,
+        tx_hash: Hash,
+        tx: Transaction,
+        deadline: Instant,
+    ) -> Option<(Hash, Transaction)> {
+        let evicted = if self.pool.len() >= self.max_size {
+            let oldest = self
+                .pool
+                .iter()
+                .min_by_key(|(_tx_hash, (_tx, deadline))| *deadline)
+                .map(|(tx_hash, _)| *tx_hash);
+            oldest.and_then(|tx_hash| self.remove_tx(&tx_hash).map(|tx| (tx_hash, tx)))
+        } else {
+            None
+        };
+        for input_hash in tx.txins() {
+            self.inputs.insert(*input_hash, tx_hash);
+        }
+        for output in tx.txouts() {
+            self.outputs.insert(Hash::digest(output), tx_hash);
+        }
+        self.pool.insert(tx_hash, (tx, deadline));
+        metrics::STEMPOOL_TRANSACTIONS.set(self.len() as i64);
+        evicted
+    }
+
+    ///
+    /// Removes a transaction from the stem pool.
+    ///
+    pub fn remove_tx(&mut self, tx_hash: &Hash) -> Option<Transaction> {
+        let tx = self.pool.remove(tx_hash).map(|(tx, _deadline)| tx);
+        if let Some(ref tx) = tx {
+            self.forget(tx);
+        }
+        metrics::STEMPOOL_TRANSACTIONS.set(self.len() as i64);
+        tx
+    }
+
+    fn forget(&mut self, tx: &Transaction) {
+        for input_hash in tx.txins() {
+            self.inputs.remove(input_hash);
+        }
+        for output in tx.txouts() {
+            self.outputs.remove(&Hash::digest(output));
+        }
+    }
+
+    ///
+    /// Removes and returns all transactions with expired embargo.
+    ///
+    pub fn expired(&mut self, now: Instant) -> Vec<(Hash, Transaction)> {
+        let expired: Vec<Hash> = self
+            .pool
+            .iter()
+            .filter(|(_tx_hash, (_tx, deadline))| *deadline <= now)
+            .map(|(tx_hash, _)| *tx_hash)
+            .collect();
+        let mut txs = Vec::with_capacity(expired.len());
+        for tx_hash in expired {
+            let (tx, _deadline) = self.pool.remove(&tx_hash).unwrap();
+            self.forget(&tx);
+            txs.push((tx_hash, tx));
+        }
+        metrics::STEMPOOL_TRANSACTIONS.set(self.len() as i64);
+        txs
+    }
+
+    ///
+    /// Returns the number of transactions in the stem pool.
+    ///
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+}
+
+impl NodeService {
+    ///
+    /// Send a new transaction in the stem phase.
+    ///
+    pub(crate) fn send_stem_transaction(&mut self, tx: Transaction) -> Result<(), Error> {
+        let tx_hash = Hash::digest(&tx);
+        if self.stempool.contains_tx(&tx_hash) {
+            return Err(NodeTransactionError::AlreadyExists(tx_hash).into());
+        }
+        if !self.is_synchronized() {
+            return Err(NodeTransactionError::NotSynchronized(tx_hash).into());
+        }
+        self.validate_stem_transaction(&tx, &tx_hash)?;
+        self.relay_stem_transaction(tx_hash, tx)
+    }
+
+    ///
+    /// Validate a transaction against the chain, the mempool and the stem pool.
+    ///
+    fn validate_stem_transaction(&self, tx: &Transaction, tx_hash: &Hash) -> Result<(), Error> {
+        self.validate_transaction(tx, tx_hash)?;
+        for input_hash in tx.txins() {
+            if self.stempool.contains_input(input_hash) {
+                return Err(TransactionError::MissingInput(*tx_hash, *input_hash).into());
+            }
+        }
+        for output in tx.txouts() {
+            let output_hash = Hash::digest(output);
+            if self.stempool.contains_output(&output_hash) {
+                return Err(TransactionError::OutputHashCollision(*tx_hash, output_hash).into());
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Handle a stem transaction received from a peer.
+    ///
+    pub(crate) fn handle_stem_transaction(
+        &mut self,
+        from: pbc::PublicKey,
+        tx: Transaction,
+    ) -> Result<(), Error> {
+        let tx_hash = Hash::digest(&tx);
+        if self.mempool.contains_tx(&tx_hash) || self.stempool.contains_tx(&tx_hash) {
+            debug!(
+                "Skip a known stem transaction: tx={}, from={}",
+                &tx_hash, from
+            );
+            return Ok(());
+        }
+
+        // Never relay or broadcast invalid transactions.
+        if !self.is_synchronized() {
+            return Err(NodeTransactionError::NotSynchronized(tx_hash).into());
+        }
+        self.validate_stem_transaction(&tx, &tx_hash)?;
+
+        let fluff = match self.cfg.tx_propagation {
+            // Nodes without Dandelion support just broadcast everything.
+            TxPropagation::Flood => true,
+            TxPropagation::Dandelion => {
+                let mut rng = rand::thread_rng();
+                rng.gen_range(0, 100) < self.cfg.dandelion_fluff_probability
+            }
+        };
+        if fluff {
+            debug!(
+                "Switching a stem transaction to the fluff phase: tx={}, from={}",
+                &tx_hash, from
+            );
+            metrics::DANDELION_FLUFF_TRANSACTIONS.inc();
+            return self.fluff_transaction(tx);
+        }
+
+        self.relay_stem_transaction(tx_hash, tx)
+    }
+
+    ///
+    /// Forward a validated transaction to the next stem hop.
+    ///
+    fn relay_stem_transaction(&mut self, tx_hash: Hash, tx: Transaction) -> Result<(), Error> {
+        let relay = match self.stem_relay() {
+            Some(relay) => relay,
+            None => {
+                debug!(
+                    "No peers for the stem phase, broadcasting transaction: tx={}",
+                    &tx_hash
+                );
+                return self.fluff_transaction(tx);
+            }
+        };
+
+        let data = tx.into_buffer()?;
+        let embargo = self.cfg.dandelion_embargo_timeout;
+        let jitter = {
+            let mut rng = rand::thread_rng();
+            let max_jitter = (embargo.as_millis() as u64).max(1);
+            Duration::from_millis(rng.gen_range(0, max_jitter))
+        };
+        let deadline = clock::now() + embargo + jitter;
+        let evicted = self.stempool.push_tx(tx_hash, tx, deadline);
+        self.network.send(relay, TX_STEM_TOPIC, data)?;
+        metrics::DANDELION_STEM_TRANSACTIONS.inc();
+        info!(
+            "Relayed transaction in the stem phase: tx={}, relay={}",
+            &tx_hash, relay
+        );
+
+        if let Some((tx_hash, tx)) = evicted {
+            warn!(
+                "Stem pool is full, broadcasting the oldest transaction: tx={}",
+                &tx_hash
+            );
+            metrics::DANDELION_STEMPOOL_OVERFLOW.inc();
+            self.fluff_stem_transaction(tx_hash, tx);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Broadcast a transaction to the network, finishing the stem phase.
+    ///
+    fn fluff_transaction(&mut self, tx: Transaction) -> Result<(), Error> {
+        let tx_hash = Hash::digest(&tx);
+        self.stempool.remove_tx(&tx_hash);
+        self.flood_transaction(tx)
+    }
+
+    ///
+    /// Broadcast a stem transaction which left the stem pool before being fluffed by others.
+    ///
+    fn fluff_stem_transaction(&mut self, tx_hash: Hash, tx: Transaction) {
+        if self.mempool.contains_tx(&tx_hash) {
+            // Already fluffed by other nodes.
+            return;
+        }
+        // The chain could have changed since the transaction was relayed.
+        if let Err(e) = self.validate_stem_transaction(&tx, &tx_hash) {
+            debug!(
+                "Dropped an invalid stem transaction: tx={}, error={}",
+                &tx_hash, e
+            );
+            return;
+        }
+        if let Err(e) = self.fluff_transaction(tx) {
+            debug!(
+                "Failed to broadcast a stem transaction: tx={}, error={}",
+                &tx_hash, e
+            );
+        }
+    }
+
+    ///
+    /// Select the next hop for stem transactions.
+    /// The relay is chosen at random among peers connected by outbound connections,
+    /// so an attacker can't become the relay just by connecting to the node.
+    /// The relay is changed every epoch or when the connection to it is lost.
+    ///
+    fn stem_relay(&mut self) -> Option<pbc::PublicKey> {
+        let epoch = self.chain.epoch();
+        let candidates: Vec<pbc::PublicKey> = self
+            .network
+            .outbound_nodes()
+            .into_iter()
+            .filter(|pkey| pkey != &self.network_pkey)
+            .collect();
+        if let Some(relay) = self.stempool.relay {
+            if self.stempool.relay_epoch == epoch && candidates.contains(&relay) {
+                return Some(relay);
+            }
+        }
+
+        let relay = if candidates.is_empty() {
+            None
+        } else {
+            let mut rng = rand::thread_rng();
+            Some(candidates[rng.gen_range(0, candidates.len())])
+        };
+        if let Some(relay) = relay {
+            debug!("Selected a new stem relay: relay={}", relay);
+        }
+        self.stempool.relay = relay;
+        self.stempool.relay_epoch = epoch;
+        relay
+    }
+
+    ///
+    /// Broadcast stem transactions with expired embargo timers.
+    ///
+    pub(crate) fn handle_stem_embargo_timer(&mut self) {
+        for (tx_hash, tx) in self.stempool.expired(clock::now()) {
+            if self.mempool.contains_tx(&tx_hash) {
+                // Already fluffed by other nodes.
+                continue;
+            }
+            warn!(
+                "Embargo timer expired for a stem transaction, broadcasting: tx={}",
+                &tx_hash
+            );
+            metrics::DANDELION_EMBARGO_EXPIRED.inc();
+            self.fluff_stem_transaction(tx_hash, tx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stegos_blockchain::PaymentTransaction;
+    use stegos_crypto::scc;
+
+    #[test]
+    fn embargo() {
+        let (skey, pkey) = scc::make_random_keys();
+        let mut stempool = StemPool::new(10);
+
+        let (tx1, _inputs1, _outputs1) =
+            PaymentTransaction::new_test(&skey, &pkey, 100, 2, 200, 1, 0)
+                .expect("transaction valid");
+        let (tx2, _inputs2, _outputs2) =
+            PaymentTransaction::new_test(&skey, &pkey, 300, 1, 100, 3, 0)
+                .expect("transaction valid");
+        let tx_hash1 = Hash::digest(&tx1);
+        let tx_hash2 = Hash::digest(&tx2);
+
+        let now = Instant::now();
+        assert!(stempool
+            .push_tx(tx_hash1, tx1.into(), now + Duration::from_secs(1))
+            .is_none());
+        assert!(stempool
+            .push_tx(tx_hash2, tx2.into(), now + Duration::from_secs(2))
+            .is_none());
+        assert!(stempool.contains_tx(&tx_hash1));
+        assert!(stempool.contains_tx(&tx_hash2));
+        assert_eq!(stempool.len(), 2);
+
+        // Nothing is expired yet.
+        assert!(stempool.expired(now).is_empty());
+        assert_eq!(stempool.len(), 2);
+
+        // The first transaction is expired.
+        let expired = stempool.expired(now + Duration::from_secs(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, tx_hash1);
+        assert!(!stempool.contains_tx(&tx_hash1));
+        assert!(stempool.contains_tx(&tx_hash2));
+
+        // The second transaction is fluffed by others.
+        assert!(stempool.remove_tx(&tx_hash2).is_some());
+        assert!(stempool.remove_tx(&tx_hash2).is_none());
+        assert!(stempool.expired(now + Duration::from_secs(3)).is_empty());
+        assert_eq!(stempool.len(), 0);
+    }
+
+    #[test]
+    fn overflow() {
+        let (skey, pkey) = scc::make_random_keys();
+        let mut stempool = StemPool::new(2);
+
+        let txs: Vec<Transaction> = (0..3)
+            .map(|i| {
+                let (tx, _inputs, _outputs) =
+                    PaymentTransaction::new_test(&skey, &pkey, 100 + i, 1, 100 + i, 1, 0)
+                        .expect("transaction valid");
+                tx.into()
+            })
+            .collect();
+        let hashes: Vec<Hash> = txs.iter().map(|tx| Hash::digest(tx)).collect();
+
+        let now = Instant::now();
+        assert!(stempool
+            .push_tx(hashes[0], txs[0].clone(), now + Duration::from_secs(2))
+            .is_none());
+        assert!(stempool
+            .push_tx(hashes[1], txs[1].clone(), now + Duration::from_secs(1))
+            .is_none());
+        assert_eq!(stempool.len(), 2);
+
+        // The transaction with the closest deadline is evicted.
+        let (evicted, _tx) = stempool
+            .push_tx(hashes[2], txs[2].clone(), now + Duration::from_secs(3))
+            .expect("evicted");
+        assert_eq!(evicted, hashes[1]);
+        assert_eq!(stempool.len(), 2);
+        assert!(stempool.contains_tx(&hashes[0]));
+        assert!(!stempool.contains_tx(&hashes[1]));
+        assert!(stempool.contains_tx(&hashes[2]));
+        for input_hash in txs[1].txins() {
+            assert!(!stempool.contains_input(input_hash));
+        }
+    }
+}
@@ -23,7 +23,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::sign_guard::{SignKind, SignPosition};
 use failure::Fail;
+use std::io;
+use std::path::PathBuf;
 use stegos_blockchain::{BlockError, BlockchainError, StorageError};
 use stegos_crypto::hash::Hash;
 
@@ -90,3 +93,24 @@ impl From<StorageError> for ForkError {
 }
 
 pub type ForkResult = Result<(), ForkError>;
+
+#[derive(Debug, Fail)]
+pub enum SignGuardError {
+    #[fail(
+        display = "Refusing to sign a conflicting {}: {}, hash={}, last_signed: {}, last_hash={}",
+        _0, _1, _2, _3, _4
+    )]
+    Conflict(SignKind, SignPosition, Hash, SignPosition, Hash),
+    #[fail(
+        display = "The last signed state is locked by another process: path={:?}, pid={}",
+        _0, _1
+    )]
+    Locked(PathBuf, u32),
+    #[fail(display = "Invalid last signed state: path={:?}, line={}", _0, _1)]
+    InvalidFormat(PathBuf, usize),
+    #[fail(
+        display = "Failed to access last signed state: path={:?}, error={}",
+        _0, _1
+    )]
+    IoError(PathBuf, io::Error),
+}
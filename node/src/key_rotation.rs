@@ -0,0 +1,248 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Rotation of the validator network key.
+//!
+//! The stake is moved to the new key by a RestakeTransaction, which is
+//! allowed by escrow even for locked stakes. The node keeps signing with
+//! the old key until the end of the epoch and switches to the new key
+//! on the next macro block.
+//!
+
+use crate::api::{NodeResponse, TransactionStatus};
+use crate::NodeService;
+use failure::{bail, format_err, Error};
+use log::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use stegos_blockchain::{ChainInfo, Output, Transaction};
+use stegos_consensus::{Direction, LocalSigner, RecordedMessage};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_keychain::keyfile::{load_network_keypair, write_network_pkey, write_network_skey};
+use stegos_network::LocalNetworkKey;
+
+const NETWORK_SKEY_FILE: &'static str = "network.skey";
+const NETWORK_PKEY_FILE: &'static str = "network.pkey";
+const NEXT_NETWORK_SKEY_FILE: &'static str = "network_next.skey";
+const NEXT_NETWORK_PKEY_FILE: &'static str = "network_next.pkey";
+
+fn next_key_files(keys_dir: &Path) -> (PathBuf, PathBuf) {
+    (
+        keys_dir.join(NEXT_NETWORK_SKEY_FILE),
+        keys_dir.join(NEXT_NETWORK_PKEY_FILE),
+    )
+}
+
+///
+/// Returns paths to the current network key files.
+///
+pub fn network_key_files(keys_dir: &Path) -> (PathBuf, PathBuf) {
+    (
+        keys_dir.join(NETWORK_SKEY_FILE),
+        keys_dir.join(NETWORK_PKEY_FILE),
+    )
+}
+
+///
+/// Finish a key switch interrupted between renames of the secret and the public key.
+/// Must be called before the network keys are loaded.
+///
+pub fn recover_network_keys(keys_dir: &Path) -> Result<(), Error> {
+    let (next_skey_file, next_pkey_file) = next_key_files(keys_dir);
+    if next_skey_file.exists() || !next_pkey_file.exists() {
+        // Either nothing to recover or the switch hasn't been started.
+        return Ok(());
+    }
+    let (skey_file, pkey_file) = network_key_files(keys_dir);
+    warn!(
+        "Recovering interrupted network key switch: {:?} => {:?}",
+        next_pkey_file, pkey_file
+    );
+    fs::rename(&next_pkey_file, &pkey_file)?;
+    // Fail early if the pair doesn't match.
+    load_network_keypair(&skey_file, &pkey_file)?;
+    Ok(())
+}
+
+///
+/// Load network keys which are waiting for the next epoch, if any.
+///
+pub(crate) fn load_pending_network_keys(
+    keys_dir: &Path,
+) -> Result<Option<(Hash, pbc::SecretKey, pbc::PublicKey)>, Error> {
+    if keys_dir.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    let (skey_file, pkey_file) = next_key_files(keys_dir);
+    if !skey_file.exists() || !pkey_file.exists() {
+        return Ok(None);
+    }
+    let (skey, pkey) = load_network_keypair(&skey_file, &pkey_file)?;
+    // The transaction hash is unknown after restart.
+    Ok(Some((Hash::zero(), skey, pkey)))
+}
+
+impl NodeService {
+    ///
+    /// Handler for NodeRequest::RotateNetworkKeys.
+    ///
+    pub(crate) fn handle_rotate_network_keys(&mut self) -> Result<NodeResponse, Error> {
+        if !self.cfg.remote_signer.as_os_str().is_empty() {
+            bail!("Network keys are managed by the remote signer");
+        }
+        let key_rotation_epoch = self.chain.cfg().key_rotation_epoch;
+        if self.chain.epoch() < key_rotation_epoch {
+            bail!(
+                "Key rotation is not enabled yet: epoch={}, key_rotation_epoch={}",
+                self.chain.epoch(),
+                key_rotation_epoch
+            );
+        }
+        if let Some((tx_hash, _, pending_pkey)) = &self.pending_network_keys {
+            // Allow to retry if the previous transaction has been lost.
+            if self.mempool.contains_tx(tx_hash)
+                || self.chain.account_by_network_key(pending_pkey).is_some()
+            {
+                bail!(
+                    "Key rotation is already in progress: new_pkey={}",
+                    pending_pkey
+                );
+            }
+        }
+
+        // All stakes of the validator must be moved at once.
+        let mut inputs: Vec<Output> = Vec::new();
+        for (input_hash, _amount, _account_pkey, _active_until_epoch) in
+            self.chain.iter_validator_stakes(&self.network_pkey)
+        {
+            let input = self
+                .chain
+                .output_by_hash(input_hash)?
+                .ok_or_else(|| format_err!("Missing stake output: utxo={}", input_hash))?;
+            inputs.push(input);
+        }
+        if inputs.is_empty() {
+            bail!("No stakes for network key {}", self.network_pkey);
+        }
+        if inputs.len() > self.cfg.max_inputs_in_tx {
+            bail!(
+                "Too many stakes to rotate: count={}, max_inputs_in_tx={}",
+                inputs.len(),
+                self.cfg.max_inputs_in_tx
+            );
+        }
+
+        let (network_skey, network_pkey) = pbc::make_random_keys();
+        let tx: Transaction = self
+            .signer
+            .rotate_stake(&inputs, &network_skey, &network_pkey)?
+            .into();
+
+        let keys_dir = self.cfg.network_keys_dir.clone();
+        if !keys_dir.as_os_str().is_empty() {
+            let (skey_file, pkey_file) = next_key_files(&keys_dir);
+            write_network_skey(&skey_file, &network_skey)?;
+            write_network_pkey(&pkey_file, &network_pkey)?;
+        }
+
+        let hash = Hash::digest(&tx);
+        let status = match self.handle_add_tx(tx) {
+            Ok(status) => status,
+            Err(e) => {
+                self.discard_pending_network_keys();
+                return Err(e);
+            }
+        };
+        match status {
+            TransactionStatus::Accepted {} => {
+                info!(
+                    "Network key rotation started: old_pkey={}, new_pkey={}, tx={}",
+                    self.network_pkey, network_pkey, hash
+                );
+                self.pending_network_keys = Some((hash, network_skey, network_pkey));
+            }
+            _ => self.discard_pending_network_keys(),
+        }
+        Ok(NodeResponse::NetworkKeysRotated {
+            network_pkey,
+            hash,
+            status,
+        })
+    }
+
+    ///
+    /// Switch to the new network keys when the stake has been moved.
+    /// Must be called on epoch boundary only.
+    ///
+    pub(crate) fn switch_pending_network_keys(&mut self) -> Result<(), Error> {
+        let (network_skey, network_pkey) = match &self.pending_network_keys {
+            Some((_, skey, pkey)) => (skey.clone(), *pkey),
+            None => return Ok(()),
+        };
+        if self.chain.account_by_network_key(&network_pkey).is_none() {
+            // Rotation transaction hasn't been committed yet.
+            return Ok(());
+        }
+
+        let keys_dir = self.cfg.network_keys_dir.clone();
+        if !keys_dir.as_os_str().is_empty() {
+            let (next_skey_file, next_pkey_file) = next_key_files(&keys_dir);
+            let (skey_file, pkey_file) = network_key_files(&keys_dir);
+            // The secret key goes first, so an interrupted switch can be
+            // detected and finished by recover_network_keys().
+            fs::rename(&next_skey_file, &skey_file)?;
+            fs::rename(&next_pkey_file, &pkey_file)?;
+        }
+        let network_key = LocalNetworkKey::new(network_skey.clone(), network_pkey);
+        self.network.change_network_keys(Arc::new(network_key))?;
+        info!(
+            "Switched network keys: old_pkey={}, new_pkey={}",
+            self.network_pkey, network_pkey
+        );
+        self.signer = Arc::new(LocalSigner::new(network_skey, network_pkey));
+        self.network_pkey = network_pkey;
+        self.pending_network_keys = None;
+        // Replay needs the current key to recognize own messages.
+        let chain = ChainInfo::from_blockchain(&self.chain);
+        self.record_message(Direction::Outgoing, || {
+            RecordedMessage::Started(network_pkey, chain)
+        });
+        self.update_stake_balance();
+        Ok(())
+    }
+
+    fn discard_pending_network_keys(&mut self) {
+        self.pending_network_keys = None;
+        let keys_dir = &self.cfg.network_keys_dir;
+        if keys_dir.as_os_str().is_empty() {
+            return;
+        }
+        let (skey_file, pkey_file) = next_key_files(keys_dir);
+        for file in &[skey_file, pkey_file] {
+            if let Err(e) = fs::remove_file(file) {
+                warn!("Failed to remove {:?}: {}", file, e);
+            }
+        }
+    }
+}
@@ -24,23 +24,38 @@
 #![deny(warnings)]
 
 pub mod api;
+mod compact;
 mod config;
+mod consensus_state;
+mod dandelion;
 mod error;
+mod key_rotation;
 mod loader;
 mod mempool;
 pub mod metrics;
 pub mod protos;
 mod replication;
+mod sign_guard;
+pub mod signer;
 #[doc(hidden)]
 pub mod test;
+mod timeouts;
 pub mod txpool;
 mod validation;
 pub use crate::api::*;
-pub use crate::config::NodeConfig;
+use crate::compact::{CompactBlockMessage, CompactMicroBlock, PendingMicroBlock};
+pub use crate::compact::{COMPACT_BLOCK_TOPIC, COMPACT_BLOCK_TXS_TOPIC};
+pub use crate::config::{NodeConfig, NodeMode, TxPropagation};
+use crate::consensus_state::save_consensus_state;
+use crate::dandelion::StemPool;
+pub use crate::dandelion::TX_STEM_TOPIC;
 use crate::error::*;
-use crate::loader::ChainLoaderMessage;
+pub use crate::key_rotation::{network_key_files, recover_network_keys};
+pub use crate::loader::ChainLoaderMessage;
 use crate::mempool::Mempool;
 use crate::replication::Replication;
+use crate::sign_guard::{SignGuard, SignKind, SignPosition};
+use crate::timeouts::BlockTimeouts;
 use crate::txpool::TransactionPoolService;
 pub use crate::txpool::MAX_PARTICIPANTS;
 use crate::validation::*;
@@ -49,9 +64,11 @@ use futures::sync::{mpsc, oneshot};
 use futures::{task, Async, AsyncSink, Future, Poll, Sink, Stream};
 use futures_stream_select_all_send::select_all;
 pub use loader::CHAIN_LOADER_TOPIC;
+use log::*;
 use rand::{self, Rng};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 use stegos_blockchain::Timestamp;
@@ -59,12 +76,17 @@ use stegos_blockchain::*;
 use stegos_consensus::optimistic::{
     AddressedViewChangeProof, SealedViewChangeProof, ViewChangeCollector, ViewChangeMessage,
 };
-use stegos_consensus::{self as consensus, Consensus, ConsensusMessage, MacroBlockProposal};
+use stegos_consensus::{
+    self as consensus, Consensus, ConsensusMessage, ConsensusMessageBody, Direction,
+    MacroBlockProposal, MessageRecorder, RecordedMessage, Signer,
+};
 use stegos_crypto::hash::Hash;
 use stegos_crypto::scc::Fr;
 use stegos_crypto::{pbc, scc};
+use stegos_network::{
+    MessageId, Misbehavior, PeerId, PubsubMessage, UnicastMessage, ValidationResult,
+};
 use stegos_network::{Network, ReplicationEvent};
-use stegos_network::{PeerId, UnicastMessage};
 use stegos_serialization::traits::ProtoConvert;
 use tokio_timer::{clock, Delay, Interval};
 use Validation::*;
@@ -106,7 +128,7 @@ impl Node {
 /// Topic used for sending transactions.
 pub const TX_TOPIC: &'static str = "tx";
 /// Topic used for consensus.
-const CONSENSUS_TOPIC: &'static str = "consensus";
+pub const CONSENSUS_TOPIC: &'static str = "consensus";
 /// Topic for ViewChange message.
 pub const VIEW_CHANGE_TOPIC: &'static str = "view_changes";
 /// Topic for ViewChange proofs broadcasts.
@@ -114,7 +136,7 @@ pub const VIEW_CHANGE_PROOFS_TOPIC: &'static str = "view_changes_proofs";
 /// Topic for ViewChange proofs.
 pub const VIEW_CHANGE_DIRECT: &'static str = "view_changes_direct";
 /// Topic used for sending sealed blocks.
-const SEALED_BLOCK_TOPIC: &'static str = "block";
+pub const SEALED_BLOCK_TOPIC: &'static str = "block";
 
 //
 // Logging utils.
@@ -151,9 +173,12 @@ pub enum NodeMessage {
         request: NodeRequest,
         tx: oneshot::Sender<NodeResponse>,
     },
-    Transaction(Vec<u8>),
+    Transaction(PubsubMessage),
+    StemTransaction(UnicastMessage),
+    CompactBlock(PubsubMessage),
+    CompactBlockMessage(UnicastMessage),
     Consensus(Vec<u8>),
-    Block(Vec<u8>),
+    Block(PubsubMessage),
     ViewChangeMessage(Vec<u8>),
     ViewChangeProof(Vec<u8>),
     ViewChangeProofMessage(UnicastMessage),
@@ -275,26 +300,124 @@ fn notify_subscribers<T: Clone>(subscribers: &mut Vec<mpsc::Sender<T>>, msg: T)
     }
 }
 
+///
+/// Decide if a transaction from the network should be forwarded.
+/// Only malformed transactions are rejected, stale transactions and
+/// transactions we can't process now are dropped without blaming the sender.
+///
+fn tx_validation_result(result: &Result<(), Error>) -> ValidationResult {
+    let e = match result {
+        Ok(()) => return ValidationResult::Accept,
+        Err(e) => e,
+    };
+    if let Some(e) = e.downcast_ref::<NodeTransactionError>() {
+        return match e {
+            NodeTransactionError::InvalidType(_)
+            | NodeTransactionError::TooManyInputs(..)
+            | NodeTransactionError::TooManyOutputs(..) => ValidationResult::Reject,
+            _ => ValidationResult::Ignore,
+        };
+    }
+    match e.downcast_ref::<BlockchainError>() {
+        Some(BlockchainError::TransactionError(e)) => return transaction_error_result(e),
+        Some(BlockchainError::OutputError(_)) => return ValidationResult::Reject,
+        _ => {}
+    }
+    if let Some(e) = e.downcast_ref::<TransactionError>() {
+        return transaction_error_result(e);
+    }
+    if let Some(_e) = e.downcast_ref::<OutputError>() {
+        return ValidationResult::Reject;
+    }
+    ValidationResult::Ignore
+}
+
+fn transaction_error_result(e: &TransactionError) -> ValidationResult {
+    match e {
+        // Inputs have been spent or outputs created by a concurrent transaction.
+        TransactionError::MissingInput(..) | TransactionError::OutputHashCollision(..) => {
+            ValidationResult::Ignore
+        }
+        _ => ValidationResult::Reject,
+    }
+}
+
+/// Outcome of processing a block from the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockStatus {
+    /// The block has been verified.
+    Processed,
+    /// The block is outdated or from the future and hasn't been verified.
+    Ignored,
+}
+
+///
+/// Decide if a block from the network should be forwarded.
+/// Only blocks with invalid signatures or proofs are rejected,
+/// other errors usually mean that the block is from another epoch or fork.
+///
+fn block_validation_result(result: &Result<BlockStatus, Error>) -> ValidationResult {
+    let e = match result {
+        Ok(BlockStatus::Processed) => return ValidationResult::Accept,
+        Ok(BlockStatus::Ignored) => return ValidationResult::Ignore,
+        Err(e) => e,
+    };
+    let e = match e.downcast_ref::<BlockchainError>() {
+        Some(BlockchainError::BlockError(e)) => Some(e),
+        _ => e.downcast_ref::<BlockError>(),
+    };
+    match e {
+        Some(BlockError::InvalidLeaderSignature(..))
+        | Some(BlockError::InvalidBlockSignature(..))
+        | Some(BlockError::LeaderIsNotValidator(..))
+        | Some(BlockError::IncorrectRandom(..))
+        | Some(BlockError::InvalidVDFProof(..)) => ValidationResult::Reject,
+        _ => ValidationResult::Ignore,
+    }
+}
+
 pub struct NodeService {
     /// Config.
     cfg: NodeConfig,
     chain_name: String,
     /// Blockchain.
     chain: Blockchain,
-    /// Network secret key.
+    /// Network public key.
     network_pkey: pbc::PublicKey,
-    /// Network secret key.
-    network_skey: pbc::SecretKey,
+    /// Signer for blocks and consensus messages.
+    signer: Arc<dyn Signer>,
+    /// New network keys to switch on the next epoch, with the rotation transaction.
+    pending_network_keys: Option<(Hash, pbc::SecretKey, pbc::PublicKey)>,
 
     /// Memory pool of pending transactions.
     mempool: Mempool,
 
+    /// Transactions in the stem phase.
+    stempool: StemPool,
+
+    /// Timer to check embargo of stem transactions.
+    stem_timer: Interval,
+
+    /// Compact micro blocks waiting for missing transactions.
+    pending_micro_blocks: HashMap<Hash, PendingMicroBlock>,
+    /// Timer to check incomplete compact micro blocks.
+    pending_micro_blocks_timer: Interval,
+
     /// Consensus state.
     validation: Validation,
 
+    /// Double-sign protection.
+    sign_guard: SignGuard,
+
     /// Monotonic clock when the latest block was registered.
     last_block_clock: Instant,
 
+    /// Adaptive block timeouts.
+    timeouts: BlockTimeouts,
+
+    /// Log of consensus messages, if enabled.
+    recorder: Option<MessageRecorder>,
+
     /// Cheating detection.
     cheating_proofs: HashMap<pbc::PublicKey, SlashingProof>,
 
@@ -336,8 +459,7 @@ impl NodeService {
     pub fn new(
         cfg: NodeConfig,
         chain: Blockchain,
-        network_skey: pbc::SecretKey,
-        network_pkey: pbc::PublicKey,
+        signer: Arc<dyn Signer>,
         network: Network,
         chain_name: String,
         peer_id: PeerId,
@@ -345,8 +467,21 @@ impl NodeService {
     ) -> Result<(Self, Node), Error> {
         let (outbox, inbox) = mpsc::unbounded();
         let mempool = Mempool::new();
+        let stempool = StemPool::new(cfg.max_stempool_size);
+        let stem_timer = Interval::new_interval(dandelion::STEM_EMBARGO_CHECK_INTERVAL);
+        let pending_micro_blocks = HashMap::new();
+        let pending_micro_blocks_timer =
+            Interval::new_interval(compact::PENDING_MICRO_BLOCK_CHECK_INTERVAL);
 
         let last_block_clock = clock::now();
+        // max_block_timeout is used by adaptive timeouts only.
+        if cfg.adaptive_timeouts
+            && (cfg.max_block_timeout < cfg.micro_block_timeout
+                || cfg.max_block_timeout < cfg.macro_block_timeout)
+        {
+            bail!("max_block_timeout must not be less than micro_block_timeout and macro_block_timeout");
+        }
+        let timeouts = BlockTimeouts::new(&cfg, chain.cfg());
         let validation = if chain.is_epoch_full() {
             MacroBlockAuditor
         } else {
@@ -355,7 +490,35 @@ impl NodeService {
         let cheating_proofs = HashMap::new();
 
         let restaking_offset = 0; // will be updated on init().
-        let is_restaking_enabled = true;
+        let is_restaking_enabled = cfg.mode == NodeMode::Validator;
+        let sign_guard = if cfg.last_signed_file.as_os_str().is_empty() {
+            SignGuard::in_memory()
+        } else {
+            SignGuard::open(&cfg.last_signed_file)?
+        };
+        if let Some(position) = sign_guard.last_signed(SignKind::MicroBlock) {
+            if (position.epoch, position.offset) >= (chain.epoch(), chain.offset()) {
+                warn!(
+                    "The node has signed blocks ahead of the local chain, micro blocks up to the last signed position will not be signed: last_signed={}, epoch={}, offset={}",
+                    position,
+                    chain.epoch(),
+                    chain.offset()
+                );
+            }
+        }
+
+        let network_pkey = signer.pkey();
+        let pending_network_keys = key_rotation::load_pending_network_keys(&cfg.network_keys_dir)?;
+        let recorder = if cfg.consensus_log_file.as_os_str().is_empty() {
+            None
+        } else {
+            Some(MessageRecorder::open(
+                &cfg.consensus_log_file,
+                cfg.consensus_log_max_size,
+                network_pkey,
+                ChainInfo::from_blockchain(&chain),
+            )?)
+        };
 
         let status_subscribers = Vec::new();
 
@@ -364,22 +527,30 @@ impl NodeService {
         // Control messages
         streams.push(Box::new(inbox));
 
-        // Transaction Requests
+        // Transaction Requests, forwarded only if valid.
         let transaction_rx = network
-            .subscribe(&TX_TOPIC)?
+            .subscribe_validated(&TX_TOPIC)?
             .map(|m| NodeMessage::Transaction(m));
         streams.push(Box::new(transaction_rx));
 
-        // Consensus Requests
-        let consensus_rx = network
-            .subscribe(&CONSENSUS_TOPIC)?
-            .map(|m| NodeMessage::Consensus(m));
-        streams.push(Box::new(consensus_rx));
+        // Transactions in the stem phase.
+        let stem_transaction_rx = network
+            .subscribe_unicast(&TX_STEM_TOPIC)?
+            .map(NodeMessage::StemTransaction);
+        streams.push(Box::new(stem_transaction_rx));
 
-        let view_change_rx = network
-            .subscribe(&VIEW_CHANGE_TOPIC)?
-            .map(|m| NodeMessage::ViewChangeMessage(m));
-        streams.push(Box::new(view_change_rx));
+        // Consensus Requests
+        if cfg.mode == NodeMode::Validator {
+            let consensus_rx = network
+                .subscribe(&CONSENSUS_TOPIC)?
+                .map(|m| NodeMessage::Consensus(m));
+            streams.push(Box::new(consensus_rx));
+
+            let view_change_rx = network
+                .subscribe(&VIEW_CHANGE_TOPIC)?
+                .map(|m| NodeMessage::ViewChangeMessage(m));
+            streams.push(Box::new(view_change_rx));
+        }
 
         let view_change_proofs_rx = network
             .subscribe(&VIEW_CHANGE_PROOFS_TOPIC)?
@@ -391,12 +562,22 @@ impl NodeService {
             .map(|m| NodeMessage::ViewChangeProofMessage(m));
         streams.push(Box::new(view_change_unicast_rx));
 
-        // Sealed blocks broadcast topic.
+        // Sealed blocks broadcast topic, forwarded only if valid.
         let block_rx = network
-            .subscribe(&SEALED_BLOCK_TOPIC)?
+            .subscribe_validated(&SEALED_BLOCK_TOPIC)?
             .map(|m| NodeMessage::Block(m));
         streams.push(Box::new(block_rx));
 
+        // Compact micro blocks, forwarded only if valid.
+        let compact_block_rx = network
+            .subscribe_validated(&COMPACT_BLOCK_TOPIC)?
+            .map(NodeMessage::CompactBlock);
+        streams.push(Box::new(compact_block_rx));
+        let compact_block_txs_rx = network
+            .subscribe_unicast(&COMPACT_BLOCK_TXS_TOPIC)?
+            .map(NodeMessage::CompactBlockMessage);
+        streams.push(Box::new(compact_block_txs_rx));
+
         // Chain loader messages.
         let requests_rx = network
             .subscribe_unicast(loader::CHAIN_LOADER_TOPIC)?
@@ -425,11 +606,19 @@ impl NodeService {
             cfg,
             chain_name,
             chain,
-            network_skey,
             network_pkey,
+            signer,
+            pending_network_keys,
             mempool,
+            stempool,
+            stem_timer,
+            pending_micro_blocks,
+            pending_micro_blocks_timer,
             validation,
+            sign_guard,
             last_block_clock,
+            timeouts,
+            recorder,
             cheating_proofs,
             restaking_offset,
             is_restaking_enabled,
@@ -450,6 +639,9 @@ impl NodeService {
 
     /// Invoked when network is ready.
     pub fn init(&mut self) -> Result<(), Error> {
+        if self.chain.offset() == 0 {
+            self.switch_pending_network_keys()?;
+        }
         self.update_validation_status();
         self.on_facilitator_changed();
         self.on_status_changed();
@@ -459,6 +651,14 @@ impl NodeService {
 
     /// Send transaction to node and to the network.
     fn send_transaction(&mut self, tx: Transaction) -> Result<(), Error> {
+        match self.cfg.tx_propagation {
+            TxPropagation::Flood => self.flood_transaction(tx),
+            TxPropagation::Dandelion => self.send_stem_transaction(tx),
+        }
+    }
+
+    /// Broadcast transaction to all peers and add it to the mempool.
+    fn flood_transaction(&mut self, tx: Transaction) -> Result<(), Error> {
         let data = tx.into_buffer()?;
         let tx_hash = Hash::digest(&tx);
         self.network.publish(&TX_TOPIC, data.clone())?;
@@ -508,8 +708,41 @@ impl NodeService {
             tx.fee()
         );
 
+        let result = self.validate_transaction(&tx, &tx_hash);
+        match result {
+            Err(ref e) if !self.is_synchronized() => {
+                sdebug!(
+                    self,
+                    "Error during transaction validating when not synchronized: {}",
+                    e
+                );
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+            Ok(()) => {}
+        };
+
+        // Queue to mempool.
+        sinfo!(
+            self,
+            "Transaction is valid, adding to mempool: tx={}",
+            &tx_hash
+        );
+        self.stempool.remove_tx(&tx_hash);
+        self.mempool.push_tx(tx_hash, tx);
+        metrics::MEMPOOL_TRANSACTIONS.set(self.mempool.len() as i64);
+        metrics::MEMPOOL_INPUTS.set(self.mempool.inputs_len() as i64);
+        metrics::MEMPOOL_OUTPUTS.set(self.mempool.inputs_len() as i64);
+
+        Ok(())
+    }
+
+    /// Check transaction type, limits and validate it against the chain and the mempool.
+    fn validate_transaction(&self, tx: &Transaction, tx_hash: &Hash) -> Result<(), Error> {
+        let tx_hash = *tx_hash;
+
         // Check that transaction has proper type.
-        let check_limits = match &tx {
+        let check_limits = match tx {
             Transaction::PaymentTransaction(_tx) => true,
             Transaction::RestakeTransaction(_tx) => false,
             _ => return Err(NodeTransactionError::InvalidType(tx_hash).into()),
@@ -545,40 +778,14 @@ impl NodeService {
 
         // Validate transaction.
         let timestamp = Timestamp::now();
-        let result = validate_external_transaction(
-            &tx,
+        validate_external_transaction(
+            tx,
             &self.mempool,
             &self.chain,
             timestamp,
             self.cfg.min_payment_fee,
             self.cfg.min_stake_fee,
-        );
-
-        match result {
-            Err(ref e) if !self.is_synchronized() => {
-                sdebug!(
-                    self,
-                    "Error during transaction validating when not synchronized: {}",
-                    e
-                );
-                return Ok(());
-            }
-            Err(e) => return Err(e),
-            Ok(()) => {}
-        };
-
-        // Queue to mempool.
-        sinfo!(
-            self,
-            "Transaction is valid, adding to mempool: tx={}",
-            &tx_hash
-        );
-        self.mempool.push_tx(tx_hash, tx);
-        metrics::MEMPOOL_TRANSACTIONS.set(self.mempool.len() as i64);
-        metrics::MEMPOOL_INPUTS.set(self.mempool.inputs_len() as i64);
-        metrics::MEMPOOL_OUTPUTS.set(self.mempool.inputs_len() as i64);
-
-        Ok(())
+        )
     }
 
     ///
@@ -624,9 +831,8 @@ impl NodeService {
         assert_eq!(self.cfg.min_stake_fee, 0);
         strace!(self, "Restaking expiring stakes");
         let mut inputs: Vec<Output> = Vec::new();
-        let mut output_info = None;
         let mut pending_txs = HashSet::new();
-        for (input_hash, amount, account_pkey, active_until_epoch) in
+        for (input_hash, amount, _account_pkey, active_until_epoch) in
             self.chain.iter_validator_stakes(&self.network_pkey)
         {
             // Re-stake in the last epoch.
@@ -656,22 +862,6 @@ impl NodeService {
                 .output_by_hash(input_hash)?
                 .expect("Stake exists");
 
-            sdebug!(
-                self,
-                "Adding output info to accumulator: amount={}, key={}",
-                amount,
-                account_pkey
-            );
-            match &mut output_info {
-                None => {
-                    output_info = Some((*account_pkey, amount));
-                }
-                Some(o) => {
-                    assert_eq!(&o.0, account_pkey, "account key should be same");
-                    o.1 += amount
-                }
-            }
-
             sinfo!(self, "Restake: old_utxo={}, amount={}", input_hash, amount);
             inputs.push(input);
         }
@@ -679,21 +869,11 @@ impl NodeService {
             return Ok(()); // Nothing to re-stake.
         }
 
-        let (account_pkey, amount) = output_info.expect("some output info");
-
-        strace!(self, "Creating StakeUTXO ...");
-        let output = Output::new_stake(
-            &account_pkey,
-            &self.network_skey,
-            &self.network_pkey,
-            amount,
-        )?;
-        let output_hash = Hash::digest(&output);
-        sinfo!(self, "Restake: new_utxo={}, amount={}", output_hash, amount);
-
         strace!(self, "Signing transaction...");
-        let tx =
-            RestakeTransaction::new(&self.network_skey, &self.network_pkey, &inputs, &[output])?;
+        let tx = self.signer.restake(&inputs)?;
+        for output in &tx.txouts {
+            sinfo!(self, "Restake: new_utxo={}", Hash::digest(output));
+        }
         let tx_hash = Hash::digest(&tx);
         sinfo!(
             self,
@@ -903,12 +1083,19 @@ impl NodeService {
 
         self.chain
             .set_view_change(proof.chain.view_change + 1, proof.proof);
+        // Allow to sign blocks of the new branch.
+        let position = SignPosition {
+            epoch,
+            offset,
+            view_change: self.chain.view_change(),
+        };
+        self.sign_guard.rollback(position).map_err(Error::from)?;
         self.update_validation_status();
         Ok(())
     }
 
     /// Handle a macro block from the network.
-    fn handle_macro_block(&mut self, block: MacroBlock) -> Result<(), Error> {
+    fn handle_macro_block(&mut self, block: MacroBlock) -> Result<BlockStatus, Error> {
         if block.header.epoch < self.chain.epoch() {
             // Ignore outdated block.
             let block_hash = Hash::digest(&block);
@@ -918,9 +1105,14 @@ impl NodeService {
                 block_hash,
                 block.header.epoch
             );
-            Ok(())
+            Ok(BlockStatus::Ignored)
         } else if block.header.epoch == self.chain.epoch() {
-            self.apply_macro_block(block)
+            let block_hash = Hash::digest(&block);
+            self.chain
+                .cfg()
+                .validate_checkpoint(block.header.epoch, &block_hash)?;
+            self.apply_macro_block(block)?;
+            Ok(BlockStatus::Processed)
         } else {
             let block_hash = Hash::digest(&block);
             sdebug!(
@@ -929,12 +1121,12 @@ impl NodeService {
                 block_hash,
                 block.header.epoch
             );
-            Ok(())
+            Ok(BlockStatus::Ignored)
         }
     }
 
     /// Handle a micro block from the network.
-    fn handle_micro_block(&mut self, block: MicroBlock) -> Result<(), Error> {
+    pub(crate) fn handle_micro_block(&mut self, block: MicroBlock) -> Result<BlockStatus, Error> {
         let block_hash = Hash::digest(&block);
         if block.header.epoch < self.chain.epoch() {
             sdebug!(self,
@@ -945,7 +1137,7 @@ impl NodeService {
                 block.header.view_change,
                 block.header.previous
             );
-            return Ok(());
+            return Ok(BlockStatus::Ignored);
         } else if block.header.epoch > self.chain.epoch()
             || block.header.offset > self.chain.offset()
         {
@@ -957,7 +1149,7 @@ impl NodeService {
                 block.header.view_change,
                 block.header.previous
             );
-            return Ok(());
+            return Ok(BlockStatus::Ignored);
         }
 
         assert_eq!(block.header.epoch, self.chain.epoch());
@@ -1007,7 +1199,7 @@ impl NodeService {
                         offset
                     );
                     assert!(offset < self.chain.offset(), "Fork didn't remove any block");
-                    return Ok(());
+                    return Ok(BlockStatus::Processed);
                 }
                 Err(ForkError::Error(e)) => return Err(e),
             }
@@ -1057,17 +1249,24 @@ impl NodeService {
                         chain: chain_info,
                         proof: proof.clone(),
                     };
-                    self.network
-                        .send(leader, VIEW_CHANGE_DIRECT, proof.into_buffer()?)?;
+                    // Resent until acknowledged, the outcome is not awaited.
+                    let _ = self.network.send_reliable(
+                        leader,
+                        VIEW_CHANGE_DIRECT,
+                        proof.into_buffer()?,
+                    )?;
+                    self.record_message(Direction::Outgoing, || {
+                        RecordedMessage::ViewChangeProof(proof)
+                    });
                 }
                 _ => {}
             }
         }
-        Ok(())
+        Ok(BlockStatus::Processed)
     }
 
     /// Handle incoming blocks received from network.
-    fn handle_block(&mut self, block: Block) -> Result<(), Error> {
+    fn handle_block(&mut self, block: Block) -> Result<BlockStatus, Error> {
         match block {
             Block::MicroBlock(block) => self.handle_micro_block(block),
             Block::MacroBlock(block) => self.handle_macro_block(block),
@@ -1082,15 +1281,17 @@ impl NodeService {
         let epoch = block.header.epoch;
         let was_synchronized = self.is_synchronized();
 
-        // Validate signature.
-        check_multi_signature(
-            &hash,
-            &block.multisig,
-            &block.multisigmap,
-            &self.chain.validators_at_epoch_start(),
-            self.chain.total_slots(),
-        )
-        .map_err(|e| BlockError::InvalidBlockSignature(e, epoch, hash))?;
+        // Validate signature, unless the block is trusted.
+        if !self.is_trusted_epoch(epoch) || self.chain.cfg().checkpoint(epoch) != Some(&hash) {
+            check_multi_signature(
+                &hash,
+                &block.multisig,
+                &block.multisigmap,
+                &self.chain.validators_at_epoch_start(),
+                self.chain.total_slots(),
+            )
+            .map_err(|e| BlockError::InvalidBlockSignature(e, epoch, hash))?;
+        }
 
         // Remove all micro blocks.
         while self.chain.offset() > 0 {
@@ -1110,6 +1311,7 @@ impl NodeService {
             .clone();
         let notification = ExtendedMacroBlock { block, epoch_info };
         self.cheating_proofs.clear();
+        self.switch_pending_network_keys()?;
         self.on_facilitator_changed();
         self.on_block_added(block_timestamp, notification.into(), was_synchronized);
 
@@ -1119,6 +1321,28 @@ impl NodeService {
         Ok(())
     }
 
+    ///
+    /// Returns true if blocks of the epoch are covered by a checkpoint
+    /// and expensive validation can be skipped.
+    ///
+    fn is_trusted_epoch(&self, epoch: u64) -> bool {
+        self.chain.consistency_check() == ConsistencyCheck::None
+            && self
+                .chain
+                .cfg()
+                .last_checkpoint_epoch()
+                .map_or(false, |last| epoch <= last)
+    }
+
+    ///
+    /// Returns true if the epoch itself is pinned by a checkpoint,
+    /// i.e. its closing macro block must match a known hash.
+    ///
+    fn is_checkpointed_epoch(&self, epoch: u64) -> bool {
+        self.chain.consistency_check() == ConsistencyCheck::None
+            && self.chain.cfg().checkpoint(epoch).is_some()
+    }
+
     /// Try to apply a new micro block into the blockchain.
     fn apply_micro_block(&mut self, block: MicroBlock) -> Result<(), Error> {
         let hash = Hash::digest(&block);
@@ -1145,9 +1369,15 @@ impl NodeService {
             epoch, offset, &hash, inputs_len, outputs_len, txs_len
         );
         let start_clock = clock::now();
+        let checkpointed = self.is_checkpointed_epoch(epoch);
         let r = {
             let mut outputs: Vec<&Output> = Vec::new();
             for tx in &block.transactions {
+                if checkpointed {
+                    // The epoch must be closed by the checkpointed macro block,
+                    // which re-checks the resulting UTXO set against its hash.
+                    break;
+                }
                 // Skip transactions from mempool.
                 let tx_hash = Hash::digest(&tx);
                 if let Some(tx2) = self.mempool.get_tx(&tx_hash) {
@@ -1233,6 +1463,7 @@ impl NodeService {
             ChainNotification::MicroBlockPrepared(block) => {
                 metrics::MICRO_BLOCK_LAG.set(lag);
                 metrics::MICRO_BLOCK_LAG_HG.observe(lag);
+                self.timeouts.observe_latency(lag);
                 let interval = self.last_block_clock.duration_since(last_block_clock);
                 let interval =
                     (interval.as_secs() as f64) + (interval.subsec_nanos() as f64) * 1e-9;
@@ -1260,6 +1491,9 @@ impl NodeService {
         // Send ChainNotification.
         notify_subscribers(&mut self.chain_subscribers, notification);
 
+        // Drop compact micro blocks which can't be applied anymore.
+        self.prune_pending_micro_blocks();
+
         // Send block to replication.
         self.replication
             .on_block(block, self.chain.cfg().micro_blocks_in_epoch);
@@ -1390,8 +1624,15 @@ impl NodeService {
     /// Send block to network.
     fn send_block(&mut self, block: Block) -> Result<(), Error> {
         let block_hash = Hash::digest(&block);
-        let data = block.into_buffer()?;
-        self.network.publish(&SEALED_BLOCK_TOPIC, data)?;
+        match block {
+            Block::MicroBlock(ref block) if self.cfg.compact_micro_blocks => {
+                self.send_compact_micro_block(block)?;
+            }
+            _ => {
+                let data = block.into_buffer()?;
+                self.network.publish(&SEALED_BLOCK_TOPIC, data)?;
+            }
+        }
         match block {
             Block::MacroBlock(ref block) => {
                 sinfo!(
@@ -1456,7 +1697,8 @@ impl NodeService {
                   leader);
             consensus::metrics::CONSENSUS_ROLE
                 .set(consensus::metrics::ConsensusRole::Validator as i64);
-            let deadline = clock::now() + self.cfg.micro_block_timeout;
+            let timeout = self.timeouts.micro_block(self.chain.view_change());
+            let deadline = clock::now() + timeout;
             std::mem::replace(
                 block_timer,
                 MicroBlockTimer::ViewChange(Delay::new(deadline)),
@@ -1507,8 +1749,7 @@ impl NodeService {
             );
             consensus::metrics::CONSENSUS_ROLE
                 .set(consensus::metrics::ConsensusRole::Validator as i64);
-            let relevant_round = 1 + consensus.round();
-            let deadline = clock::now() + relevant_round * self.cfg.macro_block_timeout;
+            let deadline = clock::now() + self.timeouts.macro_block(consensus.round());
             *block_timer = MacroBlockTimer::ViewChange(Delay::new(deadline));
         }
 
@@ -1518,7 +1759,7 @@ impl NodeService {
     /// Called when facilitator is changed.
     fn on_facilitator_changed(&mut self) {
         let facilitator = self.chain.facilitator();
-        if facilitator == &self.network_pkey {
+        if facilitator == &self.network_pkey && self.cfg.mode == NodeMode::Validator {
             sinfo!(self, "I am facilitator");
             let txpool_service =
                 TransactionPoolService::new(self.network.clone(), self.node.clone());
@@ -1529,6 +1770,13 @@ impl NodeService {
         }
     }
 
+    ///
+    /// Returns true if this node participates in consensus.
+    ///
+    fn is_validator(&self) -> bool {
+        self.cfg.mode == NodeMode::Validator && self.chain.is_validator(&self.network_pkey)
+    }
+
     ///
     /// Change validation status after applying a new block or performing a view change.
     ///
@@ -1536,7 +1784,7 @@ impl NodeService {
         if !self.chain.is_epoch_full() {
             // Expected Micro Block.
             let _prev = std::mem::replace(&mut self.validation, MicroBlockAuditor);
-            if !self.chain.is_validator(&self.network_pkey) {
+            if !self.is_validator() {
                 sinfo!(self, "I'm auditor, waiting for the next micro block: epoch={}, offset={}, view_change={}, last_block={}",
                       self.chain.epoch(),
                       self.chain.offset(),
@@ -1548,8 +1796,7 @@ impl NodeService {
                 return;
             }
 
-            let view_change_collector =
-                ViewChangeCollector::new(&self.chain, self.network_pkey, self.network_skey.clone());
+            let view_change_collector = ViewChangeCollector::new(&self.chain, self.signer.clone());
 
             self.validation = MicroBlockValidator {
                 view_change_collector,
@@ -1560,7 +1807,7 @@ impl NodeService {
         } else {
             // Expected Macro Block.
             let prev = std::mem::replace(&mut self.validation, MacroBlockAuditor);
-            if !self.chain.is_validator(&self.network_pkey) {
+            if !self.is_validator() {
                 sinfo!(
                     self,
                     "I'm auditor, waiting for the next macro block: epoch={}, last_block={}",
@@ -1574,11 +1821,11 @@ impl NodeService {
 
             let mut consensus = Consensus::new(
                 self.chain.epoch(),
-                self.network_skey.clone(),
-                self.network_pkey.clone(),
+                self.signer.clone(),
                 self.chain.election_result().clone(),
                 self.chain.validators_at_epoch_start().into_iter().collect(),
             );
+            self.restore_consensus_state(&mut consensus);
 
             // Flush pending messages.
             if let MicroBlockValidator {
@@ -1605,6 +1852,46 @@ impl NodeService {
         }
     }
 
+    ///
+    /// Append a message to the consensus log, if enabled.
+    ///
+    fn record_message<F>(&mut self, direction: Direction, message: F)
+    where
+        F: FnOnce() -> RecordedMessage,
+    {
+        if let Some(recorder) = &mut self.recorder {
+            let mut result = recorder.record(direction, message());
+            if result.is_ok() && recorder.is_full() {
+                let chain = ChainInfo::from_blockchain(&self.chain);
+                result = recorder.rotate(self.network_pkey, chain);
+            }
+            if let Err(e) = result {
+                serror!(self, "Failed to record a consensus message: {}", e);
+            }
+        }
+    }
+
+    ///
+    /// Decode a unicast message, the sender of garbage is reported to the network.
+    ///
+    fn decode_unicast<T: ProtoConvert>(&self, msg: &UnicastMessage) -> Result<T, Error> {
+        T::from_buffer(&msg.data).map_err(|e| {
+            if let Err(e) = self.network.report_peer(msg.from, Misbehavior::InvalidRpc) {
+                serror!(self, "Failed to report peer: {}", e);
+            }
+            e
+        })
+    }
+
+    ///
+    /// Report the result of validation of a message from a validated topic.
+    ///
+    fn report_validation(&self, id: MessageId, result: ValidationResult) {
+        if let Err(e) = self.network.validate(id, result) {
+            serror!(self, "Failed to report validation: {}", e);
+        }
+    }
+
     ///
     /// Handles incoming consensus requests received from network.
     ///
@@ -1687,13 +1974,41 @@ impl NodeService {
             return self.commit_proposed_block();
         }
 
+        // Save the state before any message leaves the node.
+        if let Err(e) = save_consensus_state(&self.cfg.consensus_state_file, consensus) {
+            serror!(
+                self,
+                "Consensus messages are not sent: failed to save the state: {}",
+                e
+            );
+            consensus.outbox.clear();
+            return;
+        }
+
         // Flush pending messages.
         let outbox = std::mem::replace(&mut consensus.outbox, Vec::new());
         for msg in outbox {
+            let kind = match msg.body {
+                ConsensusMessageBody::Proposal(_) => None,
+                ConsensusMessageBody::Prevote => Some(SignKind::Prevote),
+                ConsensusMessageBody::Precommit(_) => Some(SignKind::Precommit),
+            };
+            if let Some(kind) = kind {
+                let position = SignPosition {
+                    epoch: msg.epoch,
+                    offset: msg.round,
+                    view_change: 0,
+                };
+                if let Err(e) = self.sign_guard.sign(kind, position, msg.block_hash) {
+                    serror!(self, "Consensus message is not sent: {}", e);
+                    continue;
+                }
+            }
             let data = msg.into_buffer().expect("Failed to serialize");
             self.network
                 .publish(&CONSENSUS_TOPIC, data)
                 .expect("Connected");
+            self.record_message(Direction::Outgoing, || RecordedMessage::Consensus(msg));
         }
     }
 
@@ -1730,8 +2045,7 @@ impl NodeService {
         assert!(consensus.should_propose());
 
         // Set view_change timer.
-        let relevant_round = 1 + consensus.round();
-        let deadline = clock::now() + relevant_round * self.cfg.macro_block_timeout;
+        let deadline = clock::now() + self.timeouts.macro_block(consensus.round());
         std::mem::replace(
             block_timer,
             MacroBlockTimer::ViewChange(Delay::new(deadline)),
@@ -1752,10 +2066,14 @@ impl NodeService {
             .account_by_network_key(&self.network_pkey)
             .expect("Staked");
 
+        let seed = mix(self.chain.last_macro_block_random(), consensus.round());
+        let random =
+            self.signer
+                .make_macro_block_vrf(self.chain.epoch(), consensus.round(), &seed)?;
         let (block, transactions) = self.chain.create_macro_block(
             consensus.round(),
             &recipient_pkey,
-            &self.network_skey,
+            random,
             self.network_pkey.clone(),
             timestamp,
         );
@@ -1823,8 +2141,7 @@ impl NodeService {
                     "It's not my time to send macro block, wait for next autocommit timer, current_leader={}",
                     leader.0
                 );
-                let relevant_round = 1 + consensus.round();
-                let deadline = clock::now() + relevant_round * self.cfg.macro_block_timeout;
+                let deadline = clock::now() + self.timeouts.macro_block(consensus.round());
                 *block_timer = MacroBlockTimer::ViewChange(Delay::new(deadline));
                 return Ok(());
             }
@@ -1936,7 +2253,9 @@ impl NodeService {
         };
 
         // Update timer.
-        let deadline = clock::now() + self.cfg.micro_block_timeout;
+        // The view change hasn't happened yet, so wait longer than before.
+        let timeout = self.timeouts.micro_block(self.chain.view_change() + 1);
+        let deadline = clock::now() + timeout;
         std::mem::replace(
             block_timer,
             MicroBlockTimer::ViewChange(Delay::new(deadline)),
@@ -1945,9 +2264,19 @@ impl NodeService {
 
         // Send a view_change message.
         let chain_info = ChainInfo::from_blockchain(&self.chain);
-        let msg = view_change_collector.handle_timeout(chain_info);
+        let position = SignPosition {
+            epoch: chain_info.epoch,
+            offset: chain_info.offset,
+            view_change: chain_info.view_change,
+        };
+        self.sign_guard
+            .sign(SignKind::ViewChange, position, chain_info.last_block)?;
+        let msg = view_change_collector.handle_timeout(chain_info)?;
         self.network
             .publish(VIEW_CHANGE_TOPIC, msg.into_buffer()?)?;
+        self.record_message(Direction::Outgoing, || {
+            RecordedMessage::ViewChange(msg.clone())
+        });
         metrics::MICRO_BLOCK_VIEW_CHANGES.inc();
         sdebug!(
             self,
@@ -1981,6 +2310,9 @@ impl NodeService {
 
             self.network
                 .publish(VIEW_CHANGE_PROOFS_TOPIC, proof.into_buffer()?)?;
+            self.record_message(Direction::Outgoing, || {
+                RecordedMessage::ViewChangeProof(proof.view_change_proof)
+            });
         }
         self.on_status_changed();
 
@@ -2031,17 +2363,20 @@ impl NodeService {
             .account_by_network_key(&self.network_pkey)
             .expect("Staked");
         let timestamp = self.next_block_timestamp();
+        let seed = mix(self.chain.last_random(), view_change);
+        let random = self
+            .signer
+            .make_micro_block_vrf(epoch, offset, view_change, &seed)?;
         let mut block = self.mempool.create_block(
             previous,
             epoch,
             offset,
             view_change,
             view_change_proof,
-            self.chain.last_random(),
+            random,
             solution,
             self.chain.cfg().block_reward,
             &recipient_pkey,
-            &self.network_skey,
             &self.network_pkey,
             self.cfg.max_inputs_in_block,
             self.cfg.max_outputs_in_block,
@@ -2051,7 +2386,14 @@ impl NodeService {
         let block_hash = Hash::digest(&block);
 
         // Sign block.
-        block.sign(&self.network_skey, &self.network_pkey);
+        let position = SignPosition {
+            epoch,
+            offset,
+            view_change,
+        };
+        self.sign_guard
+            .sign(SignKind::MicroBlock, position, block_hash)?;
+        block.sig = self.signer.sign_micro_block(&block.header)?;
 
         let duration = clock::now().duration_since(start_clock);
         let duration = (duration.as_secs() as f64) + (duration.subsec_nanos() as f64) * 1e-9;
@@ -2243,6 +2585,46 @@ impl Future for NodeService {
             }
         }
 
+        loop {
+            match self.stem_timer.poll() {
+                Ok(Async::Ready(Some(_))) => {
+                    self.handle_stem_embargo_timer();
+                }
+                Ok(Async::Ready(None)) => {
+                    serror!(self, "Error during process embargo timer");
+                    return Ok(Async::Ready(()));
+                }
+                Err(e) => {
+                    serror!(self, "Error: {}", e);
+                    return Err(());
+                }
+                Ok(Async::NotReady) => {
+                    break;
+                }
+            }
+        }
+
+        loop {
+            match self.pending_micro_blocks_timer.poll() {
+                Ok(Async::Ready(Some(_))) => {
+                    if let Err(e) = self.handle_pending_micro_blocks_timer() {
+                        serror!(self, "Error: {}", e);
+                    }
+                }
+                Ok(Async::Ready(None)) => {
+                    serror!(self, "Error during process compact block timer");
+                    return Ok(Async::Ready(()));
+                }
+                Err(e) => {
+                    serror!(self, "Error: {}", e);
+                    return Err(());
+                }
+                Ok(Async::NotReady) => {
+                    break;
+                }
+            }
+        }
+
         // Poll chain readers.
         let mut i = 0;
         while i < self.chain_readers.len() {
@@ -2358,6 +2740,14 @@ impl Future for NodeService {
                                         },
                                     }
                                 }
+                                NodeRequest::RotateNetworkKeys {} => {
+                                    match self.handle_rotate_network_keys() {
+                                        Ok(response) => response,
+                                        Err(e) => NodeResponse::Error {
+                                            error: e.to_string(),
+                                        },
+                                    }
+                                }
                                 NodeRequest::ValidateCertificate {
                                     output_hash,
                                     spender,
@@ -2400,7 +2790,13 @@ impl Future for NodeService {
                                     },
                                 },
                                 NodeRequest::EnableRestaking {} => {
-                                    if self.is_restaking_enabled {
+                                    if self.cfg.mode == NodeMode::Observer {
+                                        NodeResponse::Error {
+                                            error: format!(
+                                                "Re-staking is disabled in observer mode"
+                                            ),
+                                        }
+                                    } else if self.is_restaking_enabled {
                                         NodeResponse::Error {
                                             error: format!("Re-staking is already enabled"),
                                         }
@@ -2496,8 +2892,21 @@ impl Future for NodeService {
                             Ok(())
                         }
                         NodeMessage::Transaction(msg) => {
-                            match Transaction::from_buffer(&msg)
-                                .and_then(|msg| self.handle_transaction(msg))
+                            let result = Transaction::from_buffer(&msg.data)
+                                .and_then(|tx| self.handle_transaction(tx));
+                            self.report_validation(msg.id, tx_validation_result(&result));
+                            match result {
+                                Ok(()) => Ok(()),
+                                Err(e) => match e.downcast_ref::<NodeTransactionError>() {
+                                    Some(NodeTransactionError::NotSynchronized(_)) => Ok(()),
+                                    _ => Err(e),
+                                },
+                            }
+                        }
+                        NodeMessage::StemTransaction(msg) => {
+                            match self
+                                .decode_unicast(&msg)
+                                .and_then(|tx| self.handle_stem_transaction(msg.from, tx))
                             {
                                 Ok(()) => Ok(()),
                                 Err(e) => match e.downcast_ref::<NodeTransactionError>() {
@@ -2507,24 +2916,74 @@ impl Future for NodeService {
                             }
                         }
                         NodeMessage::Consensus(msg) => ConsensusMessage::from_buffer(&msg)
-                            .and_then(|msg| self.handle_consensus_message(msg)),
+                            .and_then(|msg| {
+                                self.record_message(Direction::Incoming, || {
+                                    RecordedMessage::Consensus(msg.clone())
+                                });
+                                self.handle_consensus_message(msg)
+                            }),
                         NodeMessage::ViewChangeMessage(msg) => ViewChangeMessage::from_buffer(&msg)
-                            .and_then(|msg| self.handle_view_change_message(msg)),
+                            .and_then(|msg| {
+                                self.record_message(Direction::Incoming, || {
+                                    RecordedMessage::ViewChange(msg.clone())
+                                });
+                                self.handle_view_change_message(msg)
+                            }),
                         NodeMessage::ViewChangeProof(msg) => {
                             AddressedViewChangeProof::from_buffer(&msg).and_then(|proof| {
+                                self.record_message(Direction::Incoming, || {
+                                    RecordedMessage::ViewChangeProof(
+                                        proof.view_change_proof.clone(),
+                                    )
+                                });
                                 self.handle_view_change_direct(proof.view_change_proof, proof.pkey)
                             })
                         }
-                        NodeMessage::ViewChangeProofMessage(msg) => {
-                            SealedViewChangeProof::from_buffer(&msg.data)
-                                .and_then(|proof| self.handle_view_change_direct(proof, msg.from))
-                        }
-                        NodeMessage::Block(msg) => {
-                            Block::from_buffer(&msg).and_then(|msg| self.handle_block(msg))
+                        NodeMessage::ViewChangeProofMessage(msg) => self
+                            .decode_unicast(&msg)
+                            .and_then(|proof: SealedViewChangeProof| {
+                                self.record_message(Direction::Incoming, || {
+                                    RecordedMessage::ViewChangeProof(proof.clone())
+                                });
+                                self.handle_view_change_direct(proof, msg.from)
+                            }),
+                        NodeMessage::Block(msg) => match Block::from_buffer(&msg.data) {
+                            Ok(block) => {
+                                let result = self.handle_block(block);
+                                self.report_validation(msg.id, block_validation_result(&result));
+                                result.map(|_status| ())
+                            }
+                            Err(e) => {
+                                self.report_validation(msg.id, ValidationResult::Reject);
+                                Err(e)
+                            }
+                        },
+                        NodeMessage::CompactBlock(msg) => {
+                            match CompactMicroBlock::from_buffer(&msg.data) {
+                                Ok(block) => {
+                                    let result = self.handle_compact_micro_block(block);
+                                    self.report_validation(
+                                        msg.id,
+                                        block_validation_result(&result),
+                                    );
+                                    result.map(|_status| ())
+                                }
+                                Err(e) => {
+                                    self.report_validation(msg.id, ValidationResult::Reject);
+                                    Err(e)
+                                }
+                            }
                         }
+                        NodeMessage::CompactBlockMessage(msg) => self
+                            .decode_unicast(&msg)
+                            .and_then(|data: CompactBlockMessage| {
+                                self.handle_compact_block_message(msg.from, data)
+                            }),
                         NodeMessage::ChainLoaderMessage(msg) => {
-                            ChainLoaderMessage::from_buffer(&msg.data)
-                                .and_then(|data| self.handle_chain_loader_message(msg.from, data))
+                            self.decode_unicast(&msg)
+                                .and_then(|data: ChainLoaderMessage| {
+                                    self.handle_chain_loader_message(msg.from, data)
+                                })
                         }
                     };
                     if let Err(e) = result {
@@ -2542,6 +3001,13 @@ impl Future for NodeService {
                     for block in blocks {
                         if let Err(e) = self.handle_block(block) {
                             serror!(self, "Invalid block received from replication: {}", e);
+                            if let Some(BlockError::CheckpointMismatch(..)) =
+                                e.downcast_ref::<BlockError>()
+                            {
+                                // The upstream follows a different chain.
+                                self.replication.change_upstream();
+                                break;
+                            }
                         }
                     }
                 }
@@ -88,6 +88,13 @@ impl Mempool {
         self.pool.get(tx_hash)
     }
 
+    ///
+    /// Returns an iterator over all transactions.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&Hash, &Transaction)> {
+        self.pool.iter()
+    }
+
     ///
     /// Queues a transaction to the mempool.
     ///
@@ -243,19 +250,15 @@ impl Mempool {
         offset: u32,
         view_change: u32,
         view_change_proof: Option<ViewChangeProof>,
-        last_random: Hash,
+        random: pbc::VRF,
         solution: Vec<u8>,
         block_reward: i64,
         recipient_pkey: &scc::PublicKey,
-        network_skey: &pbc::SecretKey,
         network_pkey: &pbc::PublicKey,
         max_inputs_in_block: usize,
         max_outputs_in_block: usize,
         timestamp: Timestamp,
     ) -> MicroBlock {
-        let seed = mix(last_random, view_change);
-        let random = pbc::make_VRF(network_skey, &seed);
-
         // Sort transactions by fee.
         // RestakeTransactions have high priority.
         let mut pool: Vec<(&Hash, &Transaction, i64)> = self
@@ -562,17 +565,17 @@ mod test {
         let view_change = 0;
         let reward = 10;
         let solution = vec![1, 2, 3, 4];
+        let random = pbc::make_VRF(&network_skey, &Hash::digest("test"));
         let block = mempool.create_block(
             previous,
             epoch,
             offset,
             view_change,
             None,
-            Hash::digest("test"),
+            random,
             solution,
             reward,
             &recipient_pkey,
-            &network_skey,
             &network_pkey,
             max_inputs_in_block,
             max_outputs_in_block,
@@ -176,4 +176,44 @@ lazy_static! {
         register_int_gauge!("stegos_mempool_outputs", "The number of outputs in mempool.").unwrap();
     pub static ref MEMPOOL_TRANSACTIONS: IntGauge =
         register_int_gauge!("stegos_mempool_transactions", "The number of transactions in mempool.").unwrap();
+
+    //
+    // Dandelion.
+    //
+    pub static ref STEMPOOL_TRANSACTIONS: IntGauge =
+        register_int_gauge!("stegos_stempool_transactions", "The number of transactions in the stem phase.").unwrap();
+    pub static ref DANDELION_STEM_TRANSACTIONS: IntCounter = register_int_counter!(
+        "stegos_dandelion_stem_transactions",
+        "The number of transactions relayed in the stem phase."
+    )
+    .unwrap();
+    pub static ref DANDELION_FLUFF_TRANSACTIONS: IntCounter = register_int_counter!(
+        "stegos_dandelion_fluff_transactions",
+        "The number of stem transactions broadcasted by this node."
+    )
+    .unwrap();
+    pub static ref DANDELION_STEMPOOL_OVERFLOW: IntCounter = register_int_counter!(
+        "stegos_dandelion_stempool_overflow",
+        "The number of stem transactions broadcasted early because the stem pool was full."
+    )
+    .unwrap();
+    pub static ref DANDELION_EMBARGO_EXPIRED: IntCounter = register_int_counter!(
+        "stegos_dandelion_embargo_expired",
+        "The number of stem transactions broadcasted after the embargo timeout."
+    )
+    .unwrap();
+
+    //
+    // Compact micro blocks.
+    //
+    pub static ref COMPACT_MICRO_BLOCKS: IntCounter = register_int_counter!(
+        "stegos_compact_micro_blocks",
+        "The number of received compact micro blocks."
+    )
+    .unwrap();
+    pub static ref COMPACT_MICRO_BLOCK_MISSING_TRANSACTIONS: IntCounter = register_int_counter!(
+        "stegos_compact_micro_block_missing_transactions",
+        "The number of transactions requested to reconstruct compact micro blocks."
+    )
+    .unwrap();
 }
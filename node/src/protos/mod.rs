@@ -26,17 +26,26 @@ use stegos_crypto::protos::*;
 
 use stegos_serialization::traits::*;
 // link protobuf dependencies
+use stegos_blockchain::protos::view_changes;
 use stegos_blockchain::protos::*;
 include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 
+use crate::compact::{
+    CompactBlockMessage, CompactMicroBlock, RequestTransactions, ResponseTransactions,
+};
 use crate::loader::{ChainLoaderMessage, RequestBlocks, ResponseBlocks};
+use crate::signer::{SignerRequest, SignerResponse};
 use failure::{format_err, Error};
 use protobuf::RepeatedField;
 
-use stegos_blockchain::PaymentOutput;
+use stegos_blockchain::{
+    ChainInfo, MacroBlockHeader, MicroBlockHeader, Output, PaymentOutput, RestakeTransaction,
+    Transaction,
+};
+use stegos_consensus::MacroBlockProposal;
 use stegos_crypto::hash::Hash;
 use stegos_crypto::scc::SchnorrSig;
-use stegos_crypto::{dicemix, CryptoError};
+use stegos_crypto::{dicemix, pbc, CryptoError};
 
 use crate::txpool::messages::{ParticipantTXINMap, PoolInfo, PoolJoin, PoolNotification};
 
@@ -99,6 +108,118 @@ impl ProtoConvert for ChainLoaderMessage {
     }
 }
 
+impl ProtoConvert for CompactMicroBlock {
+    type Proto = compact::CompactMicroBlock;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = compact::CompactMicroBlock::new();
+        proto.set_header(self.header.into_proto());
+        proto.set_sig(self.sig.into_proto());
+        proto.set_short_ids(self.short_ids.clone());
+        for (index, tx) in &self.prefilled {
+            let mut prefilled = compact::PrefilledTransaction::new();
+            prefilled.set_index(*index);
+            prefilled.set_transaction(tx.into_proto());
+            proto.prefilled.push(prefilled);
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let header = MicroBlockHeader::from_proto(proto.get_header())?;
+        let sig = pbc::Signature::from_proto(proto.get_sig())?;
+        let short_ids = proto.get_short_ids().to_vec();
+        let mut prefilled = Vec::with_capacity(proto.prefilled.len());
+        for elt in proto.get_prefilled() {
+            let tx = Transaction::from_proto(elt.get_transaction())?;
+            prefilled.push((elt.get_index(), tx));
+        }
+        Ok(CompactMicroBlock {
+            header,
+            sig,
+            short_ids,
+            prefilled,
+        })
+    }
+}
+
+impl ProtoConvert for RequestTransactions {
+    type Proto = compact::RequestTransactions;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = compact::RequestTransactions::new();
+        proto.set_epoch(self.epoch);
+        proto.set_offset(self.offset);
+        proto.set_block_hash(self.block_hash.into_proto());
+        proto.set_indexes(self.indexes.clone());
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let epoch = proto.get_epoch();
+        let offset = proto.get_offset();
+        let block_hash = Hash::from_proto(proto.get_block_hash())?;
+        let indexes = proto.get_indexes().to_vec();
+        Ok(RequestTransactions {
+            epoch,
+            offset,
+            block_hash,
+            indexes,
+        })
+    }
+}
+
+impl ProtoConvert for ResponseTransactions {
+    type Proto = compact::ResponseTransactions;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = compact::ResponseTransactions::new();
+        proto.set_block_hash(self.block_hash.into_proto());
+        let transactions: Vec<_> = self
+            .transactions
+            .iter()
+            .map(ProtoConvert::into_proto)
+            .collect();
+        proto.set_transactions(RepeatedField::from_vec(transactions));
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let block_hash = Hash::from_proto(proto.get_block_hash())?;
+        let transactions: Result<Vec<_>, _> = proto
+            .get_transactions()
+            .iter()
+            .map(ProtoConvert::from_proto)
+            .collect();
+        let transactions = transactions?;
+        Ok(ResponseTransactions {
+            block_hash,
+            transactions,
+        })
+    }
+}
+
+impl ProtoConvert for CompactBlockMessage {
+    type Proto = compact::CompactBlockMessage;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = compact::CompactBlockMessage::new();
+        match self {
+            CompactBlockMessage::Request(r) => proto.set_request(r.into_proto()),
+            CompactBlockMessage::Response(r) => proto.set_response(r.into_proto()),
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let ref body = proto
+            .body
+            .as_ref()
+            .ok_or_else(|| format_err!("No variants in CompactBlockMessage found"))?;
+        let message = match body {
+            compact::CompactBlockMessage_oneof_body::request(ref r) => {
+                CompactBlockMessage::Request(RequestTransactions::from_proto(r)?)
+            }
+            compact::CompactBlockMessage_oneof_body::response(ref r) => {
+                CompactBlockMessage::Response(ResponseTransactions::from_proto(r)?)
+            }
+        };
+        Ok(message)
+    }
+}
+
 type TXIN = Hash;
 type UTXO = PaymentOutput;
 
@@ -223,6 +344,240 @@ impl ProtoConvert for PoolNotification {
     }
 }
 
+impl ProtoConvert for SignerRequest {
+    type Proto = signer::SignerRequest;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = signer::SignerRequest::new();
+        match self {
+            SignerRequest::PublicKey => proto.set_pkey(signer::GetPublicKey::new()),
+            SignerRequest::MicroBlock { header } => {
+                let mut r = signer::SignMicroBlock::new();
+                r.set_header(header.into_proto());
+                proto.set_micro_block(r);
+            }
+            SignerRequest::MacroBlock { round, header } => {
+                let mut r = signer::SignMacroBlock::new();
+                r.set_round(*round);
+                r.set_header(header.into_proto());
+                proto.set_macro_block(r);
+            }
+            SignerRequest::MicroBlockVRF {
+                epoch,
+                offset,
+                view_change,
+                seed,
+            } => {
+                let mut r = signer::MakeMicroBlockVRF::new();
+                r.set_epoch(*epoch);
+                r.set_offset(*offset);
+                r.set_view_change(*view_change);
+                r.set_seed(seed.into_proto());
+                proto.set_micro_block_vrf(r);
+            }
+            SignerRequest::MacroBlockVRF { epoch, round, seed } => {
+                let mut r = signer::MakeMacroBlockVRF::new();
+                r.set_epoch(*epoch);
+                r.set_round(*round);
+                r.set_seed(seed.into_proto());
+                proto.set_macro_block_vrf(r);
+            }
+            SignerRequest::ViewChange { chain } => {
+                let mut r = signer::SignViewChange::new();
+                r.set_chain(chain.into_proto());
+                proto.set_view_change(r);
+            }
+            SignerRequest::Proposal {
+                epoch,
+                round,
+                proposal,
+            } => {
+                let mut r = signer::SignProposal::new();
+                r.set_epoch(*epoch);
+                r.set_round(*round);
+                r.set_header(proposal.header.into_proto());
+                let transactions: Vec<_> = proposal
+                    .transactions
+                    .iter()
+                    .map(ProtoConvert::into_proto)
+                    .collect();
+                r.set_transactions(RepeatedField::from_vec(transactions));
+                proto.set_proposal(r);
+            }
+            SignerRequest::Prevote {
+                epoch,
+                round,
+                block_hash,
+            } => {
+                let mut r = signer::SignPrevote::new();
+                r.set_epoch(*epoch);
+                r.set_round(*round);
+                r.set_block_hash(block_hash.into_proto());
+                proto.set_prevote(r);
+            }
+            SignerRequest::Precommit {
+                epoch,
+                round,
+                block_hash,
+                block_hash_sig,
+            } => {
+                let mut r = signer::SignPrecommit::new();
+                r.set_epoch(*epoch);
+                r.set_round(*round);
+                r.set_block_hash(block_hash.into_proto());
+                r.set_block_hash_sig(block_hash_sig.into_proto());
+                proto.set_precommit(r);
+            }
+            SignerRequest::Restake { inputs } => {
+                let mut r = signer::Restake::new();
+                let inputs: Vec<_> = inputs.iter().map(ProtoConvert::into_proto).collect();
+                r.set_inputs(RepeatedField::from_vec(inputs));
+                proto.set_restake(r);
+            }
+            SignerRequest::Unicast {
+                to,
+                protocol_id,
+                rval,
+                data,
+            } => {
+                let mut r = signer::SignUnicast::new();
+                r.set_to(to.into_proto());
+                r.set_protocol_id(protocol_id.clone());
+                r.set_rval(rval.to_bytes().to_vec());
+                r.set_data(data.clone());
+                proto.set_unicast(r);
+            }
+            SignerRequest::Decrypt { id, rval, data } => {
+                let mut r = signer::DecryptUnicast::new();
+                r.set_id(id.clone());
+                r.set_rval(rval.to_bytes().to_vec());
+                r.set_data(data.clone());
+                proto.set_decrypt(r);
+            }
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let ref body = proto
+            .body
+            .as_ref()
+            .ok_or_else(|| format_err!("No variants in SignerRequest found"))?;
+        let request = match body {
+            signer::SignerRequest_oneof_body::pkey(_) => SignerRequest::PublicKey,
+            signer::SignerRequest_oneof_body::micro_block(ref r) => SignerRequest::MicroBlock {
+                header: MicroBlockHeader::from_proto(r.get_header())?,
+            },
+            signer::SignerRequest_oneof_body::macro_block(ref r) => SignerRequest::MacroBlock {
+                round: r.get_round(),
+                header: MacroBlockHeader::from_proto(r.get_header())?,
+            },
+            signer::SignerRequest_oneof_body::micro_block_vrf(ref r) => {
+                SignerRequest::MicroBlockVRF {
+                    epoch: r.get_epoch(),
+                    offset: r.get_offset(),
+                    view_change: r.get_view_change(),
+                    seed: Hash::from_proto(r.get_seed())?,
+                }
+            }
+            signer::SignerRequest_oneof_body::macro_block_vrf(ref r) => {
+                SignerRequest::MacroBlockVRF {
+                    epoch: r.get_epoch(),
+                    round: r.get_round(),
+                    seed: Hash::from_proto(r.get_seed())?,
+                }
+            }
+            signer::SignerRequest_oneof_body::view_change(ref r) => SignerRequest::ViewChange {
+                chain: ChainInfo::from_proto(r.get_chain())?,
+            },
+            signer::SignerRequest_oneof_body::proposal(ref r) => {
+                let header = MacroBlockHeader::from_proto(r.get_header())?;
+                let transactions = r
+                    .get_transactions()
+                    .iter()
+                    .map(Transaction::from_proto)
+                    .collect::<Result<Vec<_>, _>>()?;
+                SignerRequest::Proposal {
+                    epoch: r.get_epoch(),
+                    round: r.get_round(),
+                    proposal: MacroBlockProposal {
+                        header,
+                        transactions,
+                    },
+                }
+            }
+            signer::SignerRequest_oneof_body::prevote(ref r) => SignerRequest::Prevote {
+                epoch: r.get_epoch(),
+                round: r.get_round(),
+                block_hash: Hash::from_proto(r.get_block_hash())?,
+            },
+            signer::SignerRequest_oneof_body::precommit(ref r) => SignerRequest::Precommit {
+                epoch: r.get_epoch(),
+                round: r.get_round(),
+                block_hash: Hash::from_proto(r.get_block_hash())?,
+                block_hash_sig: pbc::Signature::from_proto(r.get_block_hash_sig())?,
+            },
+            signer::SignerRequest_oneof_body::restake(ref r) => {
+                let inputs = r
+                    .get_inputs()
+                    .iter()
+                    .map(Output::from_proto)
+                    .collect::<Result<Vec<_>, _>>()?;
+                SignerRequest::Restake { inputs }
+            }
+            signer::SignerRequest_oneof_body::unicast(ref r) => SignerRequest::Unicast {
+                to: pbc::PublicKey::from_proto(r.get_to())?,
+                protocol_id: r.get_protocol_id().to_string(),
+                rval: pbc::RVal::try_from_bytes(r.get_rval())?,
+                data: r.get_data().to_vec(),
+            },
+            signer::SignerRequest_oneof_body::decrypt(ref r) => SignerRequest::Decrypt {
+                id: r.get_id().to_vec(),
+                rval: pbc::RVal::try_from_bytes(r.get_rval())?,
+                data: r.get_data().to_vec(),
+            },
+        };
+        Ok(request)
+    }
+}
+
+impl ProtoConvert for SignerResponse {
+    type Proto = signer::SignerResponse;
+    fn into_proto(&self) -> Self::Proto {
+        let mut proto = signer::SignerResponse::new();
+        match self {
+            SignerResponse::PublicKey(pkey) => proto.set_pkey(pkey.into_proto()),
+            SignerResponse::Signature(sig) => proto.set_sig(sig.into_proto()),
+            SignerResponse::VRF(vrf) => proto.set_vrf(vrf.into_proto()),
+            SignerResponse::Error(e) => proto.set_error(e.clone()),
+            SignerResponse::RestakeTransaction(tx) => proto.set_restake_tx(tx.into_proto()),
+            SignerResponse::Data(data) => proto.set_data(data.clone()),
+        }
+        proto
+    }
+    fn from_proto(proto: &Self::Proto) -> Result<Self, Error> {
+        let ref body = proto
+            .body
+            .as_ref()
+            .ok_or_else(|| format_err!("No variants in SignerResponse found"))?;
+        let response = match body {
+            signer::SignerResponse_oneof_body::pkey(ref r) => {
+                SignerResponse::PublicKey(pbc::PublicKey::from_proto(r)?)
+            }
+            signer::SignerResponse_oneof_body::sig(ref r) => {
+                SignerResponse::Signature(pbc::Signature::from_proto(r)?)
+            }
+            signer::SignerResponse_oneof_body::vrf(ref r) => {
+                SignerResponse::VRF(pbc::VRF::from_proto(r)?)
+            }
+            signer::SignerResponse_oneof_body::error(ref e) => SignerResponse::Error(e.clone()),
+            signer::SignerResponse_oneof_body::restake_tx(ref r) => {
+                SignerResponse::RestakeTransaction(RestakeTransaction::from_proto(r)?)
+            }
+            signer::SignerResponse_oneof_body::data(ref r) => SignerResponse::Data(r.clone()),
+        };
+        Ok(response)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     /*
@@ -279,4 +634,20 @@ mod tests {
         let request = ChainLoaderMessage::Request(RequestBlocks::new(1));
         roundtrip(&request);
     }
+
+    #[test]
+    fn compact_block() {
+        let request = CompactBlockMessage::Request(RequestTransactions {
+            epoch: 1,
+            offset: 2,
+            block_hash: Hash::digest("block"),
+            indexes: vec![0, 3, 7],
+        });
+        roundtrip(&request);
+        let response = CompactBlockMessage::Response(ResponseTransactions {
+            block_hash: Hash::digest("block"),
+            transactions: Vec::new(),
+        });
+        roundtrip(&response);
+    }
 }
@@ -0,0 +1,517 @@
+//! Double-sign protection.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Keeps the last signed position for every kind of validator signature
+//! and refuses to sign anything at the same or an earlier position
+//! with different content. The record is persisted before the signature
+//! leaves the node, so a restored backup or a second instance with the same
+//! network key can't produce slashable messages.
+//!
+//! Micro blocks reverted by a view change proof are re-signed at lower offsets
+//! with a higher view_change. Such rollbacks are recorded with `rollback()`,
+//! which allows exactly one signature at or after the rollback position.
+//!
+
+use crate::error::SignGuardError;
+use log::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use stegos_crypto::hash::Hash;
+
+/// Kind of signed message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SignKind {
+    MicroBlock,
+    ViewChange,
+    Proposal,
+    Prevote,
+    Precommit,
+    MicroBlockVRF,
+    MacroBlockVRF,
+}
+
+impl SignKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SignKind::MicroBlock => "micro_block",
+            SignKind::ViewChange => "view_change",
+            SignKind::Proposal => "proposal",
+            SignKind::Prevote => "prevote",
+            SignKind::Precommit => "precommit",
+            SignKind::MicroBlockVRF => "micro_block_vrf",
+            SignKind::MacroBlockVRF => "macro_block_vrf",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<SignKind> {
+        match name {
+            "micro_block" => Some(SignKind::MicroBlock),
+            "view_change" => Some(SignKind::ViewChange),
+            "proposal" => Some(SignKind::Proposal),
+            "prevote" => Some(SignKind::Prevote),
+            "precommit" => Some(SignKind::Precommit),
+            "micro_block_vrf" => Some(SignKind::MicroBlockVRF),
+            "macro_block_vrf" => Some(SignKind::MacroBlockVRF),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SignKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+///
+/// Position of a signed message.
+/// For micro blocks and view changes it is (epoch, offset, view_change),
+/// for consensus messages it is (epoch, round, 0).
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignPosition {
+    pub epoch: u64,
+    pub offset: u32,
+    pub view_change: u32,
+}
+
+impl fmt::Display for SignPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "epoch={}, offset={}, view_change={}",
+            self.epoch, self.offset, self.view_change
+        )
+    }
+}
+
+/// The last signed messages.
+pub(crate) struct SignGuard {
+    /// Persistent storage, None for in-memory mode.
+    path: Option<PathBuf>,
+    /// The last signed message for every kind.
+    last_signed: BTreeMap<SignKind, (SignPosition, Hash)>,
+    /// Rollbacks which allow to sign at a lower offset, for every kind.
+    rollbacks: BTreeMap<SignKind, SignPosition>,
+}
+
+impl SignGuard {
+    ///
+    /// Creates a guard without persistent storage.
+    ///
+    pub fn in_memory() -> Self {
+        SignGuard {
+            path: None,
+            last_signed: BTreeMap::new(),
+            rollbacks: BTreeMap::new(),
+        }
+    }
+
+    ///
+    /// Opens a persistent guard and locks it for this process.
+    ///
+    pub fn open(path: &Path) -> Result<Self, SignGuardError> {
+        lock(&lock_path(path))?;
+        let mut last_signed = BTreeMap::new();
+        let mut rollbacks = BTreeMap::new();
+        match fs::read_to_string(path) {
+            Ok(data) => {
+                for (lineno, line) in data.lines().enumerate() {
+                    match parse_line(line) {
+                        Some(Record::Signed(kind, position, hash)) => {
+                            info!(
+                                "Loaded the last signed {}: {}, hash={}",
+                                kind, position, hash
+                            );
+                            last_signed.insert(kind, (position, hash));
+                        }
+                        Some(Record::Rollback(kind, position)) => {
+                            info!("Loaded a rollback of {}: {}", kind, position);
+                            rollbacks.insert(kind, position);
+                        }
+                        None => {
+                            return Err(SignGuardError::InvalidFormat(path.into(), lineno + 1));
+                        }
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(SignGuardError::IoError(path.into(), e)),
+        }
+        Ok(SignGuard {
+            path: Some(path.into()),
+            last_signed,
+            rollbacks,
+        })
+    }
+
+    ///
+    /// Returns the last signed position of the given kind.
+    ///
+    pub fn last_signed(&self, kind: SignKind) -> Option<SignPosition> {
+        self.last_signed
+            .get(&kind)
+            .map(|(position, _hash)| *position)
+    }
+
+    ///
+    /// Checks that a message doesn't conflict with previously signed ones
+    /// and records it. Must be called before a signature is released.
+    ///
+    pub fn sign(
+        &mut self,
+        kind: SignKind,
+        position: SignPosition,
+        hash: Hash,
+    ) -> Result<(), SignGuardError> {
+        if let Some((last_position, last_hash)) = self.last_signed.get(&kind) {
+            if position == *last_position && hash == *last_hash {
+                // Signing the same message again is safe.
+                return Ok(());
+            }
+            let rollback = self.rollbacks.get(&kind);
+            if !is_allowed(&position, last_position, rollback) {
+                return Err(SignGuardError::Conflict(
+                    kind,
+                    position,
+                    hash,
+                    *last_position,
+                    *last_hash,
+                ));
+            }
+        }
+        let prev = self.last_signed.insert(kind, (position, hash));
+        // A rollback allows only the next signature.
+        let prev_rollback = self.rollbacks.remove(&kind);
+        if let Err(e) = self.persist() {
+            // Don't release the signature if the record is not on the disk.
+            match prev {
+                Some(prev) => self.last_signed.insert(kind, prev),
+                None => self.last_signed.remove(&kind),
+            };
+            if let Some(prev_rollback) = prev_rollback {
+                self.rollbacks.insert(kind, prev_rollback);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Records that micro blocks were reverted by a view change proof,
+    /// so the next micro block and view change can be signed at `position`
+    /// or later, even if a higher offset was signed before.
+    /// `position.view_change` is the view_change of the chain after the rollback.
+    ///
+    pub fn rollback(&mut self, position: SignPosition) -> Result<(), SignGuardError> {
+        let prev = self.rollbacks.clone();
+        for kind in &[
+            SignKind::MicroBlock,
+            SignKind::MicroBlockVRF,
+            SignKind::ViewChange,
+        ] {
+            match self.last_signed.get(kind) {
+                Some((last_position, _last_hash))
+                    if (last_position.epoch, last_position.offset)
+                        > (position.epoch, position.offset) =>
+                {
+                    info!("Recorded a rollback of {}: {}", kind, position);
+                    self.rollbacks.insert(*kind, position);
+                }
+                _ => {}
+            }
+        }
+        if let Err(e) = self.persist() {
+            self.rollbacks = prev;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Writes the record to the disk.
+    fn persist(&self) -> Result<(), SignGuardError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut data = String::new();
+        for (kind, (position, hash)) in &self.last_signed {
+            data.push_str(&format!(
+                "{} {} {} {} {}\n",
+                kind,
+                position.epoch,
+                position.offset,
+                position.view_change,
+                hash.to_hex()
+            ));
+        }
+        for (kind, position) in &self.rollbacks {
+            data.push_str(&format!(
+                "{} {} {} {} {}\n",
+                ROLLBACK, kind, position.epoch, position.offset, position.view_change
+            ));
+        }
+        let tmp_path = path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut f = File::create(&tmp_path)?;
+            f.write_all(data.as_bytes())?;
+            f.sync_all()?;
+            fs::rename(&tmp_path, path)
+        };
+        write().map_err(|e| SignGuardError::IoError(path.clone(), e))
+    }
+}
+
+impl Drop for SignGuard {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(lock_path(path));
+        }
+    }
+}
+
+///
+/// Checks if a message at `position` can be signed after `last`.
+/// Positions within the same (epoch, offset) are ordered by view_change.
+/// A lower offset of the same epoch is allowed only after a rollback to it,
+/// and only with a view_change not lower than the chain had after the rollback.
+///
+fn is_allowed(
+    position: &SignPosition,
+    last: &SignPosition,
+    rollback: Option<&SignPosition>,
+) -> bool {
+    if position.epoch != last.epoch {
+        return position.epoch > last.epoch;
+    }
+    if position.offset > last.offset {
+        return true;
+    }
+    if position.offset == last.offset && position.view_change > last.view_change {
+        return true;
+    }
+    match rollback {
+        Some(rollback) if rollback.epoch == position.epoch => {
+            position.offset > rollback.offset
+                || (position.offset == rollback.offset
+                    && position.view_change >= rollback.view_change)
+        }
+        _ => false,
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    path.with_extension("lock")
+}
+
+/// Creates a lock file with the current process id.
+fn lock(path: &Path) -> Result<(), SignGuardError> {
+    let pid = std::process::id();
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut f) => {
+                return f
+                    .write_all(format!("{}\n", pid).as_bytes())
+                    .and_then(|()| f.sync_all())
+                    .map_err(|e| SignGuardError::IoError(path.into(), e));
+            }
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                let owner = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|s| s.trim().parse::<u32>().ok());
+                match owner {
+                    Some(owner) if owner != pid && is_running(owner) => {
+                        return Err(SignGuardError::Locked(path.into(), owner));
+                    }
+                    _ => {
+                        warn!("Removing a stale lock: path={:?}", path);
+                        fs::remove_file(path)
+                            .map_err(|e| SignGuardError::IoError(path.into(), e))?;
+                    }
+                }
+            }
+            Err(e) => return Err(SignGuardError::IoError(path.into(), e)),
+        }
+    }
+}
+
+/// Checks if a process is alive.
+/// Signal 0 only checks that the process exists, EPERM means it belongs to another user.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Checks if a process is alive.
+/// Without a portable way to check, assume the worst.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+/// The first field of a rollback line.
+const ROLLBACK: &'static str = "rollback";
+
+/// A line of the persistent record.
+enum Record {
+    Signed(SignKind, SignPosition, Hash),
+    Rollback(SignKind, SignPosition),
+}
+
+fn parse_line(line: &str) -> Option<Record> {
+    let mut fields = line.split_whitespace();
+    let first = fields.next()?;
+    let is_rollback = first == ROLLBACK;
+    let kind = if is_rollback {
+        SignKind::from_name(fields.next()?)?
+    } else {
+        SignKind::from_name(first)?
+    };
+    let epoch = fields.next()?.parse().ok()?;
+    let offset = fields.next()?.parse().ok()?;
+    let view_change = fields.next()?.parse().ok()?;
+    let position = SignPosition {
+        epoch,
+        offset,
+        view_change,
+    };
+    let record = if is_rollback {
+        Record::Rollback(kind, position)
+    } else {
+        let hash = Hash::try_from_hex(fields.next()?).ok()?;
+        Record::Signed(kind, position, hash)
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(record)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn pos(epoch: u64, offset: u32, view_change: u32) -> SignPosition {
+        SignPosition {
+            epoch,
+            offset,
+            view_change,
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        let dir = TempDir::new("sign_guard").unwrap();
+        let path = dir.path().join("last_signed");
+        let hash1 = Hash::digest("block1");
+        let hash2 = Hash::digest("block2");
+
+        let mut guard = SignGuard::open(&path).expect("opened");
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash1)
+            .expect("first signature");
+        // The same message again.
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash1)
+            .expect("same signature");
+        // A different block at the same position.
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash2)
+            .is_err());
+        // An earlier position.
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 1, 5), hash2)
+            .is_err());
+        // Kinds are independent.
+        guard
+            .sign(SignKind::Prevote, pos(1, 0, 0), hash2)
+            .expect("prevote");
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 1), hash2)
+            .expect("next view change");
+
+        // The record survives restart.
+        drop(guard);
+        let mut guard = SignGuard::open(&path).expect("reopened");
+        assert_eq!(guard.last_signed(SignKind::MicroBlock), Some(pos(1, 2, 1)));
+        assert_eq!(guard.last_signed(SignKind::Prevote), Some(pos(1, 0, 0)));
+        assert_eq!(guard.last_signed(SignKind::Precommit), None);
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash1)
+            .is_err());
+    }
+
+    #[test]
+    fn rollback() {
+        let dir = TempDir::new("sign_guard").unwrap();
+        let path = dir.path().join("last_signed");
+        let hash1 = Hash::digest("block1");
+        let hash2 = Hash::digest("block2");
+        let hash3 = Hash::digest("block3");
+
+        let mut guard = SignGuard::open(&path).expect("opened");
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash1)
+            .expect("first block");
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 3, 0), hash2)
+            .expect("second block");
+        // A lower offset with a higher view_change without a rollback.
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 1), hash3)
+            .is_err());
+
+        // The chain was rolled back to offset 2 with view_change 1.
+        guard.rollback(pos(1, 2, 1)).expect("rollback");
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 0), hash3)
+            .is_err());
+
+        // The rollback survives restart.
+        drop(guard);
+        let mut guard = SignGuard::open(&path).expect("reopened");
+        guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 1), hash3)
+            .expect("block after rollback");
+        // The rollback is used only once.
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 2), hash1)
+            .is_ok());
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(1, 2, 1), hash1)
+            .is_err());
+        // Other epochs are not affected.
+        guard.rollback(pos(1, 1, 1)).expect("rollback");
+        assert!(guard
+            .sign(SignKind::MicroBlock, pos(0, 5, 0), hash1)
+            .is_err());
+    }
+}
@@ -0,0 +1,708 @@
+//! Remote signer.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! The network key can be kept in a separate `stegos-signer` process.
+//! The node talks to it over a Unix socket using length-prefixed protobuf
+//! messages. Requests carry block headers and proposals, so the signer
+//! computes the signed hashes itself and checks them against its own
+//! double-sign record before releasing a signature.
+//!
+
+use crate::sign_guard::{SignGuard, SignKind, SignPosition};
+use failure::{bail, format_err, Error};
+use log::*;
+#[cfg(unix)]
+use std::fs;
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::thread;
+use std::time::Duration;
+use stegos_blockchain::{
+    ChainInfo, MacroBlockHeader, MicroBlockHeader, Output, RestakeTransaction,
+};
+use stegos_consensus::{
+    create_restake_transaction, ConsensusMessage, ConsensusMessageBody, MacroBlockProposal, Signer,
+};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_network::{unicast_signing_hash, NetworkKey};
+use stegos_serialization::traits::ProtoConvert;
+
+/// Maximal size of a message.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// How long wait for a response from the signer.
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request to the signer.
+#[derive(Debug, Clone)]
+pub enum SignerRequest {
+    PublicKey,
+    MicroBlock {
+        header: MicroBlockHeader,
+    },
+    MacroBlock {
+        round: u32,
+        header: MacroBlockHeader,
+    },
+    MicroBlockVRF {
+        epoch: u64,
+        offset: u32,
+        view_change: u32,
+        seed: Hash,
+    },
+    MacroBlockVRF {
+        epoch: u64,
+        round: u32,
+        seed: Hash,
+    },
+    ViewChange {
+        chain: ChainInfo,
+    },
+    Proposal {
+        epoch: u64,
+        round: u32,
+        proposal: MacroBlockProposal,
+    },
+    Prevote {
+        epoch: u64,
+        round: u32,
+        block_hash: Hash,
+    },
+    Precommit {
+        epoch: u64,
+        round: u32,
+        block_hash: Hash,
+        block_hash_sig: pbc::Signature,
+    },
+    Restake {
+        inputs: Vec<Output>,
+    },
+    Unicast {
+        to: pbc::PublicKey,
+        protocol_id: String,
+        rval: pbc::RVal,
+        data: Vec<u8>,
+    },
+    Decrypt {
+        id: Vec<u8>,
+        rval: pbc::RVal,
+        data: Vec<u8>,
+    },
+}
+
+/// Response from the signer.
+#[derive(Debug, Clone)]
+pub enum SignerResponse {
+    PublicKey(pbc::PublicKey),
+    Signature(pbc::Signature),
+    VRF(pbc::VRF),
+    Error(String),
+    RestakeTransaction(RestakeTransaction),
+    Data(Vec<u8>),
+}
+
+fn write_message<S: Write, T: ProtoConvert>(stream: &mut S, msg: &T) -> Result<(), Error> {
+    let data = msg.into_buffer()?;
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(&data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_message<S: Read, T: ProtoConvert>(stream: &mut S) -> Result<T, Error> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        bail!("Message is too large: size={}", len);
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data)?;
+    T::from_buffer(&data)
+}
+
+/// A request with the channel for its response.
+type PendingRequest = (SignerRequest, mpsc::Sender<Result<SignerResponse, Error>>);
+
+///
+/// Signer which forwards all requests to a stegos-signer process.
+///
+/// The socket is owned by a dedicated thread, so a stuck signer can't
+/// hold the node longer than SIGNER_TIMEOUT per request.
+///
+#[derive(Debug)]
+pub struct RemoteSigner {
+    pkey: pbc::PublicKey,
+    requests: Mutex<mpsc::Sender<PendingRequest>>,
+}
+
+impl RemoteSigner {
+    ///
+    /// Connects to the signer and fetches the network public key.
+    ///
+    #[cfg(unix)]
+    pub fn connect(path: &Path) -> Result<Self, Error> {
+        let mut stream = open(path)?;
+        write_message(&mut stream, &SignerRequest::PublicKey)?;
+        let pkey = match read_message(&mut stream)? {
+            SignerResponse::PublicKey(pkey) => pkey,
+            response => bail!("Unexpected response from the signer: {:?}", response),
+        };
+        let (tx, rx) = mpsc::channel();
+        let path: PathBuf = path.into();
+        thread::Builder::new()
+            .name("signer".to_string())
+            .spawn(move || forward_requests(&path, Some(stream), rx))?;
+        info!(
+            "Connected to the remote signer: path={:?}, pkey={}",
+            path, pkey
+        );
+        Ok(RemoteSigner {
+            pkey,
+            requests: Mutex::new(tx),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(path: &Path) -> Result<Self, Error> {
+        bail!(
+            "remote_signer requires Unix domain sockets, which are not supported on this platform: path={:?}",
+            path
+        )
+    }
+
+    fn request(&self, request: SignerRequest) -> Result<SignerResponse, Error> {
+        let (tx, rx) = mpsc::channel();
+        self.requests
+            .lock()
+            .unwrap()
+            .send((request, tx))
+            .map_err(|_| format_err!("The signer thread has terminated"))?;
+        match rx.recv_timeout(SIGNER_TIMEOUT) {
+            Ok(Ok(SignerResponse::Error(e))) => Err(format_err!("Signer refused: {}", e)),
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(format_err!("The signer timed out")),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(format_err!("The signer thread has terminated"))
+            }
+        }
+    }
+
+    fn request_signature(&self, request: SignerRequest) -> Result<pbc::Signature, Error> {
+        match self.request(request)? {
+            SignerResponse::Signature(sig) => Ok(sig),
+            response => bail!("Unexpected response from the signer: {:?}", response),
+        }
+    }
+
+    fn request_vrf(&self, request: SignerRequest) -> Result<pbc::VRF, Error> {
+        match self.request(request)? {
+            SignerResponse::VRF(vrf) => Ok(vrf),
+            response => bail!("Unexpected response from the signer: {:?}", response),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> Result<UnixStream, Error> {
+    let stream = UnixStream::connect(path)
+        .map_err(|e| format_err!("Failed to connect to the signer {:?}: {}", path, e))?;
+    stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+    stream.set_write_timeout(Some(SIGNER_TIMEOUT))?;
+    Ok(stream)
+}
+
+///
+/// Runs on the signer thread until RemoteSigner is dropped.
+///
+#[cfg(unix)]
+fn forward_requests(
+    path: &Path,
+    mut stream: Option<UnixStream>,
+    requests: mpsc::Receiver<PendingRequest>,
+) {
+    for (request, response_tx) in requests {
+        if stream.is_none() {
+            match open(path) {
+                Ok(s) => stream = Some(s),
+                Err(e) => {
+                    let _ = response_tx.send(Err(e));
+                    continue;
+                }
+            }
+        }
+        let s = stream.as_mut().unwrap();
+        let result = write_message(s, &request).and_then(|()| read_message(s));
+        if let Err(e) = &result {
+            warn!("Signer connection failed: {}", e);
+            // Reconnect on the next request.
+            stream = None;
+        }
+        // The node may have stopped waiting.
+        let _ = response_tx.send(result);
+    }
+    debug!("Signer thread terminated");
+}
+
+impl Signer for RemoteSigner {
+    fn pkey(&self) -> pbc::PublicKey {
+        self.pkey
+    }
+
+    fn sign_micro_block(&self, header: &MicroBlockHeader) -> Result<pbc::Signature, Error> {
+        self.request_signature(SignerRequest::MicroBlock {
+            header: header.clone(),
+        })
+    }
+
+    fn sign_macro_block(
+        &self,
+        round: u32,
+        header: &MacroBlockHeader,
+    ) -> Result<pbc::Signature, Error> {
+        self.request_signature(SignerRequest::MacroBlock {
+            round,
+            header: header.clone(),
+        })
+    }
+
+    fn make_micro_block_vrf(
+        &self,
+        epoch: u64,
+        offset: u32,
+        view_change: u32,
+        seed: &Hash,
+    ) -> Result<pbc::VRF, Error> {
+        self.request_vrf(SignerRequest::MicroBlockVRF {
+            epoch,
+            offset,
+            view_change,
+            seed: *seed,
+        })
+    }
+
+    fn make_macro_block_vrf(&self, epoch: u64, round: u32, seed: &Hash) -> Result<pbc::VRF, Error> {
+        self.request_vrf(SignerRequest::MacroBlockVRF {
+            epoch,
+            round,
+            seed: *seed,
+        })
+    }
+
+    fn sign_view_change(&self, chain: &ChainInfo) -> Result<pbc::Signature, Error> {
+        self.request_signature(SignerRequest::ViewChange {
+            chain: chain.clone(),
+        })
+    }
+
+    fn sign_consensus_message(
+        &self,
+        epoch: u64,
+        round: u32,
+        block_hash: &Hash,
+        body: &ConsensusMessageBody,
+    ) -> Result<pbc::Signature, Error> {
+        let block_hash = *block_hash;
+        let request = match body {
+            ConsensusMessageBody::Proposal(proposal) => {
+                // The signer derives the block hash from the header.
+                let proposal_hash = Hash::digest(&proposal.header);
+                if proposal_hash != block_hash {
+                    bail!(
+                        "Proposal doesn't match the block: block={}, proposal={}",
+                        block_hash,
+                        proposal_hash
+                    );
+                }
+                SignerRequest::Proposal {
+                    epoch,
+                    round,
+                    proposal: proposal.clone(),
+                }
+            }
+            ConsensusMessageBody::Prevote => SignerRequest::Prevote {
+                epoch,
+                round,
+                block_hash,
+            },
+            ConsensusMessageBody::Precommit(block_hash_sig) => SignerRequest::Precommit {
+                epoch,
+                round,
+                block_hash,
+                block_hash_sig: *block_hash_sig,
+            },
+        };
+        self.request_signature(request)
+    }
+
+    fn restake(&self, inputs: &[Output]) -> Result<RestakeTransaction, Error> {
+        let request = SignerRequest::Restake {
+            inputs: inputs.to_vec(),
+        };
+        match self.request(request)? {
+            SignerResponse::RestakeTransaction(tx) => Ok(tx),
+            response => bail!("Unexpected response from the signer: {:?}", response),
+        }
+    }
+
+    fn rotate_stake(
+        &self,
+        _inputs: &[Output],
+        _new_skey: &pbc::SecretKey,
+        _new_pkey: &pbc::PublicKey,
+    ) -> Result<RestakeTransaction, Error> {
+        bail!("Network keys are managed by the remote signer")
+    }
+}
+
+impl NetworkKey for RemoteSigner {
+    fn pkey(&self) -> pbc::PublicKey {
+        self.pkey
+    }
+
+    fn sign_unicast(
+        &self,
+        to: &pbc::PublicKey,
+        protocol_id: &str,
+        rval: &pbc::RVal,
+        data: &[u8],
+    ) -> Result<pbc::Signature, Error> {
+        self.request_signature(SignerRequest::Unicast {
+            to: *to,
+            protocol_id: protocol_id.to_string(),
+            rval: rval.clone(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn decrypt_unicast(&self, id: &[u8], rval: &pbc::RVal, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let request = SignerRequest::Decrypt {
+            id: id.to_vec(),
+            rval: rval.clone(),
+            data: data.to_vec(),
+        };
+        match self.request(request)? {
+            SignerResponse::Data(data) => Ok(data),
+            response => bail!("Unexpected response from the signer: {:?}", response),
+        }
+    }
+}
+
+///
+/// The signing side of the protocol.
+///
+struct SignerServer {
+    skey: pbc::SecretKey,
+    pkey: pbc::PublicKey,
+    guard: SignGuard,
+}
+
+impl SignerServer {
+    fn handle(&mut self, request: SignerRequest) -> Result<SignerResponse, Error> {
+        let (kind, position, hash, signing_hash) = match request {
+            SignerRequest::PublicKey => return Ok(SignerResponse::PublicKey(self.pkey)),
+            SignerRequest::MicroBlockVRF {
+                epoch,
+                offset,
+                view_change,
+                seed,
+            } => {
+                let position = SignPosition {
+                    epoch,
+                    offset,
+                    view_change,
+                };
+                return self.make_vrf(SignKind::MicroBlockVRF, position, seed);
+            }
+            SignerRequest::MacroBlockVRF { epoch, round, seed } => {
+                let position = consensus_position(epoch, round);
+                return self.make_vrf(SignKind::MacroBlockVRF, position, seed);
+            }
+            SignerRequest::Restake { inputs } => {
+                let tx = create_restake_transaction(
+                    &self.skey, &self.pkey, &inputs, &self.skey, &self.pkey,
+                )?;
+                debug!("Signed restake: tx={}", Hash::digest(&tx));
+                return Ok(SignerResponse::RestakeTransaction(tx));
+            }
+            SignerRequest::Unicast {
+                to,
+                protocol_id,
+                rval,
+                data,
+            } => {
+                // The hash starts with the network key, unlike other signed messages.
+                let hash = unicast_signing_hash(&self.pkey, &to, &protocol_id, &rval, &data);
+                let sig = pbc::sign_hash(&hash, &self.skey);
+                return Ok(SignerResponse::Signature(sig));
+            }
+            SignerRequest::Decrypt { id, rval, data } => {
+                let packet = pbc::EncryptedPacket::new(&self.pkey, &id, &rval, &data);
+                let data = pbc::ibe_decrypt(&packet, &self.skey)?;
+                return Ok(SignerResponse::Data(data));
+            }
+            SignerRequest::MicroBlock { header } => {
+                let position = SignPosition {
+                    epoch: header.epoch,
+                    offset: header.offset,
+                    view_change: header.view_change,
+                };
+                let block_hash = Hash::digest(&header);
+                (SignKind::MicroBlock, position, block_hash, block_hash)
+            }
+            SignerRequest::ViewChange { chain } => {
+                let position = SignPosition {
+                    epoch: chain.epoch,
+                    offset: chain.offset,
+                    view_change: chain.view_change,
+                };
+                let signing_hash = Hash::digest(&chain);
+                (
+                    SignKind::ViewChange,
+                    position,
+                    chain.last_block,
+                    signing_hash,
+                )
+            }
+            SignerRequest::MacroBlock { round, header } => {
+                let position = consensus_position(header.epoch, round);
+                let block_hash = Hash::digest(&header);
+                (SignKind::Precommit, position, block_hash, block_hash)
+            }
+            SignerRequest::Proposal {
+                epoch,
+                round,
+                proposal,
+            } => {
+                if proposal.header.epoch != epoch {
+                    bail!(
+                        "Proposal is for another epoch: epoch={}, block_epoch={}",
+                        epoch,
+                        proposal.header.epoch
+                    );
+                }
+                let block_hash = Hash::digest(&proposal.header);
+                let body = ConsensusMessageBody::Proposal(proposal);
+                let signing_hash = ConsensusMessage::signing_hash(epoch, round, &block_hash, &body);
+                let position = consensus_position(epoch, round);
+                (SignKind::Proposal, position, block_hash, signing_hash)
+            }
+            SignerRequest::Prevote {
+                epoch,
+                round,
+                block_hash,
+            } => {
+                let body = ConsensusMessageBody::Prevote;
+                let signing_hash = ConsensusMessage::signing_hash(epoch, round, &block_hash, &body);
+                let position = consensus_position(epoch, round);
+                (SignKind::Prevote, position, block_hash, signing_hash)
+            }
+            SignerRequest::Precommit {
+                epoch,
+                round,
+                block_hash,
+                block_hash_sig,
+            } => {
+                let body = ConsensusMessageBody::Precommit(block_hash_sig);
+                let signing_hash = ConsensusMessage::signing_hash(epoch, round, &block_hash, &body);
+                let position = consensus_position(epoch, round);
+                (SignKind::Precommit, position, block_hash, signing_hash)
+            }
+        };
+        self.guard.sign(kind, position, hash)?;
+        debug!("Signed {}: {}, hash={}", kind, position, hash);
+        let sig = pbc::sign_hash(&signing_hash, &self.skey);
+        Ok(SignerResponse::Signature(sig))
+    }
+
+    fn make_vrf(
+        &mut self,
+        kind: SignKind,
+        position: SignPosition,
+        seed: Hash,
+    ) -> Result<SignerResponse, Error> {
+        self.guard.sign(kind, position, seed)?;
+        debug!("Signed {}: {}, seed={}", kind, position, seed);
+        Ok(SignerResponse::VRF(pbc::make_VRF(&self.skey, &seed)))
+    }
+
+    fn handle_connection<S: Read + Write>(&mut self, mut stream: S) -> Result<(), Error> {
+        loop {
+            let request: SignerRequest = match read_message(&mut stream) {
+                Ok(request) => request,
+                Err(e) => {
+                    let is_eof = e
+                        .downcast_ref::<io::Error>()
+                        .map(|e| e.kind() == io::ErrorKind::UnexpectedEof)
+                        .unwrap_or(false);
+                    return if is_eof { Ok(()) } else { Err(e) };
+                }
+            };
+            let response = match self.handle(request) {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Refused to sign: {}", e);
+                    SignerResponse::Error(e.to_string())
+                }
+            };
+            write_message(&mut stream, &response)?;
+        }
+    }
+}
+
+fn consensus_position(epoch: u64, round: u32) -> SignPosition {
+    SignPosition {
+        epoch,
+        offset: round,
+        view_change: 0,
+    }
+}
+
+///
+/// Serves signing requests on a Unix socket. Never returns on success.
+///
+#[cfg(unix)]
+pub fn serve(
+    socket_path: &Path,
+    last_signed_file: &Path,
+    skey: pbc::SecretKey,
+    pkey: pbc::PublicKey,
+) -> Result<(), Error> {
+    let guard = SignGuard::open(last_signed_file)?;
+    let mut server = SignerServer { skey, pkey, guard };
+    if socket_path.exists() {
+        fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)
+        .map_err(|e| format_err!("Failed to bind {:?}: {}", socket_path, e))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    info!(
+        "Waiting for requests: socket={:?}, pkey={}",
+        socket_path, pkey
+    );
+    for stream in listener.incoming() {
+        let stream = stream?;
+        info!("Node connected");
+        // Requests are served one by one to keep the record consistent.
+        if let Err(e) = server.handle_connection(stream) {
+            warn!("Connection failed: {}", e);
+        }
+        info!("Node disconnected");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(
+    socket_path: &Path,
+    _last_signed_file: &Path,
+    _skey: pbc::SecretKey,
+    _pkey: pbc::PublicKey,
+) -> Result<(), Error> {
+    bail!(
+        "The signer requires Unix domain sockets, which are not supported on this platform: socket={:?}",
+        socket_path
+    )
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use stegos_blockchain::{MicroBlock, Timestamp};
+    use tempdir::TempDir;
+
+    #[test]
+    fn remote_signer() {
+        let dir = TempDir::new("signer").unwrap();
+        let socket_path = dir.path().join("signer.sock");
+        let last_signed_file = dir.path().join("last_signed");
+        let (skey, pkey) = pbc::make_random_keys();
+        {
+            let socket_path = socket_path.clone();
+            thread::spawn(move || serve(&socket_path, &last_signed_file, skey, pkey));
+        }
+        let signer = loop {
+            match RemoteSigner::connect(&socket_path) {
+                Ok(signer) => break signer,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert_eq!(Signer::pkey(&signer), pkey);
+
+        let header1 = fake_micro_block_header(1, 2, 0, "block1");
+        let header2 = fake_micro_block_header(1, 2, 0, "block2");
+        let hash1 = Hash::digest(&header1);
+        let hash2 = Hash::digest(&header2);
+        let sig = signer.sign_micro_block(&header1).unwrap();
+        pbc::check_hash(&hash1, &sig, &pkey).unwrap();
+        // Double-sign.
+        assert!(signer.sign_micro_block(&header2).is_err());
+
+        let seed1 = Hash::digest("seed1");
+        let seed2 = Hash::digest("seed2");
+        let vrf = signer.make_micro_block_vrf(1, 3, 0, &seed1).unwrap();
+        pbc::validate_VRF_source(&vrf, &pkey, &seed1).unwrap();
+        // The same seed again is fine.
+        signer.make_micro_block_vrf(1, 3, 0, &seed1).unwrap();
+        // Conflicting seed.
+        assert!(signer.make_micro_block_vrf(1, 3, 0, &seed2).is_err());
+
+        let body = ConsensusMessageBody::Prevote;
+        let msg = ConsensusMessage::new_signed(1, 0, hash1, &signer, body).unwrap();
+        msg.validate().unwrap();
+        let body = ConsensusMessageBody::Prevote;
+        assert!(ConsensusMessage::new_signed(1, 0, hash2, &signer, body).is_err());
+    }
+
+    fn fake_micro_block_header(
+        epoch: u64,
+        offset: u32,
+        view_change: u32,
+        previous: &str,
+    ) -> MicroBlockHeader {
+        let (skey, pkey) = pbc::make_random_keys();
+        let random = pbc::make_VRF(&skey, &Hash::digest("random"));
+        MicroBlock::empty(
+            Hash::digest(previous),
+            epoch,
+            offset,
+            view_change,
+            None,
+            pkey,
+            random,
+            Vec::new(),
+            Timestamp::now(),
+        )
+        .header
+    }
+}
@@ -0,0 +1,95 @@
+//! Test Hooks.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Read access to the internal state of the node for the sandbox in `stegos_testkit`.
+//! Not a part of the stable API.
+//!
+
+use crate::compact::CompactMicroBlock;
+use crate::{MicroBlockTimer, NodeService, Validation};
+use futures::sync::oneshot;
+use std::collections::HashMap;
+use stegos_blockchain::{Blockchain, MicroBlock, SlashingProof};
+use stegos_consensus::Consensus;
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_network::Network;
+use stegos_serialization::traits::ProtoConvert;
+
+impl NodeService {
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    pub fn network_pkey(&self) -> pbc::PublicKey {
+        self.network_pkey
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn mempool_contains_tx(&self, tx_hash: &Hash) -> bool {
+        self.mempool.contains_tx(tx_hash)
+    }
+
+    pub fn stempool_contains_tx(&self, tx_hash: &Hash) -> bool {
+        self.stempool.contains_tx(tx_hash)
+    }
+
+    /// The number of compact micro blocks waiting for missing transactions.
+    pub fn pending_micro_blocks_len(&self) -> usize {
+        self.pending_micro_blocks.len()
+    }
+
+    pub fn cheating_proofs(&self) -> &HashMap<pbc::PublicKey, SlashingProof> {
+        &self.cheating_proofs
+    }
+
+    /// The consensus of the current epoch, if this node validates the macro block.
+    pub fn consensus(&self) -> Option<&Consensus> {
+        match &self.validation {
+            Validation::MacroBlockValidator { consensus, .. } => Some(consensus),
+            _ => None,
+        }
+    }
+
+    /// The pending VDF of the micro block proposed by this node.
+    pub fn vdf_receiver(&mut self) -> Option<&mut oneshot::Receiver<Vec<u8>>> {
+        match &mut self.validation {
+            Validation::MicroBlockValidator {
+                block_timer: MicroBlockTimer::Propose(rx),
+                ..
+            } => Some(rx),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a micro block as it is sent with `compact_micro_blocks` enabled.
+pub fn compact_micro_block(block: &MicroBlock) -> Vec<u8> {
+    CompactMicroBlock::from_block(block)
+        .into_buffer()
+        .expect("serialization works")
+}
@@ -0,0 +1,197 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Block timeouts.
+//!
+//! The configured micro_block_timeout and macro_block_timeout are the lower
+//! bounds. Adaptive timeouts grow exponentially with consecutive view changes
+//! and with the observed block latency, but never exceed max_block_timeout.
+//! Macro block timeouts grow linearly with the consensus round on top of that.
+//! The upper bound is also clamped by the chain configuration, so that
+//! a slow validator can't stall the network for longer than other nodes
+//! tolerate the clock skew.
+//!
+
+use crate::config::NodeConfig;
+use log::*;
+use std::time::Duration;
+use stegos_blockchain::ChainConfig;
+
+/// Don't double timeouts more than 2^MAX_BACKOFF_SHIFT times.
+const MAX_BACKOFF_SHIFT: u32 = 16;
+/// Smoothing factor for the block latency.
+const LATENCY_ALPHA: f64 = 0.1;
+/// Timeout must be at least LATENCY_FACTOR times bigger than the latency.
+const LATENCY_FACTOR: f64 = 4.0;
+/// Adaptive timeouts never exceed MAX_DELTA_FACTOR * vetted_timestamp_delta.
+const MAX_DELTA_FACTOR: u32 = 2;
+
+pub(crate) struct BlockTimeouts {
+    adaptive: bool,
+    micro_block_timeout: Duration,
+    macro_block_timeout: Duration,
+    max_block_timeout: Duration,
+    /// Exponential moving average of the block latency, in seconds.
+    latency: f64,
+}
+
+impl BlockTimeouts {
+    pub fn new(cfg: &NodeConfig, chain_cfg: &ChainConfig) -> Self {
+        // Never go below the configured timeouts.
+        let safe_timeout = (MAX_DELTA_FACTOR * chain_cfg.vetted_timestamp_delta)
+            .max(cfg.micro_block_timeout)
+            .max(cfg.macro_block_timeout);
+        let max_block_timeout = if cfg.max_block_timeout > safe_timeout {
+            if cfg.adaptive_timeouts {
+                warn!(
+                    "Clamped max_block_timeout: configured={:?}, used={:?}",
+                    cfg.max_block_timeout, safe_timeout
+                );
+            }
+            safe_timeout
+        } else {
+            cfg.max_block_timeout
+        };
+        BlockTimeouts {
+            adaptive: cfg.adaptive_timeouts,
+            micro_block_timeout: cfg.micro_block_timeout,
+            macro_block_timeout: cfg.macro_block_timeout,
+            max_block_timeout,
+            latency: 0.0,
+        }
+    }
+
+    ///
+    /// Account the difference between the local time and the block timestamp.
+    ///
+    pub fn observe_latency(&mut self, lag: f64) {
+        let lag = lag.max(0.0).min(self.max_block_timeout.as_secs_f64());
+        self.latency = self.latency * (1.0 - LATENCY_ALPHA) + lag * LATENCY_ALPHA;
+    }
+
+    ///
+    /// How long wait for a micro block after `view_change` view changes.
+    ///
+    pub fn micro_block(&self, view_change: u32) -> Duration {
+        if !self.adaptive {
+            return self.micro_block_timeout;
+        }
+        self.backoff(self.micro_block_timeout, view_change)
+    }
+
+    ///
+    /// How long wait for a macro block in the consensus `round`.
+    ///
+    pub fn macro_block(&self, round: u32) -> Duration {
+        let timeout = if self.adaptive {
+            self.backoff(self.macro_block_timeout, 0)
+        } else {
+            self.macro_block_timeout
+        };
+        // Consensus rounds must keep growing, so the round is outside of the cap.
+        (1 + round) * timeout
+    }
+
+    fn backoff(&self, timeout: Duration, round: u32) -> Duration {
+        let latency = Duration::from_secs_f64(self.latency * LATENCY_FACTOR);
+        let timeout = timeout.max(latency);
+        let shift = round.min(MAX_BACKOFF_SHIFT);
+        timeout
+            .checked_mul(1 << shift)
+            .unwrap_or(self.max_block_timeout)
+            .min(self.max_block_timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds() {
+        let mut cfg = NodeConfig::default();
+        cfg.micro_block_timeout = Duration::from_secs(10);
+        cfg.macro_block_timeout = Duration::from_secs(20);
+        cfg.max_block_timeout = Duration::from_secs(100);
+        cfg.adaptive_timeouts = false;
+        let mut chain_cfg = ChainConfig::default();
+        chain_cfg.vetted_timestamp_delta = Duration::from_secs(100);
+        let timeouts = BlockTimeouts::new(&cfg, &chain_cfg);
+        assert_eq!(timeouts.micro_block(3), Duration::from_secs(10));
+        assert_eq!(timeouts.macro_block(2), Duration::from_secs(60));
+
+        cfg.adaptive_timeouts = true;
+        let mut timeouts = BlockTimeouts::new(&cfg, &chain_cfg);
+        assert_eq!(timeouts.micro_block(0), Duration::from_secs(10));
+        assert_eq!(timeouts.micro_block(1), Duration::from_secs(20));
+        assert_eq!(timeouts.micro_block(3), Duration::from_secs(80));
+        assert_eq!(timeouts.micro_block(4), Duration::from_secs(100));
+        assert_eq!(
+            timeouts.micro_block(u32::max_value()),
+            Duration::from_secs(100)
+        );
+        assert_eq!(timeouts.macro_block(0), Duration::from_secs(20));
+        assert_eq!(timeouts.macro_block(1), Duration::from_secs(40));
+        assert_eq!(timeouts.macro_block(9), Duration::from_secs(200));
+
+        // Small latency doesn't change anything.
+        timeouts.observe_latency(1.0);
+        assert_eq!(timeouts.micro_block(0), Duration::from_secs(10));
+        // Huge latency is bounded.
+        for _ in 0..1000 {
+            timeouts.observe_latency(1000.0);
+        }
+        assert_eq!(timeouts.micro_block(0), Duration::from_secs(100));
+        // Negative lag is ignored.
+        for _ in 0..1000 {
+            timeouts.observe_latency(-1000.0);
+        }
+        assert_eq!(timeouts.micro_block(0), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn clamp() {
+        let mut cfg = NodeConfig::default();
+        cfg.micro_block_timeout = Duration::from_secs(10);
+        cfg.macro_block_timeout = Duration::from_secs(20);
+        cfg.max_block_timeout = Duration::from_secs(300);
+        cfg.adaptive_timeouts = true;
+        let mut chain_cfg = ChainConfig::default();
+        chain_cfg.vetted_timestamp_delta = Duration::from_secs(30);
+        let timeouts = BlockTimeouts::new(&cfg, &chain_cfg);
+        assert_eq!(
+            timeouts.micro_block(u32::max_value()),
+            Duration::from_secs(60)
+        );
+        assert_eq!(timeouts.macro_block(0), Duration::from_secs(20));
+        assert_eq!(timeouts.macro_block(5), Duration::from_secs(120));
+
+        // Configured timeouts are never reduced.
+        chain_cfg.vetted_timestamp_delta = Duration::from_secs(5);
+        let timeouts = BlockTimeouts::new(&cfg, &chain_cfg);
+        assert_eq!(timeouts.micro_block(0), Duration::from_secs(10));
+        assert_eq!(
+            timeouts.micro_block(u32::max_value()),
+            Duration::from_secs(20)
+        );
+    }
+}
@@ -118,7 +118,10 @@ impl TransactionPoolService {
         let msg: PoolNotification = info.into();
         let msg = msg.into_buffer().unwrap();
         for dest in participants_pkeys {
-            if let Err(e) = self.network.send(dest, POOL_ANNOUNCE_TOPIC, msg.clone()) {
+            if let Err(e) = self
+                .network
+                .send_reliable(dest, POOL_ANNOUNCE_TOPIC, msg.clone())
+            {
                 error!("Failed to send PoolInfo to {}: {}", dest, e);
             }
         }
@@ -146,7 +149,7 @@ impl Drop for TransactionPoolService {
         for part in self.participants.keys() {
             if let Err(e) = self
                 .network
-                .send(part.pkey, POOL_ANNOUNCE_TOPIC, data.clone())
+                .send_reliable(part.pkey, POOL_ANNOUNCE_TOPIC, data.clone())
             {
                 error!("Failed to send PoolCanceled message {}: {}", part.pkey, e);
             }
@@ -0,0 +1,119 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use clap::{crate_version, App, Arg};
+use failure::Error;
+use log::*;
+use simple_logger;
+use std::path::PathBuf;
+use std::process;
+use stegos_blockchain::{chain_to_prefix, check_chain, initialize_chain, Timestamp};
+use tempdir::TempDir;
+
+fn run() -> Result<bool, Error> {
+    let args = App::new("Stegos Fsck")
+        .version(crate_version!())
+        .author("Stegos AG <info@stegos.com>")
+        .about("Checks integrity of a stopped node's chain database.")
+        .arg(
+            Arg::with_name("chain-dir")
+                .index(1)
+                .value_name("DIR")
+                .help("Chain directory, e.g. ~/.local/share/stegos/testnet/chain")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("chain")
+                .short("n")
+                .long("chain")
+                .value_name("NAME")
+                .help("Specify chain to use: testnet or dev")
+                .default_value("testnet")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("work-dir")
+                .short("w")
+                .long("work-dir")
+                .value_name("DIR")
+                .help("Empty directory for the replayed database (default: a temporary one)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Enable debug logging"),
+        )
+        .get_matches();
+
+    let level = if args.is_present("verbose") {
+        log::Level::Debug
+    } else {
+        log::Level::Info
+    };
+    simple_logger::init_with_level(level)?;
+
+    let chain = args.value_of("chain").unwrap();
+    stegos_crypto::set_network_prefix(chain_to_prefix(chain))
+        .expect("Network prefix not initialised.");
+    let (genesis, chain_cfg) = initialize_chain(chain)?;
+    let chain_dir = PathBuf::from(args.value_of("chain-dir").unwrap());
+    // Keep the temporary directory alive until the end of the check.
+    let (_temp_dir, work_dir) = match args.value_of("work-dir") {
+        Some(work_dir) => (None, PathBuf::from(work_dir)),
+        None => {
+            let temp_dir = TempDir::new("stegos-fsck")?;
+            let work_dir = temp_dir.path().to_path_buf();
+            (Some(temp_dir), work_dir)
+        }
+    };
+
+    let report = check_chain(chain_cfg, &chain_dir, &work_dir, genesis, Timestamp::now())?;
+    match report.inconsistency {
+        Some(inconsistency) => {
+            error!(
+                "Found inconsistency after {} valid blocks: {}",
+                report.blocks, inconsistency
+            );
+            Ok(false)
+        }
+        None => {
+            info!(
+                "Blockchain is consistent: blocks={}, snapshot_epoch={:?}",
+                report.blocks, report.snapshot_epoch
+            );
+            Ok(true)
+        }
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => process::exit(2),
+        Err(e) => {
+            eprintln!("{}", e);
+            error!("{:?}", e);
+            process::exit(1)
+        }
+    }
+}
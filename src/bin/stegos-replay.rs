@@ -0,0 +1,637 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Replays a log of consensus messages, written by a node with
+//! `consensus_log_file`, against its chain database.
+//!
+//! The chain database is opened read-only. The replay rebuilds the chain
+//! in a temporary directory, from genesis up to the state saved in the
+//! `Started` record of the log, and catches up the same way on every restart
+//! of the recording node.
+//!
+//! Messages of the recording node are taken from the log, so nothing
+//! is signed during the replay. The round is advanced when the node has
+//! sent a message for the next round, i.e. after its timeout.
+//! Macro blocks committed by the consensus are applied to the chain,
+//! so messages of the following epochs are replayed on the right state.
+//!
+
+use clap::{crate_version, App, Arg};
+use failure::{bail, format_err, Error};
+use log::*;
+use simple_logger;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use stegos_blockchain::{
+    chain_to_prefix, initialize_chain, Block, BlockReader, Blockchain, ChainInfo, ConsistencyCheck,
+    MacroBlock, MacroBlockHeader, MicroBlockHeader, Output, RestakeTransaction, Timestamp,
+};
+use stegos_consensus::optimistic::ViewChangeMessage;
+use stegos_consensus::{
+    Consensus, ConsensusMessage, ConsensusMessageBody, ConsensusState, Direction, MessageLog,
+    MessageRecord, RecordedMessage, Signer, ViewChangeCollector,
+};
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use tempdir::TempDir;
+
+/// Signer which knows the public key of the recording node only.
+#[derive(Debug)]
+struct ReplaySigner {
+    pkey: pbc::PublicKey,
+}
+
+impl ReplaySigner {
+    fn refuse<T>(&self) -> Result<T, Error> {
+        bail!("Not signed by replay, the message is taken from the log")
+    }
+}
+
+impl Signer for ReplaySigner {
+    fn pkey(&self) -> pbc::PublicKey {
+        self.pkey
+    }
+
+    fn sign_micro_block(&self, _: &MicroBlockHeader) -> Result<pbc::Signature, Error> {
+        self.refuse()
+    }
+
+    fn sign_macro_block(&self, _: u32, _: &MacroBlockHeader) -> Result<pbc::Signature, Error> {
+        self.refuse()
+    }
+
+    fn make_micro_block_vrf(&self, _: u64, _: u32, _: u32, _: &Hash) -> Result<pbc::VRF, Error> {
+        self.refuse()
+    }
+
+    fn make_macro_block_vrf(&self, _: u64, _: u32, _: &Hash) -> Result<pbc::VRF, Error> {
+        self.refuse()
+    }
+
+    fn sign_view_change(&self, _chain: &ChainInfo) -> Result<pbc::Signature, Error> {
+        self.refuse()
+    }
+
+    fn sign_consensus_message(
+        &self,
+        _: u64,
+        _: u32,
+        _: &Hash,
+        _: &ConsensusMessageBody,
+    ) -> Result<pbc::Signature, Error> {
+        self.refuse()
+    }
+
+    fn restake(&self, _inputs: &[Output]) -> Result<RestakeTransaction, Error> {
+        self.refuse()
+    }
+
+    fn rotate_stake(
+        &self,
+        _inputs: &[Output],
+        _new_skey: &pbc::SecretKey,
+        _new_pkey: &pbc::PublicKey,
+    ) -> Result<RestakeTransaction, Error> {
+        self.refuse()
+    }
+}
+
+enum Validation {
+    Auditor,
+    MicroBlock(ViewChangeCollector),
+    MacroBlock {
+        consensus: Consensus,
+        /// The last validated proposal.
+        validated: Option<(u32, Hash)>,
+    },
+}
+
+struct Replay {
+    chain: Blockchain,
+    /// The chain database of the recording node.
+    reader: Option<BlockReader>,
+    validation: Validation,
+    /// The key of the recording node.
+    pkey: Option<pbc::PublicKey>,
+}
+
+impl Replay {
+    fn new(chain: Blockchain, reader: Option<BlockReader>) -> Self {
+        Replay {
+            chain,
+            reader,
+            validation: Validation::Auditor,
+            pkey: None,
+        }
+    }
+
+    /// The node has (re)started with `pkey` on the `state` of its chain.
+    fn start(&mut self, pkey: pbc::PublicKey, state: ChainInfo) {
+        if let Err(e) = self.sync(&state) {
+            error!("  => failed to restore the chain: {}", e);
+        }
+        self.pkey = Some(pkey);
+        self.begin();
+    }
+
+    /// Apply blocks from the chain database up to `state`.
+    fn sync(&mut self, state: &ChainInfo) -> Result<(), Error> {
+        let reader = match &self.reader {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        let position = (self.chain.epoch(), self.chain.offset());
+        if position > (state.epoch, state.offset) {
+            bail!(
+                "The replay is ahead of the node: epoch={}, offset={}",
+                self.chain.epoch(),
+                self.chain.offset()
+            );
+        }
+        for block in reader.blocks_starting(self.chain.epoch(), self.chain.offset()) {
+            if (self.chain.epoch(), self.chain.offset()) == (state.epoch, state.offset) {
+                break;
+            }
+            match block {
+                Block::MacroBlock(block) => {
+                    self.chain.push_macro_block(block, Timestamp::now())?;
+                }
+                Block::MicroBlock(block) => {
+                    self.chain.push_micro_block(block, Timestamp::now())?;
+                }
+            }
+        }
+        if self.chain.last_block_hash() != state.last_block {
+            return Err(format_err!(
+                "The chain database doesn't have the state of the node: epoch={}, offset={}, last_block={}",
+                state.epoch,
+                state.offset,
+                state.last_block
+            ));
+        }
+        if self.chain.view_change() != state.view_change {
+            warn!(
+                "  => the node has started after a view change: view_change={}",
+                state.view_change
+            );
+        }
+        info!(
+            "  => restored: epoch={}, offset={}, last_block={}",
+            self.chain.epoch(),
+            self.chain.offset(),
+            self.chain.last_block_hash()
+        );
+        Ok(())
+    }
+
+    /// Start validation of the next block by the recording node.
+    fn begin(&mut self) {
+        let pkey = match self.pkey {
+            Some(pkey) => pkey,
+            None => return,
+        };
+        let signer = Arc::new(ReplaySigner { pkey });
+        self.validation = if self.chain.is_epoch_full() {
+            let validators = self.chain.validators_at_epoch_start();
+            if validators.iter().any(|(key, _)| *key == pkey) {
+                let consensus = Consensus::new(
+                    self.chain.epoch(),
+                    signer,
+                    self.chain.election_result().clone(),
+                    validators.into_iter().collect(),
+                );
+                info!("  => {:?}", consensus.to_info());
+                Validation::MacroBlock {
+                    consensus,
+                    validated: None,
+                }
+            } else {
+                Validation::Auditor
+            }
+        } else if self.chain.validators().iter().any(|(key, _)| *key == pkey) {
+            info!(
+                "  => view_change={}, leader={}",
+                self.chain.view_change(),
+                self.chain.leader()
+            );
+            Validation::MicroBlock(ViewChangeCollector::new(&self.chain, signer))
+        } else {
+            Validation::Auditor
+        };
+        if let Validation::Auditor = self.validation {
+            warn!(
+                "  => {} is not a validator of epoch {}",
+                pkey,
+                self.chain.epoch()
+            );
+        }
+    }
+
+    fn handle_consensus_message(&mut self, direction: Direction, msg: ConsensusMessage) {
+        let (consensus, validated) = match &mut self.validation {
+            Validation::MacroBlock {
+                consensus,
+                validated,
+            } => (consensus, validated),
+            _ => {
+                info!("  => ignored, the macro block is not expected");
+                return;
+            }
+        };
+        if direction == Direction::Outgoing && msg.epoch == consensus.epoch() {
+            while msg.round > consensus.round()
+                && consensus.to_info().state != ConsensusState::Commit
+            {
+                consensus.next_round();
+                info!("  => timed out: {:?}", consensus.to_info());
+            }
+        }
+        if let Err(e) = consensus.feed_message(msg) {
+            info!("  => rejected: {}", e);
+            return;
+        }
+
+        if consensus.should_prevote() {
+            let (block_hash, block_proposal, view_change) = consensus.get_proposal();
+            let proposal = (consensus.round(), *block_hash);
+            if *validated != Some(proposal) {
+                *validated = Some(proposal);
+                match self.chain.validate_proposed_macro_block(
+                    view_change,
+                    block_hash,
+                    &block_proposal.header,
+                    &block_proposal.transactions,
+                ) {
+                    Ok(macro_block) => {
+                        info!("  => the proposal is valid: block={}", proposal.1);
+                        consensus.prevote(macro_block);
+                    }
+                    Err(e) => {
+                        info!(
+                            "  => the proposal is invalid: block={}, e={}",
+                            proposal.1, e
+                        );
+                        consensus.reset();
+                    }
+                }
+            }
+        }
+        // Own messages are fed from the log.
+        consensus.outbox.clear();
+        info!("  => {:?}", consensus.to_info());
+        if consensus.should_commit() {
+            self.commit();
+        }
+    }
+
+    /// Apply the macro block negotiated by the consensus.
+    fn commit(&mut self) {
+        let consensus = match std::mem::replace(&mut self.validation, Validation::Auditor) {
+            Validation::MacroBlock { consensus, .. } => consensus,
+            _ => unreachable!("Expected MacroBlock state"),
+        };
+        let block = consensus.commit();
+        let block_hash = Hash::digest(&block);
+        if let Err(e) = self.apply_macro_block(block) {
+            error!(
+                "  => failed to apply the macro block: block={}, e={}",
+                block_hash, e
+            );
+            return;
+        }
+        info!(
+            "  => committed: epoch={}, block={}",
+            self.chain.epoch() - 1,
+            block_hash
+        );
+        self.begin();
+    }
+
+    fn apply_macro_block(&mut self, block: MacroBlock) -> Result<(), Error> {
+        // Remove all micro blocks.
+        while self.chain.offset() > 0 {
+            self.chain.pop_micro_block()?;
+        }
+        self.chain.push_macro_block(block, Timestamp::now())?;
+        Ok(())
+    }
+
+    fn handle_view_change_message(&mut self, msg: ViewChangeMessage) {
+        let collector = match &mut self.validation {
+            Validation::MicroBlock(collector) => collector,
+            _ => {
+                info!("  => ignored, a micro block is not expected");
+                return;
+            }
+        };
+        match collector.handle_message(&self.chain, msg) {
+            Ok(Some(proof)) => {
+                self.chain
+                    .set_view_change(self.chain.view_change() + 1, proof);
+                info!(
+                    "  => view change: view_change={}, leader={}",
+                    self.chain.view_change(),
+                    self.chain.leader()
+                );
+            }
+            Ok(None) => info!("  => accepted"),
+            Err(e) => info!("  => rejected: {}", e),
+        }
+    }
+
+    fn handle_record(&mut self, record: MessageRecord) {
+        match record.message {
+            RecordedMessage::Started(pkey, state) => self.start(pkey, state),
+            RecordedMessage::Consensus(msg) => self.handle_consensus_message(record.direction, msg),
+            RecordedMessage::ViewChange(msg) => self.handle_view_change_message(msg),
+            // Proofs only roll back forks, which needs other blocks.
+            RecordedMessage::ViewChangeProof(_) => {}
+        }
+    }
+}
+
+fn describe(record: &MessageRecord) -> String {
+    let details = match &record.message {
+        RecordedMessage::Started(pkey, state) => format!(
+            "pkey={}, epoch={}, offset={}, view_change={}, last_block={}",
+            pkey, state.epoch, state.offset, state.view_change, state.last_block
+        ),
+        RecordedMessage::Consensus(msg) => format!(
+            "epoch={}, round={}, block={}, pkey={}",
+            msg.epoch, msg.round, msg.block_hash, msg.pkey
+        ),
+        RecordedMessage::ViewChange(msg) => format!(
+            "epoch={}, offset={}, view_change={}, validator_id={}",
+            msg.chain.epoch, msg.chain.offset, msg.chain.view_change, msg.validator_id
+        ),
+        RecordedMessage::ViewChangeProof(proof) => format!(
+            "epoch={}, offset={}, view_change={}",
+            proof.chain.epoch, proof.chain.offset, proof.chain.view_change
+        ),
+    };
+    let direction = match record.direction {
+        Direction::Incoming => "<=",
+        Direction::Outgoing => "=>",
+    };
+    format!(
+        "{} {} {}: {}",
+        record.timestamp,
+        direction,
+        record.message.name(),
+        details
+    )
+}
+
+fn run() -> Result<(), Error> {
+    let args = App::new("Stegos Replay")
+        .version(crate_version!())
+        .author("Stegos AG <info@stegos.com>")
+        .about("Replays a log of consensus messages recorded by a node.")
+        .arg(
+            Arg::with_name("log")
+                .index(1)
+                .value_name("FILE")
+                .help("Log written by a node with consensus_log_file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("chain-dir")
+                .short("d")
+                .long("chain-dir")
+                .value_name("DIR")
+                .help("Chain directory of the recording node, opened read-only; without it, messages are only printed")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("chain")
+                .short("n")
+                .long("chain")
+                .value_name("NAME")
+                .help("Specify chain to use: testnet or dev")
+                .default_value("testnet")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Enable debug logging"),
+        )
+        .get_matches();
+
+    let level = if args.is_present("verbose") {
+        log::Level::Debug
+    } else {
+        log::Level::Info
+    };
+    simple_logger::init_with_level(level)?;
+
+    let chain = args.value_of("chain").unwrap();
+    stegos_crypto::set_network_prefix(chain_to_prefix(chain))
+        .expect("Network prefix not initialised.");
+    // The replayed chain is kept apart from the database of the node.
+    let replay_dir = TempDir::new("stegos-replay")?;
+    let mut replay = match args.value_of("chain-dir") {
+        Some(chain_dir) => {
+            let reader = BlockReader::open(&PathBuf::from(chain_dir))?;
+            let (genesis, chain_cfg) = initialize_chain(chain)?;
+            let chain = Blockchain::new(
+                chain_cfg,
+                replay_dir.path(),
+                ConsistencyCheck::None,
+                genesis,
+                Timestamp::now(),
+            )?;
+            Some(Replay::new(chain, Some(reader)))
+        }
+        None => None,
+    };
+
+    let log = PathBuf::from(args.value_of("log").unwrap());
+    for record in MessageLog::open(&log)? {
+        let record = record?;
+        info!("{}", describe(&record));
+        if let Some(replay) = &mut replay {
+            replay.handle_record(record);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        error!("{:?}", e);
+        process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::time::Duration;
+    use stegos_blockchain::test;
+    use stegos_blockchain::{mix, ChainConfig};
+    use stegos_consensus::{LocalSigner, MacroBlockProposal, MessageRecorder};
+    use tempdir::TempDir;
+
+    #[test]
+    fn replay_macro_block() {
+        stegos_crypto::init_test_network_prefix();
+        let cfg = ChainConfig {
+            micro_blocks_in_epoch: 2,
+            ..Default::default()
+        };
+        let chain_dir = TempDir::new("chain").unwrap();
+        let log_dir = TempDir::new("replay").unwrap();
+        let log = log_dir.path().join("consensus.log");
+
+        // Fill the epoch.
+        let mut timestamp = Timestamp::now();
+        let (keychains, genesis) = test::fake_genesis(
+            cfg.min_stake_amount,
+            10 * cfg.min_stake_amount,
+            cfg.max_slot_count,
+            3,
+            timestamp,
+            None,
+        );
+        let mut chain = Blockchain::new(
+            cfg.clone(),
+            chain_dir.path(),
+            ConsistencyCheck::None,
+            genesis.clone(),
+            timestamp,
+        )
+        .unwrap();
+        for _offset in 0..cfg.micro_blocks_in_epoch {
+            timestamp += Duration::from_millis(1);
+            let (block, _, _) = test::create_fake_micro_block(&chain, &keychains, timestamp);
+            chain.push_micro_block(block, timestamp).unwrap();
+        }
+        assert!(chain.is_epoch_full());
+        let epoch = chain.epoch();
+
+        // Run the consensus and record messages of a non-leader.
+        let mut validators: Vec<Consensus> = keychains
+            .iter()
+            .map(|keys| {
+                let signer = Arc::new(LocalSigner::new(
+                    keys.network_skey.clone(),
+                    keys.network_pkey,
+                ));
+                Consensus::new(
+                    epoch,
+                    signer,
+                    chain.election_result().clone(),
+                    chain.validators_at_epoch_start().into_iter().collect(),
+                )
+            })
+            .collect();
+        let leader = validators.iter().position(|c| c.should_propose()).unwrap();
+        let recorder_id = (leader + 1) % validators.len();
+        let mut recorder = MessageRecorder::open(
+            &log,
+            0,
+            keychains[recorder_id].network_pkey,
+            ChainInfo::from_blockchain(&chain),
+        )
+        .unwrap();
+
+        let leader_pkey = keychains[leader].network_pkey;
+        let signer = LocalSigner::new(keychains[leader].network_skey.clone(), leader_pkey);
+        let seed = mix(chain.last_macro_block_random(), 0);
+        let random = signer.make_macro_block_vrf(epoch, 0, &seed).unwrap();
+        let recipient_pkey = chain.account_by_network_key(&leader_pkey).unwrap();
+        timestamp += Duration::from_millis(1);
+        let (block, transactions) =
+            chain.create_macro_block(0, &recipient_pkey, random, leader_pkey, timestamp);
+        let block_hash = Hash::digest(&block);
+        let block_proposal = MacroBlockProposal {
+            header: block.header.clone(),
+            transactions,
+        };
+        validators[leader].propose(block_hash, block_proposal);
+        validators[leader].prevote(block);
+
+        let mut queue = VecDeque::new();
+        queue.extend(validators[leader].outbox.drain(..).map(|msg| (leader, msg)));
+        while let Some((from, msg)) = queue.pop_front() {
+            if from == recorder_id {
+                recorder
+                    .record(Direction::Outgoing, RecordedMessage::Consensus(msg.clone()))
+                    .unwrap();
+            }
+            for (id, consensus) in validators.iter_mut().enumerate() {
+                if id == from {
+                    continue;
+                }
+                if id == recorder_id {
+                    recorder
+                        .record(Direction::Incoming, RecordedMessage::Consensus(msg.clone()))
+                        .unwrap();
+                }
+                consensus.feed_message(msg.clone()).unwrap();
+                if consensus.should_prevote() {
+                    let (block_hash, block_proposal, view_change) = consensus.get_proposal();
+                    let block = chain
+                        .validate_proposed_macro_block(
+                            view_change,
+                            block_hash,
+                            &block_proposal.header,
+                            &block_proposal.transactions,
+                        )
+                        .unwrap();
+                    consensus.prevote(block);
+                }
+                queue.extend(consensus.outbox.drain(..).map(|msg| (id, msg)));
+            }
+        }
+        assert!(validators.iter().all(|c| c.should_commit()));
+        drop(recorder);
+
+        // Replay on a new chain, the database of the node is only read.
+        drop(chain);
+        let reader = BlockReader::open(chain_dir.path()).unwrap();
+        let replay_dir = TempDir::new("replay_chain").unwrap();
+        let replay_chain = Blockchain::new(
+            cfg.clone(),
+            replay_dir.path(),
+            ConsistencyCheck::None,
+            genesis,
+            timestamp,
+        )
+        .unwrap();
+        let mut replay = Replay::new(replay_chain, Some(reader));
+        for record in MessageLog::open(&log).unwrap() {
+            replay.handle_record(record.unwrap());
+        }
+        assert_eq!(replay.chain.epoch(), epoch + 1);
+        assert_eq!(replay.chain.last_block_hash(), block_hash);
+        match replay.validation {
+            Validation::MicroBlock(_) => {}
+            _ => panic!("Expected MicroBlock state"),
+        }
+    }
+}
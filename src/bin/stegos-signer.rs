@@ -0,0 +1,87 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use clap::{crate_version, App, Arg};
+use failure::Error;
+use log::*;
+use simple_logger;
+use std::path::PathBuf;
+use std::process;
+use stegos_keychain::keyfile::load_network_keypair;
+use stegos_node::signer;
+
+fn run() -> Result<(), Error> {
+    let args = App::new("Stegos Signer")
+        .version(crate_version!())
+        .author("Stegos AG <info@stegos.com>")
+        .about("Keeps the network key of a validator out of stegosd.")
+        .arg(
+            Arg::with_name("data-dir")
+                .short("d")
+                .long("data-dir")
+                .env("STEGOS_SIGNER_DATA_DIR")
+                .value_name("DIR")
+                .help("Directory with network.skey and network.pkey")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .value_name("FILE")
+                .help("Unix socket to accept connections from stegosd")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Enable debug logging"),
+        )
+        .get_matches();
+
+    let level = if args.is_present("verbose") {
+        log::Level::Debug
+    } else {
+        log::Level::Info
+    };
+    simple_logger::init_with_level(level)?;
+
+    let data_dir = PathBuf::from(args.value_of("data-dir").unwrap());
+    let socket_path = PathBuf::from(args.value_of("socket").unwrap());
+    let (network_skey, network_pkey) = load_network_keypair(
+        &data_dir.join("network.skey"),
+        &data_dir.join("network.pkey"),
+    )?;
+    // Keep a separate record from stegosd, which has its own one.
+    let last_signed_file = data_dir.join("signer_last_signed");
+    signer::serve(&socket_path, &last_signed_file, network_skey, network_pkey)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        error!("{:?}", e);
+        process::exit(1)
+    };
+}
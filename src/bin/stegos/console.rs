@@ -328,6 +328,7 @@ impl ConsoleService {
         eprintln!("unstake [AMOUNT] - unstake money");
         eprintln!("enable restaking - enable automatic re-staking (default)");
         eprintln!("disable restaking - disable automatic re-staking");
+        eprintln!("rotate network key - move stake to a new network key from the next epoch");
         eprintln!("cloak - exchange all available public outputs");
         eprintln!("show version - print version information");
         eprintln!("show validators - print active epoch validators list.");
@@ -348,6 +349,12 @@ impl ConsoleService {
         eprintln!("net publish TOPIC MESSAGE - publish a network message via floodsub");
         eprintln!("net send NETWORK_ADDRESS TOPIC MESSAGE - send a network message via unicast");
         eprintln!("net peers - show connected peers");
+        eprintln!("net ban PEER_ID - disconnect and ban a peer");
+        eprintln!("net unban PEER_ID - lift a ban");
+        eprintln!("net allow PEER_ID - never penalize or ban a peer");
+        eprintln!("net disallow PEER_ID - remove a peer from the allow list");
+        eprintln!("net reputation - show scored, banned and allowed peers");
+        eprintln!("net bandwidth - show traffic per protocol, topic and peer");
         eprintln!();
     }
 
@@ -576,6 +583,22 @@ impl ConsoleService {
             })?;
         } else if msg.starts_with("net peers") {
             self.send_network_request(NetworkRequest::ConnectedNodesRequest {})?
+        } else if msg.starts_with("net ban ") {
+            let peer_id = msg[8..].trim().to_string();
+            self.send_network_request(NetworkRequest::BanPeer { peer_id })?
+        } else if msg.starts_with("net unban ") {
+            let peer_id = msg[10..].trim().to_string();
+            self.send_network_request(NetworkRequest::UnbanPeer { peer_id })?
+        } else if msg.starts_with("net allow ") {
+            let peer_id = msg[10..].trim().to_string();
+            self.send_network_request(NetworkRequest::AllowPeer { peer_id })?
+        } else if msg.starts_with("net disallow ") {
+            let peer_id = msg[13..].trim().to_string();
+            self.send_network_request(NetworkRequest::DisallowPeer { peer_id })?
+        } else if msg.starts_with("net reputation") {
+            self.send_network_request(NetworkRequest::PeerReputationRequest {})?
+        } else if msg.starts_with("net bandwidth") {
+            self.send_network_request(NetworkRequest::BandwidthStats {})?
         } else if msg.starts_with("pay ") {
             let caps = match PAY_COMMAND_RE.captures(&msg[4..]) {
                 Some(c) => c,
@@ -853,6 +876,9 @@ impl ConsoleService {
                 payment_fee,
             };
             self.send_account_request(request)?
+        } else if msg == "rotate network key" {
+            let request = AccountRequest::RotateNetworkKey {};
+            self.send_account_request(request)?
         } else if msg == "cloak" {
             let payment_fee = PAYMENT_FEE;
             let request = AccountRequest::CloakAll { payment_fee };
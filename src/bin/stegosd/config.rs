@@ -29,7 +29,8 @@ use std::io::ErrorKind;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::result::Result;
-use stegos_blockchain::ConsistencyCheck;
+use stegos_api::EventSinkConfig;
+use stegos_blockchain::{Checkpoint, ConsistencyCheck};
 use stegos_network::NetworkConfig;
 use stegos_node::NodeConfig;
 use toml;
@@ -52,6 +53,8 @@ pub struct Config {
     pub node: NodeConfig,
     /// Network configuration.
     pub network: NetworkConfig,
+    /// Event sinks for notifications.
+    pub sinks: Vec<EventSinkConfig>,
 }
 
 /// Default values for global configuration.
@@ -61,6 +64,7 @@ impl Default for Config {
             general: Default::default(),
             node: Default::default(),
             network: Default::default(),
+            sinks: Vec::new(),
         }
     }
 }
@@ -81,6 +85,8 @@ pub struct GeneralConfig {
     pub prometheus_endpoint: String,
     /// WebSocket API endpoint,
     pub api_endpoint: String,
+    /// Additional trusted checkpoints, override built-in ones for the same epochs.
+    pub checkpoints: Vec<Checkpoint>,
 }
 
 impl Default for GeneralConfig {
@@ -100,6 +106,7 @@ impl Default for GeneralConfig {
             log_config: PathBuf::new(),
             prometheus_endpoint: "".to_string(),
             api_endpoint: "127.0.0.1:3145".to_string(),
+            checkpoints: Vec::new(),
         }
     }
 }
@@ -0,0 +1,353 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Export of the blockchain to NDJSON or CSV files.
+//!
+//! Only public fields are exported: hashes, fees, public amounts,
+//! stake amounts and timestamps.
+//!
+
+use failure::{format_err, Error};
+use log::*;
+use serde::Serialize;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use stegos_blockchain::{Block, BlockReader, Output, Timestamp, Transaction};
+use stegos_crypto::hash::Hash;
+
+/// Output file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Newline-delimited JSON.
+    Json,
+    /// Comma-separated values with a header.
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self, Error> {
+        match format {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format_err!("Unknown export format '{}'", format)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BlockRecord {
+    block_hash: Hash,
+    kind: &'static str,
+    epoch: u64,
+    offset: Option<u32>,
+    previous: Hash,
+    view_change: u32,
+    timestamp: Timestamp,
+    block_reward: i64,
+    transactions: usize,
+    inputs: usize,
+    outputs: usize,
+}
+
+#[derive(Serialize)]
+struct TransactionRecord {
+    tx_hash: Hash,
+    kind: &'static str,
+    block_hash: Hash,
+    epoch: u64,
+    offset: u32,
+    timestamp: Timestamp,
+    fee: i64,
+    inputs: usize,
+    outputs: usize,
+}
+
+#[derive(Serialize)]
+struct InputRecord {
+    input_hash: Hash,
+    tx_hash: Option<Hash>,
+    block_hash: Hash,
+    epoch: u64,
+    timestamp: Timestamp,
+}
+
+#[derive(Serialize)]
+struct OutputRecord {
+    output_hash: Hash,
+    kind: &'static str,
+    /// Amounts of PaymentOutput are hidden.
+    amount: Option<i64>,
+    tx_hash: Option<Hash>,
+    block_hash: Hash,
+    epoch: u64,
+    timestamp: Timestamp,
+}
+
+/// A file with records of one type.
+struct RecordWriter {
+    format: ExportFormat,
+    file: BufWriter<File>,
+    has_header: bool,
+    count: u64,
+}
+
+impl RecordWriter {
+    fn create(dir: &Path, name: &str, format: ExportFormat) -> Result<Self, Error> {
+        let ext = match format {
+            ExportFormat::Json => "ndjson",
+            ExportFormat::Csv => "csv",
+        };
+        let path = dir.join(format!("{}.{}", name, ext));
+        let file =
+            File::create(&path).map_err(|e| format_err!("Failed to create {:?}: {}", path, e))?;
+        Ok(RecordWriter {
+            format,
+            file: BufWriter::new(file),
+            has_header: false,
+            count: 0,
+        })
+    }
+
+    fn write<T: Serialize>(&mut self, record: &T) -> Result<(), Error> {
+        match self.format {
+            ExportFormat::Json => {
+                serde_json::to_writer(&mut self.file, record)?;
+                self.file.write_all(b"\n")?;
+            }
+            ExportFormat::Csv => {
+                let fields = match serde_json::to_value(record)? {
+                    Value::Object(fields) => fields,
+                    _ => unreachable!("records are structs"),
+                };
+                if !self.has_header {
+                    let header: Vec<&str> = fields.keys().map(|k| k.as_str()).collect();
+                    writeln!(self.file, "{}", header.join(","))?;
+                    self.has_header = true;
+                }
+                let values: Vec<String> = fields.values().map(csv_value).collect();
+                writeln!(self.file, "{}", values.join(","))?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<u64, Error> {
+        self.file.flush()?;
+        Ok(self.count)
+    }
+}
+
+fn csv_value(value: &Value) -> String {
+    let value = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    if value.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn tx_kind(tx: &Transaction) -> &'static str {
+    match tx {
+        Transaction::CoinbaseTransaction(_) => "coinbase",
+        Transaction::PaymentTransaction(_) => "payment",
+        Transaction::RestakeTransaction(_) => "restake",
+        Transaction::SlashingTransaction(_) => "slashing",
+        Transaction::ServiceAwardTransaction(_) => "service_award",
+    }
+}
+
+fn output_record(
+    output: &Output,
+    tx_hash: Option<Hash>,
+    block_hash: Hash,
+    epoch: u64,
+    timestamp: Timestamp,
+) -> OutputRecord {
+    let (kind, amount) = match output {
+        Output::PaymentOutput(_o) => ("payment", None),
+        Output::PublicPaymentOutput(o) => ("public_payment", Some(o.amount)),
+        Output::StakeOutput(o) => ("stake", Some(o.amount)),
+    };
+    OutputRecord {
+        output_hash: Hash::digest(output),
+        kind,
+        amount,
+        tx_hash,
+        block_hash,
+        epoch,
+        timestamp,
+    }
+}
+
+///
+/// Write blocks of epochs in [from_epoch, to_epoch] into `dir`.
+///
+pub fn export_chain(
+    chain: &BlockReader,
+    dir: &Path,
+    format: ExportFormat,
+    from_epoch: u64,
+    to_epoch: Option<u64>,
+) -> Result<(), Error> {
+    if !dir.exists() {
+        fs::create_dir_all(dir).map_err(|e| format_err!("Failed to create {:?}: {}", dir, e))?;
+    }
+    let mut blocks = RecordWriter::create(dir, "blocks", format)?;
+    let mut transactions = RecordWriter::create(dir, "transactions", format)?;
+    let mut inputs = RecordWriter::create(dir, "inputs", format)?;
+    let mut outputs = RecordWriter::create(dir, "outputs", format)?;
+
+    for block in chain.blocks_starting(from_epoch, 0) {
+        match block {
+            Block::MacroBlock(block) => {
+                let epoch = block.header.epoch;
+                if to_epoch.map_or(false, |to_epoch| epoch > to_epoch) {
+                    break;
+                }
+                let block_hash = Hash::digest(&block);
+                let timestamp = block.header.timestamp;
+                blocks.write(&BlockRecord {
+                    block_hash,
+                    kind: "macro",
+                    epoch,
+                    offset: None,
+                    previous: block.header.previous,
+                    view_change: block.header.view_change,
+                    timestamp,
+                    block_reward: block.header.block_reward,
+                    transactions: 0,
+                    inputs: block.inputs.len(),
+                    outputs: block.outputs.len(),
+                })?;
+                for input_hash in &block.inputs {
+                    inputs.write(&InputRecord {
+                        input_hash: *input_hash,
+                        tx_hash: None,
+                        block_hash,
+                        epoch,
+                        timestamp,
+                    })?;
+                }
+                for output in &block.outputs {
+                    outputs.write(&output_record(output, None, block_hash, epoch, timestamp))?;
+                }
+                debug!("Exported macro block: epoch={}", epoch);
+            }
+            Block::MicroBlock(block) => {
+                let epoch = block.header.epoch;
+                if to_epoch.map_or(false, |to_epoch| epoch > to_epoch) {
+                    break;
+                }
+                let offset = block.header.offset;
+                let block_hash = Hash::digest(&block);
+                let timestamp = block.header.timestamp;
+                let mut block_reward = 0;
+                let mut inputs_len = 0;
+                let mut outputs_len = 0;
+                for tx in &block.transactions {
+                    let tx_hash = Hash::digest(tx);
+                    if let Transaction::CoinbaseTransaction(tx) = tx {
+                        block_reward += tx.block_reward;
+                    }
+                    transactions.write(&TransactionRecord {
+                        tx_hash,
+                        kind: tx_kind(tx),
+                        block_hash,
+                        epoch,
+                        offset,
+                        timestamp,
+                        fee: tx.fee(),
+                        inputs: tx.txins().len(),
+                        outputs: tx.txouts().len(),
+                    })?;
+                    for input_hash in tx.txins() {
+                        inputs.write(&InputRecord {
+                            input_hash: *input_hash,
+                            tx_hash: Some(tx_hash),
+                            block_hash,
+                            epoch,
+                            timestamp,
+                        })?;
+                    }
+                    for output in tx.txouts() {
+                        outputs.write(&output_record(
+                            output,
+                            Some(tx_hash),
+                            block_hash,
+                            epoch,
+                            timestamp,
+                        ))?;
+                    }
+                    inputs_len += tx.txins().len();
+                    outputs_len += tx.txouts().len();
+                }
+                blocks.write(&BlockRecord {
+                    block_hash,
+                    kind: "micro",
+                    epoch,
+                    offset: Some(offset),
+                    previous: block.header.previous,
+                    view_change: block.header.view_change,
+                    timestamp,
+                    block_reward,
+                    transactions: block.transactions.len(),
+                    inputs: inputs_len,
+                    outputs: outputs_len,
+                })?;
+            }
+        }
+    }
+
+    let blocks = blocks.finish()?;
+    let transactions = transactions.finish()?;
+    let inputs = inputs.finish()?;
+    let outputs = outputs.finish()?;
+    info!(
+        "Exported blockchain: dir={:?}, blocks={}, transactions={}, inputs={}, outputs={}",
+        dir, blocks, transactions, inputs, outputs
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_value(&Value::Null), "");
+        assert_eq!(csv_value(&Value::from(10)), "10");
+        assert_eq!(csv_value(&Value::from("abc")), "abc");
+        assert_eq!(csv_value(&Value::from("a,\"b\"")), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -20,9 +20,10 @@
 // SOFTWARE.
 
 mod config;
+mod export;
 
 use crate::config::GeneralConfig;
-use clap::{self, App, Arg, ArgMatches};
+use clap::{self, App, Arg, ArgMatches, SubCommand};
 use dirs;
 use failure::{format_err, Error};
 use futures::{Future, Stream};
@@ -43,15 +44,18 @@ use prometheus::{self, Encoder};
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::{fs, process};
-use stegos_api::{load_or_create_api_token, WebSocketServer};
+use stegos_api::{load_or_create_api_token, EventSinkService, WebSocketServer};
 use stegos_blockchain::{
-    chain_to_prefix, initialize_chain, Blockchain, ConsistencyCheck, Timestamp,
+    chain_to_prefix, initialize_chain, BlockReader, Blockchain, ConsistencyCheck, Timestamp,
 };
+use stegos_consensus::{LocalSigner, Signer};
 use stegos_crypto::hash::Hash;
-use stegos_keychain::keyfile::load_network_keys;
-use stegos_network::{Libp2pNetwork, NETWORK_STATUS_TOPIC};
-use stegos_node::NodeService;
+use stegos_keychain::keyfile::{load_network_keys, load_network_pkey};
+use stegos_network::{Libp2pNetwork, LocalNetworkKey, NetworkKey, NETWORK_STATUS_TOPIC};
+use stegos_node::signer::RemoteSigner;
+use stegos_node::{network_key_files, recover_network_keys, NodeConfig, NodeService};
 use stegos_wallet::WalletService;
 use tokio::runtime::Runtime;
 use tokio_timer::clock;
@@ -336,8 +340,13 @@ fn load_configuration(args: &ArgMatches<'_>) -> Result<config::Config, Error> {
         })?;
     }
 
-    // Disable [node] sections.
-    if cfg.general.chain == "mainnet" && cfg.node != Default::default() {
+    // Disable [node] sections, except for the operation mode.
+    let node_cfg = NodeConfig {
+        mode: Default::default(),
+        remote_signer: Default::default(),
+        ..cfg.node.clone()
+    };
+    if cfg.general.chain == "mainnet" && node_cfg != Default::default() {
         return Err(format_err!(
             "Can't override [node] options for {}",
             cfg.general.chain
@@ -351,6 +360,25 @@ fn load_configuration(args: &ArgMatches<'_>) -> Result<config::Config, Error> {
     Ok(cfg)
 }
 
+fn open_blockchain(cfg: &config::Config, chain_dir: &Path) -> Result<Blockchain, Error> {
+    let (genesis, mut chain_cfg) = initialize_chain(&cfg.general.chain)?;
+    chain_cfg.add_checkpoints(&cfg.general.checkpoints);
+    info!(
+        "Using '{}' chain, genesis={}",
+        cfg.general.chain,
+        Hash::digest(&genesis)
+    );
+    let timestamp = Timestamp::now();
+    let chain = Blockchain::new(
+        chain_cfg,
+        chain_dir,
+        cfg.general.consistency_check,
+        genesis,
+        timestamp,
+    )?;
+    Ok(chain)
+}
+
 fn run() -> Result<(), Error> {
     let name = "Stegos Node";
     let version = format!(
@@ -480,10 +508,45 @@ fn run() -> Result<(), Error> {
                 .help("Force recovery using blocks saved on disk, rather than Snapshot.")
                 .long("recover"),
         )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export blocks, transactions, inputs and outputs and exit")
+                .arg(
+                    Arg::with_name("from-epoch")
+                        .help("First epoch to export")
+                        .long("from-epoch")
+                        .value_name("EPOCH")
+                        .default_value("0")
+                        .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+                )
+                .arg(
+                    Arg::with_name("to-epoch")
+                        .help("Last epoch to export, inclusive (default: the latest)")
+                        .long("to-epoch")
+                        .value_name("EPOCH")
+                        .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("Output format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["json", "csv"])
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output directory")
+                        .long("output")
+                        .short("o")
+                        .value_name("DIR")
+                        .required(true),
+                ),
+        )
         .get_matches();
 
     // Parse configuration
-    let cfg = load_configuration(&args)?;
+    let mut cfg = load_configuration(&args)?;
 
     // Initialize logger
     let _log = load_logger_configuration(&args, &cfg.general.data_dir, &cfg.general.log_config)?;
@@ -523,18 +586,78 @@ fn run() -> Result<(), Error> {
     stegos_crypto::set_network_prefix(chain_to_prefix(&cfg.general.chain))
         .expect("Network prefix not initialised.");
 
+    if let Some(export_args) = args.subcommand_matches("export") {
+        // Doesn't lock the database, so a running node isn't affected.
+        let chain = BlockReader::open(&chain_dir)?;
+        let dir = PathBuf::from(export_args.value_of("output").unwrap());
+        let format: export::ExportFormat = export_args.value_of("format").unwrap().parse()?;
+        let from_epoch: u64 = export_args.value_of("from-epoch").unwrap().parse()?;
+        let to_epoch: Option<u64> = match export_args.value_of("to-epoch") {
+            Some(to_epoch) => Some(to_epoch.parse()?),
+            None => None,
+        };
+        return export::export_chain(&chain, &dir, format, from_epoch, to_epoch);
+    }
+
     // Initialize keychain
-    let network_skey_file = data_dir.join("network.skey");
-    let network_pkey_file = data_dir.join("network.pkey");
-    let (network_skey, network_pkey) = load_network_keys(&network_skey_file, &network_pkey_file)?;
+    if cfg.node.network_keys_dir.as_os_str().is_empty() {
+        cfg.node.network_keys_dir = data_dir.clone();
+    }
+    recover_network_keys(&cfg.node.network_keys_dir)?;
+    let (network_skey_file, network_pkey_file) = network_key_files(&cfg.node.network_keys_dir);
+    let (network_skey, signer, network_key, peer_skey) =
+        if cfg.node.remote_signer.as_os_str().is_empty() {
+            let (network_skey, network_pkey) =
+                load_network_keys(&network_skey_file, &network_pkey_file)?;
+            let signer: Arc<dyn Signer> =
+                Arc::new(LocalSigner::new(network_skey.clone(), network_pkey));
+            let network_key: Arc<dyn NetworkKey> =
+                Arc::new(LocalNetworkKey::new(network_skey.clone(), network_pkey));
+            (
+                Some(network_skey.clone()),
+                signer,
+                network_key,
+                network_skey,
+            )
+        } else {
+            // The network secret key stays with the remote signer.
+            let network_pkey = load_network_pkey(&network_pkey_file)?;
+            let remote_signer = Arc::new(RemoteSigner::connect(&cfg.node.remote_signer)?);
+            if Signer::pkey(&*remote_signer) != network_pkey {
+                return Err(format_err!(
+                    "The remote signer uses a different network key: expected={}, got={}",
+                    network_pkey,
+                    Signer::pkey(&*remote_signer)
+                ));
+            }
+            // Libp2p identity is not bound to the network key.
+            let (peer_skey, _peer_pkey) =
+                load_network_keys(&data_dir.join("peer.skey"), &data_dir.join("peer.pkey"))?;
+            let signer: Arc<dyn Signer> = remote_signer.clone();
+            let network_key: Arc<dyn NetworkKey> = remote_signer;
+            (None, signer, network_key, peer_skey)
+        };
+    let network_pkey = signer.pkey();
+    if cfg.node.last_signed_file.as_os_str().is_empty() {
+        cfg.node.last_signed_file = data_dir.join("last_signed");
+    }
+    if cfg.node.consensus_state_file.as_os_str().is_empty() {
+        cfg.node.consensus_state_file = data_dir.join("consensus_state");
+    }
+    if cfg.network.peer_store_file.as_os_str().is_empty() {
+        cfg.network.peer_store_file = data_dir.join("peers.json");
+    }
+    if cfg.network.routing_table_file.as_os_str().is_empty() {
+        cfg.network.routing_table_file = data_dir.join("routing_table.json");
+    }
+    if cfg.network.reputation_file.as_os_str().is_empty() {
+        cfg.network.reputation_file = data_dir.join("reputation.json");
+    }
 
     // Initialize network
     let mut rt = Runtime::new()?;
-    let (network, network_service, peer_id, replication_rx) = Libp2pNetwork::new(
-        cfg.network.clone(),
-        network_skey.clone(),
-        network_pkey.clone(),
-    )?;
+    let (network, network_service, peer_id, replication_rx) =
+        Libp2pNetwork::new(cfg.network.clone(), network_key, &peer_skey)?;
 
     // Start metrics exporter
     if cfg.general.prometheus_endpoint != "" {
@@ -551,28 +674,15 @@ fn run() -> Result<(), Error> {
     }
 
     // Initialize blockchain
-    let (genesis, chain_cfg) = initialize_chain(&cfg.general.chain)?;
-    info!(
-        "Using '{}' chain, genesis={}",
-        cfg.general.chain,
-        Hash::digest(&genesis)
-    );
-    let timestamp = Timestamp::now();
-    let chain = Blockchain::new(
-        chain_cfg.clone(),
-        &chain_dir,
-        cfg.general.consistency_check,
-        genesis,
-        timestamp,
-    )?;
+    let chain = open_blockchain(&cfg, &chain_dir)?;
 
     let epoch = chain.epoch() - 1;
+    let stake_epochs = chain.cfg().stake_epochs;
     // Initialize node
     let (mut node_service, node) = NodeService::new(
         cfg.node.clone(),
         chain,
-        network_skey.clone(),
-        network_pkey.clone(),
+        signer,
         network.clone(),
         cfg.general.chain.clone(),
         peer_id,
@@ -582,17 +692,26 @@ fn run() -> Result<(), Error> {
     // Initialize Wallet.
     let (wallet_service, wallet) = WalletService::new(
         &accounts_dir,
+        cfg.node.network_keys_dir.clone(),
         network_skey,
         network_pkey,
         network.clone(),
         node.clone(),
         rt.executor(),
-        chain_cfg.stake_epochs,
+        stake_epochs,
         cfg.node.max_inputs_in_tx,
         epoch,
     )?;
     rt.spawn(wallet_service);
 
+    // Start event sinks.
+    EventSinkService::spawn(
+        cfg.sinks.clone(),
+        rt.executor(),
+        wallet.clone(),
+        node.clone(),
+    )?;
+
     // Start WebSocket API server.
     if cfg.general.api_endpoint != "" {
         let token_file = root_dir.join("api.token");
@@ -26,13 +26,15 @@ use jni::JNIEnv;
 use log::*;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 use stegos_api::{ApiToken, WebSocketServer};
 use stegos_blockchain::{
     chain_to_prefix, initialize_chain, Blockchain, ConsistencyCheck, Timestamp,
 };
+use stegos_consensus::LocalSigner;
 use stegos_crypto::hash::Hash;
 use stegos_keychain::keyfile::load_network_keys;
-use stegos_network::Libp2pNetwork;
+use stegos_network::{Libp2pNetwork, LocalNetworkKey};
 use stegos_node::{NodeConfig, NodeService};
 use stegos_wallet::WalletService;
 use tokio::runtime::Runtime;
@@ -79,14 +81,13 @@ fn init(
     let network_skey_file = data_dir.join("network.skey");
     let network_pkey_file = data_dir.join("network.pkey");
     let (network_skey, network_pkey) = load_network_keys(&network_skey_file, &network_pkey_file)?;
+    let network_key = Arc::new(LocalNetworkKey::new(network_skey.clone(), network_pkey));
+    let signer = Arc::new(LocalSigner::new(network_skey.clone(), network_pkey));
 
     // Initialize network
     let mut rt = Runtime::new()?;
-    let (network, network_service, peer_id, replication_rx) = Libp2pNetwork::new(
-        Default::default(),
-        network_skey.clone(),
-        network_pkey.clone(),
-    )?;
+    let (network, network_service, peer_id, replication_rx) =
+        Libp2pNetwork::new(Default::default(), network_key, &network_skey)?;
 
     // Initialize blockchain
     let (genesis, chain_cfg) = initialize_chain(&chain_name)?;
@@ -110,8 +111,7 @@ fn init(
     let (mut node_service, node) = NodeService::new(
         node_cfg.clone(),
         chain,
-        network_skey.clone(),
-        network_pkey.clone(),
+        signer,
         network.clone(),
         chain_name,
         peer_id,
@@ -121,7 +121,8 @@ fn init(
     // Initialize Wallet.
     let (wallet_service, wallet) = WalletService::new(
         &accounts_dir,
-        network_skey,
+        node_cfg.network_keys_dir.clone(),
+        Some(network_skey),
         network_pkey,
         network.clone(),
         node.clone(),
@@ -0,0 +1,148 @@
+//! Test Harness.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Test harness for downstream crates.
+//!
+//! Boots a network of validators and an auditor over the loopback network
+//! with a fake genesis, drives micro and macro blocks under the mock timer
+//! and exposes the chain state of every node:
+//!
+//! ```no_run
+//! use stegos_testkit::*;
+//!
+//! Sandbox::start(SandboxConfig::default(), |mut s| {
+//!     let wallets = s.wallets();
+//!     let recipient = TestWallet::new(KeyChain::new(&mut s.prng));
+//!     let tx = wallets[0]
+//!         .create_payment(s.chain(), recipient.account_pkey(), 100, 2_000)
+//!         .unwrap();
+//!     s.send_transaction(tx);
+//!     s.skip_epoch();
+//!     assert_eq!(recipient.balance(s.chain()), 100);
+//! });
+//! ```
+//!
+//! The internal state of nodes is read through hooks in `stegos_node::test`.
+//!
+
+pub mod futures_testing;
+mod logger;
+mod sandbox;
+#[cfg(test)]
+mod test;
+mod wallet;
+
+pub use crate::sandbox::*;
+pub use crate::wallet::TestWallet;
+
+use log::*;
+use stegos_blockchain::{Blockchain, Transaction};
+
+/// Helpers on top of the sandbox API.
+pub trait SandboxExt<'p>: Api<'p> {
+    /// The chain of the first validator.
+    fn chain(&self) -> &Blockchain;
+
+    /// Produce the remaining micro blocks of the current epoch and the macro block.
+    fn skip_epoch(&mut self);
+
+    fn skip_epochs(&mut self, epochs: u64) {
+        for _ in 0..epochs {
+            self.skip_epoch();
+        }
+    }
+
+    /// Deliver a transaction to all nodes, as if it was broadcast by a wallet.
+    /// The transaction is included by the next micro block.
+    fn send_transaction(&mut self, tx: Transaction);
+
+    /// Wallets of the genesis validators; the first one owns the genesis payment.
+    fn wallets(&self) -> Vec<TestWallet>;
+}
+
+impl<'p> SandboxExt<'p> for Sandbox<'p> {
+    fn chain(&self) -> &Blockchain {
+        self.first().chain()
+    }
+
+    fn skip_epoch(&mut self) {
+        let epoch = self.chain().epoch();
+        let offset = self.chain().offset();
+        for _offset in offset..self.config.chain.micro_blocks_in_epoch {
+            self.poll();
+            self.skip_micro_block();
+        }
+        self.skip_macro_block();
+        debug!("Skipped epoch: epoch={}", epoch);
+    }
+
+    fn send_transaction(&mut self, tx: Transaction) {
+        for node in self.iter_mut() {
+            node.network_service
+                .receive_broadcast(stegos_node::TX_TOPIC, tx.clone());
+        }
+        self.auditor
+            .network_service
+            .receive_broadcast(stegos_node::TX_TOPIC, tx);
+        self.poll();
+    }
+
+    fn wallets(&self) -> Vec<TestWallet> {
+        self.keychains
+            .iter()
+            .cloned()
+            .map(TestWallet::new)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment() {
+        let mut config = SandboxConfig::default();
+        config.chain.micro_blocks_in_epoch = 2;
+        Sandbox::start(config, |mut s| {
+            let wallets = s.wallets();
+            // Validators also receive block rewards.
+            let recipient = TestWallet::new(KeyChain::new(&mut s.prng));
+            let fee = 2 * s.config.node.min_payment_fee;
+            let balance = wallets[0].balance(s.chain());
+            assert!(balance > 0);
+            assert!(wallets[1]
+                .create_payment(s.chain(), recipient.account_pkey(), 100, fee)
+                .is_err());
+
+            let tx = wallets[0]
+                .create_payment(s.chain(), recipient.account_pkey(), 100, fee)
+                .unwrap();
+            s.send_transaction(tx);
+            s.skip_epoch();
+            assert_eq!(s.chain().epoch(), 2);
+            assert_eq!(recipient.balance(s.chain()), 100);
+        });
+    }
+}
@@ -22,32 +22,38 @@
 // most of the code is used in tests, so it can false positive detected as unused during build.
 #![allow(dead_code)]
 
-pub mod futures_testing;
-mod logger;
-
-use self::futures_testing::{start_test, wait, TestTimer};
-pub use stegos_network::loopback::Loopback;
-#[cfg(test)]
-mod consensus;
-#[cfg(test)]
-mod integration;
-#[cfg(test)]
-mod microblocks;
-
-use crate::*;
+use crate::futures_testing::{self, start_test, wait, TestTimer};
+use crate::logger;
 use assert_matches::assert_matches;
+use futures::sync::oneshot;
+use futures::Future;
 use log::*;
 use rand::{thread_rng, Rng};
 use rand_core::SeedableRng;
 use rand_isaac::IsaacRng;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 pub use stegos_blockchain::test::*;
 use stegos_blockchain::view_changes::ViewChangeProof;
-use stegos_consensus::optimistic::AddressedViewChangeProof;
-use stegos_consensus::ConsensusMessageBody;
+use stegos_blockchain::{
+    mix, Block, Blockchain, ChainConfig, ChainInfo, ConsistencyCheck, MacroBlock, Timestamp,
+    Transaction,
+};
+use stegos_consensus::optimistic::{
+    AddressedViewChangeProof, SealedViewChangeProof, ViewChangeMessage,
+};
+use stegos_consensus::{ConsensusMessage, ConsensusMessageBody, LocalSigner};
+use stegos_crypto::hash::Hash;
 use stegos_crypto::pbc;
 use stegos_crypto::pbc::{PublicKey, VRF};
+pub use stegos_network::loopback::Loopback;
 use stegos_network::Network;
+use stegos_node::{
+    Node, NodeConfig, NodeService, CHAIN_LOADER_TOPIC, CONSENSUS_TOPIC, SEALED_BLOCK_TOPIC,
+    TX_TOPIC, VIEW_CHANGE_PROOFS_TOPIC,
+};
+use stegos_serialization::traits::ProtoConvert;
 use tempdir::TempDir;
 use tokio_timer::Timer;
 
@@ -132,6 +138,13 @@ impl<'timer> Sandbox<'timer> {
                 );
                 nodes.push(node)
             }
+            // Validators are connected to each other and can relay stem transactions.
+            let pkeys: Vec<pbc::PublicKey> = keychains.iter().map(|k| k.network_pkey).collect();
+            for node in nodes.iter_mut() {
+                let pkey = node.node_service.network_pkey();
+                let peers = pkeys.iter().filter(|p| **p != pkey).cloned().collect();
+                node.network_service.set_outbound_nodes(peers);
+            }
             let auditor_keychain = KeyChain::new(&mut prng);
             let auditor = NodeSandbox::new(
                 config.node.clone(),
@@ -151,8 +164,8 @@ impl<'timer> Sandbox<'timer> {
             };
 
             for node in sandbox.nodes.iter() {
-                assert_eq!(node.node_service.chain.epoch(), 1);
-                assert_eq!(node.node_service.chain.offset(), 0);
+                assert_eq!(node.node_service.chain().epoch(), 1);
+                assert_eq!(node.node_service.chain().offset(), 0);
             }
             test_routine(sandbox)
         });
@@ -176,7 +189,7 @@ impl<'timer> Sandbox<'timer> {
         let mut part1 = Partition::default();
         let mut part2 = Partition::default();
         for node in self.nodes.iter_mut() {
-            if divider(node.node_service.network_pkey) {
+            if divider(node.node_service.network_pkey()) {
                 part1.nodes.push(node)
             } else {
                 part2.nodes.push(node)
@@ -257,6 +270,7 @@ impl<'p> PartitionGuard<'p> {
 
 pub struct NodeSandbox {
     pub network_service: Loopback,
+    pub network_skey: pbc::SecretKey,
     pub node: Node,
     pub node_service: NodeService,
     vdf_execution: VDFExecution,
@@ -287,8 +301,7 @@ impl NodeSandbox {
         let (mut node_service, node) = NodeService::new(
             node_cfg,
             chain,
-            network_skey,
-            network_pkey,
+            Arc::new(LocalSigner::new(network_skey.clone(), network_pkey)),
             network,
             "dev".to_string(),
             peer_id,
@@ -299,6 +312,7 @@ impl NodeSandbox {
         Self {
             vdf_execution: VDFExecution::Nothing,
             network_service,
+            network_skey,
             node,
             node_service,
         }
@@ -307,31 +321,28 @@ impl NodeSandbox {
     pub fn clone_network(&self) -> (Loopback, Network) {
         (
             self.network_service.clone(),
-            self.node_service.network.box_clone(),
+            self.node_service.network().box_clone(),
         )
     }
 
     pub fn chain(&self) -> &Blockchain {
-        &self.node_service.chain
+        &self.node_service.chain()
     }
 
     pub fn keys(&self) -> (pbc::PublicKey, pbc::SecretKey) {
-        (
-            self.node_service.network_pkey,
-            self.node_service.network_skey.clone(),
-        )
+        (self.node_service.network_pkey(), self.network_skey.clone())
     }
 
     #[allow(dead_code)]
     pub fn create_vrf_from_seed(&self, random: Hash, view_change: u32) -> VRF {
         let seed = mix(random, view_change);
-        pbc::make_VRF(&self.node_service.network_skey, &seed)
+        pbc::make_VRF(&self.network_skey, &seed)
     }
 
     pub fn validator_id(&self) -> Option<usize> {
-        let key = self.node_service.network_pkey;
+        let key = self.node_service.network_pkey();
         self.node_service
-            .chain
+            .chain()
             .validators()
             .iter()
             .enumerate()
@@ -346,17 +357,13 @@ impl NodeSandbox {
 
     pub fn poll(&mut self) {
         futures_testing::execute(
-            format!("node:{}", self.node_service.network_pkey),
+            format!("node:{}", self.node_service.network_pkey()),
             |notify| {
-                notify.internal_routine(|| match self.node_service.validation {
-                    Validation::MicroBlockValidator {
-                        block_timer: MicroBlockTimer::Propose(ref mut rx),
-                        ..
-                    } => {
+                notify.internal_routine(|| {
+                    if let Some(rx) = self.node_service.vdf_receiver() {
                         trace!("Poll in propose, vdf_execution={:?}", self.vdf_execution);
                         self.vdf_execution.add_vdf(rx);
                     }
-                    _ => {}
                 });
                 self.node_service.poll()
             },
@@ -457,52 +464,52 @@ pub trait Api<'p> {
         Box::new(self.iter_mut().filter(move |node| {
             validators
                 .iter()
-                .find(|key| **key == node.node_service.network_pkey)
+                .find(|key| **key == node.node_service.network_pkey())
                 .is_none()
         }))
     }
 
     /// Checks if all sandbox nodes synchronized.
     fn assert_synchronized(&self) {
-        let epoch = self.first().node_service.chain.epoch();
+        let epoch = self.first().node_service.chain().epoch();
         let awards = self
             .first()
             .node_service
-            .chain
+            .chain()
             .epoch_info(epoch - 1)
             .unwrap()
             .unwrap();
-        let offset = self.first().node_service.chain.offset();
-        let last_block = self.first().node_service.chain.last_block_hash();
+        let offset = self.first().node_service.chain().offset();
+        let last_block = self.first().node_service.chain().last_block_hash();
         for node in self.iter() {
             trace!("Checking node = {:?}", node.validator_id());
 
-            assert_eq!(node.node_service.chain.epoch(), epoch);
+            assert_eq!(node.node_service.chain().epoch(), epoch);
             assert_eq!(
                 node.node_service
-                    .chain
+                    .chain()
                     .epoch_info(epoch - 1)
                     .unwrap()
                     .unwrap(),
                 awards
             );
-            assert_eq!(node.node_service.chain.offset(), offset);
-            assert_eq!(node.node_service.chain.last_block_hash(), last_block);
+            assert_eq!(node.node_service.chain().offset(), offset);
+            assert_eq!(node.node_service.chain().last_block_hash(), last_block);
         }
 
         if let Some(auditor) = self.auditor() {
-            assert_eq!(auditor.node_service.chain.epoch(), epoch);
+            assert_eq!(auditor.node_service.chain().epoch(), epoch);
             assert_eq!(
                 auditor
                     .node_service
-                    .chain
+                    .chain()
                     .epoch_info(epoch - 1)
                     .unwrap()
                     .unwrap(),
                 awards
             );
-            assert_eq!(auditor.node_service.chain.offset(), offset);
-            assert_eq!(auditor.node_service.chain.last_block_hash(), last_block);
+            assert_eq!(auditor.node_service.chain().offset(), offset);
+            assert_eq!(auditor.node_service.chain().last_block_hash(), last_block);
         }
     }
 
@@ -575,14 +582,14 @@ pub trait Api<'p> {
                 messages
                     .entry(peer)
                     .or_insert(vec![])
-                    .push((msg, node.node_service.network_pkey));
+                    .push((msg, node.node_service.network_pkey()));
             }
         }
         debug!("found {} receivers, unicast them.", messages.len());
         for node in self.iter_mut() {
             let empty_slice: &[_] = &[];
             for (msg, peer) in messages
-                .get(&node.node_service.network_pkey)
+                .get(&node.node_service.network_pkey())
                 .map(AsRef::as_ref)
                 .unwrap_or(empty_slice)
             {
@@ -599,40 +606,43 @@ pub trait Api<'p> {
     fn skip_micro_block(&mut self) {
         self.assert_synchronized();
         assert!(
-            self.first().node_service.chain.offset()
-                <= self.first().node_service.chain.cfg().micro_blocks_in_epoch
+            self.first().node_service.chain().offset()
+                <= self
+                    .first()
+                    .node_service
+                    .chain()
+                    .cfg()
+                    .micro_blocks_in_epoch
         );
-        let leader_pk = self.first().node_service.chain.leader();
+        let leader_pk = self.first().node_service.chain().leader();
         trace!("Acording to partition info, next leader = {}", leader_pk);
         self.node(&leader_pk).unwrap().handle_vdf();
         self.poll();
-        self.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        self.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         let leader = self.node(&leader_pk).unwrap();
-        let block: Block = leader
-            .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+        let block: Block = leader.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
         for node in self.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
 
         if let Some(auditor) = self.auditor_mut() {
             auditor
                 .network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
         self.poll();
     }
     /// Emulate rollback of microblock, for wallet tests
     fn rollback_microblock(&mut self) {
         let mut view_changes = Vec::new();
-        let epoch = self.first().node_service.chain.epoch();
-        let offset = self.first().node_service.chain.offset();
+        let epoch = self.first().node_service.chain().epoch();
+        let offset = self.first().node_service.chain().offset();
         assert!(offset > 0);
         let block = self
             .first()
             .node_service
-            .chain
+            .chain()
             .micro_block(epoch, offset - 1)
             .unwrap();
         let chain_info = ChainInfo {
@@ -644,8 +654,7 @@ pub trait Api<'p> {
         for node in self.iter_mut() {
             // chain: ChainInfo, validator_id: ValidatorId, skey: &pbc::SecretKey
             let validator_id = node.validator_id().unwrap() as u32;
-            let msg =
-                ViewChangeMessage::new(chain_info, validator_id, &node.node_service.network_skey);
+            let msg = ViewChangeMessage::new(chain_info, validator_id, &node.network_skey);
             view_changes.push(msg)
         }
         let signatures = view_changes
@@ -653,7 +662,7 @@ pub trait Api<'p> {
             .map(|msg| (msg.validator_id, &msg.signature));
         let proof = ViewChangeProof::new(
             signatures,
-            self.first().node_service.chain.validators().len(),
+            self.first().node_service.chain().validators().len(),
         );
         let view_change_proof = SealedViewChangeProof {
             chain: chain_info,
@@ -661,7 +670,7 @@ pub trait Api<'p> {
         };
         let proof = AddressedViewChangeProof {
             view_change_proof,
-            pkey: self.first().node_service.network_pkey,
+            pkey: self.first().node_service.network_pkey(),
         };
         let msg = proof.into_buffer().unwrap();
         for node in self.iter_mut() {
@@ -678,15 +687,13 @@ pub trait Api<'p> {
 
     fn skip_macro_block(&mut self) {
         let stake_epochs = self.first().chain().cfg().stake_epochs;
-        let epoch = self.first().node_service.chain.epoch();
-        let round = self.first().node_service.chain.view_change();
-        let last_macro_block_hash = self.first().node_service.chain.last_macro_block_hash();
-        let leader_pk = self.first().node_service.chain.leader();
+        let epoch = self.first().node_service.chain().epoch();
+        let round = self.first().node_service.chain().view_change();
+        let last_macro_block_hash = self.first().node_service.chain().last_macro_block_hash();
+        let leader_pk = self.first().node_service.chain().leader();
         let leader_node = self.node(&leader_pk).unwrap();
         // Check for a proposal from the leader.
-        let proposal: ConsensusMessage = leader_node
-            .network_service
-            .get_broadcast(crate::CONSENSUS_TOPIC);
+        let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(CONSENSUS_TOPIC);
         debug!("Proposal: {:?}", proposal);
         assert_eq!(proposal.epoch, epoch);
         assert_eq!(proposal.round, round);
@@ -695,15 +702,14 @@ pub trait Api<'p> {
         // Send this proposal to other nodes.
         for node in self.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::CONSENSUS_TOPIC, proposal.clone());
+                .receive_broadcast(CONSENSUS_TOPIC, proposal.clone());
         }
         self.poll();
 
         // Check for pre-votes.
         let mut prevotes: Vec<ConsensusMessage> = Vec::with_capacity(self.num_nodes());
         for node in self.iter_mut() {
-            let prevote: ConsensusMessage =
-                node.network_service.get_broadcast(crate::CONSENSUS_TOPIC);
+            let prevote: ConsensusMessage = node.network_service.get_broadcast(CONSENSUS_TOPIC);
             assert_eq!(prevote.epoch, epoch);
             assert_eq!(prevote.round, round);
             assert_eq!(prevote.block_hash, proposal.block_hash);
@@ -716,7 +722,7 @@ pub trait Api<'p> {
             for (j, node) in self.iter_mut().enumerate() {
                 if i != j {
                     node.network_service
-                        .receive_broadcast(crate::CONSENSUS_TOPIC, prevotes[i].clone());
+                        .receive_broadcast(CONSENSUS_TOPIC, prevotes[i].clone());
                 }
             }
         }
@@ -725,8 +731,7 @@ pub trait Api<'p> {
         // Check for pre-commits.
         let mut precommits: Vec<ConsensusMessage> = Vec::with_capacity(self.num_nodes());
         for node in self.iter_mut() {
-            let precommit: ConsensusMessage =
-                node.network_service.get_broadcast(crate::CONSENSUS_TOPIC);
+            let precommit: ConsensusMessage = node.network_service.get_broadcast(CONSENSUS_TOPIC);
             assert_eq!(precommit.epoch, epoch);
             assert_eq!(precommit.round, round);
             assert_eq!(precommit.block_hash, proposal.block_hash);
@@ -734,7 +739,7 @@ pub trait Api<'p> {
                 pbc::check_hash(
                     &proposal.block_hash,
                     &block_hash_sig,
-                    &node.node_service.network_pkey,
+                    &node.node_service.network_pkey(),
                 )
                 .unwrap();
             } else {
@@ -748,7 +753,7 @@ pub trait Api<'p> {
             for (j, node) in self.iter_mut().enumerate() {
                 if i != j {
                     node.network_service
-                        .receive_broadcast(crate::CONSENSUS_TOPIC, precommits[i].clone());
+                        .receive_broadcast(CONSENSUS_TOPIC, precommits[i].clone());
                 }
             }
         }
@@ -763,7 +768,7 @@ pub trait Api<'p> {
                 .node(&leader_pk)
                 .unwrap()
                 .network_service
-                .get_broadcast(crate::TX_TOPIC);
+                .get_broadcast(TX_TOPIC);
             debug!("Got restake: {:?}", restake);
             restakes.push(restake);
         }
@@ -773,7 +778,7 @@ pub trait Api<'p> {
             .node(&leader_pk)
             .unwrap()
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
         let macro_block = block.clone().unwrap_macro();
         let block_hash = Hash::digest(&macro_block);
         assert_eq!(block_hash, proposal.block_hash);
@@ -783,36 +788,39 @@ pub trait Api<'p> {
         // Send this sealed block to all other nodes expect the leader.
         for node in self.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
 
         if let Some(auditor) = self.auditor_mut() {
             auditor
                 .network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
 
         self.poll();
 
         // Check state of all nodes.
         for node in self.iter() {
-            assert_eq!(node.node_service.chain.epoch(), epoch + 1);
-            assert_eq!(node.node_service.chain.offset(), 0);
-            assert_eq!(node.node_service.chain.last_macro_block_hash(), block_hash);
-            assert_eq!(node.node_service.chain.last_block_hash(), block_hash);
+            assert_eq!(node.node_service.chain().epoch(), epoch + 1);
+            assert_eq!(node.node_service.chain().offset(), 0);
+            assert_eq!(
+                node.node_service.chain().last_macro_block_hash(),
+                block_hash
+            );
+            assert_eq!(node.node_service.chain().last_block_hash(), block_hash);
         }
 
         // Process re-stakes.
         if restake_epoch {
             for node in self.iter_except(&[leader_pk]) {
-                let restake: Transaction = node.network_service.get_broadcast(crate::TX_TOPIC);
+                let restake: Transaction = node.network_service.get_broadcast(TX_TOPIC);
                 debug!("Got restake: {:?}", restake);
                 restakes.push(restake);
             }
             for node in self.iter_mut() {
                 for restake in restakes.iter() {
                     node.network_service
-                        .receive_broadcast(crate::TX_TOPIC, restake.clone());
+                        .receive_broadcast(TX_TOPIC, restake.clone());
                 }
             }
             self.poll();
@@ -820,15 +828,15 @@ pub trait Api<'p> {
     }
 
     fn leader(&mut self) -> pbc::PublicKey {
-        self.first_mut().node_service.chain.leader()
+        self.first_mut().node_service.chain().leader()
     }
 
     /// Returns next leader publicKey.
     /// Returns None if some of leader in chain of election was not found in current partition.
     fn future_block_leader(&mut self, idx: u32) -> Option<pbc::PublicKey> {
-        let mut leader_pk = self.first_mut().node_service.chain.leader();
-        let mut view_change = self.first_mut().node_service.chain.view_change();
-        let mut random = self.first_mut().node_service.chain.last_random();
+        let mut leader_pk = self.first_mut().node_service.chain().leader();
+        let mut view_change = self.first_mut().node_service.chain().view_change();
+        let mut random = self.first_mut().node_service.chain().last_random();
 
         trace!("First leader pk = {}", leader_pk);
 
@@ -841,7 +849,7 @@ pub trait Api<'p> {
             let mut election = self
                 .first_mut()
                 .node_service
-                .chain
+                .chain()
                 .election_result()
                 .clone();
             election.random = vrf;
@@ -853,10 +861,10 @@ pub trait Api<'p> {
 
     /// Same as next_leader, but for view_changes.
     fn future_view_change_leader(&mut self, idx: u32) -> pbc::PublicKey {
-        let view_change = self.first_mut().node_service.chain.view_change();
+        let view_change = self.first_mut().node_service.chain().view_change();
         self.first_mut()
             .node_service
-            .chain
+            .chain()
             .select_leader(view_change + idx)
     }
 
@@ -876,7 +884,7 @@ pub trait Api<'p> {
         'p: 'a,
     {
         self.iter_mut()
-            .find(|node| node.node_service.network_pkey == *pk)
+            .find(|node| node.node_service.network_pkey() == *pk)
     }
 
     /// Return node for publickey.
@@ -885,7 +893,7 @@ pub trait Api<'p> {
         'p: 'a,
     {
         self.iter()
-            .find(|node| node.node_service.network_pkey == *pk)
+            .find(|node| node.node_service.network_pkey() == *pk)
     }
 }
 
@@ -961,23 +969,21 @@ pub fn slash_cheater_inner<'a>(
     leader_pk: PublicKey,
     mut filter_nodes: Vec<PublicKey>,
 ) -> PartitionGuard<'a> {
-    s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+    s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
     filter_nodes.push(leader_pk);
     let mut r = s.split(&filter_nodes);
     let leader = &mut r.parts.0.node(&leader_pk).unwrap();
     leader.handle_vdf();
     leader.poll();
-    let b1: Block = leader
-        .network_service
-        .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+    let b1: Block = leader.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
     let mut b2 = b1.clone();
     // modify timestamp for block
     match &mut b2 {
         Block::MicroBlock(ref mut b) => {
             b.header.timestamp += Duration::from_millis(1);
             let block_hash = Hash::digest(&*b);
-            b.sig = pbc::sign_hash(&block_hash, &leader.node_service.network_skey);
+            b.sig = pbc::sign_hash(&block_hash, &leader.network_skey);
         }
         Block::MacroBlock(_) => unreachable!("Expected a MacroBlock"),
     }
@@ -985,28 +991,28 @@ pub fn slash_cheater_inner<'a>(
     info!("BROADCAST BLOCK, WITH COPY.");
     for node in r.parts.1.iter_mut() {
         node.network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, b1.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, b1.clone());
     }
 
     if let Some(auditor) = r.parts.1.auditor_mut() {
         auditor
             .network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, b1.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, b1.clone());
     }
 
     r.parts
         .1
-        .for_each(|node| assert_eq!(node.cheating_proofs.len(), 0));
+        .for_each(|node| assert_eq!(node.cheating_proofs().len(), 0));
 
     for node in r.parts.1.iter_mut() {
         node.network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, b2.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, b2.clone());
     }
 
     if let Some(auditor) = r.parts.1.auditor_mut() {
         auditor
             .network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, b2.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, b2.clone());
     }
 
     r.parts.1.poll();
@@ -1065,22 +1071,3 @@ pub fn check_unique<T: Ord + Clone + PartialEq>(original: Vec<T>) -> bool {
     array.dedup();
     original_len == array.len()
 }
-
-// tests
-#[cfg(test)]
-mod test_framework {
-    use super::*;
-    #[test]
-    fn test_partition() {
-        let config: SandboxConfig = Default::default();
-
-        Sandbox::start(config, |mut s| {
-            s.poll();
-            let leader_pk = s.nodes[0].node_service.chain.leader();
-            let r = s.split(&[leader_pk]);
-            assert_eq!(r.parts.0.nodes.len(), 1);
-            assert_eq!(r.parts.1.nodes.len(), 3);
-            s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        });
-    }
-}
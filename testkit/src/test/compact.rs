@@ -0,0 +1,219 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::*;
+use stegos_node::test::compact_micro_block;
+
+fn compact_config() -> SandboxConfig {
+    let mut config = SandboxConfig::default();
+    config.node.compact_micro_blocks = true;
+    config
+}
+
+fn payment(s: &Sandbox) -> Transaction {
+    let fee = s.config.node.min_payment_fee;
+    create_payment_transaction(s.nodes[0].chain(), &s.keychains[0], fee).into()
+}
+
+/// Let the leader propose a micro block, returns the compact block.
+fn propose_compact_block(s: &mut Sandbox) -> (pbc::PublicKey, Vec<u8>) {
+    let leader_pk = s.leader();
+    s.node(&leader_pk).unwrap().handle_vdf();
+    s.poll();
+    s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+    let compact = s
+        .node(&leader_pk)
+        .unwrap()
+        .network_service
+        .try_get_broadcast_raw(COMPACT_BLOCK_TOPIC)
+        .expect("compact block");
+    (leader_pk, compact)
+}
+
+/// A validator which is not the leader of the next micro block.
+fn follower(s: &Sandbox, leader_pk: &pbc::PublicKey) -> pbc::PublicKey {
+    s.iter()
+        .map(|node| node.node_service.network_pkey())
+        .find(|pkey| pkey != leader_pk)
+        .unwrap()
+}
+
+#[test]
+fn compact_reconstruction() {
+    Sandbox::start(compact_config(), |mut s| {
+        s.poll();
+        let tx = payment(&s);
+        let tx_hash = Hash::digest(&tx);
+        s.send_transaction(tx);
+        for node in s.iter_mut() {
+            node.network_service.take_validation_results();
+        }
+        s.auditor.network_service.take_validation_results();
+        let offset = s.first().chain().offset();
+
+        let (leader_pk, compact) = propose_compact_block(&mut s);
+        for node in s.iter_except(&[leader_pk]) {
+            node.network_service
+                .receive_broadcast_raw(COMPACT_BLOCK_TOPIC, compact.clone());
+            node.poll();
+            // All transactions are in the mempool, nothing to fetch.
+            node.network_service.assert_empty_queue();
+            let results = node.network_service.take_validation_results();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].1, stegos_network::ValidationResult::Accept);
+            assert_eq!(node.chain().offset(), offset + 1);
+            assert!(!node.node_service.mempool_contains_tx(&tx_hash));
+        }
+    });
+}
+
+#[test]
+fn compact_missing_transactions() {
+    Sandbox::start(compact_config(), |mut s| {
+        s.poll();
+        let tx = payment(&s);
+        let offset = s.first().chain().offset();
+        let leader_pk = s.leader();
+        let node_pk = follower(&s, &leader_pk);
+
+        // Only the leader knows the transaction.
+        let leader = s.node(&leader_pk).unwrap();
+        leader.network_service.receive_broadcast(TX_TOPIC, tx);
+        leader.poll();
+        leader.network_service.take_validation_results();
+        let (leader_pk, compact) = propose_compact_block(&mut s);
+
+        let node = s.node(&node_pk).unwrap();
+        node.network_service
+            .receive_broadcast_raw(COMPACT_BLOCK_TOPIC, compact);
+        node.poll();
+        let (request, to) = node
+            .network_service
+            .try_get_unicast_raw(COMPACT_BLOCK_TXS_TOPIC)
+            .expect("request");
+        assert_eq!(to, leader_pk);
+        assert_eq!(node.chain().offset(), offset);
+        assert_eq!(node.node_service.pending_micro_blocks_len(), 1);
+
+        let leader = s.node(&leader_pk).unwrap();
+        leader
+            .network_service
+            .receive_unicast_raw(node_pk, COMPACT_BLOCK_TXS_TOPIC, request);
+        leader.poll();
+        let (response, to) = leader
+            .network_service
+            .try_get_unicast_raw(COMPACT_BLOCK_TXS_TOPIC)
+            .expect("response");
+        assert_eq!(to, node_pk);
+
+        let node = s.node(&node_pk).unwrap();
+        node.network_service
+            .receive_unicast_raw(leader_pk, COMPACT_BLOCK_TXS_TOPIC, response);
+        node.poll();
+        assert_eq!(node.chain().offset(), offset + 1);
+        assert_eq!(node.node_service.pending_micro_blocks_len(), 0);
+    });
+}
+
+#[test]
+fn compact_missing_transactions_timeout() {
+    Sandbox::start(compact_config(), |mut s| {
+        s.poll();
+        let tx = payment(&s);
+        let leader_pk = s.leader();
+        let node_pk = follower(&s, &leader_pk);
+
+        let leader = s.node(&leader_pk).unwrap();
+        leader.network_service.receive_broadcast(TX_TOPIC, tx);
+        leader.poll();
+        leader.network_service.take_validation_results();
+        let (leader_pk, compact) = propose_compact_block(&mut s);
+
+        let node = s.node(&node_pk).unwrap();
+        node.network_service
+            .receive_broadcast_raw(COMPACT_BLOCK_TOPIC, compact);
+        node.poll();
+        let (_request, to) = node
+            .network_service
+            .try_get_unicast_raw(COMPACT_BLOCK_TXS_TOPIC)
+            .expect("request");
+        assert_eq!(to, leader_pk);
+
+        // The leader doesn't answer - download the full block.
+        s.wait(Duration::from_secs(6));
+        let node = s.node(&node_pk).unwrap();
+        node.poll();
+        let (_request, to) = node
+            .network_service
+            .try_get_unicast_raw(CHAIN_LOADER_TOPIC)
+            .expect("full block request");
+        assert_eq!(to, leader_pk);
+        assert_eq!(node.node_service.pending_micro_blocks_len(), 0);
+    });
+}
+
+#[test]
+fn compact_bogus_leader() {
+    Sandbox::start(compact_config(), |mut s| {
+        s.poll();
+        let tx = payment(&s);
+        let leader_pk = s.leader();
+        let bogus_pk = follower(&s, &leader_pk);
+        let node_pk = s
+            .iter()
+            .map(|node| node.node_service.network_pkey())
+            .find(|pkey| pkey != &leader_pk && pkey != &bogus_pk)
+            .unwrap();
+
+        // A validator signs a block instead of the leader.
+        let bogus = s.node(&bogus_pk).unwrap();
+        let chain = bogus.chain();
+        let seed = mix(chain.last_random(), chain.view_change());
+        let random = pbc::make_VRF(&bogus.network_skey, &seed);
+        let mut block = MicroBlock::new(
+            chain.last_block_hash(),
+            chain.epoch(),
+            chain.offset(),
+            chain.view_change(),
+            None,
+            bogus_pk,
+            random,
+            Vec::new(),
+            Timestamp::now(),
+            vec![tx],
+        );
+        block.sign(&bogus.network_skey, &bogus_pk);
+        let compact = compact_micro_block(&block);
+
+        let node = s.node(&node_pk).unwrap();
+        let offset = node.chain().offset();
+        node.network_service
+            .receive_broadcast_raw(COMPACT_BLOCK_TOPIC, compact);
+        node.poll();
+        // Neither forwarded, nor waiting for transactions from the sender.
+        let results = node.network_service.take_validation_results();
+        assert_eq!(results.len(), 1);
+        assert_ne!(results[0].1, stegos_network::ValidationResult::Accept);
+        node.network_service.assert_empty_queue();
+        assert_eq!(node.node_service.pending_micro_blocks_len(), 0);
+        assert_eq!(node.chain().offset(), offset);
+    });
+}
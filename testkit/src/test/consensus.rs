@@ -22,7 +22,6 @@
 // SOFTWARE.
 
 use super::*;
-use crate::*;
 use assert_matches::assert_matches;
 use std::time::Duration;
 use stegos_blockchain::Block;
@@ -69,12 +68,12 @@ fn autocommit() {
         // Create one micro block.
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let last_block_hash = s.nodes[0].node_service.chain.last_block_hash();
+        let last_block_hash = s.nodes[0].node_service.chain().last_block_hash();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // Check for a proposal from the leader.
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -115,12 +114,12 @@ fn autocommit() {
             .node(&leader_pk)
             .unwrap()
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
         let block_hash = Hash::digest(&block);
 
         info!("Starting checking of autocommit.");
         // dont send this block to any node, wait for autocommits.
-        let mut nodes_pk: Vec<_> = s.iter().map(|n| n.node_service.network_pkey).collect();
+        let mut nodes_pk: Vec<_> = s.iter().map(|n| n.node_service.network_pkey()).collect();
         nodes_pk.sort();
 
         for pk in nodes_pk {
@@ -136,19 +135,17 @@ fn autocommit() {
             let node = s.node(&pk).unwrap();
 
             // The last node hasn't received sealed block.
-            assert_eq!(node.node_service.chain.epoch(), epoch);
-            assert_eq!(node.node_service.chain.last_block_hash(), last_block_hash);
+            assert_eq!(node.node_service.chain().epoch(), epoch);
+            assert_eq!(node.node_service.chain().last_block_hash(), last_block_hash);
 
             // poll to update node after macroblock_timeout waits
             node.poll();
             // Check that the last node has auto-committed the block.
-            assert_eq!(node.node_service.chain.epoch(), epoch + 1);
-            assert_eq!(node.node_service.chain.last_block_hash(), block_hash);
+            assert_eq!(node.node_service.chain().epoch(), epoch + 1);
+            assert_eq!(node.node_service.chain().last_block_hash(), block_hash);
 
             // Check that the auto-committed block has been sent to the network.
-            let block2: Block = node
-                .network_service
-                .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            let block2: Block = node.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
             let block_hash2 = Hash::digest(&block2);
             assert_eq!(block_hash, block_hash2);
         }
@@ -176,25 +173,25 @@ fn round() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
+        let topic = CONSENSUS_TOPIC;
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
         let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
         let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
 
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let round = s.nodes[0].node_service.chain.view_change() + 1;
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let round = s.nodes[0].node_service.chain().view_change() + 1;
         s.wait(s.config.node.macro_block_timeout);
 
         info!("====== Waiting for keyblock timeout. =====");
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
         let leader_node = s.node(&leader_pk).unwrap();
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
         debug!("Proposal: {:?}", proposal);
@@ -240,20 +237,20 @@ fn round() {
             .node(&leader_pk)
             .unwrap()
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
         let block_hash = Hash::digest(&block);
 
         let macro_block = block.clone().unwrap_macro();
         assert_eq!(macro_block.header.view_change, round);
         for node in s.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
         s.poll();
 
         for node in s.iter_except(&[leader_pk]) {
-            assert_eq!(node.node_service.chain.epoch(), epoch + 1);
-            assert_eq!(node.node_service.chain.last_block_hash(), block_hash);
+            assert_eq!(node.node_service.chain().epoch(), epoch + 1);
+            assert_eq!(node.node_service.chain().last_block_hash(), block_hash);
         }
     });
 }
@@ -277,9 +274,9 @@ fn multiple_rounds() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let view_change = s.nodes[0].node_service.chain.view_change();
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let topic = CONSENSUS_TOPIC;
+        let view_change = s.nodes[0].node_service.chain().view_change();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
         let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -298,9 +295,12 @@ fn multiple_rounds() {
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(view_change + 1);
+        let leader_pk = s.nodes[0]
+            .node_service
+            .chain()
+            .select_leader(view_change + 1);
         let leader_node = s.node(&leader_pk).unwrap();
         let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
         let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -318,9 +318,12 @@ fn multiple_rounds() {
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(view_change + 2);
+        let leader_pk = s.nodes[0]
+            .node_service
+            .chain()
+            .select_leader(view_change + 2);
         let leader_node = s.node(&leader_pk).unwrap();
         let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
         let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -344,10 +347,10 @@ fn lock() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let mut round = s.nodes[0].node_service.chain.view_change();
+        let mut round = s.nodes[0].node_service.chain().view_change();
 
         let mut ready = false;
         for i in 0..1000 {
@@ -356,8 +359,8 @@ fn lock() {
                 i,
                 i + 1
             );
-            let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
-            let new_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+            let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
+            let new_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
 
             if leader_pk != new_leader_pk {
                 ready = true;
@@ -370,24 +373,24 @@ fn lock() {
 
             leader_node
                 .network_service
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+                .filter_unicast(&[CHAIN_LOADER_TOPIC]);
             let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             round += 1;
             // wait for current round end
             s.wait(
                 s.config.node.macro_block_timeout
-                    * (round - s.nodes[0].node_service.chain.view_change()),
+                    * (round - s.nodes[0].node_service.chain().view_change()),
             );
         }
         assert!(ready);
         info!("Starting test.");
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
@@ -401,7 +404,7 @@ fn lock() {
         }
         s.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         // for now, every node is locked at leader_propose
         for i in 0..s.num_nodes() {
             let prevote: ConsensusMessage = s.nodes[i].network_service.get_broadcast(topic);
@@ -422,17 +425,17 @@ fn lock() {
         s.poll();
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 1),
+                * (round - s.nodes[0].node_service.chain().view_change() + 1),
         );
 
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
         info!("====== Waiting for macroblock timeout. =====");
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-        let second_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+        let second_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
         let leader_node = s.node(&second_leader_pk).unwrap();
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
 
@@ -460,10 +463,10 @@ fn lock_with_second_propose() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let mut round = s.nodes[0].node_service.chain.view_change();
+        let mut round = s.nodes[0].node_service.chain().view_change();
 
         let mut ready = false;
         for i in 0..1000 {
@@ -472,8 +475,8 @@ fn lock_with_second_propose() {
                 i,
                 i + 1
             );
-            let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
-            let new_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+            let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
+            let new_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
 
             if leader_pk != new_leader_pk {
                 ready = true;
@@ -486,26 +489,26 @@ fn lock_with_second_propose() {
 
             leader_node
                 .network_service
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+                .filter_unicast(&[CHAIN_LOADER_TOPIC]);
             let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             round += 1;
             // wait for current round end
             s.wait(
                 s.config.node.macro_block_timeout
-                    * (round - s.nodes[0].node_service.chain.view_change()),
+                    * (round - s.nodes[0].node_service.chain().view_change()),
             );
         }
         assert!(ready);
         info!("Starting test.");
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
 
-        let second_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+        let second_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
@@ -519,7 +522,7 @@ fn lock_with_second_propose() {
         }
         s.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         let mut prevotes = Vec::new();
         // for now, every node EXCEPT second_leader is locked at leader_propose
         for node in s.iter_except(&[second_leader_pk]) {
@@ -545,15 +548,15 @@ fn lock_with_second_propose() {
         s.poll();
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 1),
+                * (round - s.nodes[0].node_service.chain().view_change() + 1),
         );
 
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
         info!("====== Waiting for macroblock timeout. =====");
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader_node = s.node(&second_leader_pk).unwrap();
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -591,10 +594,10 @@ fn pack_of_prevotes() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let mut round = s.nodes[0].node_service.chain.view_change();
+        let mut round = s.nodes[0].node_service.chain().view_change();
 
         let mut ready = false;
         for i in 0..1000 {
@@ -603,8 +606,8 @@ fn pack_of_prevotes() {
                 i,
                 i + 1
             );
-            let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
-            let new_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+            let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
+            let new_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
 
             if leader_pk != new_leader_pk {
                 ready = true;
@@ -617,20 +620,20 @@ fn pack_of_prevotes() {
 
             leader_node
                 .network_service
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+                .filter_unicast(&[CHAIN_LOADER_TOPIC]);
             let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             round += 1;
             // wait for current round end
             s.wait(
                 s.config.node.macro_block_timeout
-                    * (round - s.nodes[0].node_service.chain.view_change()),
+                    * (round - s.nodes[0].node_service.chain().view_change()),
             );
         }
 
         assert!(ready);
         info!("Starting test.");
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node.poll();
         // skip proposal and prevote of last leader.
@@ -639,7 +642,7 @@ fn pack_of_prevotes() {
         assert_matches!(leader_proposal.body, ConsensusMessageBody::Proposal { .. });
         let leaders = &[leader_pk];
         let first_node = s.iter_except(leaders).next().unwrap();
-        let first_node_pk = first_node.node_service.network_pkey;
+        let first_node_pk = first_node.node_service.network_pkey();
         let mut split = s.split(&[first_node_pk]);
 
         for node in split.parts.1.iter_except(&[leader_pk]) {
@@ -689,7 +692,7 @@ fn pack_of_prevotes() {
 
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 1),
+                * (round - s.nodes[0].node_service.chain().view_change() + 1),
         );
 
         info!("====== Waiting for macroblock timeout. =====");
@@ -714,10 +717,10 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let mut round = s.nodes[0].node_service.chain.view_change();
+        let mut round = s.nodes[0].node_service.chain().view_change();
 
         let mut ready = false;
         for i in 0..1000 {
@@ -726,8 +729,8 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
                 i,
                 i + 1
             );
-            let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
-            let new_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+            let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
+            let new_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
 
             if leader_pk != new_leader_pk {
                 ready = true;
@@ -740,26 +743,26 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
 
             leader_node
                 .network_service
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+                .filter_unicast(&[CHAIN_LOADER_TOPIC]);
             let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             round += 1;
             // wait for current round end
             s.wait(
                 s.config.node.macro_block_timeout
-                    * (round - s.nodes[0].node_service.chain.view_change()),
+                    * (round - s.nodes[0].node_service.chain().view_change()),
             );
         }
         assert!(ready);
         info!("Starting test.");
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
 
-        let second_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+        let second_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
@@ -773,7 +776,7 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
         }
         s.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         let mut prevotes = Vec::new();
         // for now, every node EXCEPT second_leader is locked at leader_propose
         for node in s.iter_except(&[second_leader_pk]) {
@@ -797,15 +800,15 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
         let _precommit: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 1),
+                * (round - s.nodes[0].node_service.chain().view_change() + 1),
         );
 
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
         info!("====== Waiting for macroblock timeout. =====");
         s.poll();
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader_node = s.node(&second_leader_pk).unwrap();
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -865,7 +868,7 @@ fn lock_with_second_propose_and_pack_of_prevotes() {
         s.poll();
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 2),
+                * (round - s.nodes[0].node_service.chain().view_change() + 2),
         );
 
         info!("====== Waiting for macroblock timeout. =====");
@@ -892,10 +895,10 @@ fn pack_of_prevotes_and_precommits() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
 
-        let mut round = s.nodes[0].node_service.chain.view_change();
+        let mut round = s.nodes[0].node_service.chain().view_change();
 
         let mut ready = false;
         for i in 0..1000 {
@@ -904,8 +907,8 @@ fn pack_of_prevotes_and_precommits() {
                 i,
                 i + 1
             );
-            let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
-            let new_leader_pk = s.nodes[0].node_service.chain.select_leader(round + 1);
+            let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
+            let new_leader_pk = s.nodes[0].node_service.chain().select_leader(round + 1);
 
             if leader_pk != new_leader_pk {
                 ready = true;
@@ -918,20 +921,20 @@ fn pack_of_prevotes_and_precommits() {
 
             leader_node
                 .network_service
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+                .filter_unicast(&[CHAIN_LOADER_TOPIC]);
             let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             let _prevote: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
             round += 1;
             // wait for current round end
             s.wait(
                 s.config.node.macro_block_timeout
-                    * (round - s.nodes[0].node_service.chain.view_change()),
+                    * (round - s.nodes[0].node_service.chain().view_change()),
             );
         }
 
         assert!(ready);
         info!("Starting test.");
-        let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+        let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node.poll();
         // skip proposal and prevote of last leader.
@@ -940,7 +943,7 @@ fn pack_of_prevotes_and_precommits() {
         assert_matches!(leader_proposal.body, ConsensusMessageBody::Proposal { .. });
         let leaders = &[leader_pk];
         let first_node = s.iter_except(leaders).next().unwrap();
-        let first_node_pk = first_node.node_service.network_pkey;
+        let first_node_pk = first_node.node_service.network_pkey();
         let mut split = s.split(&[first_node_pk]);
 
         for node in split.parts.1.iter_except(&[leader_pk]) {
@@ -997,7 +1000,7 @@ fn pack_of_prevotes_and_precommits() {
 
         s.wait(
             s.config.node.macro_block_timeout
-                * (round - s.nodes[0].node_service.chain.view_change() + 1),
+                * (round - s.nodes[0].node_service.chain().view_change() + 1),
         );
 
         info!("====== Waiting for macroblock timeout. =====");
@@ -1018,27 +1021,27 @@ fn out_of_order_micro_block() {
     };
 
     Sandbox::start(config, |mut s| {
-        let topic = crate::CONSENSUS_TOPIC;
+        let topic = CONSENSUS_TOPIC;
         s.poll();
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let offset = s.nodes[0].node_service.chain.offset();
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let offset = s.nodes[0].node_service.chain().offset();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         //create valid but out of order fake micro block.
         let timestamp = Timestamp::now();
 
-        let view_change = s.nodes[0].node_service.chain.view_change();
-        let last_block_hash = s.nodes[0].node_service.chain.last_block_hash();
+        let view_change = s.nodes[0].node_service.chain().view_change();
+        let last_block_hash = s.nodes[0].node_service.chain().last_block_hash();
 
         let leader = s.node(&leader_pk).unwrap();
         let seed = mix(
-            leader.node_service.chain.last_random(),
-            leader.node_service.chain.view_change(),
+            leader.node_service.chain().last_random(),
+            leader.node_service.chain().view_change(),
         );
-        let random = pbc::make_VRF(&leader.node_service.network_skey, &seed);
-        let solution = leader.node_service.chain.vdf_solver()();
+        let random = pbc::make_VRF(&leader.network_skey, &seed);
+        let solution = leader.node_service.chain().vdf_solver()();
 
         let mut block = MicroBlock::empty(
             last_block_hash,
@@ -1046,15 +1049,15 @@ fn out_of_order_micro_block() {
             offset,
             view_change + 1,
             None,
-            leader.node_service.network_pkey,
+            leader.node_service.network_pkey(),
             random,
             solution,
             timestamp,
         );
         let leader_node = s.node(&leader_pk).unwrap();
         block.sign(
-            &leader_node.node_service.network_skey,
-            &leader_node.node_service.network_pkey,
+            &leader_node.network_skey,
+            &leader_node.node_service.network_pkey(),
         );
         let block: Block = Block::MicroBlock(block);
 
@@ -1063,20 +1066,20 @@ fn out_of_order_micro_block() {
         // broadcast block to other nodes.
         for node in &mut s.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone())
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone())
         }
         s.poll();
 
         for node in &s.nodes {
-            assert_eq!(node.node_service.chain.epoch(), epoch);
-            assert_eq!(node.node_service.chain.offset(), offset);
+            assert_eq!(node.node_service.chain().epoch(), epoch);
+            assert_eq!(node.node_service.chain().offset(), offset);
         }
 
-        let leader_pk = s.first().node_service.chain.leader();
+        let leader_pk = s.first().node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         leader_node
             .network_service
-            .filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+            .filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 
@@ -1212,31 +1215,25 @@ fn create_invalid_prevotes(msg: &ConsensusMessage, key: &SecretKey) -> Vec<Conse
 }
 
 fn save_consensus_state(node: &NodeService) -> ConsensusInfo {
-    let consensus = match node.validation {
-        Validation::MacroBlockValidator { ref consensus, .. } => consensus,
-        _ => panic!("Wrong state."),
-    };
+    let consensus = node.consensus().expect("Wrong state.");
     consensus.to_info()
 }
 
 fn assert_consensus_state(info: &ConsensusInfo, node: &NodeService) {
-    let consensus = match node.validation {
-        Validation::MacroBlockValidator { ref consensus, .. } => consensus,
-        _ => panic!("Wrong state."),
-    };
+    let consensus = node.consensus().expect("Wrong state.");
     let new_info = consensus.to_info();
     assert_eq!(*info, new_info)
 }
 
 fn invalid_proposes_inner(s: &mut Sandbox, round: u32) {
-    let topic = crate::CONSENSUS_TOPIC;
-    let epoch = s.nodes[0].node_service.chain.epoch();
+    let topic = CONSENSUS_TOPIC;
+    let epoch = s.nodes[0].node_service.chain().epoch();
 
-    let leader_pk = s.nodes[0].node_service.chain.select_leader(round);
+    let leader_pk = s.nodes[0].node_service.chain().select_leader(round);
     trace!("SELECTING LEADER of round {} = {}", round, leader_pk);
     let leader_node = s.node(&leader_pk).unwrap();
 
-    let skey = leader_node.node_service.network_skey.clone();
+    let skey = leader_node.network_skey.clone();
 
     // Check for a proposal from the leader.
     let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -1301,9 +1298,9 @@ fn invalid_proposes() {
         s.poll();
         s.skip_micro_block();
 
-        let round = s.nodes[0].node_service.chain.view_change();
+        let round = s.nodes[0].node_service.chain().view_change();
         invalid_proposes_inner(&mut s, round);
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 
@@ -1324,9 +1321,9 @@ fn invalid_proposes_on_2nd_round() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
+        let topic = CONSENSUS_TOPIC;
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // skip proposal and prevote of last leader.
         let _proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -1336,9 +1333,9 @@ fn invalid_proposes_on_2nd_round() {
         info!("====== Waiting for keyblock timeout. =====");
         s.poll();
 
-        let round = s.nodes[0].node_service.chain.view_change() + 1;
+        let round = s.nodes[0].node_service.chain().view_change() + 1;
         invalid_proposes_inner(&mut s, round);
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 
@@ -1364,14 +1361,14 @@ fn multiple_proposes() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let round = s.nodes[0].node_service.chain.view_change();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let round = s.nodes[0].node_service.chain().view_change();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
 
-        let skey = leader_node.node_service.network_skey.clone();
+        let skey = leader_node.network_skey.clone();
 
         // Check for a proposal from the leader.
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -1406,8 +1403,8 @@ fn multiple_proposes() {
         // change author
         let node = r.parts.1.first();
         let mut new_msg = proposal.clone();
-        new_msg.pkey = node.node_service.network_pkey;
-        invalid_messages.push(resign_msg(new_msg, &node.node_service.network_skey));
+        new_msg.pkey = node.node_service.network_pkey();
+        invalid_messages.push(resign_msg(new_msg, &node.network_skey));
 
         // Send other valid propose
         let mut new_msg = proposal.clone();
@@ -1431,7 +1428,7 @@ fn multiple_proposes() {
             assert_consensus_state(&info, node);
         });
 
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 
@@ -1452,11 +1449,11 @@ fn invalid_prevotes() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let round = s.nodes[0].node_service.chain.view_change();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let round = s.nodes[0].node_service.chain().view_change();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // Check for a proposal from the leader.
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -1473,7 +1470,7 @@ fn invalid_prevotes() {
         s.poll();
 
         let mut r = s.split(&[leader_pk]);
-        let node_skey = r.parts.1.first().node_service.network_skey.clone();
+        let node_skey = r.parts.1.first().network_skey.clone();
         let node_prevote: ConsensusMessage =
             r.parts.1.first_mut().network_service.get_broadcast(topic);
         assert_matches!(node_prevote.body, ConsensusMessageBody::Prevote);
@@ -1508,7 +1505,7 @@ fn invalid_prevotes() {
         }
 
         s.poll();
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 // Test [multiple message on prevote (from leader)]
@@ -1528,11 +1525,11 @@ fn invalid_prevotes_leader() {
         s.poll();
         s.skip_micro_block();
 
-        let topic = crate::CONSENSUS_TOPIC;
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let round = s.nodes[0].node_service.chain.view_change();
+        let topic = CONSENSUS_TOPIC;
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let round = s.nodes[0].node_service.chain().view_change();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         let leader_node = s.node(&leader_pk).unwrap();
         // Check for a proposal from the leader.
         let proposal: ConsensusMessage = leader_node.network_service.get_broadcast(topic);
@@ -1549,7 +1546,7 @@ fn invalid_prevotes_leader() {
         s.poll();
 
         let mut r = s.split(&[leader_pk]);
-        let leader_skey = r.parts.0.first().node_service.network_skey.clone();
+        let leader_skey = r.parts.0.first().network_skey.clone();
         let leader_prevote: ConsensusMessage =
             r.parts.0.first_mut().network_service.get_broadcast(topic);
         assert_matches!(leader_prevote.body, ConsensusMessageBody::Prevote);
@@ -1584,7 +1581,7 @@ fn invalid_prevotes_leader() {
         }
 
         s.poll();
-        s.filter_broadcast(&[crate::CONSENSUS_TOPIC]);
+        s.filter_broadcast(&[CONSENSUS_TOPIC]);
     });
 }
 
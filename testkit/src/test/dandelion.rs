@@ -0,0 +1,154 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::*;
+
+fn dandelion_config(fluff_probability: u32) -> SandboxConfig {
+    let mut config = SandboxConfig::default();
+    config.node.tx_propagation = TxPropagation::Dandelion;
+    config.node.dandelion_fluff_probability = fluff_probability;
+    config.node.dandelion_embargo_timeout = Duration::from_secs(1);
+    config
+}
+
+fn payment(s: &Sandbox, fee: i64) -> Transaction {
+    create_payment_transaction(s.nodes[0].chain(), &s.keychains[0], fee).into()
+}
+
+#[test]
+fn stem_relay() {
+    Sandbox::start(dandelion_config(0), |mut s| {
+        s.poll();
+        let tx = payment(&s, s.config.node.min_payment_fee);
+        let tx_hash = Hash::digest(&tx);
+        let sender_pkey = s.nodes[0].node_service.network_pkey();
+
+        // The sender doesn't broadcast the transaction.
+        let _ = s.nodes[0].node.send_transaction(tx.clone());
+        s.nodes[0].poll();
+        let (stem_tx, relay): (Transaction, _) =
+            s.nodes[0].network_service.get_unicast(TX_STEM_TOPIC);
+        assert_eq!(Hash::digest(&stem_tx), tx_hash);
+        s.nodes[0].network_service.assert_empty_queue();
+        assert!(s.nodes[0].node_service.stempool_contains_tx(&tx_hash));
+        assert!(!s.nodes[0].node_service.mempool_contains_tx(&tx_hash));
+
+        // The relay forwards it to the next hop.
+        let relay_node = s.node(&relay).unwrap();
+        relay_node
+            .network_service
+            .receive_unicast(sender_pkey, TX_STEM_TOPIC, tx);
+        relay_node.poll();
+        let (_stem_tx, next_relay): (Transaction, _) =
+            relay_node.network_service.get_unicast(TX_STEM_TOPIC);
+        assert_ne!(next_relay, relay);
+        relay_node.network_service.assert_empty_queue();
+        assert!(relay_node.node_service.stempool_contains_tx(&tx_hash));
+        assert!(!relay_node.node_service.mempool_contains_tx(&tx_hash));
+    });
+}
+
+#[test]
+fn stem_fluff() {
+    Sandbox::start(dandelion_config(100), |mut s| {
+        s.poll();
+        let tx = payment(&s, s.config.node.min_payment_fee);
+        let tx_hash = Hash::digest(&tx);
+        let sender_pkey = s.nodes[0].node_service.network_pkey();
+
+        let node = &mut s.nodes[1];
+        node.network_service
+            .receive_unicast(sender_pkey, TX_STEM_TOPIC, tx);
+        node.poll();
+        let fluffed: Transaction = node.network_service.get_broadcast(TX_TOPIC);
+        assert_eq!(Hash::digest(&fluffed), tx_hash);
+        node.network_service.assert_empty_queue();
+        assert!(!node.node_service.stempool_contains_tx(&tx_hash));
+        assert!(node.node_service.mempool_contains_tx(&tx_hash));
+    });
+}
+
+#[test]
+fn stem_invalid() {
+    Sandbox::start(dandelion_config(100), |mut s| {
+        s.poll();
+        // Too low fee.
+        let tx = payment(&s, 0);
+        let tx_hash = Hash::digest(&tx);
+        let sender_pkey = s.nodes[0].node_service.network_pkey();
+
+        let node = &mut s.nodes[1];
+        node.network_service
+            .receive_unicast(sender_pkey, TX_STEM_TOPIC, tx);
+        node.poll();
+        node.network_service.assert_empty_queue();
+        assert!(!node.node_service.stempool_contains_tx(&tx_hash));
+        assert!(!node.node_service.mempool_contains_tx(&tx_hash));
+    });
+}
+
+#[test]
+fn stem_conflict() {
+    Sandbox::start(dandelion_config(0), |mut s| {
+        s.poll();
+        // Two transactions spending the same inputs.
+        let tx1 = payment(&s, s.config.node.min_payment_fee);
+        let tx2 = payment(&s, 2 * s.config.node.min_payment_fee);
+        let tx_hash2 = Hash::digest(&tx2);
+        let sender_pkey = s.nodes[0].node_service.network_pkey();
+
+        let node = &mut s.nodes[1];
+        node.network_service
+            .receive_unicast(sender_pkey, TX_STEM_TOPIC, tx1);
+        node.poll();
+        let (_stem_tx, _relay): (Transaction, _) = node.network_service.get_unicast(TX_STEM_TOPIC);
+
+        // The conflicting transaction is neither relayed nor broadcasted.
+        node.network_service
+            .receive_unicast(sender_pkey, TX_STEM_TOPIC, tx2);
+        node.poll();
+        node.network_service.assert_empty_queue();
+        assert!(!node.node_service.stempool_contains_tx(&tx_hash2));
+        assert!(!node.node_service.mempool_contains_tx(&tx_hash2));
+    });
+}
+
+#[test]
+fn stem_embargo() {
+    Sandbox::start(dandelion_config(0), |mut s| {
+        s.poll();
+        let tx = payment(&s, s.config.node.min_payment_fee);
+        let tx_hash = Hash::digest(&tx);
+
+        let _ = s.nodes[0].node.send_transaction(tx);
+        s.nodes[0].poll();
+        let (_stem_tx, _relay): (Transaction, _) =
+            s.nodes[0].network_service.get_unicast(TX_STEM_TOPIC);
+
+        // Nobody fluffed the transaction, the sender broadcasts it itself.
+        s.wait(s.config.node.dandelion_embargo_timeout * 2);
+        s.nodes[0].poll();
+        let fluffed: Transaction = s.nodes[0].network_service.get_broadcast(TX_TOPIC);
+        assert_eq!(Hash::digest(&fluffed), tx_hash);
+        assert!(!s.nodes[0].node_service.stempool_contains_tx(&tx_hash));
+        assert!(s.nodes[0].node_service.mempool_contains_tx(&tx_hash));
+    });
+}
@@ -24,8 +24,6 @@
 //! Test of features build on top of consensus/blockchain stack.
 
 use super::*;
-//use crate::loader::ChainLoaderMessage;
-use crate::*;
 use std::collections::HashSet;
 use stegos_blockchain::Block;
 use stegos_blockchain::ChainInfo;
@@ -69,12 +67,12 @@ fn rollback_slashing() {
 
             // third block leader
             let view_change = 0;
-            let init_random = s.first_mut().node_service.chain.last_random();
+            let init_random = s.first_mut().node_service.chain().last_random();
             let vrf = s
                 .node(&first_leader_pk)
                 .unwrap()
                 .create_vrf_from_seed(init_random, view_change);
-            let mut election = s.first_mut().node_service.chain.election_result().clone();
+            let mut election = s.first_mut().node_service.chain().election_result().clone();
             election.random = vrf;
 
             let third_leader_pk = election.select_leader(view_change);
@@ -87,9 +85,9 @@ fn rollback_slashing() {
             check_unique(leaders)
         });
 
-        let start_offset = s.first().node_service.chain.offset();
+        let start_offset = s.first().node_service.chain().offset();
 
-        let first_leader = s.first().node_service.chain.leader();
+        let first_leader = s.first().node_service.chain().leader();
 
         let second_leader = s.future_view_change_leader(1);
         info!("CREATE BLOCK. LEADER = {}", first_leader);
@@ -99,7 +97,7 @@ fn rollback_slashing() {
 
         s.wait(s.config.node.micro_block_timeout);
         s.poll();
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         let mut msgs = Vec::new();
         for node in &mut s.iter_except(&[first_leader]) {
             let msg: ViewChangeMessage = node.network_service.get_broadcast(VIEW_CHANGE_TOPIC);
@@ -113,7 +111,7 @@ fn rollback_slashing() {
         for msg in &msgs {
             new_leader_node
                 .network_service
-                .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
         }
         new_leader_node.poll();
 
@@ -124,12 +122,12 @@ fn rollback_slashing() {
 
         info!(
             "CHECK IF CHEATER WAS DETECTED. LEADER={}",
-            r.parts.1.first().node_service.chain.leader()
+            r.parts.1.first().node_service.chain().leader()
         );
         // each node should add proof of slashing into state.
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 1));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 1));
 
         // wait for block;
         r.parts.1.skip_micro_block();
@@ -138,7 +136,7 @@ fn rollback_slashing() {
         for node in r.parts.1.iter() {
             let validators: HashSet<_> = node
                 .node_service
-                .chain
+                .chain()
                 .validators()
                 .iter()
                 .map(|(p, _)| *p)
@@ -152,17 +150,17 @@ fn rollback_slashing() {
         new_leader_node.poll();
         let block: Block = new_leader_node
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
         // try to rollback cheater affect, but proof should be saved
         for node in &mut s.nodes {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone())
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone())
         }
 
         if let Some(auditor) = s.auditor_mut() {
             auditor
                 .network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         }
 
         // nodes C D should rollback fork
@@ -171,16 +169,16 @@ fn rollback_slashing() {
         r.parts.1.assert_synchronized();
         // assert that nodes recover valdators list. And has same height
         for node in r.parts.1.iter() {
-            assert_eq!(start_offset + 1, node.node_service.chain.offset());
-            assert_eq!(node.node_service.chain.validators().len(), 4);
+            assert_eq!(start_offset + 1, node.node_service.chain().offset());
+            assert_eq!(node.node_service.chain().validators().len(), 4);
         }
         // assert that each node except first one that was in partition still has a proof.
         for node in r.parts.1.iter() {
-            assert_eq!(node.node_service.cheating_proofs.len(), 1)
+            assert_eq!(node.node_service.cheating_proofs().len(), 1)
         }
 
-        s.filter_broadcast(&[crate::VIEW_CHANGE_TOPIC, crate::SEALED_BLOCK_TOPIC]);
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_broadcast(&[VIEW_CHANGE_TOPIC, SEALED_BLOCK_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
     });
 }
 
@@ -204,7 +202,7 @@ fn finalized_slashing() {
         precondition_n_different_block_leaders(&mut s, 2);
         // next leader should be from different partition.
 
-        let cheater = s.nodes[0].node_service.chain.leader();
+        let cheater = s.nodes[0].node_service.chain().leader();
         info!("CREATE BLOCK. LEADER = {}", cheater);
         s.poll();
 
@@ -212,12 +210,12 @@ fn finalized_slashing() {
 
         info!(
             "CHECK IF CHEATER WAS DETECTED. LEADER={}",
-            r.parts.1.first().node_service.chain.leader()
+            r.parts.1.first().node_service.chain().leader()
         );
         // each node should add proof of slashing into state.
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 1));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 1));
 
         // wait for block;
         r.parts.1.skip_micro_block();
@@ -226,7 +224,7 @@ fn finalized_slashing() {
         for node in r.parts.1.iter() {
             let validators: HashSet<_> = node
                 .node_service
-                .chain
+                .chain()
                 .validators()
                 .iter()
                 .map(|(p, _)| *p)
@@ -234,7 +232,7 @@ fn finalized_slashing() {
             assert!(!validators.contains(&cheater))
         }
 
-        let offset = r.parts.1.first().node_service.chain.offset();
+        let offset = r.parts.1.first().node_service.chain().offset();
 
         for _offset in offset..r.config.chain.micro_blocks_in_epoch {
             r.parts.1.poll();
@@ -243,7 +241,7 @@ fn finalized_slashing() {
         r.parts.1.skip_macro_block();
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 0));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 0));
     });
 }
 
@@ -270,7 +268,7 @@ fn finalized_slashing_with_service_award() {
         precondition_n_different_block_leaders(&mut s, 2);
         // next leader should be from different partition.
 
-        let cheater = s.nodes[0].node_service.chain.leader();
+        let cheater = s.nodes[0].node_service.chain().leader();
         info!("CREATE BLOCK. LEADER = {}", cheater);
         s.poll();
 
@@ -278,12 +276,12 @@ fn finalized_slashing_with_service_award() {
 
         info!(
             "CHECK IF CHEATER WAS DETECTED. LEADER={}",
-            r.parts.1.first().node_service.chain.leader()
+            r.parts.1.first().node_service.chain().leader()
         );
         // each node should add proof of slashing into state.
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 1));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 1));
 
         // wait for block;
         r.parts.1.skip_micro_block();
@@ -292,7 +290,7 @@ fn finalized_slashing_with_service_award() {
         for node in r.parts.1.iter() {
             let validators: HashSet<_> = node
                 .node_service
-                .chain
+                .chain()
                 .validators()
                 .iter()
                 .map(|(p, _)| *p)
@@ -300,8 +298,8 @@ fn finalized_slashing_with_service_award() {
             assert!(!validators.contains(&cheater))
         }
 
-        let offset = r.parts.1.first().node_service.chain.offset();
-        let epoch = r.parts.1.first().node_service.chain.epoch();
+        let offset = r.parts.1.first().node_service.chain().offset();
+        let epoch = r.parts.1.first().node_service.chain().epoch();
 
         // ignore microblocks for auditor
         for _offset in offset..r.config.chain.micro_blocks_in_epoch {
@@ -312,19 +310,19 @@ fn finalized_slashing_with_service_award() {
         r.parts.1.skip_macro_block();
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 0));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 0));
         let mut output = None;
         for node in r.parts.1.iter_mut() {
             //award was executed
-            assert_eq!(node.node_service.chain.service_awards().budget(), 0);
+            assert_eq!(node.node_service.chain().service_awards().budget(), 0);
             assert_eq!(
-                node.node_service.chain.last_block_hash(),
-                node.node_service.chain.last_macro_block_hash()
+                node.node_service.chain().last_block_hash(),
+                node.node_service.chain().last_macro_block_hash()
             );
-            let block_hash = node.node_service.chain.last_block_hash();
+            let block_hash = node.node_service.chain().last_block_hash();
             let block = node
                 .node_service
-                .chain
+                .chain()
                 .macro_block(epoch)
                 .unwrap()
                 .into_owned();
@@ -386,10 +384,10 @@ fn finalized_slashing_with_service_award_for_auditor() {
         // next leader should be from different partition.
 
         let budget = s.config.chain.service_award_per_epoch;
-        let cheater = s.nodes[0].node_service.chain.leader();
+        let cheater = s.nodes[0].node_service.chain().leader();
         let cheater_wallet = s.nodes[0]
             .node_service
-            .chain
+            .chain()
             .account_by_network_key(&cheater)
             .unwrap();
         info!(
@@ -405,12 +403,12 @@ fn finalized_slashing_with_service_award_for_auditor() {
 
         info!(
             "CHECK IF CHEATER WAS DETECTED. LEADER={}",
-            r.parts.1.first().node_service.chain.leader()
+            r.parts.1.first().node_service.chain().leader()
         );
         // each node should add proof of slashing into state.
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 1));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 1));
 
         // wait for block;
         r.parts.1.skip_micro_block();
@@ -419,7 +417,7 @@ fn finalized_slashing_with_service_award_for_auditor() {
         for node in r.parts.1.iter() {
             let validators: HashSet<_> = node
                 .node_service
-                .chain
+                .chain()
                 .validators()
                 .iter()
                 .map(|(p, _)| *p)
@@ -427,8 +425,8 @@ fn finalized_slashing_with_service_award_for_auditor() {
             assert!(!validators.contains(&cheater))
         }
 
-        let offset = r.parts.1.first().node_service.chain.offset();
-        let epoch = r.parts.1.first().node_service.chain.epoch();
+        let offset = r.parts.1.first().node_service.chain().offset();
+        let epoch = r.parts.1.first().node_service.chain().epoch();
 
         for _offset in offset..r.config.chain.micro_blocks_in_epoch {
             r.parts.1.poll();
@@ -439,20 +437,20 @@ fn finalized_slashing_with_service_award_for_auditor() {
         r.parts.1.skip_macro_block();
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 0));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 0));
 
         let mut output = None;
         for node in r.parts.1.iter_mut() {
             //award was executed
-            assert_eq!(node.node_service.chain.service_awards().budget(), 0);
+            assert_eq!(node.node_service.chain().service_awards().budget(), 0);
             assert_eq!(
-                node.node_service.chain.last_block_hash(),
-                node.node_service.chain.last_macro_block_hash()
+                node.node_service.chain().last_block_hash(),
+                node.node_service.chain().last_macro_block_hash()
             );
-            let block_hash = node.node_service.chain.last_block_hash();
+            let block_hash = node.node_service.chain().last_block_hash();
             let block = node
                 .node_service
-                .chain
+                .chain()
                 .macro_block(epoch)
                 .unwrap()
                 .into_owned();
@@ -507,7 +505,7 @@ fn finalized_slashing_with_service_award_for_auditor() {
             .1
             .first()
             .node_service
-            .chain
+            .chain()
             .service_awards()
             .clone();
         let auditor_award = r
@@ -516,7 +514,7 @@ fn finalized_slashing_with_service_award_for_auditor() {
             .auditor
             .unwrap()
             .node_service
-            .chain
+            .chain()
             .service_awards()
             .clone();
         assert_eq!(award, auditor_award);
@@ -524,8 +522,8 @@ fn finalized_slashing_with_service_award_for_auditor() {
 }
 
 fn service_award_round_normal(s: &mut Sandbox, service_award_budget: i64) {
-    let offset = s.first().node_service.chain.offset();
-    let epoch = s.first().node_service.chain.epoch();
+    let offset = s.first().node_service.chain().offset();
+    let epoch = s.first().node_service.chain().epoch();
 
     // ignore microblocks for auditor
     for _offset in offset..s.config.chain.micro_blocks_in_epoch {
@@ -537,15 +535,15 @@ fn service_award_round_normal(s: &mut Sandbox, service_award_budget: i64) {
     let mut output = None;
     for node in s.iter_mut() {
         //award was executed
-        assert_eq!(node.node_service.chain.service_awards().budget(), 0);
+        assert_eq!(node.node_service.chain().service_awards().budget(), 0);
         assert_eq!(
-            node.node_service.chain.last_block_hash(),
-            node.node_service.chain.last_macro_block_hash()
+            node.node_service.chain().last_block_hash(),
+            node.node_service.chain().last_macro_block_hash()
         );
-        let block_hash = node.node_service.chain.last_block_hash();
+        let block_hash = node.node_service.chain().last_block_hash();
         let block = node
             .node_service
-            .chain
+            .chain()
             .macro_block(epoch)
             .unwrap()
             .into_owned();
@@ -583,10 +581,10 @@ fn service_award_round_normal(s: &mut Sandbox, service_award_budget: i64) {
 }
 
 fn service_award_round_without_participants(s: &mut Sandbox) {
-    let offset = s.first().node_service.chain.offset();
-    let epoch = s.first().node_service.chain.epoch();
+    let offset = s.first().node_service.chain().offset();
+    let epoch = s.first().node_service.chain().epoch();
 
-    let mut nodes: HashSet<_> = s.iter().map(|n| n.node_service.network_pkey).collect();
+    let mut nodes: HashSet<_> = s.iter().map(|n| n.node_service.network_pkey()).collect();
 
     // skipp all leaders atleast once
     for offset in offset..s.config.chain.micro_blocks_in_epoch {
@@ -605,7 +603,7 @@ fn service_award_round_without_participants(s: &mut Sandbox) {
         for node in s.iter_mut() {
             assert_eq!(
                 node.node_service
-                    .chain
+                    .chain()
                     .epoch_activity()
                     .get(&leader_pk)
                     .unwrap_or(&ValidatorAwardState::Active),
@@ -622,9 +620,9 @@ fn service_award_round_without_participants(s: &mut Sandbox) {
 
         // emulate dead leader for other nodes
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         // filter block message from node.
-        s.filter_broadcast(&[crate::SEALED_BLOCK_TOPIC]);
+        s.filter_broadcast(&[SEALED_BLOCK_TOPIC]);
         info!("======= PARTITION BEGIN =======");
         let mut r = s.split(&[leader_pk]);
 
@@ -639,27 +637,27 @@ fn service_award_round_without_participants(s: &mut Sandbox) {
         for node in &mut r.parts.1.nodes {
             for msg in &msgs {
                 node.network_service
-                    .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                    .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
             }
         }
         r.parts.1.poll();
-        r.parts.0.filter_broadcast(&[crate::VIEW_CHANGE_TOPIC]);
+        r.parts.0.filter_broadcast(&[VIEW_CHANGE_TOPIC]);
         let new_leader_node = r.parts.1.node(&second_leader).unwrap();
         new_leader_node.handle_vdf();
         new_leader_node.poll();
         info!("======= BROADCAST BLOCK =======");
         let block: Block = new_leader_node
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
 
         for node in s.iter_mut() {
             node.network_service
-                .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone())
+                .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone())
         }
 
         s.auditor
             .network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
 
         s.poll();
 
@@ -669,7 +667,7 @@ fn service_award_round_without_participants(s: &mut Sandbox) {
         for node in s.iter_mut() {
             assert_eq!(
                 node.node_service
-                    .chain
+                    .chain()
                     .epoch_activity()
                     .get(&leader_pk)
                     .unwrap(),
@@ -691,25 +689,25 @@ fn service_award_round_without_participants(s: &mut Sandbox) {
     for node in s.iter_mut() {
         //award was executed without winners, list of activity should be cleared
         assert_eq!(
-            node.node_service.chain.service_awards().budget(),
+            node.node_service.chain().service_awards().budget(),
             service_award_budget
         );
         assert_eq!(
             node.node_service
-                .chain
+                .chain()
                 .service_awards()
                 .validators_activivty()
                 .len(),
             0
         );
         assert_eq!(
-            node.node_service.chain.last_block_hash(),
-            node.node_service.chain.last_macro_block_hash()
+            node.node_service.chain().last_block_hash(),
+            node.node_service.chain().last_macro_block_hash()
         );
-        let block_hash = node.node_service.chain.last_block_hash();
+        let block_hash = node.node_service.chain().last_block_hash();
         let block = node
             .node_service
-            .chain
+            .chain()
             .macro_block(epoch)
             .unwrap()
             .into_owned();
@@ -793,8 +791,8 @@ fn view_change_from_future() {
 
         let mut msgs = Vec::new();
         let sender = s.first_mut();
-        let sender_pk = sender.node_service.network_pkey.clone();
-        let source_chain_info = ChainInfo::from_blockchain(&sender.node_service.chain);
+        let sender_pk = sender.node_service.network_pkey().clone();
+        let source_chain_info = ChainInfo::from_blockchain(&sender.node_service.chain());
 
         let mut chain_info = source_chain_info.clone();
         chain_info.offset += 1;
@@ -814,7 +812,7 @@ fn view_change_from_future() {
                 ViewChangeMessage::new(
                     chain_info,
                     sender.validator_id().unwrap() as u32,
-                    &sender.node_service.network_skey,
+                    &sender.network_skey,
                 )
             })
             .collect();
@@ -835,3 +833,178 @@ fn view_change_from_future() {
         }
     });
 }
+
+#[test]
+fn transaction_validation() {
+    Sandbox::start(Default::default(), |mut s| {
+        s.poll();
+        let node = s.first_mut();
+        node.network_service
+            .receive_broadcast_raw(TX_TOPIC, vec![1, 2, 3]);
+        node.poll();
+        let results = node.network_service.take_validation_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, stegos_network::ValidationResult::Reject);
+    });
+}
+
+#[test]
+fn block_validation() {
+    Sandbox::start(Default::default(), |mut s| {
+        s.poll();
+        let node = s.first_mut();
+        for topic in &[SEALED_BLOCK_TOPIC, COMPACT_BLOCK_TOPIC] {
+            node.network_service
+                .receive_broadcast_raw(topic, vec![1, 2, 3]);
+            node.poll();
+            let results = node.network_service.take_validation_results();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].1, stegos_network::ValidationResult::Reject);
+        }
+    });
+}
+
+#[test]
+fn future_block_validation() {
+    Sandbox::start(Default::default(), |mut s| {
+        s.poll();
+        let node = s.first_mut();
+        let chain = node.chain();
+        let (skey, _pkey) = pbc::make_random_keys();
+        let seed = mix(chain.last_random(), chain.view_change());
+        let random = pbc::make_VRF(&skey, &seed);
+        let mut block = MicroBlock::empty(
+            chain.last_block_hash(),
+            chain.epoch() + 1,
+            0,
+            0,
+            None,
+            chain.leader(),
+            random,
+            Vec::new(),
+            Timestamp::now(),
+        );
+        // Signed by a random key instead of the leader.
+        block.sig = pbc::sign_hash(&Hash::digest(&block.header), &skey);
+        node.network_service
+            .receive_broadcast(SEALED_BLOCK_TOPIC, Block::MicroBlock(block));
+        node.poll();
+        // The block is not verified, so it must not be forwarded.
+        let results = node.network_service.take_validation_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, stegos_network::ValidationResult::Ignore);
+    });
+}
+
+#[test]
+fn report_garbage_unicast() {
+    Sandbox::start(Default::default(), |mut s| {
+        s.poll();
+        let (_, garbage_pkey) = stegos_crypto::pbc::make_random_keys();
+        let node = s.first_mut();
+        node.network_service
+            .receive_unicast_raw(garbage_pkey, CHAIN_LOADER_TOPIC, vec![1, 2, 3]);
+        node.poll();
+        let reported = node.network_service.take_reported_peers();
+        assert_eq!(
+            reported,
+            vec![(garbage_pkey, stegos_network::Misbehavior::InvalidRpc)]
+        );
+    });
+}
+
+#[test]
+fn checkpoint_mismatch() {
+    let mut config: SandboxConfig = Default::default();
+    config.chain.checkpoints = vec![stegos_blockchain::Checkpoint {
+        epoch: 1,
+        hash: Hash::digest("wrong"),
+    }];
+    Sandbox::start(config, |mut s| {
+        s.poll();
+        let epoch = s.first().chain().epoch();
+        assert_eq!(epoch, 1);
+        let (block, _extra_transactions) = stegos_blockchain::test::create_fake_macro_block(
+            s.first().chain(),
+            &s.keychains,
+            Timestamp::now(),
+        );
+        let block_hash = Hash::digest(&block);
+
+        let node = s.first_mut();
+        let e = node
+            .node_service
+            .handle_macro_block(block)
+            .expect_err("checkpoint mismatch");
+        match e.downcast::<stegos_blockchain::BlockError>().unwrap() {
+            stegos_blockchain::BlockError::CheckpointMismatch(e_epoch, e_hash, _expected) => {
+                assert_eq!(e_epoch, epoch);
+                assert_eq!(e_hash, block_hash);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(node.chain().epoch(), epoch);
+        assert_eq!(node.chain().offset(), 0);
+    });
+}
+
+// CASE network key rotation:
+//
+// Asserts that the validator keeps the old key until the end of the epoch,
+// switches to the new key on the macro block and continues to produce blocks.
+
+#[test]
+fn rotate_network_keys() {
+    let mut config: SandboxConfig = Default::default();
+    config.chain.key_rotation_epoch = 0;
+    Sandbox::start(config, |mut s| {
+        s.poll();
+        let old_pkey = s.first().node_service.network_pkey();
+        let node = s.first_mut();
+        let mut receive = node.node.request(NodeRequest::RotateNetworkKeys {});
+        node.poll();
+        let new_pkey = match receive.poll().unwrap() {
+            Async::Ready(NodeResponse::NetworkKeysRotated {
+                network_pkey,
+                status: TransactionStatus::Accepted {},
+                ..
+            }) => network_pkey,
+            e => panic!("Expected rotated keys, got ={:?}", e),
+        };
+        assert_ne!(new_pkey, old_pkey);
+
+        // Deliver the restake transaction to other validators.
+        let tx: Transaction = s.first_mut().network_service.get_broadcast(TX_TOPIC);
+        for node in s.iter_except(&[old_pkey]) {
+            node.network_service.receive_broadcast(TX_TOPIC, tx.clone());
+        }
+        s.poll();
+        s.skip_micro_block();
+        assert!(s
+            .first()
+            .chain()
+            .account_by_network_key(&new_pkey)
+            .is_some());
+        // The old key is used until the end of the epoch.
+        assert_eq!(s.first().node_service.network_pkey(), old_pkey);
+
+        // Cross the epoch boundary.
+        let epoch = s.first().chain().epoch();
+        s.skip_epoch();
+        assert_eq!(s.first().chain().epoch(), epoch + 1);
+        assert_eq!(s.first().node_service.network_pkey(), new_pkey);
+        let validators: Vec<pbc::PublicKey> = s
+            .first()
+            .chain()
+            .validators()
+            .iter()
+            .map(|(pkey, _)| *pkey)
+            .collect();
+        assert!(validators.contains(&new_pkey));
+        assert!(!validators.contains(&old_pkey));
+
+        // The chain goes on with the new key.
+        s.skip_epoch();
+        assert_eq!(s.first().chain().epoch(), epoch + 2);
+    });
+}
@@ -22,7 +22,6 @@
 // SOFTWARE.
 
 use super::*;
-use crate::*;
 use bit_vec::BitVec;
 use std::collections::HashSet;
 use stegos_blockchain::Block;
@@ -50,7 +49,7 @@ fn dead_leader() {
     Sandbox::start(config, |mut s| {
         s.poll();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
         // let leader shoot his block
         s.poll();
         // emulate timeout on other nodes, and wait for request
@@ -59,9 +58,7 @@ fn dead_leader() {
         s.poll();
         let mut r = s.split(&[leader_pk]);
         // emulate dead leader for other nodes
-        r.parts
-            .1
-            .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        r.parts.1.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let mut msgs = Vec::new();
         for node in &mut r.parts.1.nodes {
@@ -74,7 +71,7 @@ fn dead_leader() {
         for node in r.parts.1.iter_mut() {
             for msg in &msgs {
                 node.network_service
-                    .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                    .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
             }
         }
 
@@ -82,26 +79,92 @@ fn dead_leader() {
         r.parts.1.poll();
         for node in r.parts.1.iter_mut() {
             info!("processing validator = {:?}", node.validator_id());
-            if next_leader == node.node_service.network_pkey {
+            if next_leader == node.node_service.network_pkey() {
                 node.handle_vdf();
                 node.poll();
                 let _: Block = node.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
                 // If node was leader, they have produced micro block,
-                assert_eq!(node.node_service.chain.view_change(), 0);
+                assert_eq!(node.node_service.chain().view_change(), 0);
             } else {
-                assert_eq!(node.node_service.chain.view_change(), 1);
+                assert_eq!(node.node_service.chain().view_change(), 1);
             }
         }
 
         let first_leader = r.parts.0.first_mut();
 
-        assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+        assert_eq!(leader_pk, first_leader.node_service.network_pkey());
         first_leader
             .network_service
-            .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC, crate::SEALED_BLOCK_TOPIC]);
+            .filter_broadcast(&[VIEW_CHANGE_TOPIC, SEALED_BLOCK_TOPIC]);
         first_leader
             .network_service
-            .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            .filter_unicast(&[CHAIN_LOADER_TOPIC]);
+    });
+}
+
+// CASE adaptive timeouts:
+// Nodes [A, B, C, D]
+//
+// 1. Node A leader of view_change 1, didn't broadcast micro block (B1) to [B,C,D]
+// 2. Nodes [B, C, D] send view_change messages after micro_block_timeout.
+//
+// Asserts that Nodes [B, C, D] wait twice longer before sending view_change messages again.
+#[test]
+fn adaptive_view_change_timeout() {
+    let mut cfg: ChainConfig = Default::default();
+    cfg.micro_blocks_in_epoch = 2000;
+    let config = SandboxConfig {
+        num_nodes: 4,
+        chain: cfg,
+        node: NodeConfig {
+            adaptive_timeouts: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    Sandbox::start(config, |mut s| {
+        s.poll();
+
+        let leader_pk = s.nodes[0].node_service.chain().leader();
+        s.poll();
+        s.wait(s.config.node.micro_block_timeout);
+        s.poll();
+        let mut r = s.split(&[leader_pk]);
+        r.parts.1.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+
+        for node in r.parts.1.iter_mut() {
+            let msg: ViewChangeMessage = node.network_service.get_broadcast(VIEW_CHANGE_TOPIC);
+            assert_eq!(msg.chain.view_change, 0);
+        }
+
+        // The fixed timeout has expired, but the adaptive one has not.
+        r.wait(r.config.node.micro_block_timeout);
+        r.parts.1.poll();
+        for node in r.parts.1.iter_mut() {
+            assert!(node
+                .network_service
+                .try_get_broadcast_raw(VIEW_CHANGE_TOPIC)
+                .is_none());
+        }
+
+        // The adaptive timeout has expired.
+        r.wait(r.config.node.micro_block_timeout);
+        r.parts.1.poll();
+        for node in r.parts.1.iter_mut() {
+            assert_eq!(node.node_service.chain().view_change(), 0);
+            let msg: ViewChangeMessage = node.network_service.get_broadcast(VIEW_CHANGE_TOPIC);
+            assert_eq!(msg.chain.view_change, 0);
+        }
+
+        let first_leader = r.parts.0.first_mut();
+        assert_eq!(leader_pk, first_leader.node_service.network_pkey());
+        first_leader
+            .network_service
+            .filter_broadcast(&[VIEW_CHANGE_TOPIC, SEALED_BLOCK_TOPIC]);
+        first_leader
+            .network_service
+            .filter_unicast(&[CHAIN_LOADER_TOPIC]);
     });
 }
 
@@ -130,9 +193,9 @@ fn silent_view_change() {
 
         precondition_n_different_viewchange_leaders(&mut s, 2);
 
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let offset = s.nodes[0].node_service.chain.offset();
-        let starting_view_changes = s.nodes[0].node_service.chain.view_change();
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let offset = s.nodes[0].node_service.chain().offset();
+        let starting_view_changes = s.nodes[0].node_service.chain().view_change();
         let leader_pk = s.leader();
         let new_leader = s.future_view_change_leader(1);
 
@@ -143,7 +206,7 @@ fn silent_view_change() {
         // emulate dead leader for other nodes
         {
             // filter messages from chain loader.
-            s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
             let mut r = s.split(&[leader_pk]);
 
@@ -162,7 +225,7 @@ fn silent_view_change() {
             for msg in &msgs {
                 new_leader_node
                     .network_service
-                    .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                    .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
             }
             new_leader_node.handle_vdf();
             new_leader_node.poll();
@@ -170,7 +233,7 @@ fn silent_view_change() {
             info!("======= BROADCAST BLOCK =======");
             let block: Block = new_leader_node
                 .network_service
-                .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+                .get_broadcast(SEALED_BLOCK_TOPIC);
 
             let micro_block = block.clone().unwrap_micro();
             assert_eq!(micro_block.header.epoch, epoch);
@@ -179,7 +242,7 @@ fn silent_view_change() {
             // broadcast block to other nodes.
             for node in &mut r.parts.1.nodes {
                 node.network_service
-                    .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone())
+                    .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone())
             }
             r.parts.1.poll();
             // after this each node should go to the current block
@@ -189,17 +252,17 @@ fn silent_view_change() {
             // and go to the next view_change.
             for node in &mut r.parts.1.nodes {
                 info!("processing validator = {:?}", node.validator_id());
-                assert_eq!(node.node_service.chain.epoch(), epoch);
-                assert_eq!(node.node_service.chain.offset(), offset + 1);
-                assert_eq!(node.node_service.chain.view_change(), 0);
-                assert_eq!(node.node_service.chain.last_block_hash(), last_block_hash);
+                assert_eq!(node.node_service.chain().epoch(), epoch);
+                assert_eq!(node.node_service.chain().offset(), offset + 1);
+                assert_eq!(node.node_service.chain().view_change(), 0);
+                assert_eq!(node.node_service.chain().last_block_hash(), last_block_hash);
             }
             let first_leader = r.parts.0.first_mut();
 
-            assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+            assert_eq!(leader_pk, first_leader.node_service.network_pkey());
             first_leader
                 .network_service
-                .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC, crate::SEALED_BLOCK_TOPIC]);
+                .filter_broadcast(&[VIEW_CHANGE_TOPIC, SEALED_BLOCK_TOPIC]);
         }
     });
 }
@@ -231,17 +294,17 @@ fn double_view_change() {
         let mut blocks = 0;
 
         for _ in 0..=s.config.chain.micro_blocks_in_epoch {
-            let view_change = s.first_mut().node_service.chain.view_change();
-            let leader1 = s.first_mut().node_service.chain.leader();
+            let view_change = s.first_mut().node_service.chain().view_change();
+            let leader1 = s.first_mut().node_service.chain().leader();
             let leader2 = s
                 .first_mut()
                 .node_service
-                .chain
+                .chain()
                 .select_leader(view_change + 1);
             let leader3 = s
                 .first_mut()
                 .node_service
-                .chain
+                .chain()
                 .select_leader(view_change + 2);
 
             if leader1 != leader2 && leader2 != leader3 && leader3 != leader1 {
@@ -253,8 +316,8 @@ fn double_view_change() {
         }
         assert!(blocks < s.config.chain.micro_blocks_in_epoch as u32 - 2);
         let starting_view_changes = 0;
-        let leader_pk = s.nodes[0].node_service.chain.leader();
-        s.for_each(|node| assert_eq!(starting_view_changes, node.chain.view_change()));
+        let leader_pk = s.nodes[0].node_service.chain().leader();
+        s.for_each(|node| assert_eq!(starting_view_changes, node.chain().view_change()));
 
         s.poll();
         s.wait(s.config.node.micro_block_timeout);
@@ -263,16 +326,16 @@ fn double_view_change() {
         // emulate dead leader for other nodes
         {
             // filter messages from chain loader.
-            s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
             let mut r = s.split(&[leader_pk]);
 
             let first_leader = r.parts.0.first_mut();
 
-            assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+            assert_eq!(leader_pk, first_leader.node_service.network_pkey());
             first_leader
                 .network_service
-                .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC, crate::SEALED_BLOCK_TOPIC]);
+                .filter_broadcast(&[VIEW_CHANGE_TOPIC, SEALED_BLOCK_TOPIC]);
 
             let mut msgs = Vec::new();
             for node in &mut r.parts.1.nodes {
@@ -283,14 +346,14 @@ fn double_view_change() {
 
             let new_leader = r.parts.1.nodes[0]
                 .node_service
-                .chain
+                .chain()
                 .select_leader(starting_view_changes + 1);
 
             info!("======= BROADCAST VIEW_CHANGES =======");
             for node in &mut r.parts.1.nodes {
                 for msg in &msgs {
                     node.network_service
-                        .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                        .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
                 }
             }
 
@@ -301,13 +364,13 @@ fn double_view_change() {
             new_leader_node.poll();
             let _: Block = new_leader_node
                 .network_service
-                .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+                .get_broadcast(SEALED_BLOCK_TOPIC);
 
             // firstly check that all except leader increased view_change by one
             for node in &mut r.parts.1.iter_except(&[new_leader]) {
                 info!("processing validator = {:?}", node.validator_id());
                 assert_eq!(
-                    node.node_service.chain.view_change(),
+                    node.node_service.chain().view_change(),
                     starting_view_changes + 1
                 );
             }
@@ -316,13 +379,9 @@ fn double_view_change() {
             let mut r = s.split(&[leader_pk, new_leader]);
             r.parts.1.poll();
 
-            r.parts
-                .1
-                .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            r.parts.1.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
-            r.parts
-                .1
-                .filter_broadcast(&[crate::VIEW_CHANGE_PROOFS_TOPIC]);
+            r.parts.1.filter_broadcast(&[VIEW_CHANGE_PROOFS_TOPIC]);
             let mut msgs = Vec::new();
             for node in &mut r.parts.1.nodes {
                 let msg: ViewChangeMessage = node.network_service.get_broadcast(VIEW_CHANGE_TOPIC);
@@ -334,14 +393,14 @@ fn double_view_change() {
             for node in &mut r.parts.1.nodes {
                 for msg in &msgs {
                     node.network_service
-                        .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                        .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
                 }
             }
             // secondly check that after receiving 2/4 messages we didn't change view_counter
             for node in &mut r.parts.1.nodes {
                 info!("processing validator = {:?}", node.validator_id());
                 assert_eq!(
-                    node.node_service.chain.view_change(),
+                    node.node_service.chain().view_change(),
                     starting_view_changes + 1
                 );
             }
@@ -375,29 +434,27 @@ fn resolve_fork_for_view_change() {
 
         precondition_n_different_viewchange_leaders(&mut s, 2);
 
-        let starting_view_changes = s.nodes[0].node_service.chain.view_change();
-        let starting_offset = s.nodes[0].node_service.chain.offset();
+        let starting_view_changes = s.nodes[0].node_service.chain().view_change();
+        let starting_offset = s.nodes[0].node_service.chain().offset();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         s.poll();
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader = s.node(&leader_pk).unwrap();
         // forget block
 
         leader.handle_vdf();
         leader.poll();
-        let _b: Block = leader
-            .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+        let _b: Block = leader.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
 
         s.wait(s.config.node.micro_block_timeout);
         s.poll();
         // emulate dead leader for other nodes
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         info!("======= PARTITION BEGIN =======");
         let mut r = s.split(&[leader_pk]);
@@ -418,14 +475,14 @@ fn resolve_fork_for_view_change() {
         for msg in &msgs {
             new_leader_node
                 .network_service
-                .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
         }
         new_leader_node.handle_vdf();
         new_leader_node.poll();
         info!("======= BROADCAST BLOCK =======");
         let block: Block = new_leader_node
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
 
         let micro_block = block.clone().unwrap_micro();
         assert_eq!(micro_block.header.view_change, starting_view_changes + 1);
@@ -433,28 +490,28 @@ fn resolve_fork_for_view_change() {
         let last_block_hash = Hash::digest(&block);
 
         let first_leader = r.parts.0.first_mut();
-        assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+        assert_eq!(leader_pk, first_leader.node_service.network_pkey());
         first_leader
             .network_service
-            .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC]);
+            .filter_broadcast(&[VIEW_CHANGE_TOPIC]);
         // leader can send second block, if tx_wait_time << view_change timeout, and he is lucky
         first_leader
             .network_service
-            .filter_broadcast(&[crate::SEALED_BLOCK_TOPIC]);
+            .filter_broadcast(&[SEALED_BLOCK_TOPIC]);
         // broadcast block to old leader.
         first_leader
             .network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone());
+            .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone());
         first_leader.poll();
 
         info!("processing validator = {:?}", first_leader.validator_id());
-        assert_eq!(first_leader.node_service.chain.view_change(), 0);
+        assert_eq!(first_leader.node_service.chain().view_change(), 0);
         assert_eq!(
-            first_leader.node_service.chain.last_block_hash(),
+            first_leader.node_service.chain().last_block_hash(),
             last_block_hash
         );
         assert_eq!(
-            first_leader.node_service.chain.offset(),
+            first_leader.node_service.chain().offset(),
             starting_offset + 1
         );
     });
@@ -486,29 +543,27 @@ fn resolve_fork_without_block() {
 
         precondition_n_different_viewchange_leaders(&mut s, 2);
 
-        let starting_view_changes = s.nodes[0].node_service.chain.view_change();
-        let starting_offset = s.nodes[0].node_service.chain.offset();
+        let starting_view_changes = s.nodes[0].node_service.chain().view_change();
+        let starting_offset = s.nodes[0].node_service.chain().offset();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         s.poll();
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader = s.node(&leader_pk).unwrap();
 
         leader.handle_vdf();
         leader.poll();
         // forget block
-        let first_block: Block = leader
-            .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+        let first_block: Block = leader.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
 
         s.wait(s.config.node.micro_block_timeout);
         s.poll();
         // emulate dead leader for other nodes
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         info!("======= PARTITION BEGIN =======");
         let mut r = s.split(&[leader_pk]);
@@ -526,7 +581,7 @@ fn resolve_fork_without_block() {
         for node in r.parts.1.iter_mut() {
             for msg in &msgs {
                 node.network_service
-                    .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                    .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
             }
         }
 
@@ -538,12 +593,12 @@ fn resolve_fork_without_block() {
         info!("======= BROADCAST BLOCK =======");
         let _block: Block = new_leader_node
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
 
         // assert that each node increment his view_change
         for node in r.parts.1.iter_except(&[new_leader]) {
             assert_eq!(
-                node.node_service.chain.view_change(),
+                node.node_service.chain().view_change(),
                 starting_view_changes + 1
             );
         }
@@ -552,40 +607,40 @@ fn resolve_fork_without_block() {
         // any node that receive block from past view change, should produce view_change proof.
         let node = r.parts.1.iter_except(not_include).next().unwrap();
         node.network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, first_block);
+            .receive_broadcast(SEALED_BLOCK_TOPIC, first_block);
         node.poll();
         let proof: SealedViewChangeProof = node
             .network_service
-            .get_unicast_to_peer(crate::VIEW_CHANGE_DIRECT, &leader_pk);
+            .get_unicast_to_peer(VIEW_CHANGE_DIRECT, &leader_pk);
 
         let first_leader = r.parts.0.first_mut();
-        assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+        assert_eq!(leader_pk, first_leader.node_service.network_pkey());
         first_leader
             .network_service
-            .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC]);
+            .filter_broadcast(&[VIEW_CHANGE_TOPIC]);
         // leader can send second block, if tx_wait_time << view_change timeout, and he is lucky
         first_leader
             .network_service
-            .filter_broadcast(&[crate::SEALED_BLOCK_TOPIC]);
+            .filter_broadcast(&[SEALED_BLOCK_TOPIC]);
 
         first_leader.poll();
         // unicast view_change proof to old leader.
         first_leader
             .network_service
-            .receive_unicast(new_leader, crate::VIEW_CHANGE_DIRECT, proof);
+            .receive_unicast(new_leader, VIEW_CHANGE_DIRECT, proof);
 
-        assert_eq!(first_leader.node_service.chain.view_change(), 0);
+        assert_eq!(first_leader.node_service.chain().view_change(), 0);
         // sometimes leader can produce more than one block.
-        assert!(first_leader.node_service.chain.offset() > starting_offset);
+        assert!(first_leader.node_service.chain().offset() > starting_offset);
 
         first_leader.poll();
         // assert leader state
         info!("processing validator = {:?}", first_leader.validator_id());
         assert_eq!(
-            first_leader.node_service.chain.view_change(),
+            first_leader.node_service.chain().view_change(),
             starting_view_changes + 1
         );
-        assert_eq!(first_leader.node_service.chain.offset(), starting_offset);
+        assert_eq!(first_leader.node_service.chain().offset(), starting_offset);
     });
 }
 
@@ -609,29 +664,27 @@ fn issue_896_resolve_fork() {
 
         precondition_n_different_viewchange_leaders(&mut s, 2);
 
-        let starting_view_changes = s.nodes[0].node_service.chain.view_change();
-        let starting_offset = s.nodes[0].node_service.chain.offset();
+        let starting_view_changes = s.nodes[0].node_service.chain().view_change();
+        let starting_offset = s.nodes[0].node_service.chain().offset();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         s.poll();
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         let leader = s.node(&leader_pk).unwrap();
 
         leader.handle_vdf();
         leader.poll();
         // forget block
-        let first_block: Block = leader
-            .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+        let first_block: Block = leader.network_service.get_broadcast(SEALED_BLOCK_TOPIC);
 
         s.wait(s.config.node.micro_block_timeout);
         s.poll();
         // emulate dead leader for other nodes
 
         // filter messages from chain loader.
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         info!("======= PARTITION BEGIN =======");
         let mut r = s.split(&[leader_pk]);
@@ -649,7 +702,7 @@ fn issue_896_resolve_fork() {
         for node in r.parts.1.iter_mut() {
             for msg in &msgs {
                 node.network_service
-                    .receive_broadcast(crate::VIEW_CHANGE_TOPIC, msg.clone())
+                    .receive_broadcast(VIEW_CHANGE_TOPIC, msg.clone())
             }
         }
 
@@ -662,12 +715,12 @@ fn issue_896_resolve_fork() {
         info!("======= BROADCAST BLOCK =======");
         let _block: Block = new_leader_node
             .network_service
-            .get_broadcast(crate::SEALED_BLOCK_TOPIC);
+            .get_broadcast(SEALED_BLOCK_TOPIC);
 
         // assert that each node increment his view_change
         for node in r.parts.1.iter_except(&[new_leader]) {
             assert_eq!(
-                node.node_service.chain.view_change(),
+                node.node_service.chain().view_change(),
                 starting_view_changes + 1
             );
         }
@@ -675,38 +728,38 @@ fn issue_896_resolve_fork() {
         // any node that receive block from past view change, should produce view_change proof.
         let node = r.parts.1.iter_except(not_include).next().unwrap();
         node.network_service
-            .receive_broadcast(crate::SEALED_BLOCK_TOPIC, first_block);
+            .receive_broadcast(SEALED_BLOCK_TOPIC, first_block);
         node.poll();
         let proof: SealedViewChangeProof = node
             .network_service
-            .get_unicast_to_peer(crate::VIEW_CHANGE_DIRECT, &leader_pk);
+            .get_unicast_to_peer(VIEW_CHANGE_DIRECT, &leader_pk);
 
         // wait half of view_change timer
         r.wait(r.config.node.micro_block_timeout / 2);
 
         let first_leader = r.parts.0.first_mut();
-        assert_eq!(leader_pk, first_leader.node_service.network_pkey);
+        assert_eq!(leader_pk, first_leader.node_service.network_pkey());
         first_leader
             .network_service
-            .filter_broadcast(&[crate::VIEW_CHANGE_TOPIC]);
+            .filter_broadcast(&[VIEW_CHANGE_TOPIC]);
         first_leader.poll();
         // unicast view_change proof to old leader.
         first_leader
             .network_service
-            .receive_unicast(new_leader, crate::VIEW_CHANGE_DIRECT, proof);
+            .receive_unicast(new_leader, VIEW_CHANGE_DIRECT, proof);
 
-        assert_eq!(first_leader.node_service.chain.view_change(), 0);
+        assert_eq!(first_leader.node_service.chain().view_change(), 0);
         // sometimes leader can produce more than one block.
-        assert!(first_leader.node_service.chain.offset() > starting_offset);
+        assert!(first_leader.node_service.chain().offset() > starting_offset);
 
         first_leader.poll();
         // assert leader state
         info!("processing validator = {:?}", first_leader.validator_id());
         assert_eq!(
-            first_leader.node_service.chain.view_change(),
+            first_leader.node_service.chain().view_change(),
             starting_view_changes + 1
         );
-        assert_eq!(first_leader.node_service.chain.offset(), starting_offset);
+        assert_eq!(first_leader.node_service.chain().offset(), starting_offset);
 
         // wait for panic.
         r.wait(r.config.node.micro_block_timeout - r.config.node.micro_block_timeout / 2);
@@ -719,20 +772,20 @@ fn issue_896_resolve_fork() {
         let first_leader = r.parts.0.first_mut();
         first_leader
             .network_service
-            .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            .filter_unicast(&[CHAIN_LOADER_TOPIC]);
 
         // leader can send second block, if tx_wait_time << view_change timeout, and he is lucky
         first_leader
             .network_service
-            .filter_broadcast(&[crate::SEALED_BLOCK_TOPIC]);
+            .filter_broadcast(&[SEALED_BLOCK_TOPIC]);
 
         let msg: ViewChangeMessage = first_leader
             .network_service
-            .get_broadcast(crate::VIEW_CHANGE_TOPIC);
+            .get_broadcast(VIEW_CHANGE_TOPIC);
 
         let _: AddressedViewChangeProof = first_leader
             .network_service
-            .get_broadcast(crate::VIEW_CHANGE_PROOFS_TOPIC);
+            .get_broadcast(VIEW_CHANGE_PROOFS_TOPIC);
 
         assert_eq!(msg.chain.view_change, starting_view_changes + 1);
     });
@@ -748,21 +801,21 @@ fn out_of_order_keyblock_proposal() {
     Sandbox::start(config, |mut s| {
         s.poll();
 
-        let epoch = s.nodes[0].node_service.chain.epoch();
-        let round = s.nodes[0].node_service.chain.view_change();
+        let epoch = s.nodes[0].node_service.chain().epoch();
+        let round = s.nodes[0].node_service.chain().view_change();
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         let proposal = {
-            let previous = s.nodes[0].node_service.chain.last_block_hash();
-            let last_random = s.nodes[0].node_service.chain.last_random();
+            let previous = s.nodes[0].node_service.chain().last_block_hash();
+            let last_random = s.nodes[0].node_service.chain().last_random();
             let leader_node = s.node(&leader_pk).unwrap();
 
             let timestamp = Timestamp::now();
             let seed = mix(last_random, round);
-            let random = pbc::make_VRF(&leader_node.node_service.network_skey, &seed);
-            let difficulty = leader_node.node_service.chain.difficulty();
-            let leader = leader_node.node_service.network_pkey;
+            let random = pbc::make_VRF(&leader_node.network_skey, &seed);
+            let difficulty = leader_node.node_service.chain().difficulty();
+            let leader = leader_node.node_service.network_pkey();
             let block_reward = 0;
             let activity_map = BitVec::new();
             let validators = vec![];
@@ -787,8 +840,8 @@ fn out_of_order_keyblock_proposal() {
                 epoch,
                 round + 1,
                 block_hash,
-                &leader_node.node_service.network_skey,
-                &leader_node.node_service.network_pkey,
+                &leader_node.network_skey,
+                &leader_node.node_service.network_pkey(),
                 body,
             )
         };
@@ -796,27 +849,25 @@ fn out_of_order_keyblock_proposal() {
         // broadcast block to other nodes.
         for node in &mut s.iter_except(&[leader_pk]) {
             node.network_service
-                .receive_broadcast(crate::CONSENSUS_TOPIC, proposal.clone())
+                .receive_broadcast(CONSENSUS_TOPIC, proposal.clone())
         }
         s.poll();
 
         let mut p = s.split(&[leader_pk]);
 
         p.parts.1.for_each(|node| {
-            assert_eq!(node.chain.epoch(), epoch);
-            assert_eq!(node.chain.view_change(), round);
+            assert_eq!(node.chain().epoch(), epoch);
+            assert_eq!(node.chain().view_change(), round);
         });
 
         let leader = p.parts.0.first_mut();
 
-        assert_eq!(leader_pk, leader.node_service.network_pkey);
+        assert_eq!(leader_pk, leader.node_service.network_pkey());
+        leader.network_service.filter_unicast(&[CHAIN_LOADER_TOPIC]);
         leader
             .network_service
-            .filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        leader
-            .network_service
-            .filter_broadcast(&[crate::SEALED_BLOCK_TOPIC]);
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+            .filter_broadcast(&[SEALED_BLOCK_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
     });
 }
 
@@ -830,12 +881,12 @@ fn invalid_microblock() {
     Sandbox::start(config, |mut s| {
         s.poll();
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
-        let offset = s.nodes[0].node_service.chain.offset();
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+        let offset = s.nodes[0].node_service.chain().offset();
 
-        s.for_each(|node| assert_eq!(node.chain.offset(), offset));
+        s.for_each(|node| assert_eq!(node.chain().offset(), offset));
 
-        let leader_pk = s.nodes[0].node_service.chain.leader();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
 
         let mut r = s.split(&[leader_pk]);
         let leader = r.parts.0.first_mut();
@@ -862,10 +913,7 @@ fn invalid_microblock() {
                     {
                         $do
                     }
-                    $block.sign(
-                        &leader.node_service.network_skey,
-                        &leader.node_service.network_pkey,
-                    );
+                    $block.sign(&leader.network_skey, &leader.node_service.network_pkey());
                     blocks.push(Block::MicroBlock($block));
                 };
             }
@@ -888,11 +936,8 @@ fn invalid_microblock() {
 
             // invalid pk
             let mut block = source.clone();
-            block.header.pkey = other.node_service.network_pkey;
-            block.sign(
-                &other.node_service.network_skey,
-                &other.node_service.network_pkey,
-            );
+            block.header.pkey = other.node_service.network_pkey();
+            block.sign(&other.network_skey, &other.node_service.network_pkey());
 
             // invalid previous
             modify_block!(block, block.header.previous = Hash::digest("test"));
@@ -925,16 +970,16 @@ fn invalid_microblock() {
         for node in &mut r.parts.1.iter_mut() {
             for block in &blocks {
                 node.network_service
-                    .receive_broadcast(crate::SEALED_BLOCK_TOPIC, block.clone())
+                    .receive_broadcast(SEALED_BLOCK_TOPIC, block.clone())
             }
         }
         r.parts.1.poll();
 
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.chain.offset(), offset));
+            .for_each(|node| assert_eq!(node.chain().offset(), offset));
 
-        s.filter_unicast(&[crate::loader::CHAIN_LOADER_TOPIC]);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
     });
 }
 
@@ -962,7 +1007,7 @@ fn slash_cheater() {
         precondition_n_different_block_leaders(&mut s, 2);
         // next leader should be from different partition.
 
-        let cheater = s.nodes[0].node_service.chain.leader();
+        let cheater = s.nodes[0].node_service.chain().leader();
         info!("CREATE BLOCK. LEADER = {}", cheater);
         s.poll();
 
@@ -970,19 +1015,19 @@ fn slash_cheater() {
 
         info!(
             "CHECK IF CHEATER WAS DETECTED. LEADER={}",
-            r.parts.1.first().node_service.chain.leader()
+            r.parts.1.first().node_service.chain().leader()
         );
         // each node should add proof of slashing into state.
         r.parts
             .1
-            .for_each(|node| assert_eq!(node.cheating_proofs.len(), 1));
+            .for_each(|node| assert_eq!(node.cheating_proofs().len(), 1));
         r.parts.1.skip_micro_block();
 
         // assert that nodes in partition 1 exclude node from partition 0.
         for node in r.parts.1.iter() {
             let validators: HashSet<_> = node
                 .node_service
-                .chain
+                .chain()
                 .validators()
                 .iter()
                 .map(|(p, _)| *p)
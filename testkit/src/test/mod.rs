@@ -0,0 +1,56 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! Sandbox tests of the node.
+//!
+
+mod compact;
+mod consensus;
+mod dandelion;
+mod integration;
+mod microblocks;
+mod observer;
+
+use crate::*;
+use futures::{Async, Future};
+use log::*;
+use std::time::Duration;
+use stegos_blockchain::*;
+use stegos_consensus::optimistic::{AddressedViewChangeProof, ViewChangeMessage};
+use stegos_consensus::ConsensusMessage;
+use stegos_crypto::hash::Hash;
+use stegos_crypto::pbc;
+use stegos_node::*;
+
+#[test]
+fn test_partition() {
+    let config: SandboxConfig = Default::default();
+
+    Sandbox::start(config, |mut s| {
+        s.poll();
+        let leader_pk = s.nodes[0].node_service.chain().leader();
+        let r = s.split(&[leader_pk]);
+        assert_eq!(r.parts.0.nodes.len(), 1);
+        assert_eq!(r.parts.1.nodes.len(), 3);
+        s.filter_unicast(&[CHAIN_LOADER_TOPIC]);
+    });
+}
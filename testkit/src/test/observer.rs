@@ -0,0 +1,98 @@
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::*;
+
+/// Replaces the auditor with an observer, which has the network keys of the first validator.
+fn start_observer(s: &mut Sandbox) -> pbc::PublicKey {
+    let mut node_cfg = s.config.node.clone();
+    node_cfg.mode = NodeMode::Observer;
+    let genesis = s.first().chain().macro_block(0).unwrap().into_owned();
+    let (pkey, skey) = s.first().keys();
+    s.auditor = NodeSandbox::new(node_cfg, s.config.chain.clone(), skey, pkey, genesis);
+    pkey
+}
+
+#[test]
+fn observer() {
+    Sandbox::start(Default::default(), |mut s| {
+        let observer_pk = start_observer(&mut s);
+        s.poll();
+        // The stake is there, but the observer stays silent.
+        assert!(s.auditor.chain().is_validator(&observer_pk));
+        s.auditor.network_service.assert_empty_queue();
+
+        // Transactions are validated and kept in the mempool.
+        let fee = s.config.node.min_payment_fee;
+        let tx: Transaction =
+            create_payment_transaction(s.first().chain(), &s.keychains[0], fee).into();
+        let tx_hash = Hash::digest(&tx);
+        s.send_transaction(tx);
+        assert!(s.auditor.node_service.mempool_contains_tx(&tx_hash));
+        s.auditor.network_service.assert_empty_queue();
+
+        // Micro blocks are applied, but never proposed.
+        let epoch = s.first().chain().epoch();
+        for _offset in s.first().chain().offset()..s.config.chain.micro_blocks_in_epoch {
+            s.poll();
+            s.skip_micro_block();
+            s.auditor.network_service.assert_empty_queue();
+        }
+        assert!(!s.auditor.node_service.mempool_contains_tx(&tx_hash));
+        assert_eq!(
+            s.auditor.chain().last_block_hash(),
+            s.first().chain().last_block_hash()
+        );
+
+        // The macro block is not negotiated by the observer.
+        assert!(s.first().node_service.consensus().is_some());
+        assert!(s.auditor.node_service.consensus().is_none());
+        s.skip_macro_block();
+        s.auditor.network_service.assert_empty_queue();
+        assert_eq!(s.auditor.chain().epoch(), epoch + 1);
+        assert_eq!(
+            s.auditor.chain().last_macro_block_hash(),
+            s.first().chain().last_macro_block_hash()
+        );
+
+        // API is served, except for re-staking.
+        let mut status = s.auditor.node.request(NodeRequest::StatusInfo {});
+        let mut restaking = s.auditor.node.request(NodeRequest::EnableRestaking {});
+        s.auditor.poll();
+        match status.poll().unwrap() {
+            Async::Ready(NodeResponse::StatusInfo(info)) => {
+                assert_eq!(info.epoch, epoch + 1);
+                assert_eq!(info.offset, 0);
+            }
+            e => panic!("Expected status info, got ={:?}", e),
+        }
+        match restaking.poll().unwrap() {
+            Async::Ready(NodeResponse::Error { .. }) => {}
+            e => panic!("Expected an error, got ={:?}", e),
+        }
+
+        // No view changes when the leader is silent.
+        let micro_block_timeout = s.config.node.micro_block_timeout;
+        s.wait(micro_block_timeout);
+        s.auditor.poll();
+        s.auditor.network_service.assert_empty_queue();
+    });
+}
@@ -0,0 +1,124 @@
+//! Test Wallet.
+
+//
+// Copyright (c) 2019 Stegos AG
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//!
+//! A minimal wallet, which scans the UTXO set of a node.
+//!
+
+use failure::{bail, Error};
+use stegos_blockchain::test::KeyChain;
+use stegos_blockchain::{Blockchain, Output, PaymentOutput, PaymentTransaction, Transaction};
+use stegos_crypto::scc::{Fr, PublicKey};
+
+#[derive(Clone, Debug)]
+pub struct TestWallet {
+    pub keys: KeyChain,
+}
+
+impl TestWallet {
+    pub fn new(keys: KeyChain) -> Self {
+        TestWallet { keys }
+    }
+
+    pub fn account_pkey(&self) -> &PublicKey {
+        &self.keys.account_pkey
+    }
+
+    /// Unspent payment outputs of this account with their amounts.
+    pub fn unspent(&self, chain: &Blockchain) -> Vec<(Output, i64)> {
+        let mut unspent = Vec::new();
+        for output_hash in chain.unspent() {
+            let output = chain
+                .output_by_hash(output_hash)
+                .expect("no disk errors")
+                .expect("exists");
+            let amount = match &output {
+                Output::PaymentOutput(o) => {
+                    match o.decrypt_payload(&self.keys.account_pkey, &self.keys.account_skey) {
+                        Ok(payload) => payload.amount,
+                        Err(_) => continue,
+                    }
+                }
+                Output::PublicPaymentOutput(o) if o.recipient == self.keys.account_pkey => o.amount,
+                _ => continue,
+            };
+            unspent.push((output, amount));
+        }
+        unspent
+    }
+
+    pub fn balance(&self, chain: &Blockchain) -> i64 {
+        self.unspent(chain).iter().map(|(_, amount)| amount).sum()
+    }
+
+    ///
+    /// Create a payment with change back to this account.
+    /// `fee` must cover the minimal fee of the node for two outputs.
+    ///
+    pub fn create_payment(
+        &self,
+        chain: &Blockchain,
+        recipient: &PublicKey,
+        amount: i64,
+        fee: i64,
+    ) -> Result<Transaction, Error> {
+        assert!(amount > 0);
+        let mut inputs = Vec::new();
+        let mut total = 0;
+        for (output, output_amount) in self.unspent(chain) {
+            if total >= amount + fee {
+                break;
+            }
+            inputs.push(output);
+            total += output_amount;
+        }
+        if total < amount + fee {
+            bail!(
+                "Not enough money: balance={}, required={}",
+                total,
+                amount + fee
+            );
+        }
+
+        let mut outputs = Vec::new();
+        let mut outputs_gamma = Fr::zero();
+        let (output, gamma) = PaymentOutput::new(recipient, amount)?;
+        outputs.push(output.into());
+        outputs_gamma += gamma;
+        let change = total - amount - fee;
+        if change > 0 {
+            let (output, gamma) = PaymentOutput::new(&self.keys.account_pkey, change)?;
+            outputs.push(output.into());
+            outputs_gamma += gamma;
+        }
+
+        let tx = PaymentTransaction::new(
+            &self.keys.account_skey,
+            &inputs,
+            &outputs,
+            &outputs_gamma,
+            fee,
+        )?;
+        Ok(tx.into())
+    }
+}
@@ -203,6 +203,8 @@ pub enum AccountRequest {
     CloakAll {
         payment_fee: i64,
     },
+    /// Move all stakes to a new network key, used by the node from the next epoch.
+    RotateNetworkKey {},
     AccountInfo {},
     BalanceInfo {},
     UnspentInfo {},
@@ -283,6 +285,10 @@ pub enum AccountResponse {
     },
     PasswordChanged,
     Recovery(AccountRecovery),
+    NetworkKeyRotated {
+        tx_hash: Hash,
+        network_pkey: pbc::PublicKey,
+    },
     Error {
         error: String,
     },
@@ -60,7 +60,9 @@ use stegos_keychain::keyfile::{
 };
 use stegos_keychain::KeyError;
 use stegos_network::Network;
-use stegos_node::{ChainNotification, Node, NodeRequest, NodeResponse, TransactionStatus};
+use stegos_node::{
+    network_key_files, ChainNotification, Node, NodeRequest, NodeResponse, TransactionStatus,
+};
 use tokio::runtime::TaskExecutor;
 use tokio_timer::{clock, Interval};
 
@@ -125,6 +127,11 @@ impl ChainSubscription {
     }
 }
 
+/// Rotation of the network key, waiting for the node response.
+struct NetworkKeyRotation {
+    response: oneshot::Receiver<NodeResponse>,
+}
+
 struct UnsealedAccountService {
     //
     // Config
@@ -137,8 +144,10 @@ struct UnsealedAccountService {
     account_skey: scc::SecretKey,
     /// Account Public Key.
     account_pkey: scc::PublicKey,
-    /// Network Secret Key.
-    network_skey: pbc::SecretKey,
+    /// Directory with the network keys of the node.
+    network_keys_dir: PathBuf,
+    /// Network Secret Key, if the node doesn't use a remote signer.
+    network_skey: Option<pbc::SecretKey>,
     /// Network Public Key.
     network_pkey: pbc::PublicKey,
     /// Lifetime of stake.
@@ -173,6 +182,12 @@ struct UnsealedAccountService {
     // Response from mempool about transaction.
     //
     transaction_response: Option<oneshot::Receiver<NodeResponse>>,
+    //
+    // Pending rotation of the network key.
+    //
+    network_key_rotation: Option<(NetworkKeyRotation, oneshot::Sender<AccountResponse>)>,
+    /// New network key, used by the node from the next epoch.
+    pending_network_pkey: Option<pbc::PublicKey>,
 
     //
     // Api subscribers
@@ -196,7 +211,8 @@ impl UnsealedAccountService {
         account_dir: PathBuf,
         account_skey: scc::SecretKey,
         account_pkey: scc::PublicKey,
-        network_skey: pbc::SecretKey,
+        network_keys_dir: PathBuf,
+        network_skey: Option<pbc::SecretKey>,
         network_pkey: pbc::PublicKey,
         network: Network,
         node: Node,
@@ -218,6 +234,8 @@ impl UnsealedAccountService {
         let epoch = database.epoch();
         debug!("Opened database: epoch={}", epoch);
         let transaction_response = None;
+        let network_key_rotation = None;
+        let pending_network_pkey = None;
         let resend_tx = Interval::new(clock::now(), RESEND_TX_INTERVAL);
         let check_pending_utxos = Interval::new(clock::now(), CHECK_PENDING_UTXO);
         let chain_notifications = ChainSubscription::new(&node, epoch, 0);
@@ -228,6 +246,7 @@ impl UnsealedAccountService {
             account_dir,
             account_skey,
             account_pkey,
+            network_keys_dir,
             network_skey,
             network_pkey,
             database,
@@ -244,6 +263,8 @@ impl UnsealedAccountService {
             events,
             chain_notifications,
             transaction_response,
+            network_key_rotation,
+            pending_network_pkey,
         }
     }
 
@@ -482,14 +503,17 @@ impl UnsealedAccountService {
         self.stake_inner(amount, payment_fee, network_pkey, network_skey)
     }
 
+    /// Network secret key of the local node.
+    fn network_skey(&self) -> Result<&pbc::SecretKey, Error> {
+        self.network_skey
+            .as_ref()
+            .ok_or_else(|| format_err!("The network secret key is not available to this wallet"))
+    }
+
     /// Stake money into the escrow.
     fn stake(&mut self, amount: i64, payment_fee: i64) -> Result<TransactionInfo, Error> {
-        self.stake_inner(
-            amount,
-            payment_fee,
-            self.network_pkey,
-            self.network_skey.clone(),
-        )
+        let network_skey = self.network_skey()?.clone();
+        self.stake_inner(amount, payment_fee, self.network_pkey, network_skey)
     }
 
     /// Unstake money from the escrow.
@@ -509,7 +533,7 @@ impl UnsealedAccountService {
             &self.account_skey,
             &self.account_pkey,
             &self.network_pkey,
-            &self.network_skey,
+            self.network_skey()?,
             unspent_iter,
             amount,
             payment_fee,
@@ -543,6 +567,18 @@ impl UnsealedAccountService {
         self.unstake(amount, payment_fee)
     }
 
+    /// Move all stakes of the current network key to a new key, generated by the node.
+    /// The node switches to the new key at the end of epoch.
+    fn rotate_network_key(&mut self) -> Result<NetworkKeyRotation, Error> {
+        if self.network_key_rotation.is_some() || self.pending_network_pkey.is_some() {
+            return Err(format_err!("Network key rotation is already in progress"));
+        }
+        info!("Rotating network key: old_pkey={}", self.network_pkey);
+        let request = NodeRequest::RotateNetworkKeys {};
+        let response = self.node.request(request);
+        Ok(NetworkKeyRotation { response })
+    }
+
     /// Cloak all available public outputs.
     fn cloak_all(&mut self, fee: i64) -> Result<TransactionInfo, Error> {
         // Secret key to sign the transaction.
@@ -913,6 +949,7 @@ impl UnsealedAccountService {
             snowball.change_facilitator(self.facilitator_pkey.clone());
         }
         self.last_macro_block_timestamp = last_macro_block_timestamp;
+        self.switch_pending_network_key();
         let updated_statuses = self
             .database
             .finalize_epoch(epoch)
@@ -920,6 +957,61 @@ impl UnsealedAccountService {
         self.on_tx_statuses_changed(&updated_statuses);
     }
 
+    /// Switch to the new network key together with the node, when the stake has been moved.
+    fn switch_pending_network_key(&mut self) {
+        let network_pkey = match self.pending_network_pkey {
+            Some(network_pkey) => network_pkey,
+            None => return,
+        };
+        let is_moved = self
+            .database
+            .iter_unspent()
+            .filter_map(|(_k, v)| v.stake())
+            .any(|v| v.output.validator == network_pkey);
+        if !is_moved {
+            // Rotation transaction hasn't been committed yet.
+            return;
+        }
+        if self.network_skey.is_some() {
+            // The node has already saved the new keys on the epoch boundary.
+            match self.load_network_keys(&network_pkey) {
+                Ok(network_skey) => self.network_skey = Some(network_skey),
+                Err(e) => {
+                    error!(
+                        "Failed to load the new network key: new_pkey={}, error={}",
+                        network_pkey, e
+                    );
+                    // Try again on the next epoch.
+                    return;
+                }
+            }
+        }
+        info!(
+            "Switched network key: old_pkey={}, new_pkey={}",
+            self.network_pkey, network_pkey
+        );
+        self.network_pkey = network_pkey;
+        self.pending_network_pkey = None;
+    }
+
+    /// Load the network secret key saved by the node.
+    fn load_network_keys(&self, network_pkey: &pbc::PublicKey) -> Result<pbc::SecretKey, Error> {
+        if self.network_keys_dir.as_os_str().is_empty() {
+            return Err(format_err!("Network keys are kept in memory by the node"));
+        }
+        let (network_skey_file, network_pkey_file) = network_key_files(&self.network_keys_dir);
+        let (network_skey, disk_network_pkey) =
+            load_network_keypair(&network_skey_file, &network_pkey_file)?;
+        if &disk_network_pkey != network_pkey {
+            return Err(format_err!(
+                "Unexpected network key on disk: expected={}, got={}",
+                network_pkey,
+                disk_network_pkey
+            ));
+        }
+        Ok(network_skey)
+    }
+
     fn handle_snowball_transaction(
         &mut self,
         tx: PaymentTransaction,
@@ -1145,6 +1237,39 @@ impl Future for UnsealedAccountService {
             }
         }
 
+        if let Some((mut rotation, tx)) = self.network_key_rotation.take() {
+            match rotation.response.poll().expect("connected") {
+                Async::Ready(response) => {
+                    let response = match response {
+                        NodeResponse::NetworkKeysRotated {
+                            network_pkey,
+                            hash,
+                            status,
+                        } => match status {
+                            TransactionStatus::Accepted {} => {
+                                info!(
+                                    "Network key rotation started: old_pkey={}, new_pkey={}, tx={}",
+                                    self.network_pkey, network_pkey, hash
+                                );
+                                self.pending_network_pkey = Some(network_pkey);
+                                AccountResponse::NetworkKeyRotated {
+                                    tx_hash: hash,
+                                    network_pkey,
+                                }
+                            }
+                            status => AccountResponse::Error {
+                                error: format!("Key rotation failed: status={:?}", status),
+                            },
+                        },
+                        NodeResponse::Error { error } => AccountResponse::Error { error },
+                        _ => unreachable!("Expected NetworkKeysRotated|Error response"),
+                    };
+                    tx.send(response).ok();
+                }
+                Async::NotReady => self.network_key_rotation = Some((rotation, tx)),
+            }
+        }
+
         loop {
             match self.resend_tx.poll().expect("no errors in timers") {
                 Async::Ready(Some(_t)) => self.handle_resend_pending_txs(),
@@ -1263,6 +1388,18 @@ impl Future for UnsealedAccountService {
                             AccountRequest::CloakAll { payment_fee } => {
                                 self.cloak_all(payment_fee).into()
                             }
+                            AccountRequest::RotateNetworkKey {} => {
+                                match self.rotate_network_key() {
+                                    Ok(rotation) => {
+                                        self.network_key_rotation = (rotation, tx).into();
+                                        task::current().notify();
+                                        continue;
+                                    }
+                                    Err(e) => AccountResponse::Error {
+                                        error: format!("{}", e),
+                                    },
+                                }
+                            }
                             AccountRequest::AccountInfo {} => {
                                 let account_info = AccountInfo {
                                     account_pkey: self.account_pkey.clone(),
@@ -1500,8 +1637,10 @@ struct SealedAccountService {
     account_dir: PathBuf,
     /// Account Public Key.
     account_pkey: scc::PublicKey,
-    /// Network Secret Key.
-    network_skey: pbc::SecretKey,
+    /// Directory with the network keys of the node.
+    network_keys_dir: PathBuf,
+    /// Network Secret Key, if the node doesn't use a remote signer.
+    network_skey: Option<pbc::SecretKey>,
     /// Network Public Key.
     network_pkey: pbc::PublicKey,
     /// Lifetime of stake.
@@ -1527,7 +1666,8 @@ impl SealedAccountService {
         database_dir: PathBuf,
         account_dir: PathBuf,
         account_pkey: scc::PublicKey,
-        network_skey: pbc::SecretKey,
+        network_keys_dir: PathBuf,
+        network_skey: Option<pbc::SecretKey>,
         network_pkey: pbc::PublicKey,
         network: Network,
         node: Node,
@@ -1540,6 +1680,7 @@ impl SealedAccountService {
             database_dir,
             account_dir,
             account_pkey,
+            network_keys_dir,
             network_skey,
             network_pkey,
             stake_epochs,
@@ -1646,6 +1787,7 @@ impl Future for AccountService {
                         sealed.account_dir,
                         account_skey,
                         sealed.account_pkey,
+                        sealed.network_keys_dir,
                         sealed.network_skey,
                         sealed.network_pkey,
                         sealed.network,
@@ -1685,6 +1827,7 @@ impl Future for AccountService {
                         unsealed.database_dir,
                         unsealed.account_dir,
                         unsealed.account_pkey,
+                        unsealed.network_keys_dir,
                         unsealed.network_skey,
                         unsealed.network_pkey,
                         unsealed.network,
@@ -1709,7 +1852,8 @@ impl AccountService {
     fn new(
         database_dir: &Path,
         account_dir: &Path,
-        network_skey: pbc::SecretKey,
+        network_keys_dir: PathBuf,
+        network_skey: Option<pbc::SecretKey>,
         network_pkey: pbc::PublicKey,
         network: Network,
         node: Node,
@@ -1724,6 +1868,7 @@ impl AccountService {
             database_dir.to_path_buf(),
             account_dir.to_path_buf(),
             account_pkey,
+            network_keys_dir,
             network_skey,
             network_pkey,
             network,
@@ -1784,7 +1929,8 @@ struct AccountHandle {
 
 pub struct WalletService {
     accounts_dir: PathBuf,
-    network_skey: pbc::SecretKey,
+    network_keys_dir: PathBuf,
+    network_skey: Option<pbc::SecretKey>,
     network_pkey: pbc::PublicKey,
     network: Network,
     node: Node,
@@ -1801,7 +1947,8 @@ pub struct WalletService {
 impl WalletService {
     pub fn new(
         accounts_dir: &Path,
-        network_skey: pbc::SecretKey,
+        network_keys_dir: PathBuf,
+        network_skey: Option<pbc::SecretKey>,
         network_pkey: pbc::PublicKey,
         network: Network,
         node: Node,
@@ -1815,6 +1962,7 @@ impl WalletService {
         let chain_notifications = ChainSubscription::new(&node, last_epoch, 0);
         let mut service = WalletService {
             accounts_dir: accounts_dir.to_path_buf(),
+            network_keys_dir,
             network_skey,
             network_pkey,
             network,
@@ -1897,6 +2045,7 @@ impl WalletService {
         let (account_service, account) = AccountService::new(
             &account_database_dir,
             &account_dir,
+            self.network_keys_dir.clone(),
             self.network_skey.clone(),
             self.network_pkey.clone(),
             self.network.clone(),
@@ -564,8 +564,10 @@ impl Snowball {
             ownsig,
         };
         let msg = msg.into_buffer().unwrap();
-        self.network
-            .send(self.facilitator, POOL_JOIN_TOPIC, msg)
+        // Resent until acknowledged, the outcome is not awaited.
+        let _ = self
+            .network
+            .send_reliable(self.facilitator, POOL_JOIN_TOPIC, msg)
             .expect("Connected");
 
         self.msg_queue.clear();
@@ -1926,8 +1928,9 @@ impl Snowball {
                 };
                 let bmsg = msg.into_buffer().expect("serialized");
                 sdebug!(self, "sending msg {:?} to {}", &msg, pkey);
-                self.network
-                    .send(pkey.pkey.clone(), SNOWBALL_TOPIC, bmsg)
+                let _ = self
+                    .network
+                    .send_reliable(pkey.pkey.clone(), SNOWBALL_TOPIC, bmsg)
                     .expect("connected");
             }
         }
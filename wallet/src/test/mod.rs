@@ -21,12 +21,13 @@
 
 #![allow(unused)]
 
-pub use stegos_node::test::*;
+pub use stegos_testkit::*;
 mod account_transaction;
 use super::Account;
 use crate::{
     AccountEvent, AccountNotification, AccountResponse, AccountService, UnsealedAccountService,
 };
+use std::path::PathBuf;
 use stegos_blockchain::{Blockchain, ChainConfig};
 use stegos_crypto::scc;
 use stegos_network::Network;
@@ -92,7 +93,8 @@ impl AccountSandbox {
             temp_path.to_path_buf(),
             account_skey,
             account_pkey,
-            network_skey,
+            PathBuf::new(),
+            Some(network_skey),
             network_pkey,
             network,
             node.node.clone(),